@@ -0,0 +1,55 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Format of the `aacc_chg_profile` node.
+//!
+//! The node holds the selected profile as a bare decimal index, optionally
+//! followed by a newline. The number of profiles comes from the separate
+//! `aacc_chg_profile_cnt` node, so the index is the only field.
+
+use std::fmt;
+
+pub const EXPECTED_FORMAT: &str = "a single non-negative profile index, e.g. \"2\"";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AaccProfile {
+    pub index: i32,
+}
+
+impl AaccProfile {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().parse::<i32>() {
+            Ok(index) if index >= 0 => Ok(Self { index }),
+            _ => Err(format!("bad AACC profile '{s}': expected {EXPECTED_FORMAT}")),
+        }
+    }
+}
+
+/// Writes the node format, so `parse(&p.to_string()) == Ok(p)`.
+impl fmt::Display for AaccProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_round_trip() {
+        for index in [0, 1, 7, i32::MAX] {
+            let p = AaccProfile { index };
+            assert_eq!(AaccProfile::parse(&p.to_string()), Ok(p));
+            assert_eq!(AaccProfile::parse(&format!("{p}\n")), Ok(p));
+        }
+    }
+
+    #[test]
+    fn malformed_profiles_name_the_expected_format() {
+        for s in ["", "\n", "-1", "1 2", "0x2", "two", "2.0", "99999999999"] {
+            let e = AaccProfile::parse(s).unwrap_err();
+            assert!(e.contains(EXPECTED_FORMAT), "{s:?}: {e}");
+        }
+    }
+}
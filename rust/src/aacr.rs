@@ -0,0 +1,45 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! States of the `aacr_state` node and which of them userspace may request.
+//!
+//! The driver moves between ENABLED and RUNNING on its own as capacity
+//! estimates come in, so RUNNING is never a valid write target.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AacrState {
+    Disabled,
+    Enabled,
+    Running,
+}
+
+impl AacrState {
+    pub fn from_i32(v: i32) -> Option<Self> {
+        match v {
+            0 => Some(Self::Disabled),
+            1 => Some(Self::Enabled),
+            2 => Some(Self::Running),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32(self) -> i32 {
+        match self {
+            Self::Disabled => 0,
+            Self::Enabled => 1,
+            Self::Running => 2,
+        }
+    }
+
+    /// States a write may move to from this one, besides staying put.
+    pub fn valid_transitions(self) -> &'static [AacrState] {
+        match self {
+            Self::Disabled => &[Self::Enabled],
+            Self::Enabled | Self::Running => &[Self::Disabled],
+        }
+    }
+
+    pub fn can_transition_to(self, target: AacrState) -> bool {
+        self == target || self.valid_transitions().contains(&target)
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! States of the `aafv_state` node. The driver silently ignores anything but
+//! 0 and 1, so values are checked here before they are written.
+//!
+//! Either transition is allowed at any time: enabling needs no precondition,
+//! and disabling just stops further float voltage adjustment.
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AafvState {
+    Disabled,
+    Enabled,
+}
+
+impl AafvState {
+    pub fn as_i32(self) -> i32 {
+        match self {
+            Self::Disabled => 0,
+            Self::Enabled => 1,
+        }
+    }
+}
+
+impl From<bool> for AafvState {
+    fn from(enabled: bool) -> Self {
+        if enabled {
+            Self::Enabled
+        } else {
+            Self::Disabled
+        }
+    }
+}
+
+impl FromStr for AafvState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().parse::<i32>() {
+            Ok(0) => Ok(Self::Disabled),
+            Ok(1) => Ok(Self::Enabled),
+            _ => Err(format!("AAFV state '{s}' must be 0 or 1")),
+        }
+    }
+}
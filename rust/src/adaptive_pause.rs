@@ -0,0 +1,118 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Temporary pause of adaptive charging. The deadline is kept as a wall-clock
+//! target rather than seconds remaining, so resuming after any delay (or a HAL
+//! restart) re-arms it with the time actually left.
+
+use std::{fs, io, path::Path};
+
+use crate::persist;
+
+pub const STATE_FILE: &str = "adaptive_pause";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pause {
+    /// Wall-clock milliseconds of the deadline, if one was set when pausing.
+    pub target_ms: Option<i64>,
+    // Set once a charger is seen, so unplugging ends the pause.
+    pub plugged_seen: bool,
+}
+
+impl Pause {
+    pub fn new(remaining_sec: i64, now_ms: i64) -> Self {
+        Self {
+            target_ms: (remaining_sec > 0).then(|| now_ms + remaining_sec * 1000),
+            plugged_seen: false,
+        }
+    }
+
+    /// Seconds left until the stored deadline, or None once it has passed or
+    /// if there was none.
+    pub fn remaining_sec(&self, now_ms: i64) -> Option<i64> {
+        let left = (self.target_ms? - now_ms) / 1000;
+        (left > 0).then_some(left)
+    }
+
+    /// Feeds the charger state in and returns whether the pause should end.
+    pub fn step(&mut self, plugged: bool) -> bool {
+        if plugged {
+            self.plugged_seen = true;
+            false
+        } else {
+            self.plugged_seen
+        }
+    }
+
+    fn to_line(self) -> String {
+        format!("{} {}", self.target_ms.unwrap_or(0), self.plugged_seen as i32)
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let target_ms = fields.next()?.parse::<i64>().ok()?;
+        let plugged_seen = fields.next()?.parse::<i32>().ok()?;
+        if fields.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            target_ms: (target_ms > 0).then_some(target_ms),
+            plugged_seen: plugged_seen != 0,
+        })
+    }
+}
+
+pub fn load(path: &Path) -> Option<Pause> {
+    Pause::from_line(fs::read_to_string(path).ok()?.trim())
+}
+
+pub fn save(path: &Path, pause: &Pause) -> io::Result<()> {
+    persist::write_atomic(path, &pause.to_line())
+}
+
+pub fn clear(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_deadline_is_kept_as_a_wall_clock_target() {
+        let p = Pause::new(3600, 1_000_000);
+        assert_eq!(p.target_ms, Some(4_600_000));
+        assert_eq!(p.remaining_sec(1_000_000), Some(3600));
+        assert_eq!(p.remaining_sec(4_000_000), Some(600));
+        assert_eq!(p.remaining_sec(4_600_000), None);
+        for remaining in [0, -1] {
+            let p = Pause::new(remaining, 1_000_000);
+            assert_eq!((p.target_ms, p.remaining_sec(0)), (None, None));
+        }
+    }
+
+    #[test]
+    fn only_an_unplug_after_a_plug_ends_the_pause() {
+        let mut p = Pause::new(60, 0);
+        assert!(!p.step(false));
+        assert!(!p.step(true));
+        assert!(!p.step(true));
+        assert!(p.step(false));
+    }
+
+    #[test]
+    fn state_round_trips_and_rejects_junk() {
+        for p in [
+            Pause::new(3600, 1_000_000),
+            Pause { target_ms: None, plugged_seen: true },
+        ] {
+            assert_eq!(Pause::from_line(&p.to_line()), Some(p));
+        }
+        for line in ["", "1", "1 0 2", "x 0", "1 y"] {
+            assert_eq!(Pause::from_line(line), None, "{line:?}");
+        }
+    }
+}
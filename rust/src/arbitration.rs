@@ -0,0 +1,68 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Charge limit arbitration between clients. Each client's request is kept
+//! separately and the most restrictive one is applied, so a device policy
+//! limit can't be undone by another client's later write.
+
+use std::collections::BTreeMap;
+
+/// Tag used for plain `setChargeLimit` calls.
+pub const DEFAULT_CLIENT: &str = "default";
+
+/// Tag for the charge_to_limit toggle, which competes like any other client so
+/// it and a custom stop/start limit resolve the same way: the lower stop wins.
+pub const CHARGE_TO_LIMIT_CLIENT: &str = "charge_to_limit";
+pub const CHARGE_TO_LIMIT_REQUEST: Request = Request { stop: 80, start: 75 };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request {
+    pub stop: i32,
+    pub start: i32,
+}
+
+/// Picks the winning request: lowest stop, then lowest start. Ties go to the
+/// first tag in sort order so the result doesn't depend on call order.
+pub fn resolve(requests: &BTreeMap<String, Request>) -> Option<(&str, Request)> {
+    requests
+        .iter()
+        .min_by_key(|(_, r)| (r.stop, r.start))
+        .map(|(tag, r)| (tag.as_str(), *r))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requests(list: &[(&str, i32, i32)]) -> BTreeMap<String, Request> {
+        list.iter()
+            .map(|&(tag, stop, start)| (tag.to_owned(), Request { stop, start }))
+            .collect()
+    }
+
+    #[test]
+    fn no_requests_no_winner() {
+        assert_eq!(resolve(&BTreeMap::new()), None);
+    }
+
+    #[test]
+    fn lowest_stop_wins() {
+        let r = requests(&[("settings", 90, 80), ("policy", 70, 60), (DEFAULT_CLIENT, 85, 75)]);
+        assert_eq!(resolve(&r), Some(("policy", Request { stop: 70, start: 60 })));
+    }
+
+    #[test]
+    fn equal_stops_go_to_the_lower_start_then_the_first_tag() {
+        let r = requests(&[("b", 80, 70), ("a", 80, 75), ("c", 80, 70)]);
+        assert_eq!(resolve(&r), Some(("b", Request { stop: 80, start: 70 })));
+        let r = requests(&[("z", 80, 70), ("y", 80, 70)]);
+        assert_eq!(resolve(&r).map(|(tag, _)| tag), Some("y"));
+    }
+
+    #[test]
+    fn removing_the_winner_hands_over() {
+        let mut r = requests(&[("settings", 90, 80), ("policy", 70, 60)]);
+        r.remove("policy");
+        assert_eq!(resolve(&r), Some(("settings", Request { stop: 90, start: 80 })));
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Recent setStringProperty writes with the node's value from before each
+//! one, for working out why a setting didn't take: a kernel that rejects or
+//! rewrites a value shows up as an entry whose next `old_value` isn't what
+//! was written.
+
+use std::{collections::VecDeque, io, io::Write, sync::Mutex};
+
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::Feature::Feature;
+
+use crate::{lock::MutexExt, persist, sysfs};
+
+pub const CAPACITY: usize = 64;
+/// Stands in for the old value when the node couldn't be read before the write.
+pub const NOT_PRESENT: &str = "<not present>";
+
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub at_ms: i64,
+    pub uid: u32,
+    pub feature: Feature,
+    pub prop: i32,
+    pub path: &'static str,
+    pub old_value: String,
+    pub new_value: String,
+    pub ok: bool,
+}
+
+/// The node's content before a write, or NOT_PRESENT.
+pub fn old_value(path: &str) -> String {
+    sysfs::read_string(path).unwrap_or_else(|_| NOT_PRESENT.to_owned())
+}
+
+#[derive(Default)]
+pub struct AuditLog {
+    entries: Mutex<VecDeque<AuditEntry>>,
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, mut entry: AuditEntry) {
+        entry.at_ms = persist::now_ms();
+        let mut entries = self.entries.lock_recover();
+        if entries.len() == CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    pub fn dump(&self, w: &mut dyn Write) -> io::Result<()> {
+        let entries = self.entries.lock_recover();
+        writeln!(w, "Property writes ({}):", entries.len())?;
+        for e in entries.iter() {
+            writeln!(
+                w,
+                "  {} uid={} {:?}/{} {}: '{}' -> '{}'{}",
+                e.at_ms,
+                e.uid,
+                e.feature,
+                e.prop,
+                e.path,
+                e.old_value,
+                e.new_value,
+                if e.ok { "" } else { " FAILED" }
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Retry schedule for publishing the service while servicemanager isn't
+//! reachable yet, as can happen early in boot. The delay doubles from
+//! FIRST_DELAY up to MAX_DELAY, for ATTEMPTS tries over about 30 seconds.
+
+use std::time::Duration;
+
+pub const ATTEMPTS: u32 = 10;
+pub const FIRST_DELAY: Duration = Duration::from_millis(100);
+pub const MAX_DELAY: Duration = Duration::from_secs(8);
+
+/// The wait after failed attempt `attempt`, counting from 1, or None when
+/// that was the last one.
+pub fn delay(attempt: u32) -> Option<Duration> {
+    if attempt >= ATTEMPTS {
+        return None;
+    }
+    let doubled = FIRST_DELAY.saturating_mul(1 << (attempt - 1).min(31));
+    Some(doubled.min(MAX_DELAY))
+}
+
+/// Runs `attempt` until it succeeds, fails with an error `retryable` rejects,
+/// or runs out of attempts. `wait` gets the failed attempt's number, its
+/// error and the delay before the next one, and is expected to sleep.
+pub fn retry<T, E>(
+    mut attempt: impl FnMut() -> Result<T, E>,
+    retryable: impl Fn(&E) -> bool,
+    mut wait: impl FnMut(u32, &E, Duration),
+) -> Result<T, E> {
+    let mut n = 1;
+    loop {
+        let e = match attempt() {
+            Ok(v) => return Ok(v),
+            Err(e) => e,
+        };
+        match delay(n) {
+            Some(d) if retryable(&e) => wait(n, &e, d),
+            _ => return Err(e),
+        }
+        n += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs retry over `results`, one per attempt, with a fake clock that
+    /// only adds up the waits. Returns the outcome, attempts and time spent.
+    fn run(results: &[Result<(), bool>]) -> (Result<(), bool>, usize, Duration) {
+        let mut results = results.iter().copied();
+        let attempts = std::cell::Cell::new(0);
+        let mut clock = Duration::ZERO;
+        let outcome = retry(
+            || {
+                attempts.set(attempts.get() + 1);
+                results.next().expect("attempted past the end")
+            },
+            |&retryable| retryable,
+            |n, _, d| {
+                assert_eq!(n as usize, attempts.get());
+                clock += d;
+            },
+        );
+        (outcome, attempts.get(), clock)
+    }
+
+    #[test]
+    fn delays_double_up_to_the_cap() {
+        let delays: Vec<_> = (1..ATTEMPTS).map(|n| delay(n).unwrap().as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1600, 3200, 6400, 8000, 8000]);
+        assert_eq!(delay(ATTEMPTS), None);
+        assert_eq!(delay(u32::MAX), None);
+    }
+
+    #[test]
+    fn success_stops_retrying() {
+        assert_eq!(run(&[Ok(())]), (Ok(()), 1, Duration::ZERO));
+        let (outcome, attempts, clock) = run(&[Err(true), Err(true), Ok(())]);
+        assert_eq!((outcome, attempts), (Ok(()), 3));
+        assert_eq!(clock, Duration::from_millis(300));
+    }
+
+    #[test]
+    fn a_final_error_is_not_retried() {
+        assert_eq!(run(&[Err(true), Err(false)]), (Err(false), 2, Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn retries_give_up_after_about_thirty_seconds() {
+        let (outcome, attempts, clock) = run(&[Err(true); ATTEMPTS as usize]);
+        assert_eq!((outcome, attempts), (Err(true), ATTEMPTS as usize));
+        assert!((Duration::from_secs(25)..=Duration::from_secs(30)).contains(&clock), "{clock:?}");
+    }
+}
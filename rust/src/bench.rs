@@ -0,0 +1,67 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Benchmarks of the SysfsPath read helpers the getters sit on, against a
+//! fixture tree of regular files made in a temp dir for the run. Reading
+//! sysfs proper costs a driver show() on top, but what the HAL does around
+//! it (opening, buffering, trimming, parsing) is the same.
+//!
+//! Built with `--cfg benzene_bench`, which swaps main for criterion's.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use criterion::{black_box, criterion_group, Criterion};
+
+use crate::sysfs::SysfsPath;
+
+/// `path` as the `&'static str` a SysfsPath holds.
+fn leak(path: PathBuf) -> &'static str {
+    Box::leak(path.to_string_lossy().into_owned().into_boxed_str())
+}
+
+/// Writes a node of `content` under `dir`.
+fn node(dir: &Path, name: &str, content: &str) -> &'static str {
+    let path = dir.join(name);
+    fs::write(&path, content).expect("write fixture node");
+    leak(path)
+}
+
+fn sysfs_reads(c: &mut Criterion) {
+    let dir = env::temp_dir().join(format!("benzene_battery_bench.{}", process::id()));
+    fs::create_dir_all(&dir).expect("create fixture dir");
+    let level = SysfsPath::new(node(&dir, "charge_stop_level", "80\n"));
+    let status = SysfsPath::new(node(&dir, "status", "Charging\n"));
+    let stats = SysfsPath::new(node(
+        &dir,
+        "charge_stats",
+        "S:4,9000,2000,15,82,4120\n0:1200,1450,2010\n1:1180,1440,2005\n",
+    ));
+    let absent = leak(dir.join("absent"));
+    let alternate = SysfsPath::with_alt(absent, level.primary);
+    let missing = SysfsPath::new(absent);
+
+    c.bench_function("read_int", |b| b.iter(|| black_box(&level).read_int()));
+    c.bench_function("read_int/alternate", |b| {
+        b.iter(|| black_box(&alternate).read_int())
+    });
+    c.bench_function("read_int_or", |b| {
+        b.iter(|| black_box(&level).read_int_or(-1))
+    });
+    c.bench_function("read_int_or/missing", |b| {
+        b.iter(|| black_box(&missing).read_int_or(-1))
+    });
+    c.bench_function("read_string", |b| {
+        b.iter(|| black_box(&status).read_string())
+    });
+    c.bench_function("read_string/long", |b| {
+        b.iter(|| black_box(&stats).read_string())
+    });
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+criterion_group!(benches, sysfs_reads);
@@ -0,0 +1,87 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! IBattery calls counted by caller and method, to find clients that poll the
+//! HAL far more than they need to. A caller that goes over RATE_LIMIT calls of
+//! one method within a second is logged.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use crate::lock::MutexExt;
+
+/// Calls of one method per UID per RATE_WINDOW before a warning is logged.
+pub const RATE_LIMIT: u64 = 100;
+pub const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+struct Count {
+    total: u64,
+    window_start: Instant,
+    in_window: u64,
+}
+
+#[derive(Default)]
+pub struct CallCounter {
+    counts: Mutex<HashMap<u32, HashMap<&'static str, Count>>>,
+}
+
+impl CallCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, uid: u32, method: &'static str) {
+        let now = Instant::now();
+        let mut counts = self.counts.lock_recover();
+        let count = counts
+            .entry(uid)
+            .or_default()
+            .entry(method)
+            .or_insert(Count {
+                total: 0,
+                window_start: now,
+                in_window: 0,
+            });
+        count.total += 1;
+        if now.duration_since(count.window_start) >= RATE_WINDOW {
+            count.window_start = now;
+            count.in_window = 0;
+        }
+        count.in_window += 1;
+        // Once per window, as the limit is crossed.
+        if count.in_window == RATE_LIMIT + 1 {
+            warn!("uid {uid} called {method} more than {RATE_LIMIT} times in {RATE_WINDOW:?}");
+        }
+    }
+
+    /// Totals since start as `{"uid": {"method": count, ...}, ...}`, sorted by
+    /// UID and method.
+    pub fn to_json(&self) -> String {
+        let counts = self.counts.lock_recover();
+        let mut uids = counts.keys().copied().collect::<Vec<_>>();
+        uids.sort_unstable();
+        let mut out = String::from("{");
+        for (i, uid) in uids.iter().enumerate() {
+            let mut methods = counts[uid]
+                .iter()
+                .map(|(m, c)| (*m, c.total))
+                .collect::<Vec<_>>();
+            methods.sort_unstable();
+            let sep = if i == 0 { "" } else { "," };
+            let _ = write!(out, "{sep}\"{uid}\":{{");
+            for (j, (method, total)) in methods.iter().enumerate() {
+                let sep = if j == 0 { "" } else { "," };
+                let _ = write!(out, "{sep}\"{method}\":{total}");
+            }
+            out.push('}');
+        }
+        out.push('}');
+        out
+    }
+}
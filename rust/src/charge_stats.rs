@@ -0,0 +1,144 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Parser for the google charger's per-session `charge_stats`.
+//!
+//! Each session starts with a header, followed by one line per charge tier:
+//!
+//! ```text
+//! S:<adapter_type>,<adapter_mv>,<adapter_ma>,<soc_in>[,<soc_out>,<duration_s>]
+//! <tier>:<duration_s>,<cc_in>,<cc_out>
+//! RESET
+//! ```
+//!
+//! A header without `soc_out` is a session still in progress. `RESET` marks
+//! the kernel clearing its counters: it closes the current session, and tier
+//! lines after it that have no header of their own are dropped.
+
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
+    ChargeSessionStats::ChargeSessionStats, ChargeTierStats::ChargeTierStats,
+};
+
+fn ints(s: &str) -> Result<Vec<i32>, String> {
+    s.split(',')
+        .map(|v| v.trim().parse().map_err(|_| format!("bad value '{v}'")))
+        .collect()
+}
+
+fn parse_header(fields: &str) -> Result<ChargeSessionStats, String> {
+    let v = ints(fields)?;
+    let (ad, rest) = match v.as_slice() {
+        [t, mv, ma, rest @ ..] => ([*t, *mv, *ma], rest),
+        _ => return Err(format!("short session header '{fields}'")),
+    };
+    let mut session = ChargeSessionStats {
+        adapterType: ad[0],
+        adapterVoltageMv: ad[1],
+        adapterCurrentMa: ad[2],
+        ..Default::default()
+    };
+    match rest {
+        [soc_in] => {
+            session.socIn = *soc_in;
+            session.inProgress = true;
+        }
+        [soc_in, soc_out, duration] => {
+            session.socIn = *soc_in;
+            session.socOut = *soc_out;
+            session.durationSec = *duration;
+        }
+        _ => return Err(format!("bad session header '{fields}'")),
+    }
+    Ok(session)
+}
+
+fn parse_tier(tier: &str, fields: &str) -> Result<ChargeTierStats, String> {
+    let tier = tier
+        .trim()
+        .parse()
+        .map_err(|_| format!("bad tier index '{tier}'"))?;
+    match ints(fields)?.as_slice() {
+        [duration, cc_in, cc_out] => Ok(ChargeTierStats {
+            tier,
+            durationSec: *duration,
+            ccIn: *cc_in,
+            ccOut: *cc_out,
+        }),
+        _ => Err(format!("bad tier line '{fields}'")),
+    }
+}
+
+pub fn parse(content: &str) -> Result<Vec<ChargeSessionStats>, String> {
+    let mut sessions = Vec::new();
+    let mut current: Option<ChargeSessionStats> = None;
+    for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if line == "RESET" {
+            sessions.extend(current.take());
+            continue;
+        }
+        let Some((key, fields)) = line.split_once(':') else {
+            return Err(format!("malformed line '{line}'"));
+        };
+        if key == "S" {
+            sessions.extend(current.replace(parse_header(fields)?));
+        } else if let Some(session) = current.as_mut() {
+            session.tiers.push(parse_tier(key, fields)?);
+        }
+    }
+    sessions.extend(current);
+    Ok(sessions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiers(s: &ChargeSessionStats) -> Vec<[i32; 4]> {
+        s.tiers
+            .iter()
+            .map(|t| [t.tier, t.durationSec, t.ccIn, t.ccOut])
+            .collect()
+    }
+
+    #[test]
+    fn parses_finished_and_in_progress_sessions() {
+        let sessions =
+            parse("S:4,9000,2000,15,82,4120\n0:1200,1450,2010\nS:9,5000,1500,40\n").unwrap();
+        assert_eq!(sessions.len(), 2);
+        let done = &sessions[0];
+        assert_eq!(
+            [done.adapterType, done.adapterVoltageMv, done.adapterCurrentMa],
+            [4, 9000, 2000]
+        );
+        assert_eq!([done.socIn, done.socOut, done.durationSec], [15, 82, 4120]);
+        assert!(!done.inProgress);
+        assert_eq!(tiers(done), [[0, 1200, 1450, 2010]]);
+        assert!(sessions[1].inProgress);
+        assert_eq!(sessions[1].socIn, 40);
+        assert!(sessions[1].tiers.is_empty());
+    }
+
+    #[test]
+    fn reset_closes_the_session_and_drops_orphan_tiers() {
+        let content = "S:4,9000,2000,15\n0:60,1,2\nRESET\n3:60,3420,3440\nRESET\n";
+        let sessions = parse(content).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(tiers(&sessions[0]), [[0, 60, 1, 2]]);
+        assert!(parse("RESET\n\n  \n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let cases = [
+            ("S:4,9000", "short session header '4,9000'"),
+            ("S:4,9000,2000,15,82", "bad session header '4,9000,2000,15,82'"),
+            ("S:4,9000,2000,x", "bad value 'x'"),
+            ("S:4,9000,2000,15\nA:1,2,3", "bad tier index 'A'"),
+            ("S:4,9000,2000,15\n0:1,2", "bad tier line '1,2'"),
+            ("garbage", "malformed line 'garbage'"),
+        ];
+        for (content, error) in cases {
+            assert_eq!(parse(content).unwrap_err(), error, "{content:?}");
+        }
+    }
+}
@@ -0,0 +1,479 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Startup configuration from Android system properties. These are read once
+//! when the service starts; runtime calls such as setChargeLimit override them.
+//!
+//! Tunables that a device rather than a user picks are read from CONFIG_FILE,
+//! one `key = value` per line, with `#` comments; `[section]` headers are
+//! allowed for grouping and otherwise ignored. Every key is optional and
+//! defaults to the built-in value, see Config. A property for the same
+//! setting wins over the file.
+//!
+//! - `persist.vendor.benzene_battery.stop_level`: charge stop level, 50-100.
+//! - `persist.vendor.benzene_battery.start_level`: charge start level, at least
+//!   `min_gap` below the stop level. Only used together with `stop_level`.
+//! - `persist.vendor.benzene_battery.policy`: `default`, `longlife`,
+//!   `adaptive` or `custom`, the charging policy to start with. A policy a
+//!   client set and the HAL persisted wins over this.
+//! - `persist.vendor.benzene_battery.enable_adaptive`: `true` or `1` to start
+//!   with the ADAPTIVE charging policy. Ignored when `policy` is set.
+//! - `persist.vendor.benzene_battery.feature.<name>`: `true`/`1` or
+//!   `false`/`0` to turn a feature on or off at startup, for each name in
+//!   FEATURE_PROPS. Unset leaves the kernel default.
+//! - `persist.vendor.benzene_battery.strict_nodes`: `true` or `1` to fail
+//!   setters with UNSUPPORTED when their node is missing, instead of
+//!   succeeding without doing anything.
+//! - `persist.vendor.benzene_battery.metrics`: `false` or `0` to stop writing
+//!   fleet metrics. On by default.
+//! - `persist.vendor.benzene_battery.health_drop_per_week`: health points a
+//!   reading may fall within a week before the watchdog alerts.
+//! - `persist.vendor.benzene_battery.health_jump`: points a reading may rise
+//!   between daily samples before it is treated as a fuel gauge reset.
+//! - `persist.vendor.benzene_battery.binder_threads`: binder threads to
+//!   start with, overriding `binder_threads` in CONFIG_FILE.
+//! - `persist.vendor.benzene_battery.loglevel`: `error`, `warn`, `info`,
+//!   `debug` or `verbose`, the log level to start with. `info` by default.
+//! - `persist.vendor.benzene_battery.refuse_in_bypass`: `true` or `1` to
+//!   fail charge limit requests with ERROR_BYPASS_ACTIVE while maxfg's
+//!   charge limit bypass is on, instead of only logging it.
+//! - `persist.vendor.benzene_battery.abort_on_panic`: `false` or `0` to let
+//!   a panicking thread end without taking the process down. On by default;
+//!   monitor tasks never abort, see crash.rs.
+//! - `ro.vendor.benzene_battery.lazy`: `true` or `1` to register as a lazy
+//!   service. Has to match the init service definition, see lazy.rs.
+//!
+//! Values that don't parse or fail validation are logged and ignored.
+
+use std::{fs, io, str::FromStr, sync::OnceLock};
+
+use log::{info, warn};
+use rustutils::system_properties;
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
+    ChargingPolicy::ChargingPolicy, Feature::Feature,
+};
+
+use crate::{health_watch::Thresholds, validate};
+
+pub const CONFIG_FILE: &str = "/vendor/etc/benzene_battery.conf";
+
+pub const DEFAULT_STOP: i32 = 80;
+pub const DEFAULT_START: i32 = 70;
+pub const DEFAULT_MIN_GAP: i32 = 5;
+
+pub const PROP_STOP_LEVEL: &str = "persist.vendor.benzene_battery.stop_level";
+pub const PROP_START_LEVEL: &str = "persist.vendor.benzene_battery.start_level";
+pub const PROP_POLICY: &str = "persist.vendor.benzene_battery.policy";
+pub const PROP_ENABLE_ADAPTIVE: &str = "persist.vendor.benzene_battery.enable_adaptive";
+pub const PROP_FEATURE_PREFIX: &str = "persist.vendor.benzene_battery.feature.";
+pub const PROP_STRICT_NODES: &str = "persist.vendor.benzene_battery.strict_nodes";
+pub const PROP_METRICS: &str = "persist.vendor.benzene_battery.metrics";
+pub const PROP_HEALTH_DROP_PER_WEEK: &str = "persist.vendor.benzene_battery.health_drop_per_week";
+pub const PROP_HEALTH_JUMP: &str = "persist.vendor.benzene_battery.health_jump";
+pub const PROP_BINDER_THREADS: &str = "persist.vendor.benzene_battery.binder_threads";
+pub const PROP_LOG_LEVEL: &str = "persist.vendor.benzene_battery.loglevel";
+pub const PROP_REFUSE_IN_BYPASS: &str = "persist.vendor.benzene_battery.refuse_in_bypass";
+pub const PROP_ABORT_ON_PANIC: &str = "persist.vendor.benzene_battery.abort_on_panic";
+pub const PROP_LAZY: &str = "ro.vendor.benzene_battery.lazy";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// `default_stop` / `default_start`: levels with no request or property.
+    pub default_stop: i32,
+    pub default_start: i32,
+    /// `min_gap`: how far start must be below stop, 1-50.
+    pub min_gap: i32,
+    /// `hot_cache_ttl_ms`: lifetime of cached charging getters, up to 10000.
+    pub hot_cache_ttl_ms: u64,
+    /// `health_drop_per_week` / `health_jump`: health watchdog thresholds.
+    pub health: Thresholds,
+    /// `defender_settle_ms`: how long a defender clear waits before
+    /// restoring the trickle values, up to 10000.
+    pub defender_settle_ms: u64,
+    /// `max_charging_speed`: the fastest ChargingSpeedLevel the hardware
+    /// supports, 1-5. -1 leaves it to the kernel.
+    pub max_charging_speed: i32,
+    /// `binder_threads` / `binder_threads_max`: binder threads to start with
+    /// and the most the pool grows to under load, 1-16.
+    pub binder_threads: usize,
+    pub binder_threads_max: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_stop: DEFAULT_STOP,
+            default_start: DEFAULT_START,
+            min_gap: DEFAULT_MIN_GAP,
+            hot_cache_ttl_ms: 500,
+            health: Thresholds::default(),
+            defender_settle_ms: 2000,
+            max_charging_speed: -1,
+            binder_threads: 2,
+            binder_threads_max: 4,
+        }
+    }
+}
+
+/// Parses `value` into `field` when it is in range, else keeps the default.
+fn set<T: FromStr + PartialOrd>(field: &mut T, key: &str, value: &str, min: T, max: T) {
+    match value.parse::<T>() {
+        Ok(v) if v >= min && v <= max => *field = v,
+        _ => warn!("{CONFIG_FILE}: ignoring {key} = '{value}'"),
+    }
+}
+
+impl Config {
+    /// Bad values fall back to the default one key at a time.
+    pub fn parse(content: &str) -> Self {
+        let mut c = Self::default();
+        for (n, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() || line.starts_with('[') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                warn!("{CONFIG_FILE}:{}: expected key = value", n + 1);
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "default_stop" => set(&mut c.default_stop, key, value, 50, 100),
+                "default_start" => set(&mut c.default_start, key, value, 0, 95),
+                "min_gap" => set(&mut c.min_gap, key, value, 1, 50),
+                "hot_cache_ttl_ms" => set(&mut c.hot_cache_ttl_ms, key, value, 0, 10_000),
+                "health_drop_per_week" => set(&mut c.health.drop_per_week, key, value, 1, 100),
+                "health_jump" => set(&mut c.health.jump, key, value, 1, 100),
+                "defender_settle_ms" => set(&mut c.defender_settle_ms, key, value, 0, 10_000),
+                "max_charging_speed" => set(&mut c.max_charging_speed, key, value, 1, 5),
+                "binder_threads" => set(&mut c.binder_threads, key, value, 1, 16),
+                "binder_threads_max" => set(&mut c.binder_threads_max, key, value, 1, 16),
+                _ => warn!("{CONFIG_FILE}:{}: unknown key {key}", n + 1),
+            }
+        }
+        if let Err(e) = validate::levels(c.default_stop, c.default_start, c.min_gap) {
+            let d = Self::default();
+            warn!(
+                "{CONFIG_FILE}: default levels {e}, using {}/{}",
+                d.default_stop, d.default_start
+            );
+            c.default_stop = d.default_stop;
+            c.default_start = d.default_start;
+            c.min_gap = d.min_gap;
+        }
+        if c.binder_threads_max < c.binder_threads {
+            warn!(
+                "{CONFIG_FILE}: binder_threads_max {} below binder_threads, using {}",
+                c.binder_threads_max, c.binder_threads
+            );
+            c.binder_threads_max = c.binder_threads;
+        }
+        c
+    }
+}
+
+/// CONFIG_FILE, read on first use; the defaults when it doesn't exist.
+pub fn get() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(|| match fs::read_to_string(CONFIG_FILE) {
+        Ok(content) => {
+            let c = Config::parse(&content);
+            info!("Loaded {CONFIG_FILE}: {c:?}");
+            c
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Config::default(),
+        Err(e) => {
+            warn!("Failed to read {CONFIG_FILE}: {e}");
+            Config::default()
+        }
+    })
+}
+
+#[cfg(test)]
+thread_local! {
+    static PROPERTY_OVERRIDES: std::cell::RefCell<Vec<(String, String)>> =
+        const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Sets what read_property returns for `key` on this thread, so tests can
+/// run the startup code against different property combinations.
+#[cfg(test)]
+pub fn set_property(key: &str, value: &str) {
+    PROPERTY_OVERRIDES.with_borrow_mut(|props| {
+        props.retain(|(k, _)| k != key);
+        props.push((key.to_owned(), value.to_owned()));
+    });
+}
+
+/// Drops every override set_property made on this thread.
+#[cfg(test)]
+pub fn clear_properties() {
+    PROPERTY_OVERRIDES.with_borrow_mut(Vec::clear);
+}
+
+/// Reads `key`, returning `default` when it is unset or can't be read.
+pub fn read_property(key: &str, default: &str) -> String {
+    #[cfg(test)]
+    if let Some(v) =
+        PROPERTY_OVERRIDES.with_borrow(|props| props.iter().find(|(k, _)| k == key).cloned())
+    {
+        return if v.1.is_empty() { default.to_owned() } else { v.1 };
+    }
+    match system_properties::read(key) {
+        Ok(Some(v)) if !v.is_empty() => v,
+        Ok(_) => default.to_owned(),
+        Err(e) => {
+            warn!("Failed to read {key}: {e}");
+            default.to_owned()
+        }
+    }
+}
+
+/// Configured stop/start levels, if both are set and valid.
+pub fn charge_levels() -> Option<(i32, i32)> {
+    let stop = read_property(PROP_STOP_LEVEL, "");
+    let start = read_property(PROP_START_LEVEL, "");
+    if stop.is_empty() && start.is_empty() {
+        return None;
+    }
+    let (Ok(stop), Ok(start)) = (stop.parse::<i32>(), start.parse::<i32>()) else {
+        warn!("Ignoring {PROP_STOP_LEVEL}='{stop}' / {PROP_START_LEVEL}='{start}'");
+        return None;
+    };
+    match validate::charge_limit(stop, start) {
+        Ok(()) => {
+            info!("Charge levels from properties: {stop}/{start}");
+            Some((stop, start))
+        }
+        Err(e) => {
+            warn!("Ignoring charge level properties: {e}");
+            None
+        }
+    }
+}
+
+/// Features with a startup property, by the name after PROP_FEATURE_PREFIX.
+pub const FEATURE_PROPS: &[(&str, Feature)] = &[
+    ("dock_defend", Feature::DOCK_DEFEND),
+    ("fg_cycle", Feature::FG_CYCLE),
+    ("aacr", Feature::AACR),
+];
+
+fn adaptive_enabled() -> bool {
+    matches!(read_property(PROP_ENABLE_ADAPTIVE, "false").as_str(), "true" | "1")
+}
+
+/// The configured startup policy, falling back to `enable_adaptive`.
+pub fn default_policy() -> Option<ChargingPolicy> {
+    let value = read_property(PROP_POLICY, "");
+    let policy = match value.as_str() {
+        "" => return adaptive_enabled().then_some(ChargingPolicy::ADAPTIVE),
+        "default" => ChargingPolicy::DEFAULT,
+        "longlife" => ChargingPolicy::LONGLIFE,
+        "adaptive" => ChargingPolicy::ADAPTIVE,
+        "custom" => ChargingPolicy::CUSTOM,
+        _ => {
+            warn!("Ignoring {PROP_POLICY}='{value}'");
+            return None;
+        }
+    };
+    Some(policy)
+}
+
+/// Startup feature states, for the features whose property is set.
+pub fn feature_defaults() -> Vec<(Feature, bool)> {
+    FEATURE_PROPS
+        .iter()
+        .filter_map(|&(name, feature)| {
+            let key = format!("{PROP_FEATURE_PREFIX}{name}");
+            match read_property(&key, "").as_str() {
+                "" => None,
+                "true" | "1" => Some((feature, true)),
+                "false" | "0" => Some((feature, false)),
+                other => {
+                    warn!("Ignoring {key}='{other}'");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+pub fn strict_nodes() -> bool {
+    matches!(read_property(PROP_STRICT_NODES, "false").as_str(), "true" | "1")
+}
+
+/// Binder pool bounds as (start, max): PROP_BINDER_THREADS when set and
+/// valid, else CONFIG_FILE's. The max is raised to the start if below it.
+pub fn binder_threads() -> (usize, usize) {
+    binder_threads_from(get())
+}
+
+fn binder_threads_from(c: &Config) -> (usize, usize) {
+    let raw = read_property(PROP_BINDER_THREADS, "");
+    let start = match raw.parse::<usize>() {
+        _ if raw.is_empty() => c.binder_threads,
+        Ok(n @ 1..=16) => n,
+        _ => {
+            warn!("Ignoring {PROP_BINDER_THREADS}='{raw}'");
+            c.binder_threads
+        }
+    };
+    (start, c.binder_threads_max.max(start))
+}
+
+pub fn lazy() -> bool {
+    matches!(read_property(PROP_LAZY, "false").as_str(), "true" | "1")
+}
+
+pub fn refuse_in_bypass() -> bool {
+    matches!(read_property(PROP_REFUSE_IN_BYPASS, "false").as_str(), "true" | "1")
+}
+
+pub fn abort_on_panic() -> bool {
+    !matches!(read_property(PROP_ABORT_ON_PANIC, "true").as_str(), "false" | "0")
+}
+
+pub fn metrics_enabled() -> bool {
+    !matches!(read_property(PROP_METRICS, "true").as_str(), "false" | "0")
+}
+
+/// Health watchdog thresholds, with CONFIG_FILE's for values that are unset
+/// or not positive.
+pub fn health_thresholds() -> Thresholds {
+    health_thresholds_from(get())
+}
+
+fn health_thresholds_from(c: &Config) -> Thresholds {
+    let read = |key: &str, default: i32| {
+        let raw = read_property(key, "");
+        if raw.is_empty() {
+            return default;
+        }
+        match raw.parse::<i32>() {
+            Ok(v) if v > 0 => v,
+            _ => {
+                warn!("Ignoring {key}='{raw}', using {default}");
+                default
+            }
+        }
+    };
+    let d = c.health;
+    Thresholds {
+        drop_per_week: read(PROP_HEALTH_DROP_PER_WEEK, d.drop_per_week),
+        jump: read(PROP_HEALTH_JUMP, d.jump),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn health_thresholds_come_from_the_config_file() {
+        let c = Config::parse("[health]\nhealth_drop_per_week = 3\nhealth_jump = 0 # off\n");
+        assert_eq!(c.health.drop_per_week, 3);
+        // Out of range, so the default stays.
+        assert_eq!(c.health.jump, Thresholds::default().jump);
+    }
+
+    #[test]
+    fn an_empty_file_keeps_every_default() {
+        assert_eq!(Config::parse(""), Config::default());
+        assert_eq!(Config::parse("# nothing\n\n[section]\n"), Config::default());
+    }
+
+    #[test]
+    fn keys_parse_with_comments_sections_and_whitespace() {
+        let c = Config::parse(
+            "[levels]\n  default_stop=90  \ndefault_start = 60 # lower\nmin_gap = 10\n\
+             [timing]\nhot_cache_ttl_ms = 0\ndefender_settle_ms = 10000\n\
+             [hw]\nmax_charging_speed = 3\nbinder_threads = 3\nbinder_threads_max = 8\n",
+        );
+        let want = Config {
+            default_stop: 90,
+            default_start: 60,
+            min_gap: 10,
+            hot_cache_ttl_ms: 0,
+            defender_settle_ms: 10_000,
+            max_charging_speed: 3,
+            binder_threads: 3,
+            binder_threads_max: 8,
+            ..Config::default()
+        };
+        assert_eq!(c, want);
+    }
+
+    #[test]
+    fn bad_values_fall_back_one_key_at_a_time() {
+        let c = Config::parse(
+            "min_gap = 0\nhot_cache_ttl_ms = -1\ndefender_settle_ms = 20000\n\
+             max_charging_speed = fast\nno_equals_sign\nretries = 3\nbinder_threads = 6\n",
+        );
+        // Only the one valid key took effect.
+        assert_eq!(c, Config { binder_threads: 6, binder_threads_max: 6, ..Config::default() });
+    }
+
+    #[test]
+    fn inconsistent_default_levels_fall_back_together() {
+        let d = Config::default();
+        for content in ["default_stop = 60\ndefault_start = 58\n", "min_gap = 40\n"] {
+            let c = Config::parse(content);
+            assert_eq!(
+                (c.default_stop, c.default_start, c.min_gap),
+                (d.default_stop, d.default_start, d.min_gap)
+            );
+        }
+        // A gap that fits the levels is kept.
+        let c = Config::parse("default_stop = 60\ndefault_start = 58\nmin_gap = 2\n");
+        assert_eq!((c.default_stop, c.default_start, c.min_gap), (60, 58, 2));
+    }
+
+    #[test]
+    fn properties_win_over_the_file_and_the_file_over_defaults() {
+        clear_properties();
+        let file =
+            Config::parse("health_drop_per_week = 7\nhealth_jump = 20\nbinder_threads = 3\n");
+        assert_eq!(health_thresholds_from(&file), Thresholds { drop_per_week: 7, jump: 20 });
+        assert_eq!(binder_threads_from(&file), (3, 4));
+        assert_eq!(binder_threads_from(&Config::default()), (2, 4));
+
+        set_property(PROP_HEALTH_DROP_PER_WEEK, "2");
+        set_property(PROP_BINDER_THREADS, "6");
+        assert_eq!(health_thresholds_from(&file), Thresholds { drop_per_week: 2, jump: 20 });
+        // The pool max follows a larger start.
+        assert_eq!(binder_threads_from(&file), (6, 6));
+
+        // Bad properties fall back to the file, not the built-in default.
+        set_property(PROP_HEALTH_DROP_PER_WEEK, "-3");
+        set_property(PROP_BINDER_THREADS, "64");
+        assert_eq!(health_thresholds_from(&file).drop_per_week, 7);
+        assert_eq!(binder_threads_from(&file), (3, 4));
+        clear_properties();
+    }
+
+    #[test]
+    fn startup_policy_property_falls_back_to_enable_adaptive() {
+        clear_properties();
+        assert_eq!(default_policy(), None);
+        set_property(PROP_ENABLE_ADAPTIVE, "1");
+        assert_eq!(default_policy(), Some(ChargingPolicy::ADAPTIVE));
+        set_property(PROP_POLICY, "longlife");
+        assert_eq!(default_policy(), Some(ChargingPolicy::LONGLIFE));
+        set_property(PROP_POLICY, "fast");
+        assert_eq!(default_policy(), None);
+        clear_properties();
+    }
+
+    #[test]
+    fn feature_properties_skip_unset_and_malformed_values() {
+        clear_properties();
+        assert!(feature_defaults().is_empty());
+        set_property(&format!("{PROP_FEATURE_PREFIX}dock_defend"), "0");
+        set_property(&format!("{PROP_FEATURE_PREFIX}fg_cycle"), "yes");
+        set_property(&format!("{PROP_FEATURE_PREFIX}aacr"), "true");
+        let got: Vec<_> = feature_defaults().iter().map(|(f, on)| (f.0, *on)).collect();
+        assert_eq!(got, [(Feature::DOCK_DEFEND.0, false), (Feature::AACR.0, true)]);
+        clear_properties();
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Panic handling. The default hook prints to stderr, which nothing reads on
+//! Android, and a panic on a binder thread only ends that thread, leaving the
+//! service running with whatever its handler left half done. This hook logs
+//! the panic with a backtrace, counts it in COUNT_FILE and aborts, so init
+//! restarts the service from its persisted state.
+//!
+//! The exception is a panic inside contain(), which the caller has said it
+//! can recover from: it is logged and counted like any other but unwinds to
+//! contain() instead of aborting. The monitor runs each task that way, so one
+//! broken task doesn't restart the service, and a lock such a task poisons is
+//! recovered by lock_recover().
+
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::Cell,
+    fs,
+    panic::{self, UnwindSafe},
+    process, thread,
+};
+
+use log::error;
+
+use crate::{config, persist};
+
+pub const COUNT_FILE: &str = "panic_count";
+
+thread_local! {
+    /// Whether this thread is inside contain().
+    static CONTAINED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f`, catching a panic instead of aborting on it. The panic is still
+/// logged and counted by the hook.
+pub fn contain<R>(f: impl FnOnce() -> R + UnwindSafe) -> Result<R, Box<dyn Any + Send>> {
+    let outer = CONTAINED.replace(true);
+    let result = panic::catch_unwind(f);
+    CONTAINED.set(outer);
+    result
+}
+
+/// Whether a panic here aborts the process, given abort_on_panic.
+fn aborts(abort_on_panic: bool) -> bool {
+    abort_on_panic && !CONTAINED.get()
+}
+
+/// The message a panic was raised with, for the two payload types `panic!`
+/// produces.
+pub fn payload_str(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "<non-string payload>"
+    }
+}
+
+/// Panics recorded since the data directory was created.
+pub fn count() -> u64 {
+    fs::read_to_string(persist::data_path(COUNT_FILE))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+pub fn install() {
+    let abort = config::abort_on_panic();
+    panic::set_hook(Box::new(move |info| {
+        let thread = thread::current();
+        let location = info
+            .location()
+            .map_or_else(|| "unknown location".to_owned(), |l| l.to_string());
+        error!(
+            "Panic on thread '{}' at {location}: {}\n{}",
+            thread.name().unwrap_or("<unnamed>"),
+            payload_str(info.payload()),
+            Backtrace::force_capture()
+        );
+        let count = count() + 1;
+        if let Err(e) = persist::write_atomic(&persist::data_path(COUNT_FILE), &count.to_string())
+        {
+            error!("Failed to record panic: {e}");
+        }
+        if aborts(abort) {
+            error!("Aborting after panic #{count}");
+            process::abort();
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_uncontained_panics_abort() {
+        assert!(aborts(true));
+        assert!(!aborts(false));
+        let inside = contain(|| (aborts(true), contain(|| aborts(true)).ok(), aborts(true)));
+        assert_eq!(inside.ok(), Some((false, Some(false), false)));
+        assert!(aborts(true));
+    }
+
+    #[test]
+    fn contain_returns_the_panic_and_resets() {
+        let err = contain(|| -> () { panic!("task failed") }).unwrap_err();
+        assert_eq!(payload_str(err.as_ref()), "task failed");
+        assert!(aborts(true));
+        assert_eq!(contain(|| 7).ok(), Some(7));
+    }
+}
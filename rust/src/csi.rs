@@ -0,0 +1,99 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Parser for the `csi_stats` node.
+//!
+//! The node is a list of `key=value` (or `key:value`) tokens separated by
+//! whitespace. `status` and `type` are always present; the rest depend on
+//! the kernel branch and are left at -1 when missing. Unknown keys are
+//! ignored so newer kernels don't break older HALs.
+
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::CsiStats::CsiStats;
+
+pub fn parse(content: &str) -> Result<CsiStats, String> {
+    let mut stats = CsiStats::default();
+    let (mut status, mut kind) = (None, None);
+    for token in content.split_whitespace() {
+        let Some((key, value)) = token.split_once(['=', ':']) else {
+            return Err(format!("malformed token '{token}'"));
+        };
+        let value: i32 = value
+            .parse()
+            .map_err(|_| format!("bad value for {key}: '{value}'"))?;
+        match key {
+            "status" => status = Some(value),
+            "type" => kind = Some(value),
+            "speed" => stats.speed = value,
+            "time_effective" => stats.timeEffectiveSec = value,
+            "time_total" => stats.timeTotalSec = value,
+            "temp_min" => stats.tempMinDeciC = value,
+            "temp_max" => stats.tempMaxDeciC = value,
+            "ssoc_in" => stats.ssocIn = value,
+            "ssoc_out" => stats.ssocOut = value,
+            _ => {}
+        }
+    }
+    stats.status = status.ok_or("missing status")?;
+    stats.kind = kind.ok_or("missing type")?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(s: &CsiStats) -> [i32; 9] {
+        [
+            s.status,
+            s.kind,
+            s.speed,
+            s.timeEffectiveSec,
+            s.timeTotalSec,
+            s.tempMinDeciC,
+            s.tempMaxDeciC,
+            s.ssocIn,
+            s.ssocOut,
+        ]
+    }
+
+    #[test]
+    fn parses_every_field() {
+        let content = "status=1 type=2 speed=88 time_effective=900 time_total=960 \
+                       temp_min=260 temp_max=355 ssoc_in=45 ssoc_out=71\n";
+        let stats = parse(content).unwrap();
+        assert_eq!(fields(&stats), [1, 2, 88, 900, 960, 260, 355, 45, 71]);
+    }
+
+    #[test]
+    fn missing_optional_fields_stay_unset() {
+        let stats = parse("status=3 type=0").unwrap();
+        assert_eq!(fields(&stats), [3, 0, -1, -1, -1, -1, -1, -1, -1]);
+    }
+
+    #[test]
+    fn accepts_colons_and_any_whitespace() {
+        let stats = parse("status:1\ttype:2\n speed=-5\n").unwrap();
+        assert_eq!(fields(&stats)[..3], [1, 2, -5]);
+    }
+
+    #[test]
+    fn ignores_unknown_keys() {
+        let stats = parse("lvl=3 status=1 cc_lim=1500 type=2").unwrap();
+        assert_eq!(fields(&stats), fields(&parse("status=1 type=2").unwrap()));
+    }
+
+    #[test]
+    fn rejects_malformed_and_incomplete_content() {
+        let cases = [
+            ("status=1 type", "malformed token 'type'"),
+            ("status=1 type=usb", "bad value for type: 'usb'"),
+            ("status= type=2", "bad value for status: ''"),
+            ("type=2 speed=10", "missing status"),
+            ("status=1", "missing type"),
+            ("", "missing status"),
+        ];
+        for (content, error) in cases {
+            assert_eq!(parse(content).unwrap_err(), error, "{content:?}");
+        }
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Adaptive charging deadline and stage normalization. Some kernel branches
+//! keep `charge_deadline` as seconds remaining, others as an absolute
+//! CLOCK_BOOTTIME target in seconds.
+
+use log::warn;
+
+/// Adaptive charging never schedules further out than this, so larger values
+/// can only be absolute targets. A target set within this long of boot still
+/// reads as seconds remaining, off by at most the uptime at the time it was set.
+pub const MAX_REMAINING_SEC: i64 = 2 * 24 * 60 * 60;
+
+/// `charge_stage` values. Older kernels report the adaptive charging state as
+/// Inactive/Enabled/Active instead of none/AC.
+pub mod stage {
+    use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::ChargeStage::ChargeStage;
+
+    pub const NONE: &str = "none";
+    pub const AC_CHARGE: &str = "AC";
+    pub const TAPER_FAST: &str = "TF";
+    pub const FULL_CHARGE: &str = "FC";
+    pub const BATTERY_DEFEND: &str = "BD";
+    pub const INACTIVE: &str = "Inactive";
+    pub const ENABLED: &str = "Enabled";
+    pub const ACTIVE: &str = "Active";
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum StageDecode {
+        None,
+        AcCharging,
+        TaperFast,
+        FullCharge,
+        BatteryDefend,
+        Unknown(String),
+    }
+
+    pub fn decode(s: &str) -> StageDecode {
+        match s {
+            "" | NONE | INACTIVE => StageDecode::None,
+            AC_CHARGE | ENABLED | ACTIVE => StageDecode::AcCharging,
+            TAPER_FAST => StageDecode::TaperFast,
+            FULL_CHARGE => StageDecode::FullCharge,
+            BATTERY_DEFEND => StageDecode::BatteryDefend,
+            other => StageDecode::Unknown(other.to_owned()),
+        }
+    }
+
+    impl From<&StageDecode> for ChargeStage {
+        fn from(s: &StageDecode) -> Self {
+            match s {
+                StageDecode::None => Self::NONE,
+                StageDecode::AcCharging => Self::AC_CHARGING,
+                StageDecode::TaperFast => Self::TAPER_FAST,
+                StageDecode::FullCharge => Self::FULL_CHARGE,
+                StageDecode::BatteryDefend => Self::BATTERY_DEFEND,
+                StageDecode::Unknown(_) => Self::UNKNOWN,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Convention {
+    Remaining,
+    Absolute,
+}
+
+impl Convention {
+    pub fn detect(raw: i64) -> Self {
+        if raw > MAX_REMAINING_SEC {
+            Self::Absolute
+        } else {
+            Self::Remaining
+        }
+    }
+}
+
+/// Seconds until the deadline, with expired deadlines clamped to 0. Zero and
+/// negative values mean no deadline is set and pass through unchanged.
+pub fn seconds_remaining(raw: i64, boot_now_sec: i64) -> i64 {
+    if raw <= 0 {
+        return raw;
+    }
+    match Convention::detect(raw) {
+        Convention::Remaining => raw,
+        Convention::Absolute => (raw - boot_now_sec).max(0),
+    }
+}
+
+/// Range setChargingDeadlineLocal accepts, one minute to a day out.
+pub const LOCAL_MIN_SEC: i32 = 60;
+pub const LOCAL_MAX_SEC: i32 = 24 * 60 * 60;
+
+/// Checks a raw deadline before it is written: -1 or 0 to clear, seconds
+/// remaining up to MAX_REMAINING_SEC, or an absolute target still ahead of
+/// `boot_now_sec`.
+pub fn validate(raw: i64, boot_now_sec: i64) -> Result<(), String> {
+    if raw == -1 || raw == 0 {
+        return Ok(());
+    }
+    if raw < 0 {
+        return Err(format!("deadline {raw} is negative; use -1 or 0 to clear"));
+    }
+    match Convention::detect(raw) {
+        Convention::Remaining => Ok(()),
+        Convention::Absolute if raw > boot_now_sec => Ok(()),
+        Convention::Absolute => Err(format!(
+            "deadline {raw} is in the past (boottime {boot_now_sec})"
+        )),
+    }
+}
+
+/// Seconds since boot, including suspend.
+pub fn boottime_sec() -> Option<i64> {
+    // SAFETY: clock_gettime only writes the timespec it is given.
+    let mut ts: libc::timespec = unsafe { std::mem::zeroed() };
+    if unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut ts) } != 0 {
+        return None;
+    }
+    Some(ts.tv_sec)
+}
+
+/// Decodes `raw`, warning about unknown stages. Those are still passed on as
+/// strings, since a newer kernel adding a stage shouldn't blank it out for clients.
+pub fn check_stage(raw: &str) -> stage::StageDecode {
+    let decoded = stage::decode(raw);
+    if let stage::StageDecode::Unknown(s) = &decoded {
+        warn!("Unknown charge stage '{s}'");
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::ChargeStage::ChargeStage;
+
+    use super::stage::*;
+
+    #[test]
+    fn every_known_stage_decodes() {
+        let known = [
+            ("", StageDecode::None, ChargeStage::NONE),
+            (NONE, StageDecode::None, ChargeStage::NONE),
+            (INACTIVE, StageDecode::None, ChargeStage::NONE),
+            (AC_CHARGE, StageDecode::AcCharging, ChargeStage::AC_CHARGING),
+            (ENABLED, StageDecode::AcCharging, ChargeStage::AC_CHARGING),
+            (ACTIVE, StageDecode::AcCharging, ChargeStage::AC_CHARGING),
+            (TAPER_FAST, StageDecode::TaperFast, ChargeStage::TAPER_FAST),
+            (FULL_CHARGE, StageDecode::FullCharge, ChargeStage::FULL_CHARGE),
+            (BATTERY_DEFEND, StageDecode::BatteryDefend, ChargeStage::BATTERY_DEFEND),
+        ];
+        for (raw, decoded, aidl) in known {
+            assert_eq!(decode(raw), decoded, "{raw:?}");
+            assert_eq!(ChargeStage::from(&decoded), aidl, "{raw:?}");
+        }
+    }
+
+    #[test]
+    fn other_stages_are_kept_as_unknown() {
+        for raw in ["XY", "ac", "None", " AC"] {
+            let decoded = decode(raw);
+            assert_eq!(decoded, StageDecode::Unknown(raw.to_owned()));
+            assert_eq!(ChargeStage::from(&decoded), ChargeStage::UNKNOWN);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tuned trickle values a defender clear zeroes. Clearing TRICKLE writes 0 to
+//! `bd_trickle_reset_sec` and clearing DWELL writes 0 to `bd_trickle_rate`,
+//! which would leave both off until reboot, so the old values are written back
+//! once the defender drops. They are persisted before the clear so a restart
+//! part way through still puts them back.
+
+use std::{
+    fs, io,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{config, persist, sysfs::paths};
+
+pub const STATE_FILE: &str = "defender_stash";
+pub const SETTLE_POLL: Duration = Duration::from_millis(100);
+
+/// Zero means the node was already off, so there is nothing to restore.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stash {
+    pub reset_sec: i32,
+    pub rate: i32,
+}
+
+impl Stash {
+    pub fn is_empty(&self) -> bool {
+        self.reset_sec == 0 && self.rate == 0
+    }
+
+    fn to_line(self) -> String {
+        format!("{} {}", self.reset_sec, self.rate)
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let v = line
+            .split_whitespace()
+            .map(str::parse::<i32>)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        match v.as_slice() {
+            [reset_sec, rate] => Some(Self {
+                reset_sec: *reset_sec,
+                rate: *rate,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Polls until the trickle defender is no longer active. Returns false if it
+/// still is after `defender_settle_ms` from the config file.
+pub fn wait_for_drop() -> bool {
+    let timeout = Duration::from_millis(config::get().defender_settle_ms);
+    let started = Instant::now();
+    while paths::BD_TRICKLE_CNT.read_int_or(0) > 0 {
+        if started.elapsed() >= timeout {
+            return false;
+        }
+        thread::sleep(SETTLE_POLL);
+    }
+    true
+}
+
+pub fn load(path: &Path) -> Option<Stash> {
+    Stash::from_line(fs::read_to_string(path).ok()?.trim())
+}
+
+pub fn save(path: &Path, stash: &Stash) -> io::Result<()> {
+    persist::write_atomic(path, &stash.to_line())
+}
+
+pub fn clear(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stashes_round_trip_and_reject_junk() {
+        for stash in [Stash { reset_sec: 600, rate: 30 }, Stash { reset_sec: 0, rate: 5 }] {
+            assert_eq!(Stash::from_line(&stash.to_line()), Some(stash));
+        }
+        for line in ["", "600", "600 30 1", "600 x"] {
+            assert_eq!(Stash::from_line(line), None, "{line:?}");
+        }
+        assert!(Stash::default().is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Crash-loop detection. Each start bumps a counter in ATTEMPTS_FILE, which
+//! is cleared after HEALTHY_AFTER of uptime or on a clean shutdown. A node
+//! that panics or wedges the service at startup keeps it from ever getting
+//! there, so once the counter passes THRESHOLD the service starts degraded:
+//! no startup reconciliation, no background threads, and only the passive
+//! getters are served. That keeps the state readable while whatever the
+//! reconciler or monitor trips over is left alone.
+//!
+//! A degraded run that stays up for HEALTHY_AFTER clears the counter, so the
+//! next start is a normal one. On debuggable builds
+//! `dumpsys ... --clear-degraded` clears it straight away.
+
+use std::{
+    ffi::CStr,
+    fs, io,
+    io::Write,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use log::{info, warn};
+
+use crate::{metrics, permission, persist, shutdown};
+
+pub const ATTEMPTS_FILE: &str = "startup_attempts";
+/// Starts in a row without a healthy run before the next one is degraded.
+pub const THRESHOLD: u32 = 3;
+pub const HEALTHY_AFTER: Duration = Duration::from_secs(5 * 60);
+pub const DUMP_ARG: &str = "--clear-degraded";
+
+static DEGRADED: AtomicBool = AtomicBool::new(false);
+
+fn load() -> u32 {
+    fs::read_to_string(persist::data_path(ATTEMPTS_FILE))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Forgets the failed starts so far.
+pub fn clear() {
+    match fs::remove_file(persist::data_path(ATTEMPTS_FILE)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            warn!("Failed to clear startup attempts: {e}");
+        }
+        _ => {}
+    }
+}
+
+/// Counts this start and decides whether it is degraded. Call once, before
+/// anything that touches the kernel.
+pub fn on_start() -> bool {
+    let attempts = load() + 1;
+    if let Err(e) = persist::write_atomic(&persist::data_path(ATTEMPTS_FILE), &attempts.to_string())
+    {
+        warn!("Failed to record startup attempt: {e}");
+    }
+    let degraded = attempts > THRESHOLD;
+    if degraded {
+        warn!("{attempts} starts without a healthy run, starting degraded");
+        metrics::emit(metrics::Event::Degraded { attempts });
+    }
+    DEGRADED.store(degraded, Ordering::Relaxed);
+    degraded
+}
+
+pub fn is_degraded() -> bool {
+    DEGRADED.load(Ordering::Relaxed)
+}
+
+/// Whether `method` may run: everything normally, only getters when degraded.
+pub fn allows(method: &str) -> bool {
+    !is_degraded() || permission::is_getter(method)
+}
+
+/// Clears the counter after HEALTHY_AFTER of uptime, and on a clean shutdown
+/// before then.
+pub fn spawn_healthy_timer() -> io::Result<()> {
+    shutdown::on_flush(clear);
+    let handle = thread::Builder::new()
+        .name("battery_healthy".into())
+        .spawn(|| {
+            if shutdown::sleep(HEALTHY_AFTER) {
+                return;
+            }
+            info!("Up for {HEALTHY_AFTER:?}, clearing startup attempts");
+            clear();
+        })?;
+    shutdown::track(handle);
+    Ok(())
+}
+
+/// Handles the DUMP_ARG dump arg. Returns false when `args` are something
+/// else, for the normal dump to go ahead.
+pub fn handle_dump_args(w: &mut dyn Write, args: &[&CStr]) -> io::Result<bool> {
+    if args.first().map(|a| a.to_bytes()) != Some(DUMP_ARG.as_bytes()) {
+        return Ok(false);
+    }
+    if !permission::is_debuggable() {
+        writeln!(w, "{DUMP_ARG} needs a debuggable build")?;
+        return Ok(true);
+    }
+    clear();
+    info!("Startup attempts cleared from dump");
+    writeln!(w, "Startup attempts cleared; restart the service to leave degraded mode")?;
+    Ok(true)
+}
+
+pub fn dump(w: &mut dyn Write) -> io::Result<()> {
+    writeln!(
+        w,
+        "Degraded mode: {} ({} starts without a healthy run)",
+        is_degraded(),
+        load()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeSysfs;
+
+    /// Installs a FakeSysfs for a data dir of its own, and leaves degraded
+    /// mode when dropped so other tests' setters still go through.
+    struct Fresh {
+        _env: crate::testing::Env,
+    }
+
+    impl Drop for Fresh {
+        fn drop(&mut self) {
+            DEGRADED.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn fresh() -> Fresh {
+        Fresh { _env: FakeSysfs::builder().install() }
+    }
+
+    fn dump_text() -> String {
+        let mut out = Vec::new();
+        dump(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn starts_are_counted_across_restarts() {
+        let _env = fresh();
+        for n in 1..=THRESHOLD {
+            assert!(!on_start());
+            assert_eq!(load(), n);
+        }
+        assert_eq!(
+            fs::read_to_string(persist::data_path(ATTEMPTS_FILE)).unwrap(),
+            THRESHOLD.to_string()
+        );
+    }
+
+    #[test]
+    fn passing_the_threshold_starts_degraded() {
+        let _env = fresh();
+        for _ in 0..THRESHOLD {
+            on_start();
+        }
+        let events = metrics::capture(|| assert!(on_start()));
+        let want = THRESHOLD + 1;
+        assert!(
+            matches!(events[..], [metrics::Event::Degraded { attempts }] if attempts == want),
+            "{events:?}"
+        );
+        assert!(is_degraded());
+        assert!(allows("getChargeLimit") && allows("isAdaptiveChargingPaused"));
+        assert!(!allows("setChargeLimit") && !allows("clearBatteryDefenders"));
+        assert_eq!(
+            dump_text(),
+            format!("Degraded mode: true ({} starts without a healthy run)\n", THRESHOLD + 1)
+        );
+    }
+
+    #[test]
+    fn a_healthy_run_ends_degraded_mode_on_the_next_start() {
+        let _env = fresh();
+        for _ in 0..=THRESHOLD {
+            on_start();
+        }
+        assert!(is_degraded());
+        clear();
+        assert_eq!(load(), 0);
+        assert!(!on_start());
+        assert!(allows("setChargeLimit"));
+        // Clearing with nothing recorded is fine.
+        clear();
+        clear();
+    }
+
+    #[test]
+    fn clearing_from_dump_needs_a_debuggable_build() {
+        let _env = fresh();
+        on_start();
+        let mut out = Vec::new();
+        assert!(!handle_dump_args(&mut out, &[c"--log-level"]).unwrap());
+        assert!(handle_dump_args(&mut out, &[c"--clear-degraded"]).unwrap());
+        // ro.debuggable is unset in tests.
+        assert_eq!(String::from_utf8(out).unwrap(), "--clear-degraded needs a debuggable build\n");
+        assert_eq!(load(), 1);
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Charge current derating by battery temperature. While enabled and the
+//! battery is above the threshold, a monitor task cuts `input_current_limit`
+//! by the configured percentage for every started STEP_DECI_C above it, to no
+//! less than MIN_LIMIT_PCT of the limit from before derating started. Once the
+//! battery is back at or below the threshold that limit is written back.
+//!
+//! The original limit is persisted with the settings, so a restart while
+//! derated still puts it back.
+
+use std::{path::PathBuf, sync::Mutex, time::Duration};
+
+use log::{info, warn};
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::DeratingStatus::DeratingStatus;
+
+use crate::{
+    lock::MutexExt,
+    persist,
+    sysfs::{self, paths},
+};
+
+pub const SETTINGS_FILE: &str = "temp_derating";
+pub const DEFAULT_THRESHOLD_DECI_C: i32 = 400;
+pub const DEFAULT_REDUCTION_PCT: i32 = 10;
+pub const THRESHOLD_RANGE: std::ops::RangeInclusive<i32> = 300..=500;
+pub const REDUCTION_RANGE: std::ops::RangeInclusive<i32> = 1..=50;
+pub const STEP_DECI_C: i32 = 50;
+pub const MIN_LIMIT_PCT: i32 = 20;
+pub const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    pub enabled: bool,
+    pub threshold_deci_c: i32,
+    pub reduction_pct: i32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_deci_c: DEFAULT_THRESHOLD_DECI_C,
+            reduction_pct: DEFAULT_REDUCTION_PCT,
+        }
+    }
+}
+
+/// The limit for `temp_deci_c`, from the limit in place before derating.
+pub fn derated(base_ua: i32, temp_deci_c: i32, settings: Settings) -> i32 {
+    let excess = temp_deci_c - settings.threshold_deci_c;
+    if excess <= 0 {
+        return base_ua;
+    }
+    let steps = (excess + STEP_DECI_C - 1) / STEP_DECI_C;
+    let pct = 100_i32
+        .saturating_sub(steps.saturating_mul(settings.reduction_pct))
+        .max(MIN_LIMIT_PCT);
+    (i64::from(base_ua) * i64::from(pct) / 100) as i32
+}
+
+struct Inner {
+    settings: Settings,
+    /// The limit before derating started; Some while derated.
+    base_ua: Option<i32>,
+    temp_deci_c: Option<i32>,
+}
+
+impl Inner {
+    fn to_line(&self) -> String {
+        let s = self.settings;
+        format!(
+            "{} {} {} {}",
+            s.enabled as i32,
+            s.threshold_deci_c,
+            s.reduction_pct,
+            self.base_ua.unwrap_or(-1)
+        )
+    }
+
+    fn from_line(line: &str) -> Option<(Settings, Option<i32>)> {
+        let v = line
+            .split_whitespace()
+            .map(str::parse::<i32>)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        match v.as_slice() {
+            [enabled, threshold, reduction, base] => Some((
+                Settings {
+                    enabled: *enabled != 0,
+                    threshold_deci_c: *threshold,
+                    reduction_pct: *reduction,
+                },
+                (*base >= 0).then_some(*base),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Writes the pre-derating limit back, if derated.
+    fn restore(&mut self) -> sysfs::Result<()> {
+        if let Some(base) = self.base_ua {
+            paths::INPUT_CURRENT_LIMIT.write_int(base)?;
+            info!("Temperature derating over, input current limit back to {base} uA");
+            self.base_ua = None;
+        }
+        Ok(())
+    }
+}
+
+pub struct Derating {
+    path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+impl Derating {
+    pub fn load(path: PathBuf) -> Self {
+        let (settings, base_ua) = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| Inner::from_line(s.trim()))
+            .unwrap_or_default();
+        Self {
+            path,
+            inner: Mutex::new(Inner {
+                settings,
+                base_ua,
+                temp_deci_c: None,
+            }),
+        }
+    }
+
+    fn save(&self, inner: &Inner) {
+        if let Err(e) = persist::write_atomic(&self.path, &inner.to_line()) {
+            warn!("Failed to persist temperature derating: {e}");
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.lock_recover().settings.enabled
+    }
+
+    /// Applies new settings. Disabling restores the original limit straight
+    /// away; otherwise the next tick derates against the new settings.
+    pub fn configure(&self, settings: Settings) -> sysfs::Result<()> {
+        let mut inner = self.inner.lock_recover();
+        inner.settings = settings;
+        let restored = if settings.enabled { Ok(()) } else { inner.restore() };
+        self.save(&inner);
+        info!("Temperature derating: {settings:?}");
+        restored
+    }
+
+    /// Called from the monitor every TICK_INTERVAL.
+    pub fn tick(&self) {
+        let temp = paths::BATTERY_UEVENT
+            .read_string()
+            .ok()
+            .and_then(|u| sysfs::uevent_value(&u, "TEMP").and_then(|t| t.parse().ok()));
+        let mut inner = self.inner.lock_recover();
+        inner.temp_deci_c = temp;
+        let settings = inner.settings;
+        let Some(temp) = temp else {
+            return;
+        };
+        // Disabled can still have a limit to restore, persisted before a restart.
+        if !settings.enabled || temp <= settings.threshold_deci_c {
+            if inner.base_ua.is_some() {
+                if let Err(e) = inner.restore() {
+                    warn!("Failed to restore input current limit: {e}");
+                }
+                self.save(&inner);
+            }
+            return;
+        }
+        let base = match inner.base_ua {
+            Some(base) => base,
+            None => match paths::INPUT_CURRENT_LIMIT.read_int() {
+                Ok(base) if base > 0 => {
+                    info!("Battery at {temp} dC, derating input current from {base} uA");
+                    inner.base_ua = Some(base);
+                    self.save(&inner);
+                    base
+                }
+                Ok(_) => return,
+                Err(e) => {
+                    warn!("Can't derate, input current limit unreadable: {e}");
+                    return;
+                }
+            },
+        };
+        if let Err(e) = paths::INPUT_CURRENT_LIMIT.write_int(derated(base, temp, settings)) {
+            warn!("Failed to derate input current limit: {e}");
+        }
+    }
+
+    /// Puts the original limit back, for shutdown.
+    pub fn flush(&self) {
+        let mut inner = self.inner.lock_recover();
+        if let Err(e) = inner.restore() {
+            warn!("Failed to restore input current limit: {e}");
+        }
+        self.save(&inner);
+    }
+
+    pub fn status(&self) -> DeratingStatus {
+        let ma = |ua: i32| ua / 1000;
+        // Read before locking, so the monitor never waits on this read.
+        let limit_ma = paths::INPUT_CURRENT_LIMIT.read_int().map_or(-1, ma);
+        let inner = self.inner.lock_recover();
+        DeratingStatus {
+            enabled: inner.settings.enabled,
+            thresholdDeciC: inner.settings.threshold_deci_c,
+            reductionPercent: inner.settings.reduction_pct,
+            active: inner.base_ua.is_some(),
+            tempDeciC: inner.temp_deci_c.unwrap_or(0),
+            limitMa: limit_ma,
+            baseLimitMa: inner.base_ua.map_or(-1, ma),
+        }
+    }
+}
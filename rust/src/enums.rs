@@ -0,0 +1,257 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers on the AIDL enums. The types are generated, so these live in
+//! extension traits.
+
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
+    BatteryTechnology::BatteryTechnology, CalibrationState::CalibrationState,
+    ChargingSpeedLevel::ChargingSpeedLevel, ChargingStatus::ChargingStatus,
+    ChargingType::ChargingType, HealthStatus::HealthStatus,
+};
+
+pub trait ChargingTypeExt {
+    fn label(&self) -> &'static str;
+    fn is_wireless(&self) -> bool;
+    fn is_wired(&self) -> bool;
+}
+
+/// Values follow the google,charger `charging_type` encoding.
+impl ChargingTypeExt for ChargingType {
+    fn label(&self) -> &'static str {
+        match *self {
+            Self::USB_A => "USB-A",
+            Self::USB_C => "USB-C",
+            Self::USB_PD => "USB-PD",
+            Self::USB_PD_PPS => "USB-PD-PPS",
+            Self::AC_BRICK => "AC-Brick",
+            Self::WIRELESS_BPP => "Wireless-BPP",
+            Self::WIRELESS_EPP => "Wireless-EPP",
+            Self::DOCK => "Dock",
+            _ => "Unknown",
+        }
+    }
+
+    fn is_wireless(&self) -> bool {
+        matches!(*self, Self::WIRELESS_BPP | Self::WIRELESS_EPP)
+    }
+
+    /// The dock is a pogo-pin connection, so it counts as wired.
+    fn is_wired(&self) -> bool {
+        matches!(
+            *self,
+            Self::USB_A | Self::USB_C | Self::USB_PD | Self::USB_PD_PPS | Self::AC_BRICK | Self::DOCK
+        )
+    }
+}
+
+/// google,charger `charging_status` values and what they mean here. The
+/// defender states are in the 100s, so they can't be passed through as is.
+pub const KERNEL_CHARGING_STATUS: &[(i32, ChargingStatus)] = &[
+    (1, ChargingStatus::NORMAL),
+    (2, ChargingStatus::TOO_COLD),
+    (3, ChargingStatus::TOO_HOT),
+    (4, ChargingStatus::LONG_LIFE),
+    (5, ChargingStatus::ADAPTIVE),
+    (100, ChargingStatus::DEFENDER_TEMP),
+    (101, ChargingStatus::DEFENDER_DWELL),
+    (102, ChargingStatus::DEFENDER_TRICKLE),
+    (103, ChargingStatus::DEFENDER_DOCK),
+];
+
+pub trait ChargingStatusExt: Sized {
+    fn from_kernel(raw: i32) -> Self;
+    fn is_defender(&self) -> bool;
+}
+
+impl ChargingStatusExt for ChargingStatus {
+    /// Maps a raw `charging_status` value, with anything not in
+    /// KERNEL_CHARGING_STATUS as UNKNOWN.
+    fn from_kernel(raw: i32) -> Self {
+        KERNEL_CHARGING_STATUS
+            .iter()
+            .find(|(k, _)| *k == raw)
+            .map_or(Self::UNKNOWN, |(_, s)| *s)
+    }
+
+    /// Charging is held off by one of the battery defenders.
+    fn is_defender(&self) -> bool {
+        matches!(
+            *self,
+            Self::DEFENDER_TEMP | Self::DEFENDER_DWELL | Self::DEFENDER_TRICKLE | Self::DEFENDER_DOCK
+        )
+    }
+}
+
+pub trait ChargingSpeedLevelExt: Sized {
+    fn from_raw(v: i32) -> Self;
+    fn typical_power_mw(&self) -> Option<i32>;
+}
+
+impl ChargingSpeedLevelExt for ChargingSpeedLevel {
+    /// Maps a raw `charging_speed` value, with anything unrecognized as UNKNOWN.
+    fn from_raw(v: i32) -> Self {
+        Self::enum_values()
+            .into_iter()
+            .find(|l| l.0 == v)
+            .unwrap_or(Self::UNKNOWN)
+    }
+
+    /// Typical charger output for the level, for display only.
+    fn typical_power_mw(&self) -> Option<i32> {
+        match *self {
+            Self::NONE => Some(0),
+            Self::SLOW => Some(5_000),
+            Self::NORMAL => Some(7_500),
+            Self::FAST => Some(18_000),
+            Self::VERY_FAST => Some(27_000),
+            Self::TURBO => Some(45_000),
+            _ => None,
+        }
+    }
+}
+
+pub trait CalibrationStateExt {
+    fn description(&self) -> &'static str;
+    fn is_terminal(&self) -> bool;
+}
+
+/// Values follow the `health_get_cal_state` encoding.
+impl CalibrationStateExt for CalibrationState {
+    fn description(&self) -> &'static str {
+        match *self {
+            Self::NOT_CALIBRATING => "idle",
+            Self::COLLECTING => "collecting",
+            Self::COMPUTING => "computing",
+            Self::COMPLETE => "complete",
+            Self::FAILED => "failed",
+            _ => "unknown",
+        }
+    }
+
+    /// Whether the kernel is done with this calibration run.
+    fn is_terminal(&self) -> bool {
+        matches!(*self, Self::COMPLETE | Self::FAILED)
+    }
+}
+
+pub trait HealthStatusExt {
+    fn description(&self) -> String;
+}
+
+/// Values follow the `health_status` node encoding.
+impl HealthStatusExt for HealthStatus {
+    fn description(&self) -> String {
+        let s = match *self {
+            Self::UNSUPPORTED => "Unsupported",
+            Self::UNKNOWN => "Unknown",
+            Self::GOOD => "Good",
+            Self::OVERHEAT => "Overheat",
+            Self::DEAD => "Dead",
+            Self::OVER_VOLTAGE => "OverVoltage",
+            Self::UNSPECIFIED_FAILURE => "UnspecifiedFailure",
+            Self::COLD => "Cold",
+            Self::WATCHDOG_TIMER_EXPIRE => "Watchdog_Timer_Expire",
+            Self::SAFETY_TIMER_EXPIRE => "Safety_Timer_Expire",
+            Self::OVERCURRENT => "Overcurrent",
+            Self(v) => return format!("Unknown({v})"),
+        };
+        s.to_owned()
+    }
+}
+
+pub trait BatteryTechnologyExt: Sized {
+    fn from_label(s: &str) -> Self;
+}
+
+impl BatteryTechnologyExt for BatteryTechnology {
+    /// Parses the power_supply `technology` string.
+    fn from_label(s: &str) -> Self {
+        match s {
+            "NiMH" => Self::NIMH,
+            "Li-ion" => Self::LI_ION,
+            "Li-poly" => Self::LI_POLY,
+            "LiFe" => Self::LIFE,
+            "NiCd" => Self::NICD,
+            "LiMn" => Self::LIMN,
+            _ => Self::UNKNOWN,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charging_types_follow_the_driver_encoding() {
+        let expected = [
+            (1, "USB-A", true),
+            (2, "USB-C", true),
+            (3, "USB-PD", true),
+            (4, "USB-PD-PPS", true),
+            (5, "AC-Brick", true),
+            (6, "Wireless-BPP", false),
+            (7, "Wireless-EPP", false),
+            (8, "Dock", true),
+        ];
+        for (raw, label, wired) in expected {
+            let t = ChargingType(raw);
+            assert_eq!(t.label(), label);
+            assert_eq!((t.is_wired(), t.is_wireless()), (wired, !wired), "{label}");
+        }
+    }
+
+    #[test]
+    fn calibration_states_follow_the_driver_encoding() {
+        let expected = [
+            (0, "idle", false),
+            (1, "collecting", false),
+            (2, "computing", false),
+            (3, "complete", true),
+            (4, "failed", true),
+        ];
+        for (raw, description, terminal) in expected {
+            let state = CalibrationState(raw);
+            assert_eq!(state.description(), description);
+            assert_eq!(state.is_terminal(), terminal, "{description}");
+        }
+        for raw in [-1, 5, 100] {
+            let state = CalibrationState(raw);
+            assert_eq!(state.description(), "unknown");
+            assert!(!state.is_terminal(), "{raw}");
+        }
+    }
+
+    #[test]
+    fn health_statuses_follow_the_hal_values() {
+        let expected = [
+            (-1, "Unsupported"),
+            (0, "Unknown"),
+            (1, "Good"),
+            (2, "Overheat"),
+            (3, "Dead"),
+            (4, "OverVoltage"),
+            (5, "UnspecifiedFailure"),
+            (6, "Cold"),
+            (7, "Watchdog_Timer_Expire"),
+            (8, "Safety_Timer_Expire"),
+            (9, "Overcurrent"),
+        ];
+        for (raw, description) in expected {
+            assert_eq!(HealthStatus(raw).description(), description);
+        }
+        for raw in [-2, 10, 100] {
+            assert_eq!(HealthStatus(raw).description(), format!("Unknown({raw})"));
+        }
+    }
+
+    #[test]
+    fn unknown_charging_types_are_neither_wired_nor_wireless() {
+        for raw in [-1, 0, 9, 100] {
+            let t = ChargingType(raw);
+            assert_eq!(t.label(), "Unknown");
+            assert!(!t.is_wired() && !t.is_wireless(), "{raw}");
+        }
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Service-specific error codes. The values are the `ERROR_*` constants in
+//! IBattery.aidl, which clients match on.
+
+use std::io;
+
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery;
+
+use crate::sysfs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    NodeMissing,
+    KernelRejected,
+    VerifyFailed,
+    ParseError,
+    Busy,
+    Io,
+    BypassActive,
+}
+
+impl ErrorCode {
+    pub fn code(self) -> i32 {
+        match self {
+            Self::NodeMissing => IBattery::ERROR_NODE_MISSING,
+            Self::KernelRejected => IBattery::ERROR_KERNEL_REJECTED,
+            Self::VerifyFailed => IBattery::ERROR_VERIFY_FAILED,
+            Self::ParseError => IBattery::ERROR_PARSE,
+            Self::Busy => IBattery::ERROR_BUSY,
+            Self::Io => IBattery::ERROR_IO,
+            Self::BypassActive => IBattery::ERROR_BYPASS_ACTIVE,
+        }
+    }
+
+    fn from_io(e: &io::Error) -> Self {
+        match e.raw_os_error() {
+            Some(libc::EINVAL | libc::ERANGE) => Self::KernelRejected,
+            Some(libc::EBUSY | libc::EAGAIN) => Self::Busy,
+            _ => Self::Io,
+        }
+    }
+}
+
+impl From<&sysfs::Error> for ErrorCode {
+    fn from(e: &sysfs::Error) -> Self {
+        match e {
+            sysfs::Error::NotFound { .. } => Self::NodeMissing,
+            sysfs::Error::Io { source, .. } => Self::from_io(source),
+            sysfs::Error::Parse { .. } => Self::ParseError,
+            sysfs::Error::Verify { .. } => Self::VerifyFailed,
+            // The driver may recover, as with EBUSY.
+            sysfs::Error::Timeout { .. } => Self::Busy,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [ErrorCode; 7] = [
+        ErrorCode::NodeMissing,
+        ErrorCode::KernelRejected,
+        ErrorCode::VerifyFailed,
+        ErrorCode::ParseError,
+        ErrorCode::Busy,
+        ErrorCode::Io,
+        ErrorCode::BypassActive,
+    ];
+
+    fn io(errno: i32) -> sysfs::Error {
+        sysfs::Error::Io {
+            path: "node".into(),
+            source: io::Error::from_raw_os_error(errno),
+            written: None,
+        }
+    }
+
+    #[test]
+    fn codes_are_distinct_and_not_generic() {
+        let codes = ALL.map(ErrorCode::code);
+        assert_eq!(codes, [2, 3, 4, 5, 6, 7, 8]);
+        assert!(!codes.contains(&IBattery::ERROR_GENERIC));
+    }
+
+    #[test]
+    fn sysfs_errors_map_onto_codes() {
+        let path = || "node".to_owned();
+        let cases = [
+            (sysfs::Error::NotFound { path: path() }, ErrorCode::NodeMissing),
+            (io(libc::EINVAL), ErrorCode::KernelRejected),
+            (io(libc::ERANGE), ErrorCode::KernelRejected),
+            (io(libc::EBUSY), ErrorCode::Busy),
+            (io(libc::EAGAIN), ErrorCode::Busy),
+            (io(libc::EACCES), ErrorCode::Io),
+            (
+                sysfs::Error::Parse {
+                    path: path(),
+                    content: "x".into(),
+                },
+                ErrorCode::ParseError,
+            ),
+            (
+                sysfs::Error::Verify {
+                    path: path(),
+                    expected: "1".into(),
+                    actual: "0".into(),
+                },
+                ErrorCode::VerifyFailed,
+            ),
+            (sysfs::Error::Timeout { path: path() }, ErrorCode::Busy),
+        ];
+        for (e, code) in cases {
+            assert_eq!(ErrorCode::from(&e), code, "{e}");
+        }
+    }
+}
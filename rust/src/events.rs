@@ -0,0 +1,189 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bounded, persisted history of battery defender activations and clears.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use log::warn;
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
+    DefenderEvent::DefenderEvent, DefenderEventKind::DefenderEventKind,
+    DefenderType::DefenderType,
+};
+
+use crate::{
+    lock::MutexExt,
+    metrics::{self, Event as Metric},
+    persist,
+};
+
+pub const EVENTS_FILE: &str = "defender_events";
+pub const MAX_EVENTS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub defender: DefenderType,
+    pub kind: DefenderEventKind,
+    pub timestamp_ms: i64,
+    pub duration_ms: i64,
+}
+
+impl Event {
+    fn to_line(self) -> String {
+        format!(
+            "{} {} {} {}",
+            self.defender.0, self.kind.0, self.timestamp_ms, self.duration_ms
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut it = line.split_whitespace().map(str::parse::<i64>);
+        let event = Self {
+            defender: DefenderType(it.next()?.ok()?.try_into().ok()?),
+            kind: DefenderEventKind(it.next()?.ok()?.try_into().ok()?),
+            timestamp_ms: it.next()?.ok()?,
+            duration_ms: it.next()?.ok()?,
+        };
+        it.next().is_none().then_some(event)
+    }
+}
+
+impl From<Event> for DefenderEvent {
+    fn from(e: Event) -> Self {
+        Self {
+            defender: e.defender,
+            kind: e.kind,
+            timestampMs: e.timestamp_ms,
+            durationMs: e.duration_ms,
+        }
+    }
+}
+
+struct Active {
+    since_ms: i64,
+    user_cleared: bool,
+}
+
+#[derive(Default)]
+struct Inner {
+    events: VecDeque<Event>,
+    active: HashMap<DefenderType, Active>,
+}
+
+pub struct DefenderLog {
+    path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+impl DefenderLog {
+    /// Loads the history from `path`, skipping lines that don't parse.
+    pub fn load(path: PathBuf) -> Self {
+        let mut inner = Inner::default();
+        if let Ok(content) = fs::read_to_string(&path) {
+            for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                match Event::from_line(line) {
+                    Some(e) => inner.events.push_back(e),
+                    None => warn!("Skipping bad defender event line: '{line}'"),
+                }
+            }
+            while inner.events.len() > MAX_EVENTS {
+                inner.events.pop_front();
+            }
+        }
+        Self {
+            path,
+            inner: Mutex::new(inner),
+        }
+    }
+
+    /// Feeds a polled defender state; records an event on each transition.
+    pub fn observe(&self, defender: DefenderType, active: bool, now_ms: i64) {
+        let mut inner = self.inner.lock_recover();
+        match (active, inner.active.get(&defender)) {
+            (true, None) => {
+                inner.active.insert(
+                    defender,
+                    Active {
+                        since_ms: now_ms,
+                        user_cleared: false,
+                    },
+                );
+                self.push(&mut inner, defender, DefenderEventKind::ACTIVATED, now_ms, 0);
+                metrics::emit(Metric::DefenderTriggered { defender });
+            }
+            (false, Some(a)) => {
+                // A user clear was already recorded when it was requested.
+                let (since, user_cleared) = (a.since_ms, a.user_cleared);
+                inner.active.remove(&defender);
+                if !user_cleared {
+                    let kind = DefenderEventKind::CLEARED;
+                    self.push(&mut inner, defender, kind, now_ms, now_ms - since);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Records a clear requested through `clearBatteryDefenders`.
+    pub fn user_cleared(&self, defender: DefenderType, now_ms: i64) {
+        let mut inner = self.inner.lock_recover();
+        let mut duration = 0;
+        for (d, a) in inner.active.iter_mut() {
+            if defender == DefenderType::ALL || *d == defender {
+                a.user_cleared = true;
+                duration = duration.max(now_ms - a.since_ms);
+            }
+        }
+        let kind = DefenderEventKind::USER_CLEARED;
+        self.push(&mut inner, defender, kind, now_ms, duration);
+    }
+
+    /// Activations recorded at or after `since_ms`.
+    pub fn activations_since(&self, since_ms: i64) -> usize {
+        self.inner
+            .lock_recover()
+            .events
+            .iter()
+            .filter(|e| e.kind == DefenderEventKind::ACTIVATED && e.timestamp_ms >= since_ms)
+            .count()
+    }
+
+    /// Up to `max` of the most recent events, oldest first.
+    pub fn recent(&self, max: usize) -> Vec<Event> {
+        let inner = self.inner.lock_recover();
+        let skip = inner.events.len().saturating_sub(max);
+        inner.events.iter().skip(skip).copied().collect()
+    }
+
+    fn push(
+        &self,
+        inner: &mut Inner,
+        defender: DefenderType,
+        kind: DefenderEventKind,
+        timestamp_ms: i64,
+        duration_ms: i64,
+    ) {
+        inner.events.push_back(Event {
+            defender,
+            kind,
+            timestamp_ms,
+            duration_ms,
+        });
+        while inner.events.len() > MAX_EVENTS {
+            inner.events.pop_front();
+        }
+        let mut content = String::new();
+        for e in &inner.events {
+            content.push_str(&e.to_line());
+            content.push('\n');
+        }
+        if let Err(e) = persist::write_atomic(&self.path, &content) {
+            warn!("Failed to persist defender events: {e}");
+        }
+    }
+}
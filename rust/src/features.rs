@@ -0,0 +1,156 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feature prerequisites and enabled-state probing.
+
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::Feature::Feature;
+
+use crate::sysfs::{self, paths, property};
+
+/// dd_settings values setEnable writes for DOCK_DEFEND, and the one
+/// resetDefender writes to clear an active dock defend.
+pub const DOCK_DEFEND_ON: &str = "B2";
+pub const DOCK_DEFEND_OFF: &str = "1M";
+pub const DOCK_DEFEND_CLEAR: &str = "02";
+
+/// A dd_settings value this HAL knows. The node may hold anything else a
+/// vendor tool wrote, which reads as none of these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DockDefendSetting {
+    On,
+    Off,
+    Clear,
+}
+
+impl DockDefendSetting {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            DOCK_DEFEND_ON => Some(Self::On),
+            DOCK_DEFEND_OFF => Some(Self::Off),
+            DOCK_DEFEND_CLEAR => Some(Self::Clear),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::On => DOCK_DEFEND_ON,
+            Self::Off => DOCK_DEFEND_OFF,
+            Self::Clear => DOCK_DEFEND_CLEAR,
+        }
+    }
+}
+
+/// `(feature, prerequisite)`: the prerequisite must be enabled first.
+pub const FEATURE_DEPS: &[(Feature, Feature)] = &[
+    // AAFV adjusts float voltage along the AACR capacity curve.
+    (Feature::AAFV, Feature::AACR),
+    // AACT bands come from the health algorithm's temperature buckets.
+    (Feature::AACT, Feature::HEALTH),
+];
+
+/// Whether `feature` is currently on, from its enable or state node. HEALTH
+/// has neither and counts as on while a health algorithm is selected, and
+/// DOCK_DEFEND while dd_settings holds what setEnable writes to turn it on.
+pub fn is_enabled(feature: Feature) -> bool {
+    if feature == Feature::DOCK_DEFEND {
+        let setting = paths::DD_SETTINGS.read_string().ok();
+        return setting.and_then(|s| DockDefendSetting::parse(&s)) == Some(DockDefendSetting::On);
+    }
+    let props: &[i32] = match feature {
+        Feature::HEALTH => &[property::ALGO],
+        _ => &[property::ENABLE, property::STATE],
+    };
+    props
+        .iter()
+        .filter_map(|&p| sysfs::get_property_sysfs(feature, p))
+        .find_map(|path| sysfs::read_int(path).ok())
+        .is_some_and(|v| v > 0)
+}
+
+/// Features setEnable can switch on this device.
+pub fn is_controllable(feature: Feature) -> bool {
+    match feature {
+        Feature::DOCK_DEFEND => paths::DD_SETTINGS.exists(),
+        Feature::FG_CYCLE => paths::MAXFG_FIX_CYCLE_COUNT.exists(),
+        _ => false,
+    }
+}
+
+/// Length of the longest prerequisite chain below `feature`.
+fn depth(feature: Feature) -> usize {
+    FEATURE_DEPS
+        .iter()
+        .filter(|(f, _)| *f == feature)
+        .map(|(_, dep)| depth(*dep) + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Order to apply a batch in: prerequisites first when enabling, dependents
+/// first when disabling. Duplicates are dropped and ties keep their order.
+pub fn apply_order(features: &[Feature], enabled: bool) -> Vec<Feature> {
+    let mut order = Vec::with_capacity(features.len());
+    for f in features {
+        if !order.contains(f) {
+            order.push(*f);
+        }
+    }
+    if enabled {
+        order.sort_by_key(|f| depth(*f));
+    } else {
+        order.sort_by_key(|f| std::cmp::Reverse(depth(*f)));
+    }
+    order
+}
+
+/// The first prerequisite of `feature` that isn't enabled.
+pub fn check_feature_deps(feature: Feature) -> Option<Feature> {
+    FEATURE_DEPS
+        .iter()
+        .filter(|(f, _)| *f == feature)
+        .map(|(_, dep)| *dep)
+        .find(|dep| !is_enabled(*dep))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeSysfs;
+
+    /// The node whose value decides whether `feature` counts as enabled.
+    fn state_node(feature: Feature) -> &'static str {
+        match feature {
+            Feature::AACR => paths::AACR_STATE.primary,
+            Feature::HEALTH => paths::HEALTH_ALGO.primary,
+            _ => unreachable!("no state node for {feature:?}"),
+        }
+    }
+
+    #[test]
+    fn each_dependency_is_reported_until_its_prerequisite_is_on() {
+        for &(feature, dep) in FEATURE_DEPS {
+            let env = FakeSysfs::builder().node(state_node(dep), 0).install();
+            assert_eq!(check_feature_deps(feature), Some(dep), "{feature:?}");
+            env.set(state_node(dep), 1);
+            assert_eq!(check_feature_deps(feature), None, "{feature:?}");
+            assert_eq!(check_feature_deps(dep), None, "{dep:?}");
+        }
+    }
+
+    #[test]
+    fn missing_prerequisites_count_as_off() {
+        let _env = FakeSysfs::builder().install();
+        for &(feature, dep) in FEATURE_DEPS {
+            assert_eq!(check_feature_deps(feature), Some(dep), "{feature:?}");
+        }
+    }
+
+    #[test]
+    fn prerequisites_are_applied_first_and_removed_last() {
+        for &(feature, dep) in FEATURE_DEPS {
+            assert_eq!(apply_order(&[feature, dep, feature], true), [dep, feature]);
+            assert_eq!(apply_order(&[dep, feature], false), [feature, dep]);
+        }
+    }
+}
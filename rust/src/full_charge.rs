@@ -0,0 +1,131 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! One-shot "charge to full" bypass of the charge limit. The levels and policy
+//! it replaced are persisted so a HAL restart mid-bypass can still restore them.
+
+use std::{fs, io, path::Path};
+
+use crate::persist;
+
+pub const STATE_FILE: &str = "full_charge_once";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bypass {
+    pub stop: i32,
+    pub start: i32,
+    pub policy: i32,
+    // Set once a charger is seen, so requesting before plugging in works.
+    pub plugged_seen: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Done {
+    Full,
+    Unplugged,
+}
+
+impl Bypass {
+    fn to_line(self) -> String {
+        format!(
+            "{} {} {} {}",
+            self.stop, self.start, self.policy, self.plugged_seen as i32
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let v = line
+            .split_whitespace()
+            .map(str::parse::<i32>)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        match v.as_slice() {
+            [stop, start, policy, plugged_seen] => Some(Self {
+                stop: *stop,
+                start: *start,
+                policy: *policy,
+                plugged_seen: *plugged_seen != 0,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Feeds the latest readings in and returns why the bypass ended, if it has.
+    pub fn step(&mut self, soc: i32, plugged: bool) -> Option<Done> {
+        if soc >= 100 {
+            return Some(Done::Full);
+        }
+        if plugged {
+            self.plugged_seen = true;
+            None
+        } else if self.plugged_seen {
+            Some(Done::Unplugged)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Option<Bypass> {
+    Bypass::from_line(fs::read_to_string(path).ok()?.trim())
+}
+
+pub fn save(path: &Path, bypass: &Bypass) -> io::Result<()> {
+    persist::write_atomic(path, &bypass.to_line())
+}
+
+pub fn clear(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn bypass() -> Bypass {
+        Bypass {
+            stop: 80,
+            start: 70,
+            policy: 2,
+            plugged_seen: false,
+        }
+    }
+
+    #[test]
+    fn ends_when_full() {
+        let mut b = bypass();
+        assert_eq!(b.step(99, true), None);
+        assert_eq!(b.step(100, true), Some(Done::Full));
+        assert_eq!(bypass().step(100, false), Some(Done::Full));
+    }
+
+    #[test]
+    fn ends_on_unplug_only_after_a_charger_was_seen() {
+        let mut b = bypass();
+        assert_eq!(b.step(40, false), None);
+        assert!(!b.plugged_seen);
+        assert_eq!(b.step(41, true), None);
+        assert!(b.plugged_seen);
+        assert_eq!(b.step(42, false), Some(Done::Unplugged));
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let path = testing::data_dir().join("full_charge_round_trip");
+        let b = Bypass {
+            plugged_seen: true,
+            ..bypass()
+        };
+        save(&path, &b).unwrap();
+        assert_eq!(load(&path), Some(b));
+        fs::write(&path, "80 70 2").unwrap();
+        assert_eq!(load(&path), None);
+        clear(&path).unwrap();
+        clear(&path).unwrap();
+        assert_eq!(load(&path), None);
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Randomized tests for the parsers and validators fed from outside the
+//! process: setStringProperty values, which any system app can send, and the
+//! stats nodes, whose format drifts between kernel branches.
+//!
+//! Inputs come from a seeded generator that mixes the tokens the formats are
+//! built from with arbitrary characters, and from mutations of well-formed
+//! contents. Nothing may panic, and whatever a parser accepts has to be what
+//! the input says: a value that can't be read has to be an error, not a
+//! success with shifted or made-up fields.
+//!
+//! Runs are reproducible. `BENZENE_FUZZ_SEED` picks another seed and
+//! `BENZENE_FUZZ_RUNS` the number of inputs per target, for longer runs by
+//! hand.
+
+use std::{env, panic};
+
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
+    Feature::Feature, HealthStats::HealthStats,
+};
+
+use crate::{
+    aacc::AaccProfile,
+    charge_stats, csi,
+    features::DockDefendSetting,
+    health_stats::{self, ParseError, FIELD_COUNT},
+    sysfs::{self, property},
+    validate,
+};
+
+const DEFAULT_SEED: u64 = 0x6265_6e7a_656e_6521;
+const DEFAULT_RUNS: usize = 5000;
+
+/// Pieces the formats are made of, and values that sit on their edges.
+const TOKENS: &[&str] = &[
+    "0", "1", "2", "3", "42", "-1", "-", "+", "007", "2147483647", "2147483648",
+    "-2147483648", "-2147483649", "99999999999", "0x1f", "1e3", "1.5", ",", ",,", ":", "::",
+    "=", "==", " ", "  ", "\t", "\n", "\r\n", "status", "type", "speed", "time_effective",
+    "temp_min", "ssoc_out", "S", "RESET", "B2", "1M", "02", "true", "é", "∞", "\u{0}", "\u{7f}",
+    "\u{1b}", "\u{85}", "\u{feff}", "\u{2028}",
+];
+
+/// Well-formed contents to mutate, one per format.
+const SEEDS: &[&str] = &[
+    "1: 98 4630 4688 4700 132 131 140 187 800 2\n3: 91,4400,4688,4700,150,149,140,402,800,3\n",
+    "status=1 type=2 speed=88 time_effective=900 time_total=960 temp_min=260 temp_max=355\n",
+    "S:4,9000,2000,15,82,4120\n0:1200,1450,2010\nRESET\nS:9,5000,1500,40\n",
+    "10,20,30,40",
+    "3600",
+    "2,70",
+];
+
+/// xorshift64*, enough to spread inputs around without a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+
+    fn char(&mut self) -> char {
+        match self.below(4) {
+            0 => char::from(self.below(0x80) as u8),
+            1 => char::from_u32(self.below(0x800) as u32).unwrap_or('?'),
+            _ => char::from(b' ' + self.below(0x5f) as u8),
+        }
+    }
+
+    /// Tokens and characters strung together.
+    fn tokens(&mut self) -> String {
+        let mut s = String::new();
+        for _ in 0..self.below(24) {
+            if self.below(4) == 0 {
+                s.push(self.char());
+            } else {
+                s.push_str(self.pick::<&str>(TOKENS));
+            }
+        }
+        s
+    }
+
+    /// A seed with a few characters replaced, inserted or removed.
+    fn mutated(&mut self) -> String {
+        let mut chars: Vec<char> = self.pick(SEEDS).chars().collect();
+        for _ in 0..1 + self.below(4) {
+            let at = self.below(chars.len() + 1);
+            match self.below(3) {
+                0 if at < chars.len() => chars[at] = self.char(),
+                1 if at < chars.len() => {
+                    chars.remove(at);
+                }
+                _ => {
+                    let token: Vec<char> = self.pick(TOKENS).chars().collect();
+                    chars.splice(at..at, token);
+                }
+            }
+        }
+        chars.into_iter().collect()
+    }
+
+    fn input(&mut self) -> String {
+        match self.below(8) {
+            0 => (0..self.below(64)).map(|_| self.char()).collect(),
+            1..=3 => self.tokens(),
+            _ => self.mutated(),
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Runs `check` on RUNS generated inputs, naming the seed and input on a
+/// failure so it can be turned into a plain test.
+fn fuzz(target: &str, mut check: impl FnMut(&mut Rng, &str)) {
+    let seed = env_or("BENZENE_FUZZ_SEED", DEFAULT_SEED);
+    let runs = env_or("BENZENE_FUZZ_RUNS", DEFAULT_RUNS);
+    let mut rng = Rng::new(seed ^ target.len() as u64);
+    for run in 0..runs {
+        let input = rng.input();
+        let outcome = panic::catch_unwind(panic::AssertUnwindSafe(|| check(&mut rng, &input)));
+        if let Err(e) = outcome {
+            eprintln!("{target}: seed {seed:#x} run {run} failed on {input:?}");
+            panic::resume_unwind(e);
+        }
+    }
+}
+
+/// The integers a stats line lists, up to the first that isn't one.
+fn leading_ints(fields: &str) -> Vec<i32> {
+    fields
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map_while(|s| s.parse().ok())
+        .collect()
+}
+
+fn health_fields(s: &HealthStats) -> [i32; FIELD_COUNT] {
+    [
+        s.healthIndex,
+        s.capacityFcc,
+        s.capacityRaw,
+        s.capacityDesign,
+        s.impedanceRaw,
+        s.impedanceAvg,
+        s.impedanceDesign,
+        s.cycleCount,
+        s.cycleCountDesign,
+        s.tempBucket,
+    ]
+}
+
+#[test]
+fn property_values_are_checked_or_rejected() {
+    let features = Feature::enum_values();
+    fuzz("property", |rng, value| {
+        let feature = *rng.pick(&features);
+        let prop = rng.below(property::MAX_PROPERTY_ID as usize + 2) as i32;
+        let Ok(out) = validate::property(feature, prop, value) else {
+            return;
+        };
+        assert!(out.len() <= validate::MAX_PROPERTY_VALUE_LEN, "{value:?}");
+        assert!(
+            !out.chars().any(|c| c.is_control() && !c.is_ascii_whitespace()),
+            "{value:?} -> {out:?}"
+        );
+        // What a validator returns is the form it accepts as is.
+        assert_eq!(validate::property(feature, prop, &out).as_deref(), Ok(out.as_str()));
+    });
+}
+
+#[test]
+fn validated_properties_accept_their_own_output() {
+    for v in validate::PROPERTY_VALIDATORS {
+        fuzz("validator", |_, value| {
+            if let Ok(out) = (v.validate)(value) {
+                assert_eq!((v.validate)(&out).as_deref(), Ok(out.as_str()), "{value:?}");
+            }
+        });
+    }
+}
+
+#[test]
+fn health_stats_match_the_line_they_came_from() {
+    fuzz("health_stats", |rng, content| {
+        let algo = rng.below(5) as i32 - 1;
+        let line = content.lines().find_map(|l| {
+            let (a, rest) = l.split_once(':')?;
+            (a.trim().parse::<i32>().ok() == Some(algo)).then_some(rest)
+        });
+        let ints = line.map(leading_ints).unwrap_or_default();
+        match health_stats::parse(content, algo) {
+            Ok(stats) => {
+                assert!(ints.len() >= FIELD_COUNT, "{content:?} gave {stats:?}");
+                assert_eq!(health_fields(&stats)[..], ints[..FIELD_COUNT], "{content:?}");
+            }
+            Err(ParseError::PartialData { found, expected, stats }) => {
+                assert_eq!(expected, FIELD_COUNT);
+                assert!((1..FIELD_COUNT).contains(&found), "{content:?}");
+                assert_eq!(health_fields(&stats)[..found], ints[..found], "{content:?}");
+                assert!(health_fields(&stats)[found..].iter().all(|&v| v == 0));
+            }
+            Err(ParseError::NoEntry) => assert!(ints.is_empty(), "{content:?}"),
+            Err(ParseError::Read(e)) => panic!("parse read a node: {e}"),
+        }
+    });
+}
+
+#[test]
+fn csi_stats_only_come_from_well_formed_tokens() {
+    fuzz("csi", |_, content| {
+        let Ok(stats) = csi::parse(content) else {
+            return;
+        };
+        let pairs: Vec<(&str, i32)> = content
+            .split_whitespace()
+            .map(|t| {
+                let (k, v) = t.split_once(['=', ':']).expect("accepted a malformed token");
+                (k, v.parse().expect("accepted a bad value"))
+            })
+            .collect();
+        let last = |key| pairs.iter().rev().find(|(k, _)| *k == key).map(|&(_, v)| v);
+        assert_eq!(last("status"), Some(stats.status), "{content:?}");
+        assert_eq!(last("type"), Some(stats.kind), "{content:?}");
+    });
+}
+
+#[test]
+fn charge_stats_sessions_come_from_headers() {
+    fuzz("charge_stats", |_, content| {
+        if let Ok(sessions) = charge_stats::parse(content) {
+            let headers = content.lines().filter(|l| l.trim().starts_with("S:")).count();
+            assert!(sessions.len() <= headers, "{content:?}");
+        }
+    });
+}
+
+#[test]
+fn int_lists_round_trip() {
+    fuzz("ints", |_, s| {
+        if let Some(values) = sysfs::parse_ints(s) {
+            assert_eq!(sysfs::parse_ints(&sysfs::format_ints(&values)), Some(values), "{s:?}");
+        }
+        if let Ok(p) = AaccProfile::parse(s) {
+            assert_eq!(AaccProfile::parse(&p.to_string()), Ok(p), "{s:?}");
+        }
+    });
+}
+
+#[test]
+fn dd_settings_are_only_known_values() {
+    fuzz("dd_settings", |_, s| {
+        if let Some(setting) = DockDefendSetting::parse(s) {
+            assert_eq!(s.trim(), setting.as_str(), "{s:?}");
+        }
+    });
+    for setting in [DockDefendSetting::On, DockDefendSetting::Off, DockDefendSetting::Clear] {
+        assert_eq!(DockDefendSetting::parse(&format!("{}\n", setting.as_str())), Some(setting));
+    }
+}
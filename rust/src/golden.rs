@@ -0,0 +1,124 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Golden-file tests for the stats node parsers.
+//!
+//! `testdata/<node>/` holds node contents captured from each kernel branch,
+//! one `<name>.txt` per capture, and next to each a `<name>.expected` with
+//! what its parser returns: the structured result, or the error it must
+//! give. Bringing up a device with a new format means adding its capture and
+//! running the tests with `BENZENE_UPDATE_GOLDEN=1` to write the expectation,
+//! then checking that in after reading it.
+//!
+//! The tests fail on a capture without an expectation, an expectation without
+//! a capture, and a directory no parser reads.
+
+use std::{
+    env,
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{charge_stats, csi, health_stats};
+
+/// Health algorithms each health_index_stats capture is parsed for, so the
+/// expectation shows both the lines that are there and one that isn't.
+const HEALTH_ALGOS: [i32; 3] = [0, 1, 2];
+
+/// Runs a capture through its parser and shows the result.
+type Parser = fn(&str) -> String;
+
+/// The parser each testdata directory is run through.
+const PARSERS: [(&str, Parser); 3] = [
+    ("charge_stats", |c| show(&charge_stats::parse(c))),
+    ("csi_stats", |c| show(&csi::parse(c))),
+    ("health_index_stats", |c| {
+        HEALTH_ALGOS
+            .iter()
+            .map(|&algo| format!("algo {algo}: {}", show(&health_stats::parse(c, algo))))
+            .collect()
+    }),
+];
+
+fn show(result: &impl Debug) -> String {
+    format!("{result:#?}\n")
+}
+
+/// Installed next to the test binary on a device; the package root under
+/// cargo.
+fn testdata() -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join("testdata")))
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| PathBuf::from("testdata"))
+}
+
+fn files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("read {}: {e}", dir.display()))
+        .map(|entry| entry.expect("read testdata entry").path())
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+#[test]
+fn parsers_match_golden_files() {
+    let update = env::var_os("BENZENE_UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+    for dir in files(&testdata()) {
+        let name = dir.file_name().unwrap_or_default().to_string_lossy();
+        let Some(&(_, parse)) = PARSERS.iter().find(|(node, _)| *node == name) else {
+            failures.push(format!("{}: no parser reads this directory", dir.display()));
+            continue;
+        };
+        let files = files(&dir);
+        for file in &files {
+            let (capture, expected) = match file.extension().and_then(|e| e.to_str()) {
+                Some("txt") => (file.clone(), file.with_extension("expected")),
+                Some("expected") => {
+                    if !files.contains(&file.with_extension("txt")) {
+                        failures.push(format!("{}: no capture", file.display()));
+                    }
+                    continue;
+                }
+                _ => {
+                    let what = "neither a capture nor an expectation";
+                    failures.push(format!("{}: {what}", file.display()));
+                    continue;
+                }
+            };
+            let content = fs::read_to_string(&capture).expect("read capture");
+            let actual = parse(&content);
+            if update {
+                fs::write(&expected, &actual).expect("write expectation");
+                continue;
+            }
+            match fs::read_to_string(&expected) {
+                Ok(want) if want == actual => {}
+                Ok(want) => failures.push(format!(
+                    "{}: parser output differs\n--- expected\n{want}--- actual\n{actual}",
+                    capture.display()
+                )),
+                Err(_) => failures.push(format!("{}: no expectation", capture.display())),
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn every_parser_has_captures() {
+    for (node, _) in PARSERS {
+        let dir = testdata().join(node);
+        assert!(
+            files(&dir)
+                .iter()
+                .any(|f| f.extension().is_some_and(|e| e == "txt")),
+            "no captures in {}",
+            dir.display()
+        );
+    }
+}
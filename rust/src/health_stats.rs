@@ -0,0 +1,173 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Parser for the `health_index_stats` node.
+//!
+//! The node has one `algo: v0, v1, ...` line per health algorithm, with
+//! FIELD_COUNT values separated by commas or whitespace. Some kernels cut the
+//! line short; the values that are there are still kept, with the rest zero.
+
+use std::fmt;
+
+use log::warn;
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::HealthStats::HealthStats;
+
+use crate::sysfs;
+
+pub const FIELD_COUNT: usize = 10;
+
+#[derive(Debug)]
+pub enum ParseError {
+    Read(sysfs::Error),
+    /// No line for the algorithm, or one without any values.
+    NoEntry,
+    /// The line had fewer than `expected` values. `stats` holds the first
+    /// `found` and zero for the rest.
+    PartialData {
+        found: usize,
+        expected: usize,
+        stats: HealthStats,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(e) => write!(f, "{e}"),
+            Self::NoEntry => write!(f, "no entry"),
+            Self::PartialData {
+                found, expected, ..
+            } => write!(f, "{found} of {expected} fields"),
+        }
+    }
+}
+
+fn from_fields(algo: i32, v: [i32; FIELD_COUNT]) -> HealthStats {
+    HealthStats {
+        algo,
+        healthIndex: v[0],
+        capacityFcc: v[1],
+        capacityRaw: v[2],
+        capacityDesign: v[3],
+        impedanceRaw: v[4],
+        impedanceAvg: v[5],
+        impedanceDesign: v[6],
+        cycleCount: v[7],
+        cycleCountDesign: v[8],
+        tempBucket: v[9],
+    }
+}
+
+pub fn parse(content: &str, algo: i32) -> Result<HealthStats, ParseError> {
+    for line in content.lines() {
+        let Some((a, rest)) = line.split_once(':') else {
+            continue;
+        };
+        if a.trim().parse::<i32>().ok() != Some(algo) {
+            continue;
+        }
+        // A token that isn't a number ends the line: skipping it would shift
+        // every later value into the wrong field.
+        let values = rest
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .map_while(|s| s.parse().ok())
+            .collect::<Vec<i32>>();
+        let found = values.len().min(FIELD_COUNT);
+        if found == 0 {
+            return Err(ParseError::NoEntry);
+        }
+        let mut fields = [0; FIELD_COUNT];
+        fields[..found].copy_from_slice(&values[..found]);
+        let stats = from_fields(algo, fields);
+        if found < FIELD_COUNT {
+            warn!("Health stats for algo {algo} have {found} of {FIELD_COUNT} fields, rest zeroed");
+            return Err(ParseError::PartialData {
+                found,
+                expected: FIELD_COUNT,
+                stats,
+            });
+        }
+        return Ok(stats);
+    }
+    Err(ParseError::NoEntry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = "1: 98 4630 4688 4700 132 131 140 187 800 2\n\
+                           3: 91,4400,4688,4700,150,149,140,402,800,3\n\
+                           42: 80 4000\n";
+
+    #[test]
+    fn any_algo_id_is_looked_up() {
+        let custom = parse(CONTENT, 3).unwrap();
+        assert_eq!((custom.algo, custom.healthIndex, custom.cycleCount), (3, 91, 402));
+        assert_eq!(parse(CONTENT, 1).unwrap().capacityFcc, 4630);
+        let Err(ParseError::PartialData { found, stats, .. }) = parse(CONTENT, 42) else {
+            panic!("algo 42 has a short line");
+        };
+        assert_eq!((found, stats.algo, stats.capacityFcc, stats.tempBucket), (2, 42, 4000, 0));
+    }
+
+    #[test]
+    fn short_lines_keep_their_fields_and_zero_the_rest() {
+        let all = [98, 4630, 4688, 4700, 132, 131, 140, 187, 800, 2];
+        for found in [1, 5, 9] {
+            let values = all[..found].iter().map(i32::to_string).collect::<Vec<_>>().join(" ");
+            let Err(ParseError::PartialData {
+                found: got,
+                expected,
+                stats,
+            }) = parse(&format!("1: {values}\n"), 1)
+            else {
+                panic!("{found} fields should be partial");
+            };
+            assert_eq!((got, expected), (found, FIELD_COUNT));
+            let mut want = [0; FIELD_COUNT];
+            want[..found].copy_from_slice(&all[..found]);
+            let fields = [
+                stats.healthIndex,
+                stats.capacityFcc,
+                stats.capacityRaw,
+                stats.capacityDesign,
+                stats.impedanceRaw,
+                stats.impedanceAvg,
+                stats.impedanceDesign,
+                stats.cycleCount,
+                stats.cycleCountDesign,
+                stats.tempBucket,
+            ];
+            assert_eq!(fields, want, "{found}");
+        }
+    }
+
+    #[test]
+    fn extra_fields_are_ignored() {
+        let stats = parse("1: 1 2 3 4 5 6 7 8 9 10 11 12\n", 1).unwrap();
+        assert_eq!((stats.healthIndex, stats.tempBucket), (1, 10));
+    }
+
+    #[test]
+    fn values_stop_at_the_first_non_number() {
+        let Err(ParseError::PartialData { found, stats, .. }) =
+            parse("1: 98 4630 n/a 4700 132 131 140 187 800 2 11\n", 1)
+        else {
+            panic!("a bad value can't leave the later ones in the wrong fields");
+        };
+        assert_eq!((found, stats.capacityFcc, stats.capacityRaw), (2, 4630, 0));
+        assert!(matches!(parse("1: ? 98 4630\n", 1), Err(ParseError::NoEntry)));
+        let stats = parse("1: 1 2 3 4 5 6 7 8 9 10 (stale)\n", 1).unwrap();
+        assert_eq!(stats.tempBucket, 10);
+    }
+
+    #[test]
+    fn unknown_algos_have_no_entry() {
+        for algo in [0, 2, 4, -1] {
+            assert!(matches!(parse(CONTENT, algo), Err(ParseError::NoEntry)), "{algo}");
+        }
+        assert!(matches!(parse("5:\n", 5), Err(ParseError::NoEntry)));
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Daily health samples, checked for readings that move faster than a
+//! battery wears. A fall of more than `drop_per_week` points against the
+//! oldest sample from the last week points at a failing cell; a rise of more
+//! than `jump` since the previous sample usually means the fuel gauge was
+//! reset. Samples are persisted so the comparison survives restarts; alerts
+//! are kept for the life of the service.
+
+use std::{collections::VecDeque, fs, path::PathBuf, sync::Mutex};
+
+use log::{error, warn};
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
+    HealthAlert::HealthAlert, HealthAlertKind::HealthAlertKind, HealthMetric::HealthMetric,
+};
+
+use crate::{
+    lock::MutexExt,
+    metrics::{self, Event as Metric},
+    persist,
+};
+
+pub const HISTORY_FILE: &str = "health_history";
+pub const MAX_SAMPLES: usize = 60;
+pub const MAX_ALERTS: usize = 16;
+pub const SAMPLE_INTERVAL_MS: i64 = 24 * 60 * 60 * 1000;
+pub const WEEK_MS: i64 = 7 * SAMPLE_INTERVAL_MS;
+
+pub const DEFAULT_DROP_PER_WEEK: i32 = 5;
+pub const DEFAULT_JUMP: i32 = 10;
+
+/// Readings are -1 where the device has no node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub at_ms: i64,
+    pub index: i32,
+    pub capacity: i32,
+    pub impedance: i32,
+}
+
+impl Sample {
+    fn to_line(self) -> String {
+        format!(
+            "{} {} {} {}",
+            self.at_ms, self.index, self.capacity, self.impedance
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut it = line.split_whitespace().map(str::parse::<i64>);
+        let sample = Self {
+            at_ms: it.next()?.ok()?,
+            index: it.next()?.ok()?.try_into().ok()?,
+            capacity: it.next()?.ok()?.try_into().ok()?,
+            impedance: it.next()?.ok()?.try_into().ok()?,
+        };
+        it.next().is_none().then_some(sample)
+    }
+
+    fn metrics(self) -> [(HealthMetric, i32); 3] {
+        [
+            (HealthMetric::INDEX, self.index),
+            (HealthMetric::CAPACITY, self.capacity),
+            (HealthMetric::IMPEDANCE, self.impedance),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alert {
+    pub kind: HealthAlertKind,
+    pub metric: HealthMetric,
+    pub from: i32,
+    pub to: i32,
+    pub at_ms: i64,
+}
+
+impl From<Alert> for HealthAlert {
+    fn from(a: Alert) -> Self {
+        Self {
+            kind: a.kind,
+            metric: a.metric,
+            fromValue: a.from,
+            toValue: a.to,
+            timestampMs: a.at_ms,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    pub drop_per_week: i32,
+    pub jump: i32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            drop_per_week: DEFAULT_DROP_PER_WEEK,
+            jump: DEFAULT_JUMP,
+        }
+    }
+}
+
+/// Alerts for `latest` against `history`, oldest sample first.
+pub fn compare(history: &[Sample], latest: Sample, t: Thresholds) -> Vec<Alert> {
+    let week_ago = history.iter().find(|s| latest.at_ms - s.at_ms <= WEEK_MS);
+    let previous = history.last();
+    let mut alerts = Vec::new();
+    for (i, (metric, now)) in latest.metrics().into_iter().enumerate() {
+        if now < 0 {
+            continue;
+        }
+        let alert = |kind, from| Alert {
+            kind,
+            metric,
+            from,
+            to: now,
+            at_ms: latest.at_ms,
+        };
+        if let Some(from) = previous.map(|s| s.metrics()[i].1).filter(|v| *v >= 0) {
+            if now - from > t.jump {
+                alerts.push(alert(HealthAlertKind::FG_RESET, from));
+                continue;
+            }
+        }
+        if let Some(from) = week_ago.map(|s| s.metrics()[i].1).filter(|v| *v >= 0) {
+            if from - now > t.drop_per_week {
+                alerts.push(alert(HealthAlertKind::SUDDEN_DROP, from));
+            }
+        }
+    }
+    alerts
+}
+
+#[derive(Default)]
+struct Inner {
+    samples: VecDeque<Sample>,
+    alerts: VecDeque<Alert>,
+}
+
+pub struct HealthWatch {
+    path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+impl HealthWatch {
+    /// Loads the sample history from `path`, skipping lines that don't parse.
+    pub fn load(path: PathBuf) -> Self {
+        let mut inner = Inner::default();
+        if let Ok(content) = fs::read_to_string(&path) {
+            for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                match Sample::from_line(line) {
+                    Some(s) => inner.samples.push_back(s),
+                    None => warn!("Skipping bad health sample line: '{line}'"),
+                }
+            }
+            while inner.samples.len() > MAX_SAMPLES {
+                inner.samples.pop_front();
+            }
+        }
+        Self {
+            path,
+            inner: Mutex::new(inner),
+        }
+    }
+
+    /// Whether a day has passed since the last sample.
+    pub fn due(&self, now_ms: i64) -> bool {
+        match self.inner.lock_recover().samples.back() {
+            Some(s) => now_ms - s.at_ms >= SAMPLE_INTERVAL_MS,
+            None => true,
+        }
+    }
+
+    /// Checks `sample` against the history, then adds it.
+    pub fn record(&self, sample: Sample, t: Thresholds) {
+        let mut inner = self.inner.lock_recover();
+        let history = inner.samples.iter().copied().collect::<Vec<_>>();
+        for alert in compare(&history, sample, t) {
+            error!(
+                "Health {:?} {:?}: {} -> {}",
+                alert.metric, alert.kind, alert.from, alert.to
+            );
+            metrics::emit(Metric::HealthAlert {
+                kind: alert.kind,
+                metric: alert.metric,
+                from: alert.from,
+                to: alert.to,
+            });
+            inner.alerts.push_back(alert);
+            while inner.alerts.len() > MAX_ALERTS {
+                inner.alerts.pop_front();
+            }
+        }
+        inner.samples.push_back(sample);
+        while inner.samples.len() > MAX_SAMPLES {
+            inner.samples.pop_front();
+        }
+        let mut content = String::new();
+        for s in &inner.samples {
+            content.push_str(&s.to_line());
+            content.push('\n');
+        }
+        if let Err(e) = persist::write_atomic(&self.path, &content) {
+            warn!("Failed to persist health history: {e}");
+        }
+    }
+
+    /// Alerts raised since the service started, oldest first.
+    pub fn alerts(&self) -> Vec<Alert> {
+        self.inner.lock_recover().alerts.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = SAMPLE_INTERVAL_MS;
+
+    fn sample(day: i64, index: i32) -> Sample {
+        Sample {
+            at_ms: day * DAY,
+            index,
+            capacity: 4600,
+            impedance: -1,
+        }
+    }
+
+    /// One sample a day from `indexes`, with the last one checked against
+    /// the rest.
+    fn alerts_for(indexes: &[i32], t: Thresholds) -> Vec<Alert> {
+        let samples: Vec<_> = (0..).zip(indexes).map(|(d, i)| sample(d, *i)).collect();
+        let (latest, history) = samples.split_last().unwrap();
+        compare(history, *latest, t)
+    }
+
+    #[test]
+    fn gradual_decline_raises_nothing() {
+        // A point every two days is well under five a week.
+        let indexes: Vec<i32> = (0..30).map(|d| 100 - d / 2).collect();
+        assert!(alerts_for(&indexes, Thresholds::default()).is_empty());
+    }
+
+    #[test]
+    fn a_sudden_drop_is_measured_against_a_week_ago() {
+        let alerts = alerts_for(&[95, 95, 95, 95, 95, 95, 95, 95, 89], Thresholds::default());
+        assert_eq!(alerts.len(), 1);
+        let a = alerts[0];
+        assert_eq!((a.kind, a.metric), (HealthAlertKind::SUDDEN_DROP, HealthMetric::INDEX));
+        assert_eq!((a.from, a.to, a.at_ms), (95, 89, 8 * DAY));
+        // The same fall spread over more than a week is wear.
+        let slow: Vec<i32> = (0..16).map(|d| 95 - d * 6 / 15).collect();
+        assert!(alerts_for(&slow, Thresholds::default()).is_empty(), "{slow:?}");
+    }
+
+    #[test]
+    fn a_jump_up_is_a_fuel_gauge_reset() {
+        let alerts = alerts_for(&[80, 80, 95], Thresholds::default());
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, HealthAlertKind::FG_RESET);
+        assert_eq!((alerts[0].from, alerts[0].to), (80, 95));
+        assert!(alerts_for(&[80, 80, 90], Thresholds::default()).is_empty());
+    }
+
+    #[test]
+    fn thresholds_are_tunable() {
+        let strict = Thresholds {
+            drop_per_week: 1,
+            jump: 1,
+        };
+        let drop = alerts_for(&[95, 93], strict);
+        assert_eq!(drop[0].kind, HealthAlertKind::SUDDEN_DROP);
+        assert_eq!(alerts_for(&[93, 95], strict)[0].kind, HealthAlertKind::FG_RESET);
+        assert!(alerts_for(&[95, 93], Thresholds::default()).is_empty());
+    }
+
+    #[test]
+    fn missing_readings_are_skipped() {
+        let history = [sample(0, -1)];
+        let latest = Sample {
+            capacity: -1,
+            ..sample(1, 50)
+        };
+        assert!(compare(&history, latest, Thresholds::default()).is_empty());
+    }
+
+    #[test]
+    fn history_survives_a_restart_and_alerts_are_emitted() {
+        let _env = crate::testing::FakeSysfs::builder().install();
+        let path = persist::data_path(HISTORY_FILE);
+        let watch = HealthWatch::load(path.clone());
+        assert!(watch.due(0));
+        watch.record(sample(0, 95), Thresholds::default());
+        assert!(!watch.due(DAY - 1) && watch.due(DAY));
+
+        let watch = HealthWatch::load(path);
+        let events = metrics::capture(|| watch.record(sample(1, 85), Thresholds::default()));
+        assert_eq!(watch.alerts().len(), 1);
+        assert_eq!(
+            events,
+            [Metric::HealthAlert {
+                kind: HealthAlertKind::SUDDEN_DROP,
+                metric: HealthMetric::INDEX,
+                from: 95,
+                to: 85,
+            }]
+        );
+    }
+}
@@ -0,0 +1,298 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Opt-in sampling of battery temperature, SOC and status into a bounded ring.
+
+use std::{collections::VecDeque, path::PathBuf, sync::Mutex};
+
+use log::{info, warn};
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::TemperatureSample::TemperatureSample;
+
+use crate::{lock::MutexExt, persist, sysfs::paths};
+
+pub const SETTINGS_FILE: &str = "temp_sampling";
+pub const MAX_SAMPLES: usize = 288;
+pub const DEFAULT_INTERVAL_MIN: i32 = 5;
+
+// android.hardware.health BatteryStatus values
+const STATUS_UNKNOWN: i32 = 1;
+const STATUS_CHARGING: i32 = 2;
+const STATUS_DISCHARGING: i32 = 3;
+const STATUS_NOT_CHARGING: i32 = 4;
+const STATUS_FULL: i32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub timestamp_ms: i64,
+    pub temp_deci_c: i32,
+    pub soc: i32,
+    pub status: i32,
+}
+
+impl From<Sample> for TemperatureSample {
+    fn from(s: Sample) -> Self {
+        Self {
+            timestampMs: s.timestamp_ms,
+            tempDeciC: s.temp_deci_c,
+            soc: s.soc,
+            status: s.status,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    pub enabled: bool,
+    pub interval_min: i32,
+    pub pause_on_discharge: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_min: DEFAULT_INTERVAL_MIN,
+            pause_on_discharge: false,
+        }
+    }
+}
+
+impl Settings {
+    fn to_line(self) -> String {
+        format!(
+            "{} {} {}",
+            self.enabled as i32, self.interval_min, self.pause_on_discharge as i32
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let v = line
+            .split_whitespace()
+            .map(str::parse::<i32>)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        match v.as_slice() {
+            [enabled, interval, pause] => Some(Self {
+                enabled: *enabled != 0,
+                interval_min: *interval,
+                pause_on_discharge: *pause != 0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Reads temperature, SOC and status from the battery uevent in one go.
+fn read_sample(now_ms: i64) -> Option<Sample> {
+    let uevent = paths::BATTERY_UEVENT.read_string().ok()?;
+    let mut sample = Sample {
+        timestamp_ms: now_ms,
+        temp_deci_c: i32::MIN,
+        soc: -1,
+        status: STATUS_UNKNOWN,
+    };
+    for (key, value) in uevent.lines().filter_map(|l| l.split_once('=')) {
+        match key {
+            "POWER_SUPPLY_TEMP" => sample.temp_deci_c = value.parse().ok()?,
+            "POWER_SUPPLY_CAPACITY" => sample.soc = value.parse().ok()?,
+            "POWER_SUPPLY_STATUS" => {
+                sample.status = match value {
+                    "Charging" => STATUS_CHARGING,
+                    "Discharging" => STATUS_DISCHARGING,
+                    "Not charging" => STATUS_NOT_CHARGING,
+                    "Full" => STATUS_FULL,
+                    _ => STATUS_UNKNOWN,
+                }
+            }
+            _ => {}
+        }
+    }
+    (sample.temp_deci_c != i32::MIN).then_some(sample)
+}
+
+struct Inner {
+    settings: Settings,
+    samples: VecDeque<Sample>,
+    last_ms: Option<i64>,
+}
+
+pub struct TempHistory {
+    path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+impl TempHistory {
+    pub fn load(path: PathBuf) -> Self {
+        let settings = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| Settings::from_line(s.trim()))
+            .unwrap_or_default();
+        Self {
+            path,
+            inner: Mutex::new(Inner {
+                settings,
+                samples: VecDeque::new(),
+                last_ms: None,
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.inner.lock_recover().settings
+    }
+
+    pub fn configure(&self, settings: Settings) {
+        let mut inner = self.inner.lock_recover();
+        if !settings.enabled {
+            inner.samples.clear();
+        }
+        inner.settings = settings;
+        inner.last_ms = None;
+        if let Err(e) = persist::write_atomic(&self.path, &settings.to_line()) {
+            warn!("Failed to persist temperature sampling settings: {e}");
+        }
+        info!("Temperature sampling: {settings:?}");
+    }
+
+    /// Called from the monitor; takes a sample when enabled and one is due.
+    pub fn tick(&self, now_ms: i64) {
+        let settings = {
+            let inner = self.inner.lock_recover();
+            let due = match inner.last_ms {
+                Some(t) => now_ms - t >= i64::from(inner.settings.interval_min) * 60_000,
+                None => true,
+            };
+            if !inner.settings.enabled || !due {
+                return;
+            }
+            inner.settings
+        };
+        // Read outside the lock so binder callers never wait on sysfs.
+        let Some(sample) = read_sample(now_ms) else {
+            return;
+        };
+        let mut inner = self.inner.lock_recover();
+        inner.last_ms = Some(now_ms);
+        if settings.pause_on_discharge && sample.status == STATUS_DISCHARGING {
+            return;
+        }
+        inner.samples.push_back(sample);
+        while inner.samples.len() > MAX_SAMPLES {
+            inner.samples.pop_front();
+        }
+    }
+
+    /// All samples, oldest first.
+    pub fn samples(&self) -> Vec<Sample> {
+        self.inner.lock_recover().samples.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Env, FakeSysfs};
+
+    const MINUTE_MS: i64 = 60_000;
+
+    fn uevent(temp: i32, soc: i32, status: &str) -> String {
+        format!(
+            "POWER_SUPPLY_NAME=battery\nPOWER_SUPPLY_STATUS={status}\n\
+             POWER_SUPPLY_CAPACITY={soc}\nPOWER_SUPPLY_TEMP={temp}\n"
+        )
+    }
+
+    fn history() -> (Env, TempHistory) {
+        let env = FakeSysfs::builder()
+            .read_only(paths::BATTERY_UEVENT, uevent(250, 50, "Charging"))
+            .install();
+        (env, TempHistory::load(persist::data_path(SETTINGS_FILE)))
+    }
+
+    fn enabled(interval_min: i32, pause_on_discharge: bool) -> Settings {
+        Settings {
+            enabled: true,
+            interval_min,
+            pause_on_discharge,
+        }
+    }
+
+    #[test]
+    fn ring_keeps_the_newest_samples() {
+        let (_env, h) = history();
+        h.configure(enabled(1, false));
+        let extra = 5;
+        for i in 0..MAX_SAMPLES as i64 + extra {
+            h.tick(i * MINUTE_MS);
+        }
+        let samples = h.samples();
+        assert_eq!(samples.len(), MAX_SAMPLES);
+        let newest = MAX_SAMPLES as i64 + extra - 1;
+        assert_eq!(samples[MAX_SAMPLES - 1].timestamp_ms, newest * MINUTE_MS);
+        assert_eq!(
+            samples[0],
+            Sample {
+                timestamp_ms: extra * MINUTE_MS,
+                temp_deci_c: 250,
+                soc: 50,
+                status: STATUS_CHARGING,
+            }
+        );
+    }
+
+    #[test]
+    fn samples_only_when_enabled_and_due() {
+        let (_env, h) = history();
+        h.tick(0);
+        assert!(h.samples().is_empty());
+        h.configure(enabled(5, false));
+        for minute in [0, 1, 4, 5, 9, 10] {
+            h.tick(minute * MINUTE_MS);
+        }
+        let taken = h.samples().iter().map(|s| s.timestamp_ms / MINUTE_MS).collect::<Vec<_>>();
+        assert_eq!(taken, [0, 5, 10]);
+    }
+
+    #[test]
+    fn disabling_clears_and_settings_persist() {
+        let (_env, h) = history();
+        h.configure(enabled(2, true));
+        h.tick(0);
+        assert_eq!(h.samples().len(), 1);
+        let reloaded = TempHistory::load(persist::data_path(SETTINGS_FILE));
+        assert_eq!(reloaded.settings(), enabled(2, true));
+        assert!(reloaded.samples().is_empty());
+
+        h.configure(Settings::default());
+        assert!(h.samples().is_empty());
+        h.tick(MINUTE_MS);
+        assert!(h.samples().is_empty());
+        let reloaded = TempHistory::load(persist::data_path(SETTINGS_FILE));
+        assert_eq!(reloaded.settings(), Settings::default());
+    }
+
+    #[test]
+    fn discharge_is_skipped_when_paused() {
+        let (env, h) = history();
+        env.set(paths::BATTERY_UEVENT, uevent(310, 40, "Discharging"));
+        h.configure(enabled(1, true));
+        h.tick(0);
+        assert!(h.samples().is_empty());
+        h.configure(enabled(1, false));
+        h.tick(0);
+        assert_eq!(h.samples()[0].status, STATUS_DISCHARGING);
+    }
+
+    #[test]
+    fn unreadable_uevents_take_no_sample() {
+        let (env, h) = history();
+        h.configure(enabled(1, false));
+        env.set(paths::BATTERY_UEVENT, "POWER_SUPPLY_CAPACITY=50\n");
+        h.tick(0);
+        env.set(paths::BATTERY_UEVENT, uevent(250, 50, "Full").replace("=50", "=fifty"));
+        h.tick(0);
+        assert!(h.samples().is_empty());
+        assert_eq!(Settings::from_line("1 5"), None);
+        assert_eq!(Settings::from_line(&enabled(7, true).to_line()), Some(enabled(7, true)));
+    }
+}
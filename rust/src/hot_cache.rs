@@ -0,0 +1,114 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Short-lived cache for the charging getters SystemUI and the framework call
+//! constantly. Entries are dropped on every sysfs write the HAL makes and on
+//! every power_supply uevent, which the monitor delivers for plugging in,
+//! unplugging and the driver's own transitions. The TTL bounds how stale an
+//! entry gets when a change comes without either.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{config, lock::MutexExt};
+
+/// Entry lifetime, `hot_cache_ttl_ms` in the config file.
+fn ttl() -> Duration {
+    Duration::from_millis(config::get().hot_cache_ttl_ms)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+    Status,
+    Type,
+    Speed,
+}
+
+struct HotCache {
+    entries: Mutex<BTreeMap<Key, (i32, Instant)>>,
+    // Bumped by every invalidate, so a read that raced a write isn't cached.
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+static CACHE: HotCache = HotCache {
+    entries: Mutex::new(BTreeMap::new()),
+    generation: AtomicU64::new(0),
+    hits: AtomicU64::new(0),
+    misses: AtomicU64::new(0),
+};
+
+/// The cached value for `key`, or the result of `read` when there is none
+/// younger than the TTL.
+pub fn get(key: Key, read: impl FnOnce() -> i32) -> i32 {
+    if let Some(&(value, at)) = CACHE.entries.lock_recover().get(&key) {
+        if at.elapsed() < ttl() {
+            CACHE.hits.fetch_add(1, Ordering::Relaxed);
+            return value;
+        }
+    }
+    CACHE.misses.fetch_add(1, Ordering::Relaxed);
+    let generation = CACHE.generation.load(Ordering::Acquire);
+    let value = read();
+    let mut entries = CACHE.entries.lock_recover();
+    if CACHE.generation.load(Ordering::Acquire) == generation {
+        entries.insert(key, (value, Instant::now()));
+    }
+    value
+}
+
+pub fn invalidate() {
+    let mut entries = CACHE.entries.lock_recover();
+    CACHE.generation.fetch_add(1, Ordering::Release);
+    entries.clear();
+}
+
+/// Hit and miss counts since start.
+pub fn stats() -> (u64, u64) {
+    (
+        CACHE.hits.load(Ordering::Relaxed),
+        CACHE.misses.load(Ordering::Relaxed),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::testing::FakeSysfs;
+
+    #[test]
+    fn entries_expire_after_the_ttl() {
+        let _env = FakeSysfs::builder().install();
+        assert_eq!(get(Key::Speed, || 1), 1);
+        assert_eq!(get(Key::Speed, || 2), 1);
+        assert_eq!(get(Key::Type, || 3), 3);
+        thread::sleep(ttl());
+        assert_eq!(get(Key::Speed, || 2), 2);
+    }
+
+    #[test]
+    fn invalidate_drops_every_entry_and_racing_reads() {
+        let _env = FakeSysfs::builder().install();
+        assert_eq!(get(Key::Status, || 1), 1);
+        assert_eq!(get(Key::Type, || 1), 1);
+        invalidate();
+        assert_eq!(get(Key::Status, || 2), 2);
+        // A read that an invalidate overtakes is returned but not kept.
+        let racing = || {
+            invalidate();
+            2
+        };
+        assert_eq!(get(Key::Type, racing), 2);
+        assert_eq!(get(Key::Type, || 3), 3);
+        assert_eq!(get(Key::Type, || 4), 3);
+    }
+}
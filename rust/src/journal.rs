@@ -0,0 +1,51 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Undo log for multi-node writes. Each node's previous content is read before
+//! it is written, so a write that fails part way can put the earlier nodes
+//! back instead of leaving the kernel with half a configuration.
+
+use log::{info, warn};
+
+use crate::sysfs::{self, SysfsPath};
+
+#[derive(Default)]
+pub struct Journal {
+    entries: Vec<(&'static str, String)>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the node's current value, then writes and verifies `value`.
+    /// The node is recorded before the write, so one the kernel accepted but
+    /// rewrote is restored along with the rest. A node that can't be read is
+    /// never written, since it couldn't be restored.
+    pub fn write_int_verified(&mut self, path: SysfsPath, value: i32) -> sysfs::Result<()> {
+        let resolved = path.resolve().ok_or_else(|| sysfs::Error::NotFound {
+            path: path.primary.into(),
+        })?;
+        self.entries.push((resolved, sysfs::read_string(resolved)?));
+        path.write_int_verified(value)
+    }
+
+    /// Restores every recorded node, newest first. Returns the nodes that
+    /// could not be restored.
+    pub fn rollback(self) -> Vec<&'static str> {
+        let mut failed = Vec::new();
+        for (path, old) in self.entries.into_iter().rev() {
+            // The verified write cached its value, so `old` differs from it
+            // and goes through.
+            match sysfs::write_string(path, &old) {
+                Ok(()) => info!("Rolled back {path} to '{old}'"),
+                Err(e) => {
+                    warn!("Rollback of {path} failed: {e}");
+                    failed.push(path);
+                }
+            }
+        }
+        failed
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Running as a lazy service. With `ro.vendor.benzene_battery.lazy` set the
+//! service is registered with `register_lazy_service`: init starts the
+//! process for the first client, and once no client holds it the registrar
+//! unregisters it and exits the process. The next client gets a fresh
+//! instance, so anything that has to outlive one lives in the persisted files
+//! BatteryService::new loads.
+//!
+//! Some state only does anything while the process runs: a charge schedule,
+//! a full charge bypass or adaptive pause waiting to run out, per-client
+//! limits, a calibration being watched and temperature sampling. While any of
+//! those is pending a LazyServiceGuard keeps the service registered.
+//!
+//! The registrar's own exit skips the shutdown flush, so the service takes
+//! over the decision with an active services callback: once the last client
+//! is gone and nothing is pending it unregisters, flushes and exits itself.
+//! tryUnregister fails when a client binds in the meantime, in which case the
+//! service simply stays up.
+
+use std::sync::{Mutex, OnceLock};
+
+use binder::LazyServiceGuard;
+use log::{info, warn};
+
+use crate::{config, lock::MutexExt};
+
+static GUARD: Mutex<Option<LazyServiceGuard>> = Mutex::new(None);
+
+/// Fixed by the init service definition, so read once.
+pub fn is_enabled() -> bool {
+    static LAZY: OnceLock<bool> = OnceLock::new();
+    *LAZY.get_or_init(config::lazy)
+}
+
+/// Keeps the service registered while `pending` says there is work that
+/// would be lost with the process. Does nothing unless running lazily.
+pub fn hold(pending: impl FnOnce() -> bool) {
+    if !is_enabled() {
+        return;
+    }
+    let pending = pending();
+    let mut guard = GUARD.lock_recover();
+    match (pending, guard.is_some()) {
+        (true, false) => {
+            info!("Pending work, keeping the lazy service up");
+            *guard = Some(LazyServiceGuard::default());
+        }
+        (false, true) => {
+            info!("No pending work, the lazy service may exit");
+            *guard = None;
+        }
+        _ => {}
+    }
+}
+
+#[cfg(not(test))]
+fn is_held() -> bool {
+    GUARD.lock_recover().is_some()
+}
+
+/// The service manager calls behind the active services callback.
+trait Registrar {
+    fn try_unregister(&self) -> bool;
+    fn re_register(&self);
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Decision {
+    /// Leave it to the registrar.
+    Default,
+    /// Stay up; the registrar must not exit.
+    Stay,
+    /// Unregistered; flush and exit.
+    Exit,
+}
+
+/// Handles the registrar reporting that the service gained its first client
+/// or lost its last one.
+fn on_clients(has_clients: bool, held: impl Fn() -> bool, registrar: &impl Registrar) -> Decision {
+    if has_clients {
+        return Decision::Default;
+    }
+    if held() {
+        info!("No clients, staying up for pending work");
+        return Decision::Stay;
+    }
+    if !registrar.try_unregister() {
+        info!("No clients, but unregistering failed; staying up");
+        return Decision::Stay;
+    }
+    // A call finishing while we unregistered may have started pending work.
+    if held() {
+        warn!("Work became pending while unregistering, registering again");
+        registrar.re_register();
+        return Decision::Stay;
+    }
+    Decision::Exit
+}
+
+#[cfg(not(test))]
+mod ndk {
+    use std::ffi::c_void;
+
+    extern "C" {
+        pub fn AServiceManager_setActiveServicesCallback(
+            callback: extern "C" fn(bool, *mut c_void) -> bool,
+            context: *mut c_void,
+        );
+        pub fn AServiceManager_tryUnregister() -> bool;
+        pub fn AServiceManager_reRegister();
+    }
+}
+
+#[cfg(not(test))]
+struct Ndk;
+
+#[cfg(not(test))]
+impl Registrar for Ndk {
+    fn try_unregister(&self) -> bool {
+        // SAFETY: a plain call into libbinder_ndk.
+        unsafe { ndk::AServiceManager_tryUnregister() }
+    }
+
+    fn re_register(&self) {
+        // SAFETY: a plain call into libbinder_ndk.
+        unsafe { ndk::AServiceManager_reRegister() }
+    }
+}
+
+#[cfg(not(test))]
+extern "C" fn active_services_changed(has_clients: bool, _context: *mut std::ffi::c_void) -> bool {
+    match on_clients(has_clients, is_held, &Ndk) {
+        Decision::Default => false,
+        Decision::Stay => true,
+        Decision::Exit => {
+            info!("No clients and no pending work, exiting");
+            crate::shutdown::shutdown();
+            std::process::exit(0);
+        }
+    }
+}
+
+/// Takes over exiting from the registrar, see the module docs. Call once
+/// the service is registered lazily.
+#[cfg(not(test))]
+pub fn watch_clients() {
+    // SAFETY: the callback is a plain function and ignores its context.
+    unsafe {
+        ndk::AServiceManager_setActiveServicesCallback(
+            active_services_changed,
+            std::ptr::null_mut(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+
+    /// Records calls; `unregister` is what try_unregister returns, and
+    /// `on_unregister` runs inside it.
+    struct Fake<'a> {
+        unregister: bool,
+        on_unregister: Box<dyn Fn() + 'a>,
+        calls: RefCell<Vec<&'static str>>,
+    }
+
+    impl<'a> Fake<'a> {
+        fn new(unregister: bool, on_unregister: impl Fn() + 'a) -> Self {
+            Self {
+                unregister,
+                on_unregister: Box::new(on_unregister),
+                calls: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Registrar for Fake<'_> {
+        fn try_unregister(&self) -> bool {
+            self.calls.borrow_mut().push("try_unregister");
+            (self.on_unregister)();
+            self.unregister
+        }
+
+        fn re_register(&self) {
+            self.calls.borrow_mut().push("re_register");
+        }
+    }
+
+    #[test]
+    fn a_new_client_is_left_to_the_registrar() {
+        let fake = Fake::new(true, || {});
+        assert_eq!(on_clients(true, || false, &fake), Decision::Default);
+        assert!(fake.calls.borrow().is_empty());
+    }
+
+    #[test]
+    fn pending_work_keeps_the_service_registered() {
+        let fake = Fake::new(true, || {});
+        assert_eq!(on_clients(false, || true, &fake), Decision::Stay);
+        assert!(fake.calls.borrow().is_empty());
+    }
+
+    #[test]
+    fn the_last_client_leaving_unregisters_and_exits() {
+        let fake = Fake::new(true, || {});
+        assert_eq!(on_clients(false, || false, &fake), Decision::Exit);
+        assert_eq!(*fake.calls.borrow(), ["try_unregister"]);
+    }
+
+    #[test]
+    fn a_failed_unregister_stays_up() {
+        let fake = Fake::new(false, || {});
+        assert_eq!(on_clients(false, || false, &fake), Decision::Stay);
+        assert_eq!(*fake.calls.borrow(), ["try_unregister"]);
+    }
+
+    #[test]
+    fn work_started_while_unregistering_registers_again() {
+        let held = Cell::new(false);
+        let fake = Fake::new(true, || held.set(true));
+        assert_eq!(on_clients(false, || held.get(), &fake), Decision::Stay);
+        assert_eq!(*fake.calls.borrow(), ["try_unregister", "re_register"]);
+    }
+}
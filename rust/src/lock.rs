@@ -0,0 +1,48 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Poison-tolerant locking. A panic that doesn't abort, in a monitor task or
+//! with abort_on_panic off, must not take every later call down with it, so a
+//! poisoned lock is logged and its data reused; every guarded state here stays
+//! consistent between individual field writes.
+
+use std::sync::{Mutex, MutexGuard};
+
+use log::warn;
+
+pub trait MutexExt<T> {
+    fn lock_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> MutexExt<T> for Mutex<T> {
+    fn lock_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(|e| {
+            warn!("Recovering poisoned lock");
+            e.into_inner()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn a_poisoned_lock_is_recovered() {
+        let m = Arc::new(Mutex::new(1));
+        let held = m.clone();
+        let panicked = thread::spawn(move || {
+            let mut guard = held.lock_recover();
+            *guard = 2;
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(m.is_poisoned());
+        assert_eq!(*m.lock_recover(), 2);
+        *m.lock_recover() = 3;
+        assert_eq!(*m.lock_recover(), 3);
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Log verbosity. The level is read from PROP_LOG_LEVEL at startup and can be
+//! changed until the next restart with
+//! `dumpsys vendor.benzeneos.battery.IBattery/default --log-level <level>`.
+//! The logger itself is set up to pass everything, so the `log` crate's max
+//! level is the only filter.
+//!
+//! At debug and below the call trace keeps longer argument summaries.
+
+use std::{ffi::CStr, io, io::Write};
+
+use log::{info, LevelFilter};
+
+use crate::config;
+
+pub const DUMP_ARG: &str = "--log-level";
+
+/// `off`, `error`, `warn`, `info`, `debug`, `trace` or Android's `verbose`,
+/// in any case.
+pub fn parse(s: &str) -> Option<LevelFilter> {
+    match s.to_ascii_lowercase().as_str() {
+        "verbose" => Some(LevelFilter::Trace),
+        other => other.parse().ok(),
+    }
+}
+
+/// Applies the startup level; call once the logger is up.
+pub fn init() {
+    let raw = config::read_property(config::PROP_LOG_LEVEL, "info");
+    let level = parse(&raw).unwrap_or_else(|| {
+        log::warn!("Ignoring {}='{raw}'", config::PROP_LOG_LEVEL);
+        LevelFilter::Info
+    });
+    log::set_max_level(level);
+}
+
+pub fn is_verbose() -> bool {
+    log::max_level() >= LevelFilter::Debug
+}
+
+/// Handles `--log-level <level>` dump args. Returns false when `args` are
+/// something else, for the normal dump to go ahead.
+pub fn handle_dump_args(w: &mut dyn Write, args: &[&CStr]) -> io::Result<bool> {
+    let Some((first, rest)) = args.split_first() else {
+        return Ok(false);
+    };
+    if first.to_bytes() != DUMP_ARG.as_bytes() {
+        return Ok(false);
+    }
+    match rest.first().and_then(|a| a.to_str().ok()).and_then(parse) {
+        Some(level) => {
+            log::set_max_level(level);
+            info!("Log level set to {level} from dump");
+            writeln!(w, "Log level: {level}")?;
+        }
+        None => writeln!(
+            w,
+            "usage: {DUMP_ARG} off|error|warn|info|debug|verbose (now {})",
+            log::max_level()
+        )?,
+    }
+    Ok(true)
+}
+
+/// Serializes tests that change the global max level or depend on it.
+#[cfg(test)]
+pub static TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock::MutexExt;
+
+    fn dump(args: &[&CStr]) -> (bool, String) {
+        let mut out = Vec::new();
+        let handled = handle_dump_args(&mut out, args).unwrap();
+        (handled, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn levels_parse_in_any_case() {
+        let cases = [
+            ("off", Some(LevelFilter::Off)),
+            ("ERROR", Some(LevelFilter::Error)),
+            ("Warn", Some(LevelFilter::Warn)),
+            ("info", Some(LevelFilter::Info)),
+            ("debug", Some(LevelFilter::Debug)),
+            ("trace", Some(LevelFilter::Trace)),
+            ("verbose", Some(LevelFilter::Trace)),
+            ("VERBOSE", Some(LevelFilter::Trace)),
+            ("", None),
+            ("loud", None),
+            ("3", None),
+        ];
+        for (s, want) in cases {
+            assert_eq!(parse(s), want, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn the_startup_level_comes_from_the_property() {
+        let _lock = TEST_LOCK.lock_recover();
+        config::set_property(config::PROP_LOG_LEVEL, "debug");
+        init();
+        assert_eq!(log::max_level(), LevelFilter::Debug);
+        assert!(is_verbose());
+        // A bad value falls back to info.
+        config::set_property(config::PROP_LOG_LEVEL, "chatty");
+        init();
+        assert_eq!(log::max_level(), LevelFilter::Info);
+        assert!(!is_verbose());
+        config::clear_properties();
+    }
+
+    #[test]
+    fn dump_args_set_the_level() {
+        let _lock = TEST_LOCK.lock_recover();
+        log::set_max_level(LevelFilter::Info);
+        let (handled, out) = dump(&[c"--log-level", c"verbose"]);
+        assert!(handled);
+        assert_eq!(out, "Log level: TRACE\n");
+        assert_eq!(log::max_level(), LevelFilter::Trace);
+
+        // A missing or bad level prints usage and keeps the current one.
+        for args in [&[c"--log-level"][..], &[c"--log-level", c"loud"]] {
+            let (handled, out) = dump(args);
+            assert!(handled);
+            assert!(out.starts_with("usage: --log-level"), "{out}");
+            assert_eq!(log::max_level(), LevelFilter::Trace);
+        }
+        log::set_max_level(LevelFilter::Info);
+    }
+
+    #[test]
+    fn other_dump_args_are_left_alone() {
+        for args in [&[][..], &[c"--clear-degraded"], &[c"log-level", c"debug"]] {
+            assert_eq!(dump(args), (false, String::new()));
+        }
+    }
+}
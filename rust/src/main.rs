@@ -3,27 +3,100 @@
 
 //! Benzene Battery HAL service.
 
+// A bench build keeps only what src/bench.rs reaches.
+#![cfg_attr(benzene_bench, allow(dead_code, unused_imports))]
+
+mod aacc;
+mod aacr;
+mod aafv;
+mod adaptive_pause;
+mod arbitration;
+mod audit;
+mod backoff;
+#[cfg(benzene_bench)]
+mod bench;
+mod call_counter;
+mod charge_stats;
+mod config;
+mod crash;
+mod csi;
+mod deadline;
+mod defender_stash;
+mod degraded;
+mod derate;
+mod enums;
+mod error;
+mod events;
+mod features;
+mod full_charge;
+#[cfg(test)]
+mod fuzz;
+#[cfg(test)]
+mod golden;
+mod health_stats;
+mod health_watch;
+mod history;
+mod hot_cache;
+mod journal;
+mod lazy;
+mod lock;
+mod log_level;
+mod main_limits;
+mod metrics;
+mod missing;
+mod monitor;
+mod permission;
+mod persist;
+mod policy;
+mod schedule;
+mod selftest;
 mod service;
+mod session;
+mod shutdown;
+mod startup;
 mod sysfs;
+#[cfg(test)]
+mod testing;
+mod thread_pool;
+mod trace;
+mod validate;
+mod worker;
 
 use log::{error, info};
 
+#[cfg(benzene_bench)]
+criterion::criterion_main!(bench::benches);
+
+#[cfg(not(benzene_bench))]
 fn main() {
     android_logger::init_once(
         android_logger::Config::default()
             .with_tag("benzene_battery")
-            .with_max_level(log::LevelFilter::Info),
+            .with_max_level(log::LevelFilter::Trace),
     );
+    log_level::init();
 
+    crash::install();
     info!("Starting Benzene Battery HAL");
+    // Before any thread starts, so they all inherit the blocked mask.
+    if let Err(e) = shutdown::block_signals() {
+        error!("Failed to block shutdown signals: {e}");
+    }
+    main_limits::apply();
+    sysfs::paths::log_all_resolved(log::Level::Info);
 
-    binder::ProcessState::set_thread_pool_max_thread_count(1);
+    let (threads, _) = config::binder_threads();
+    binder::ProcessState::set_thread_pool_max_thread_count(threads as u32);
     binder::ProcessState::start_thread_pool();
 
     if let Err(e) = service::register() {
-        error!("Failed to register: {e:?}");
+        error!("Failed to register: {e}");
         std::process::exit(1);
     }
 
+    if let Err(e) = shutdown::spawn_handler() {
+        error!("Failed to start shutdown handler: {e}");
+    }
+
     binder::ProcessState::join_thread_pool();
 }
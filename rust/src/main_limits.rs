@@ -0,0 +1,25 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Resource limits for the service process, so an fd leak fails inside the
+//! HAL instead of pressuring the rest of the system.
+//!
+//! There is no address space limit: a 64-bit process reserves far more
+//! virtual memory than it uses (thread stacks, allocator arenas, binder's
+//! mapping), so any cap low enough to matter breaks thread and binder setup
+//! long before it catches a leak.
+
+use log::{debug, warn};
+use nix::sys::resource::{rlim_t, setrlimit, Resource};
+
+pub const OPEN_FILES_LIMIT: rlim_t = 64;
+
+/// Applies the limit. A failure (e.g. denied by SELinux) is logged and
+/// otherwise ignored, since this is only a safety net.
+pub fn apply() {
+    let (resource, limit) = (Resource::RLIMIT_NOFILE, OPEN_FILES_LIMIT);
+    match setrlimit(resource, limit, limit) {
+        Ok(()) => debug!("Set {resource:?} to {limit}"),
+        Err(e) => warn!("Failed to set {resource:?} to {limit}: {e}"),
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fleet metrics: a daily snapshot plus defender triggers, finished
+//! calibrations, firmware updates, health alerts and degraded starts. There
+//! is no statsd binding for vendor Rust in this tree, so each event is
+//! appended as one line to METRICS_FILE for a collector to pick up. `emit`
+//! only queues the event for a writer thread and drops it when the queue is
+//! full, so it never holds up a binder call. `config::PROP_METRICS` turns the
+//! whole thing off.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    sync::{
+        mpsc::{self, SyncSender},
+        Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
+    CalibrationState::CalibrationState, DefenderType::DefenderType,
+    HealthAlertKind::HealthAlertKind, HealthMetric::HealthMetric,
+};
+
+use crate::{config, lock::MutexExt, persist};
+
+pub const METRICS_FILE: &str = "metrics";
+/// Past this the file is moved to `metrics.old` and a new one started.
+pub const MAX_FILE_BYTES: u64 = 256 * 1024;
+pub const QUEUE_DEPTH: usize = 64;
+pub const DAILY_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Daily {
+        health_index: i32,
+        cycle_count: i32,
+        /// Defender activations over the last DAILY_INTERVAL.
+        defender_activations: usize,
+        stop: i32,
+        start: i32,
+        clients: usize,
+        policy: i32,
+    },
+    DefenderTriggered {
+        defender: DefenderType,
+    },
+    CalibrationDone {
+        state: CalibrationState,
+    },
+    FirmwareUpdate {
+        ok: bool,
+    },
+    HealthAlert {
+        kind: HealthAlertKind,
+        metric: HealthMetric,
+        from: i32,
+        to: i32,
+    },
+    Degraded {
+        attempts: u32,
+    },
+}
+
+impl Event {
+    /// `<at_ms> <name> key=value ...`
+    pub fn to_line(self, at_ms: i64) -> String {
+        match self {
+            Self::Daily {
+                health_index,
+                cycle_count,
+                defender_activations,
+                stop,
+                start,
+                clients,
+                policy,
+            } => format!(
+                "{at_ms} daily health_index={health_index} cycle_count={cycle_count} \
+                 defender_activations={defender_activations} stop={stop} start={start} \
+                 clients={clients} policy={policy}"
+            ),
+            Self::DefenderTriggered { defender } => {
+                format!("{at_ms} defender_triggered defender={}", defender.0)
+            }
+            Self::CalibrationDone { state } => {
+                format!("{at_ms} calibration_done state={}", state.0)
+            }
+            Self::FirmwareUpdate { ok } => format!("{at_ms} firmware_update ok={}", ok as i32),
+            Self::HealthAlert {
+                kind,
+                metric,
+                from,
+                to,
+            } => format!(
+                "{at_ms} health_alert kind={} metric={} from={from} to={to}",
+                kind.0, metric.0
+            ),
+            Self::Degraded { attempts } => format!("{at_ms} degraded attempts={attempts}"),
+        }
+    }
+}
+
+fn append(line: &str) -> io::Result<()> {
+    let path = persist::data_path(METRICS_FILE);
+    if fs::metadata(&path).is_ok_and(|m| m.len() > MAX_FILE_BYTES) {
+        fs::rename(&path, path.with_extension("old"))?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{line}")
+}
+
+/// The writer's queue, started on first use. None when metrics are off or
+/// the thread couldn't start.
+fn queue() -> Option<&'static SyncSender<(i64, Event)>> {
+    static QUEUE: OnceLock<Option<SyncSender<(i64, Event)>>> = OnceLock::new();
+    QUEUE
+        .get_or_init(|| {
+            if !config::metrics_enabled() {
+                info!("Metrics disabled");
+                return None;
+            }
+            let (tx, rx) = mpsc::sync_channel::<(i64, Event)>(QUEUE_DEPTH);
+            let spawned = thread::Builder::new()
+                .name("battery_metrics".into())
+                .spawn(move || {
+                    for (at_ms, event) in rx {
+                        if let Err(e) = append(&event.to_line(at_ms)) {
+                            warn!("Failed to write metrics: {e}");
+                        }
+                    }
+                });
+            match spawned {
+                Ok(_) => Some(tx),
+                Err(e) => {
+                    warn!("Failed to start metrics thread: {e}");
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+#[cfg(test)]
+thread_local! {
+    static CAPTURED: std::cell::RefCell<Option<Vec<Event>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Collects the events emitted on this thread while `f` runs instead of
+/// queueing them.
+#[cfg(test)]
+pub fn capture(f: impl FnOnce()) -> Vec<Event> {
+    CAPTURED.set(Some(Vec::new()));
+    f();
+    CAPTURED.take().unwrap_or_default()
+}
+
+pub fn emit(event: Event) {
+    #[cfg(test)]
+    if CAPTURED.with_borrow_mut(|c| c.as_mut().map(|c| c.push(event))).is_some() {
+        return;
+    }
+    let Some(tx) = queue() else {
+        return;
+    };
+    if tx.try_send((persist::now_ms(), event)).is_err() {
+        debug!("Metrics queue full, dropped {event:?}");
+    }
+}
+
+/// True once per DAILY_INTERVAL, starting with the first call.
+pub fn daily_due() -> bool {
+    static LAST: Mutex<Option<Instant>> = Mutex::new(None);
+    let mut last = LAST.lock_recover();
+    let due = match *last {
+        Some(at) => at.elapsed() >= DAILY_INTERVAL,
+        None => true,
+    };
+    if due {
+        *last = Some(Instant::now());
+    }
+    due
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_serialize_as_one_line_each() {
+        let cases = [
+            (
+                Event::Daily {
+                    health_index: 92,
+                    cycle_count: 310,
+                    defender_activations: 2,
+                    stop: 80,
+                    start: 70,
+                    clients: 1,
+                    policy: 2,
+                },
+                "5 daily health_index=92 cycle_count=310 defender_activations=2 stop=80 \
+                 start=70 clients=1 policy=2",
+            ),
+            (
+                Event::DefenderTriggered {
+                    defender: DefenderType::TEMP,
+                },
+                "5 defender_triggered defender=1",
+            ),
+            (
+                Event::CalibrationDone {
+                    state: CalibrationState::FAILED,
+                },
+                "5 calibration_done state=4",
+            ),
+            (Event::FirmwareUpdate { ok: true }, "5 firmware_update ok=1"),
+            (
+                Event::HealthAlert {
+                    kind: HealthAlertKind(1),
+                    metric: HealthMetric(2),
+                    from: 90,
+                    to: 80,
+                },
+                "5 health_alert kind=1 metric=2 from=90 to=80",
+            ),
+            (Event::Degraded { attempts: 3 }, "5 degraded attempts=3"),
+        ];
+        for (event, line) in cases {
+            assert_eq!(event.to_line(5), line);
+        }
+    }
+
+    #[test]
+    fn emitted_events_reach_the_injected_sink() {
+        let events = capture(|| {
+            emit(Event::FirmwareUpdate { ok: false });
+            emit(Event::Degraded { attempts: 1 });
+        });
+        assert_eq!(
+            events,
+            [Event::FirmwareUpdate { ok: false }, Event::Degraded { attempts: 1 }]
+        );
+        assert!(capture(|| {}).is_empty());
+    }
+
+    #[test]
+    fn a_full_file_is_rotated() {
+        let _env = crate::testing::FakeSysfs::builder().install();
+        let path = persist::data_path(METRICS_FILE);
+        fs::write(&path, "x".repeat(MAX_FILE_BYTES as usize + 1)).unwrap();
+        append("1 degraded attempts=1").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1 degraded attempts=1\n");
+        assert!(path.with_extension("old").exists());
+        append("2 degraded attempts=2").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+}
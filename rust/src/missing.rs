@@ -0,0 +1,128 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Setters called on a device without the node they write. By default they
+//! still succeed, as they always have, but each miss is counted for dump and
+//! logged at most once per WARN_INTERVAL, so a toggle that does nothing shows
+//! up in a bugreport. With `config::PROP_STRICT_NODES` set they fail instead.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use crate::{config, lock::MutexExt};
+
+pub const WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Site {
+    ChargingPolicy,
+    Calibration,
+}
+
+impl Site {
+    pub const ALL: [Site; 2] = [Site::ChargingPolicy, Site::Calibration];
+
+    pub fn method(self) -> &'static str {
+        match self {
+            Self::ChargingPolicy => "setChargingPolicy",
+            Self::Calibration => "scheduleCalibration",
+        }
+    }
+}
+
+struct Counter {
+    misses: AtomicU64,
+    last_warn: Mutex<Option<Instant>>,
+}
+
+impl Counter {
+    const fn new() -> Self {
+        Self {
+            misses: AtomicU64::new(0),
+            last_warn: Mutex::new(None),
+        }
+    }
+}
+
+static COUNTERS: [Counter; Site::ALL.len()] = [Counter::new(), Counter::new()];
+
+#[cfg(test)]
+thread_local! {
+    static STRICT_OVERRIDE: std::cell::Cell<Option<bool>> = const { std::cell::Cell::new(None) };
+}
+
+/// Sets the mode for calls on this thread, so tests can cover both.
+#[cfg(test)]
+pub fn set_strict(strict: bool) {
+    STRICT_OVERRIDE.set(Some(strict));
+}
+
+/// Fixed at startup, like the rest of the configuration.
+pub fn is_strict() -> bool {
+    #[cfg(test)]
+    if let Some(strict) = STRICT_OVERRIDE.get() {
+        return strict;
+    }
+    static STRICT: OnceLock<bool> = OnceLock::new();
+    *STRICT.get_or_init(config::strict_nodes)
+}
+
+/// Counts a call at `site` that found `path` missing.
+pub fn record(site: Site, path: &str) {
+    let counter = &COUNTERS[site as usize];
+    let misses = counter.misses.fetch_add(1, Ordering::Relaxed) + 1;
+    let mut last = counter.last_warn.lock_recover();
+    let due = match *last {
+        Some(at) => at.elapsed() >= WARN_INTERVAL,
+        None => true,
+    };
+    if due {
+        *last = Some(Instant::now());
+        warn!("{}: {path} not found, call ignored ({misses} so far)", site.method());
+    }
+}
+
+/// Misses per site since start.
+pub fn counts() -> impl Iterator<Item = (Site, u64)> {
+    Site::ALL
+        .into_iter()
+        .map(|s| (s, COUNTERS[s as usize].misses.load(Ordering::Relaxed)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(site: Site) -> u64 {
+        counts().find(|(s, _)| s.method() == site.method()).unwrap().1
+    }
+
+    #[test]
+    fn misses_are_counted_per_site() {
+        // Tests that record hold the FakeSysfs lock, so the counts are stable.
+        let _env = crate::testing::FakeSysfs::builder().install();
+        let before = (count(Site::ChargingPolicy), count(Site::Calibration));
+        record(Site::ChargingPolicy, "/sys/x");
+        record(Site::ChargingPolicy, "/sys/x");
+        record(Site::Calibration, "/sys/y");
+        assert_eq!(count(Site::ChargingPolicy), before.0 + 2);
+        assert_eq!(count(Site::Calibration), before.1 + 1);
+    }
+
+    #[test]
+    fn the_override_is_per_thread() {
+        set_strict(true);
+        assert!(is_strict());
+        set_strict(false);
+        assert!(!is_strict());
+        set_strict(true);
+        assert!(!std::thread::spawn(is_strict).join().unwrap());
+    }
+}
@@ -0,0 +1,683 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Background watcher for kernel state the HAL has to notice on its own.
+//!
+//! Features register tasks with the service's Monitor instead of starting
+//! threads of their own. A task runs every interval, on each uevent from a
+//! subsystem, or when one of a set of paths is modified. One thread sleeps in
+//! poll() on a kernel uevent socket, an inotify instance holding the path
+//! watches and a wake pipe, with the time to the next periodic task as its
+//! timeout, and is joined on shutdown.
+//!
+//! inotify sees both a write from another process and a driver's
+//! sysfs_notify(), but not a value a driver changes silently, so a watch is
+//! no substitute for a periodic task where that matters.
+//!
+//! Tasks run one at a time outside the task list's lock, so a task may add
+//! tasks or dump the monitor. Each runs inside crash::contain(), so a task
+//! that panics is logged and counted, even with abort_on_panic on, and the
+//! others are unaffected.
+
+use std::{
+    collections::BTreeMap,
+    ffi::CString,
+    fs::File,
+    io::{self, ErrorKind, Read, Write},
+    mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::DefenderType::DefenderType;
+
+use crate::{
+    crash,
+    deadline::stage,
+    lock::MutexExt,
+    shutdown,
+    sysfs::{paths, SysfsPath},
+};
+
+pub const POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// The subsystem of the charger and battery uevents.
+pub const POWER_SUPPLY: &str = "power_supply";
+/// Longer uevents are cut; the kernel caps them at 2 KiB.
+const UEVENT_BUF_LEN: usize = 8192;
+
+/// Current activity of each defender that exposes its state.
+pub fn defender_states() -> [(DefenderType, bool); 3] {
+    [
+        (
+            DefenderType::TEMP,
+            paths::CHARGE_STAGE
+                .read_string()
+                .is_ok_and(|s| s == stage::BATTERY_DEFEND),
+        ),
+        (DefenderType::TRICKLE, paths::BD_TRICKLE_CNT.read_int_or(0) > 0),
+        (DefenderType::DOCK, paths::DD_STATE.read_int_or(0) == 1),
+    ]
+}
+
+/// The nodes `defender_states` reads, to watch for changes.
+pub const DEFENDER_NODES: [SysfsPath; 3] =
+    [paths::CHARGE_STAGE, paths::BD_TRICKLE_CNT, paths::DD_STATE];
+
+/// A kernel uevent: the `KEY=value` pairs after its `action@devpath` header.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Uevent {
+    pub vars: Vec<(String, String)>,
+}
+
+impl Uevent {
+    /// Parses one uevent datagram. udev's rebroadcasts, which start with a
+    /// `libudev` header instead, are not uevents from the kernel and give
+    /// None.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let mut fields = buf
+            .split(|&b| b == 0)
+            .filter(|f| !f.is_empty())
+            .map(|f| std::str::from_utf8(f).ok());
+        fields.next()??.split_once('@')?;
+        let vars = fields
+            .flatten()
+            .filter_map(|f| f.split_once('='))
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect();
+        Some(Self { vars })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.vars
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn subsystem(&self) -> Option<&str> {
+        self.get("SUBSYSTEM")
+    }
+}
+
+enum Trigger {
+    Every(Duration),
+    Uevent(&'static str),
+    Paths(Vec<&'static str>),
+}
+
+/// Why a task runs.
+enum Event<'a> {
+    Tick,
+    Uevent(&'a Uevent),
+    Changed(&'static str),
+}
+
+type Run = Box<dyn FnMut(&Event<'_>) + Send>;
+
+struct Task {
+    name: &'static str,
+    trigger: Trigger,
+    /// When an `Every` task runs next.
+    due: Mutex<Instant>,
+    panics: AtomicU32,
+    run: Mutex<Run>,
+}
+
+impl Task {
+    fn run(&self, event: &Event<'_>) {
+        let mut run = self.run.lock_recover();
+        if let Err(payload) = crash::contain(AssertUnwindSafe(|| run(event))) {
+            let panics = self.panics.fetch_add(1, Ordering::Relaxed) + 1;
+            error!(
+                "Monitor task '{}' panicked ({panics} so far): {}",
+                self.name,
+                crash::payload_str(payload.as_ref())
+            );
+        }
+    }
+}
+
+type Tasks = Arc<Mutex<Vec<Arc<Task>>>>;
+
+/// The tasks `pick` selects, taken out of the list so they run without its
+/// lock.
+fn select(tasks: &Mutex<Vec<Arc<Task>>>, pick: impl Fn(&Task) -> bool) -> Vec<Arc<Task>> {
+    tasks
+        .lock_recover()
+        .iter()
+        .filter(|t| pick(t))
+        .cloned()
+        .collect()
+}
+
+/// Runs the periodic tasks due at `now` and returns when the next one is due.
+fn run_due(tasks: &Mutex<Vec<Arc<Task>>>, now: Instant) -> Option<Instant> {
+    let periodic = select(tasks, |t| matches!(t.trigger, Trigger::Every(_)));
+    for task in &periodic {
+        let Trigger::Every(every) = task.trigger else {
+            continue;
+        };
+        let mut due = task.due.lock_recover();
+        if *due > now {
+            continue;
+        }
+        *due = now + every;
+        drop(due);
+        task.run(&Event::Tick);
+    }
+    periodic.iter().map(|t| *t.due.lock_recover()).min()
+}
+
+/// Every path a task watches.
+fn watched_paths(tasks: &Mutex<Vec<Arc<Task>>>) -> Vec<&'static str> {
+    let tasks = tasks.lock_recover();
+    let paths = tasks.iter().filter_map(|t| match &t.trigger {
+        Trigger::Paths(paths) => Some(paths),
+        _ => None,
+    });
+    paths.flatten().copied().collect()
+}
+
+/// Runs the tasks that `wakeup` is for.
+fn dispatch(tasks: &Mutex<Vec<Arc<Task>>>, wakeup: &Wakeup) {
+    let (event, matching) = match wakeup {
+        Wakeup::Uevent(uevent) => (
+            Event::Uevent(uevent),
+            select(
+                tasks,
+                |t| matches!(t.trigger, Trigger::Uevent(s) if uevent.subsystem() == Some(s)),
+            ),
+        ),
+        Wakeup::Changed(path) => (
+            Event::Changed(path),
+            select(
+                tasks,
+                |t| matches!(&t.trigger, Trigger::Paths(p) if p.contains(path)),
+            ),
+        ),
+    };
+    for task in matching {
+        task.run(&event);
+    }
+}
+
+/// Something the monitor thread woke up for, other than a timeout.
+#[derive(Debug, PartialEq, Eq)]
+enum Wakeup {
+    Uevent(Uevent),
+    Changed(&'static str),
+}
+
+fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+/// Takes ownership of a descriptor the caller just opened.
+fn owned(fd: libc::c_int) -> io::Result<File> {
+    // SAFETY: `fd` was just returned by the kernel and nothing else owns it.
+    check(fd).map(|fd| File::from(unsafe { OwnedFd::from_raw_fd(fd) }))
+}
+
+/// A socket receiving the uevents the kernel broadcasts.
+fn uevent_socket() -> io::Result<File> {
+    // SAFETY: plain syscalls; the address is a fully initialized sockaddr_nl.
+    unsafe {
+        let sock = owned(libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+            libc::NETLINK_KOBJECT_UEVENT,
+        ))?;
+        let mut addr: libc::sockaddr_nl = mem::zeroed();
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        // The kernel's multicast group, as opposed to udev's.
+        addr.nl_groups = 1;
+        check(libc::bind(
+            sock.as_raw_fd(),
+            (&addr as *const libc::sockaddr_nl).cast(),
+            mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        ))?;
+        Ok(sock)
+    }
+}
+
+/// Reads until the non-blocking `file` is drained, passing each read on.
+fn drain(file: &mut File, buf: &mut [u8], mut each: impl FnMut(&[u8])) {
+    loop {
+        match file.read(buf) {
+            Ok(0) => return,
+            Ok(n) => each(&buf[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => {
+                if e.kind() != ErrorKind::WouldBlock {
+                    warn!("Monitor read failed: {e}");
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// The watch descriptors of the inotify events in `buf`.
+fn inotify_wds(buf: &[u8]) -> Vec<libc::c_int> {
+    const HEADER: usize = mem::size_of::<libc::inotify_event>();
+    let mut wds = Vec::new();
+    let mut at = 0;
+    while let Some(header) = buf.get(at..at + HEADER) {
+        let field = |i: usize| [header[i], header[i + 1], header[i + 2], header[i + 3]];
+        let (wd, mask, len) = (
+            i32::from_ne_bytes(field(0)),
+            u32::from_ne_bytes(field(4)),
+            u32::from_ne_bytes(field(12)),
+        );
+        if mask & libc::IN_IGNORED == 0 {
+            wds.push(wd);
+        }
+        at += HEADER + len as usize;
+    }
+    wds
+}
+
+/// What the monitor thread sleeps on. Without the uevent socket or inotify
+/// the tasks needing them never run, and the rest go on.
+struct Sources {
+    uevents: Option<File>,
+    inotify: Option<File>,
+    wake: File,
+    /// Watched paths and their watch descriptors, None where adding the watch
+    /// failed so it isn't retried on every pass.
+    watches: BTreeMap<&'static str, Option<libc::c_int>>,
+}
+
+impl Sources {
+    /// Opens the sources and returns them with the write end of the wake pipe.
+    fn open() -> io::Result<(Self, File)> {
+        let mut fds = [0; 2];
+        // SAFETY: pipe2 fills both descriptors on success, which `owned`
+        // then takes over.
+        check(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) })?;
+        let (wake, waker) = (owned(fds[0])?, owned(fds[1])?);
+        let uevents = uevent_socket()
+            .inspect_err(|e| warn!("No uevent socket, uevent tasks won't run: {e}"))
+            .ok();
+        // SAFETY: a plain syscall.
+        let inotify = owned(unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) })
+            .inspect_err(|e| warn!("No inotify, path watches won't run: {e}"))
+            .ok();
+        let sources = Self {
+            uevents,
+            inotify,
+            wake,
+            watches: BTreeMap::new(),
+        };
+        Ok((sources, waker))
+    }
+
+    /// Adds a watch for every path a task watches that has none yet.
+    fn watch(&mut self, tasks: &Mutex<Vec<Arc<Task>>>) {
+        let Some(inotify) = &self.inotify else {
+            return;
+        };
+        let paths = watched_paths(tasks);
+        for path in paths {
+            if self.watches.contains_key(path) {
+                continue;
+            }
+            let added = CString::new(path)
+                .map_err(io::Error::from)
+                .and_then(|c| {
+                    // SAFETY: `c` is a valid C string for the call's duration.
+                    check(unsafe {
+                        libc::inotify_add_watch(inotify.as_raw_fd(), c.as_ptr(), libc::IN_MODIFY)
+                    })
+                })
+                .inspect_err(|e| warn!("Failed to watch {path}: {e}"));
+            self.watches.insert(path, added.ok());
+        }
+    }
+
+    /// Waits up to `timeout` for any source and returns what arrived. Empty
+    /// on a timeout, a signal or the wake pipe.
+    fn wait(&mut self, timeout: Duration) -> Vec<Wakeup> {
+        let mut fds = [
+            Some(&self.wake),
+            self.uevents.as_ref(),
+            self.inotify.as_ref(),
+        ]
+        .map(|f| libc::pollfd {
+            fd: f.map_or(-1, |f| f.as_raw_fd()),
+            events: libc::POLLIN,
+            revents: 0,
+        });
+        let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        // SAFETY: `fds` is valid for its length; poll ignores negative fds.
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+        let mut wakeups = Vec::new();
+        if ready <= 0 {
+            return wakeups;
+        }
+        let mut buf = vec![0; UEVENT_BUF_LEN];
+        let [wake, uevents, inotify] = fds.map(|p| p.revents != 0);
+        if wake {
+            drain(&mut self.wake, &mut buf, |_| {});
+        }
+        if let Some(sock) = self.uevents.as_mut().filter(|_| uevents) {
+            drain(sock, &mut buf, |d| {
+                wakeups.extend(Uevent::parse(d).map(Wakeup::Uevent))
+            });
+        }
+        if let Some(file) = self.inotify.as_mut().filter(|_| inotify) {
+            let mut wds = Vec::new();
+            drain(file, &mut buf, |d| wds.extend(inotify_wds(d)));
+            for (&path, _) in self
+                .watches
+                .iter()
+                .filter(|(_, wd)| wd.is_some_and(|w| wds.contains(&w)))
+            {
+                wakeups.push(Wakeup::Changed(path));
+            }
+        }
+        wakeups
+    }
+}
+
+#[derive(Default)]
+pub struct Monitor {
+    tasks: Tasks,
+    started: AtomicBool,
+    /// Wakes the thread, so a watch added after start is picked up.
+    waker: OnceLock<Mutex<File>>,
+}
+
+impl Monitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add(&self, name: &'static str, trigger: Trigger, run: Run) {
+        self.tasks.lock_recover().push(Arc::new(Task {
+            name,
+            trigger,
+            due: Mutex::new(Instant::now()),
+            panics: AtomicU32::new(0),
+            run: Mutex::new(run),
+        }));
+        self.wake();
+    }
+
+    fn wake(&self) {
+        if let Some(waker) = self.waker.get() {
+            let _ = waker.lock_recover().write(&[0]);
+        }
+    }
+
+    /// Runs `run` every `every`, first on the next pass. Tasks run in the
+    /// order they were added.
+    pub fn add_task(
+        &self,
+        name: &'static str,
+        every: Duration,
+        mut run: impl FnMut() + Send + 'static,
+    ) {
+        self.add(name, Trigger::Every(every), Box::new(move |_| run()));
+    }
+
+    /// Runs `run` on every kernel uevent from `subsystem`.
+    pub fn on_uevent(
+        &self,
+        name: &'static str,
+        subsystem: &'static str,
+        mut run: impl FnMut(&Uevent) + Send + 'static,
+    ) {
+        self.add(
+            name,
+            Trigger::Uevent(subsystem),
+            Box::new(move |event| {
+                if let Event::Uevent(uevent) = event {
+                    run(uevent);
+                }
+            }),
+        );
+    }
+
+    /// Runs `run` with the path whenever one of `paths` is modified. Nodes
+    /// that don't exist are left out.
+    pub fn watch(
+        &self,
+        name: &'static str,
+        paths: impl IntoIterator<Item = SysfsPath>,
+        mut run: impl FnMut(&'static str) + Send + 'static,
+    ) {
+        let paths = paths.into_iter().filter_map(|p| p.resolve()).collect();
+        self.add(
+            name,
+            Trigger::Paths(paths),
+            Box::new(move |event| {
+                if let Event::Changed(path) = event {
+                    run(path);
+                }
+            }),
+        );
+    }
+
+    /// Runs the tasks a raw uevent is for, as the monitor thread would.
+    #[cfg(test)]
+    pub fn deliver(&self, raw: &[u8]) {
+        let uevent = Uevent::parse(raw).expect("uevent");
+        dispatch(&self.tasks, &Wakeup::Uevent(uevent));
+    }
+
+    /// Whether the monitor thread is still alive; it holds the only other
+    /// reference to the task list.
+    #[cfg(test)]
+    pub fn is_running(&self) -> bool {
+        Arc::strong_count(&self.tasks) > 1
+    }
+
+    /// Starts the monitor thread once; later calls do nothing. The thread
+    /// stops and is joined on shutdown.
+    pub fn start(&self) -> io::Result<()> {
+        if self.started.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let (mut sources, waker) = match Sources::open() {
+            Ok(opened) => opened,
+            Err(e) => {
+                self.started.store(false, Ordering::Relaxed);
+                return Err(e);
+            }
+        };
+        let waker = self.waker.get_or_init(|| Mutex::new(waker));
+        let tasks = self.tasks.clone();
+        let spawned = thread::Builder::new()
+            .name("battery_monitor".into())
+            .spawn(move || loop {
+                let now = Instant::now();
+                let wait = run_due(&tasks, now)
+                    .map_or(POLL_INTERVAL, |due| due.saturating_duration_since(now))
+                    .min(POLL_INTERVAL);
+                sources.watch(&tasks);
+                let wakeups = sources.wait(wait);
+                if shutdown::is_stopping() {
+                    break;
+                }
+                for wakeup in &wakeups {
+                    dispatch(&tasks, wakeup);
+                }
+            });
+        match spawned {
+            Ok(handle) => {
+                // Out of poll() as soon as shutdown starts.
+                let waker = waker.lock_recover().try_clone()?;
+                shutdown::on_flush(move || {
+                    let _ = (&waker).write(&[0]);
+                });
+                shutdown::track(handle);
+                info!("Monitor started");
+                Ok(())
+            }
+            Err(e) => {
+                self.started.store(false, Ordering::Relaxed);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn dump(&self, w: &mut dyn io::Write) -> io::Result<()> {
+        let tasks = self.tasks.lock_recover().clone();
+        writeln!(w, "Monitor tasks ({}):", tasks.len())?;
+        for t in &tasks {
+            let panics = t.panics.load(Ordering::Relaxed);
+            match &t.trigger {
+                Trigger::Every(every) => write!(w, "  {} every {every:?}", t.name)?,
+                Trigger::Uevent(subsystem) => write!(w, "  {} on {subsystem} uevents", t.name)?,
+                Trigger::Paths(paths) => write!(w, "  {} on {} paths", t.name, paths.len())?,
+            }
+            writeln!(w, ", {panics} panics")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::FakeSysfs;
+
+    /// A monitor whose tasks push their name to the returned log as they run.
+    fn logged() -> (Monitor, Arc<Mutex<Vec<&'static str>>>) {
+        (Monitor::new(), Arc::new(Mutex::new(Vec::new())))
+    }
+
+    fn logger(
+        log: &Arc<Mutex<Vec<&'static str>>>,
+        name: &'static str,
+    ) -> impl FnMut() + Send + 'static {
+        let log = log.clone();
+        move || log.lock_recover().push(name)
+    }
+
+    #[test]
+    fn periodic_tasks_follow_the_clock() {
+        let (m, log) = logged();
+        m.add_task("fast", Duration::from_secs(10), logger(&log, "fast"));
+        m.add_task("slow", Duration::from_secs(60), logger(&log, "slow"));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(run_due(&m.tasks, at(0)), Some(at(10)));
+        assert_eq!(*log.lock_recover(), ["fast", "slow"]);
+        assert_eq!(run_due(&m.tasks, at(5)), Some(at(10)));
+        assert_eq!(log.lock_recover().len(), 2);
+        assert_eq!(run_due(&m.tasks, at(10)), Some(at(20)));
+        assert_eq!(run_due(&m.tasks, at(61)), Some(at(71)));
+        assert_eq!(
+            *log.lock_recover(),
+            ["fast", "slow", "fast", "fast", "slow"]
+        );
+    }
+
+    #[test]
+    fn a_panicking_task_spares_the_others() {
+        let (m, log) = logged();
+        m.add_task("boom", Duration::from_secs(1), || panic!("task failed"));
+        m.add_task("after", Duration::from_secs(1), logger(&log, "after"));
+        let start = Instant::now();
+        run_due(&m.tasks, start);
+        run_due(&m.tasks, start + Duration::from_secs(1));
+        assert_eq!(*log.lock_recover(), ["after", "after"]);
+        assert_eq!(m.tasks.lock_recover()[0].panics.load(Ordering::Relaxed), 2);
+        let mut dump = Vec::new();
+        m.dump(&mut dump).unwrap();
+        assert!(String::from_utf8(dump)
+            .unwrap()
+            .contains("boom every 1s, 2 panics"));
+    }
+
+    #[test]
+    fn tasks_run_outside_the_list_lock() {
+        let m = Arc::new(Monitor::new());
+        let inner = m.clone();
+        m.add_task("adder", Duration::from_secs(1), move || {
+            inner.add_task("added", Duration::from_secs(1), || {});
+            inner.dump(&mut io::sink()).unwrap();
+        });
+        run_due(&m.tasks, Instant::now());
+        assert_eq!(m.tasks.lock_recover().len(), 2);
+    }
+
+    #[test]
+    fn uevents_reach_their_subsystem() {
+        let (m, log) = logged();
+        let mut battery = logger(&log, "power_supply");
+        m.on_uevent("battery", POWER_SUPPLY, move |u| {
+            assert_eq!(u.get("POWER_SUPPLY_NAME"), Some("battery"));
+            battery();
+        });
+        m.on_uevent("usb", "usb", |_| panic!("not a usb uevent"));
+        let raw = b"change@/devices/platform/google,battery/power_supply/battery\0\
+                    ACTION=change\0SUBSYSTEM=power_supply\0POWER_SUPPLY_NAME=battery\0";
+        let uevent = Uevent::parse(raw).unwrap();
+        assert_eq!(uevent.subsystem(), Some(POWER_SUPPLY));
+        dispatch(&m.tasks, &Wakeup::Uevent(uevent));
+        assert_eq!(*log.lock_recover(), ["power_supply"]);
+        assert_eq!(m.tasks.lock_recover()[1].panics.load(Ordering::Relaxed), 0);
+        assert_eq!(
+            Uevent::parse(b"libudev\0\xfe\xed\xca\xfeSUBSYSTEM=power_supply\0"),
+            None
+        );
+    }
+
+    #[test]
+    fn watches_skip_missing_nodes() {
+        let _env = FakeSysfs::builder().node(paths::DD_STATE, 0).install();
+        let (m, log) = logged();
+        let mut changed = logger(&log, "changed");
+        m.watch("defenders", DEFENDER_NODES, move |path| {
+            assert_eq!(path, paths::DD_STATE.primary);
+            changed();
+        });
+        let watched = watched_paths(&m.tasks);
+        assert_eq!(watched, [paths::DD_STATE.primary]);
+        dispatch(&m.tasks, &Wakeup::Changed(paths::DD_STATE.primary));
+        dispatch(&m.tasks, &Wakeup::Changed(paths::BD_TRICKLE_CNT.primary));
+        assert_eq!(*log.lock_recover(), ["changed"]);
+    }
+
+    #[test]
+    fn inotify_reports_a_modified_path() {
+        let dir = crate::testing::data_dir();
+        let path: &'static str = Box::leak(
+            dir.join("monitor_watch")
+                .to_string_lossy()
+                .into_owned()
+                .into_boxed_str(),
+        );
+        fs::write(path, "0").unwrap();
+        let m = Monitor::new();
+        m.add("file", Trigger::Paths(vec![path]), Box::new(|_| {}));
+        let (mut sources, mut waker) = Sources::open().unwrap();
+        sources.watch(&m.tasks);
+        assert!(sources.wait(Duration::ZERO).is_empty());
+        fs::write(path, "1").unwrap();
+        assert_eq!(
+            sources.wait(Duration::from_secs(5)),
+            [Wakeup::Changed(path)]
+        );
+        waker.write_all(&[0]).unwrap();
+        assert!(sources.wait(Duration::from_secs(5)).is_empty());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Who may call the mutating IBattery methods. The vendor binder is reachable
+//! from more than the framework, so only system and root may change charging
+//! state. On debuggable builds DEBUG_ALLOWLIST is let through as well, for
+//! `adb shell` testing.
+//!
+//! TracedBattery checks every call with `enforce` before it reaches the
+//! service, so a new mutator is covered without doing anything.
+
+use std::sync::OnceLock;
+
+use binder::{ExceptionCode, Status, ThreadState};
+use log::warn;
+use rustutils::system_properties;
+
+pub const AID_ROOT: u32 = 0;
+pub const AID_SYSTEM: u32 = 1000;
+pub const AID_SHELL: u32 = 2000;
+
+/// Always allowed.
+pub const ALLOWED: &[u32] = &[AID_ROOT, AID_SYSTEM];
+/// Allowed only when `ro.debuggable` is set.
+pub const DEBUG_ALLOWLIST: &[u32] = &[AID_SHELL];
+
+/// Whether `method` only reads: the getters, open to every caller.
+pub fn is_getter(method: &str) -> bool {
+    method.starts_with("get") || method.starts_with("is")
+}
+
+pub fn is_allowed(uid: u32, debuggable: bool) -> bool {
+    ALLOWED.contains(&uid) || (debuggable && DEBUG_ALLOWLIST.contains(&uid))
+}
+
+/// Fixed for the life of the build, so read once.
+pub fn is_debuggable() -> bool {
+    static DEBUGGABLE: OnceLock<bool> = OnceLock::new();
+    *DEBUGGABLE.get_or_init(|| {
+        system_properties::read_bool("ro.debuggable", false).unwrap_or_else(|e| {
+            warn!("Failed to read ro.debuggable: {e}");
+            false
+        })
+    })
+}
+
+pub fn calling_uid() -> u32 {
+    ThreadState::get_calling_uid()
+}
+
+/// Fails with SECURITY unless `uid` may make the call.
+pub fn enforce(method: &str, uid: u32) -> binder::Result<()> {
+    if is_getter(method) || is_allowed(uid, is_debuggable()) {
+        return Ok(());
+    }
+    warn!("Rejected {method} from uid {uid}");
+    Err(Status::new_exception_str(
+        ExceptionCode::SECURITY,
+        Some(format!("uid {uid} may not change battery settings")),
+    ))
+}
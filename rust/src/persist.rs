@@ -0,0 +1,53 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! State kept under /data/vendor across restarts and reboots.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const DATA_DIR: &str = "/data/vendor/benzene_battery";
+
+pub fn data_path(name: &str) -> PathBuf {
+    data_dir().join(name)
+}
+
+#[cfg(not(test))]
+fn data_dir() -> &'static Path {
+    Path::new(DATA_DIR)
+}
+
+/// Test builds keep their state in the scratch directory of the running test.
+#[cfg(test)]
+use crate::testing::data_dir;
+
+/// Replaces `path` via a temporary file and rename, so a crash never leaves it truncated.
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+/// Wall-clock milliseconds since the epoch, for timestamps that outlive a boot.
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_never_touch_the_real_data_dir() {
+        let path = data_path("probe");
+        assert!(!path.starts_with(DATA_DIR), "{path:?}");
+        write_atomic(&path, "1").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1");
+        fs::remove_file(&path).unwrap();
+    }
+}
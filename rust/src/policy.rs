@@ -0,0 +1,85 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! The charging policy a client asked for. LONGLIFE and CUSTOM are the same
+//! `charging_policy` value to the kernel, so the node alone can't say which
+//! one the user picked; the request is persisted to tell them apart.
+
+use std::{fs, io, path::Path};
+
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::ChargingPolicy::ChargingPolicy;
+
+use crate::persist;
+
+pub const STATE_FILE: &str = "charging_policy";
+/// The policy setAdaptiveChargingEnabled turned adaptive charging on over.
+pub const OVERRIDE_FILE: &str = "adaptive_override";
+/// The policy setChargeToLimit replaced with LONGLIFE.
+pub const FORCED_FILE: &str = "charge_to_limit_policy";
+
+/// The `charging_policy` node value for `policy`.
+pub fn kernel_value(policy: ChargingPolicy) -> Option<i32> {
+    match policy {
+        ChargingPolicy::DEFAULT => Some(1),
+        ChargingPolicy::LONGLIFE | ChargingPolicy::CUSTOM => Some(2),
+        ChargingPolicy::ADAPTIVE => Some(3),
+        _ => None,
+    }
+}
+
+/// The policy to report for a node value, preferring the recorded request
+/// when it maps to that value.
+pub fn reported(node: i32, requested: Option<ChargingPolicy>) -> ChargingPolicy {
+    match requested {
+        Some(p) if kernel_value(p) == Some(node) => p,
+        _ => ChargingPolicy(node),
+    }
+}
+
+pub fn load(path: &Path) -> Option<ChargingPolicy> {
+    let policy = ChargingPolicy(fs::read_to_string(path).ok()?.trim().parse().ok()?);
+    kernel_value(policy).map(|_| policy)
+}
+
+pub fn save(path: &Path, policy: ChargingPolicy) -> io::Result<()> {
+    persist::write_atomic(path, &policy.0.to_string())
+}
+
+pub fn clear(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_request_is_reported_only_while_the_node_matches() {
+        let custom = Some(ChargingPolicy::CUSTOM);
+        assert_eq!(reported(2, custom), ChargingPolicy::CUSTOM);
+        assert_eq!(reported(2, None), ChargingPolicy::LONGLIFE);
+        assert_eq!(reported(1, custom), ChargingPolicy::DEFAULT);
+        assert_eq!(reported(3, custom), ChargingPolicy::ADAPTIVE);
+        assert_eq!(reported(2, Some(ChargingPolicy::ADAPTIVE)), ChargingPolicy::LONGLIFE);
+    }
+
+    #[test]
+    fn only_known_policies_are_loaded() {
+        let _env = crate::testing::FakeSysfs::builder().install();
+        let path = persist::data_path(STATE_FILE);
+        for p in [ChargingPolicy::DEFAULT, ChargingPolicy::LONGLIFE, ChargingPolicy::CUSTOM] {
+            save(&path, p).unwrap();
+            assert_eq!(load(&path), Some(p));
+        }
+        for junk in ["", "9", "-1", "custom"] {
+            fs::write(&path, junk).unwrap();
+            assert_eq!(load(&path), None, "{junk:?}");
+        }
+        clear(&path).unwrap();
+        clear(&path).unwrap();
+        assert_eq!(load(&path), None);
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Weekly charge limit windows. Outside every window the base limit applies.
+
+use std::{
+    fs, io,
+    path::Path,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::warn;
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::ChargeLimitWindow::ChargeLimitWindow;
+
+use crate::{persist, shutdown};
+
+pub const SCHEDULE_FILE: &str = "charge_limit_schedule";
+pub const MINUTES_PER_DAY: i32 = 24 * 60;
+
+/// Short enough that a clock or timezone change is picked up within a minute.
+pub const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A window on one day of the week. `day` is 0 for Sunday, matching `tm_wday`,
+/// and the window covers `[start_min, end_min)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub day: i32,
+    pub start_min: i32,
+    pub end_min: i32,
+    pub stop: i32,
+    pub start: i32,
+}
+
+impl Window {
+    fn to_line(self) -> String {
+        format!(
+            "{} {} {} {} {}",
+            self.day, self.start_min, self.end_min, self.stop, self.start
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let v = line
+            .split_whitespace()
+            .map(str::parse::<i32>)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        match v.as_slice() {
+            [day, start_min, end_min, stop, start] => Some(Self {
+                day: *day,
+                start_min: *start_min,
+                end_min: *end_min,
+                stop: *stop,
+                start: *start,
+            }),
+            _ => None,
+        }
+    }
+
+    fn contains(&self, now: LocalTime) -> bool {
+        self.day == now.day && (self.start_min..self.end_min).contains(&now.minute)
+    }
+
+    fn overlaps(&self, other: &Self) -> bool {
+        self.day == other.day && self.start_min < other.end_min && other.start_min < self.end_min
+    }
+}
+
+impl From<&ChargeLimitWindow> for Window {
+    fn from(w: &ChargeLimitWindow) -> Self {
+        Self {
+            day: w.day,
+            start_min: w.startMinute,
+            end_min: w.endMinute,
+            stop: w.stopLevel,
+            start: w.startLevel,
+        }
+    }
+}
+
+/// Checks window bounds and rejects overlaps. Levels are checked by the caller
+/// with the same rules as a plain charge limit.
+pub fn validate(windows: &[Window]) -> Result<(), String> {
+    for (i, w) in windows.iter().enumerate() {
+        if !(0..7).contains(&w.day) {
+            return Err(format!("window {i}: day {} outside 0-6", w.day));
+        }
+        if !(0 <= w.start_min && w.start_min < w.end_min && w.end_min <= MINUTES_PER_DAY) {
+            return Err(format!(
+                "window {i}: minutes {}-{} not within 0-{MINUTES_PER_DAY}",
+                w.start_min, w.end_min
+            ));
+        }
+        if let Some(j) = windows[..i].iter().position(|o| o.overlaps(w)) {
+            return Err(format!("window {i} overlaps window {j}"));
+        }
+    }
+    Ok(())
+}
+
+/// Day of week and minute of day in local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub day: i32,
+    pub minute: i32,
+}
+
+impl LocalTime {
+    /// Current local time. Bionic re-reads the timezone property on each call,
+    /// so timezone changes show up here without a restart.
+    pub fn now() -> Option<Self> {
+        // SAFETY: `tm` is plain data and localtime_r only writes through the
+        // pointers it is given.
+        unsafe {
+            let t = libc::time(std::ptr::null_mut());
+            let mut tm: libc::tm = std::mem::zeroed();
+            if libc::localtime_r(&t, &mut tm).is_null() {
+                return None;
+            }
+            Some(Self {
+                day: tm.tm_wday,
+                minute: tm.tm_hour * 60 + tm.tm_min,
+            })
+        }
+    }
+}
+
+/// The window covering `now`, if any.
+pub fn active_at(windows: &[Window], now: LocalTime) -> Option<Window> {
+    windows.iter().copied().find(|w| w.contains(now))
+}
+
+/// Loads the schedule, dropping it entirely if any line is bad so a partial
+/// schedule never applies.
+pub fn load(path: &Path) -> Vec<Window> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let windows = content
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(Window::from_line)
+        .collect::<Option<Vec<_>>>();
+    match windows {
+        Some(w) if validate(&w).is_ok() => w,
+        _ => {
+            warn!("Ignoring bad charge limit schedule in {}", path.display());
+            Vec::new()
+        }
+    }
+}
+
+pub fn save(path: &Path, windows: &[Window]) -> io::Result<()> {
+    let mut out = String::new();
+    for w in windows {
+        out.push_str(&w.to_line());
+        out.push('\n');
+    }
+    persist::write_atomic(path, &out)
+}
+
+/// Calls `tick` with the local time every TICK_INTERVAL.
+pub fn spawn(mut tick: impl FnMut(LocalTime) + Send + 'static) -> io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name("charge_schedule".into())
+        .spawn(move || loop {
+            match LocalTime::now() {
+                Some(now) => tick(now),
+                None => warn!("localtime failed, skipping schedule tick"),
+            }
+            if shutdown::sleep(TICK_INTERVAL) {
+                break;
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const FRIDAY: i32 = 5;
+
+    fn window(day: i32, start_min: i32, end_min: i32) -> Window {
+        Window {
+            day,
+            start_min,
+            end_min,
+            stop: 100,
+            start: 90,
+        }
+    }
+
+    fn at(day: i32, minute: i32) -> LocalTime {
+        LocalTime { day, minute }
+    }
+
+    #[test]
+    fn validate_checks_bounds_and_overlaps() {
+        let ok = [window(1, 0, MINUTES_PER_DAY), window(2, 0, 60), window(2, 60, 120)];
+        assert_eq!(validate(&ok), Ok(()));
+        let cases = [
+            (window(7, 0, 60), "window 1: day 7 outside 0-6"),
+            (window(-1, 0, 60), "window 1: day -1 outside 0-6"),
+            (window(3, 60, 60), "window 1: minutes 60-60 not within 0-1440"),
+            (window(3, -1, 60), "window 1: minutes -1-60 not within 0-1440"),
+            (window(3, 0, MINUTES_PER_DAY + 1), "window 1: minutes 0-1441 not within 0-1440"),
+            (window(1, 1000, 1001), "window 1 overlaps window 0"),
+        ];
+        for (second, error) in cases {
+            assert_eq!(validate(&[window(1, 0, MINUTES_PER_DAY), second]), Err(error.to_owned()));
+        }
+    }
+
+    #[test]
+    fn active_window_follows_the_clock() {
+        let windows = [window(FRIDAY, 18 * 60, MINUTES_PER_DAY), window(1, 0, 60)];
+        assert_eq!(active_at(&windows, at(FRIDAY, 18 * 60 - 1)), None);
+        assert_eq!(active_at(&windows, at(FRIDAY, 18 * 60)), Some(windows[0]));
+        assert_eq!(active_at(&windows, at(FRIDAY, MINUTES_PER_DAY - 1)), Some(windows[0]));
+        assert_eq!(active_at(&windows, at(FRIDAY + 1, 0)), None);
+        assert_eq!(active_at(&windows, at(1, 59)), Some(windows[1]));
+        assert_eq!(active_at(&windows, at(1, 60)), None);
+        assert_eq!(active_at(&[], at(1, 0)), None);
+    }
+
+    #[test]
+    fn schedules_round_trip_and_bad_files_are_dropped() {
+        let path = testing::data_dir().join("schedule_round_trip");
+        let windows = [window(FRIDAY, 18 * 60, MINUTES_PER_DAY), window(0, 0, 30)];
+        save(&path, &windows).unwrap();
+        assert_eq!(load(&path), windows);
+        for bad in ["5 1080 1440 100 90\n0 0 30 100\n", "5 0 60 100 90\n5 30 90 100 90\n"] {
+            fs::write(&path, bad).unwrap();
+            assert!(load(&path).is_empty(), "{bad:?}");
+        }
+        fs::remove_file(&path).unwrap();
+        assert!(load(&path).is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Read-only walk over every known sysfs node, for device bringup. Reports
+//! where each node resolved, its access, its value and, for nodes with a known
+//! format, whether that value parses. Nothing is ever written.
+
+use std::time::{Duration, Instant};
+
+use log::warn;
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
+    SelfTestEntry::SelfTestEntry, SelfTestReport::SelfTestReport,
+};
+
+use crate::{
+    aacc::AaccProfile,
+    charge_stats, csi,
+    sysfs::{self, paths, Format},
+    validate::LowBoundaryFormat,
+};
+
+/// The walk stops here; any remaining nodes are left out and the report is
+/// marked truncated.
+pub const TIME_BUDGET: Duration = Duration::from_secs(2);
+/// Longer values (uevent, charge_stats) are cut to this many bytes.
+pub const MAX_VALUE_LEN: usize = 256;
+
+fn is_int(s: &str) -> bool {
+    s.parse::<i32>().is_ok()
+}
+
+/// Check for a node's format, if anything here parses it.
+fn parser_for(format: Format) -> Option<fn(&str) -> bool> {
+    Some(match format {
+        Format::Int => is_int,
+        Format::Ints => |s| sysfs::parse_ints(s).is_some(),
+        Format::CsiStats => |s| csi::parse(s).is_ok(),
+        Format::ChargeStats => |s| charge_stats::parse(s).is_ok(),
+        Format::AaccProfile => |s| AaccProfile::parse(s).is_ok(),
+        Format::LowBoundary => |s| LowBoundaryFormat::detect(s).is_some(),
+        Format::Text => return None,
+    })
+}
+
+fn probe(name: String, path: Option<&str>, parser: Option<fn(&str) -> bool>) -> SelfTestEntry {
+    let Some(path) = path else {
+        return SelfTestEntry {
+            name,
+            ..Default::default()
+        };
+    };
+    let readable = sysfs::can_access(path, false);
+    let value = if readable {
+        sysfs::read_string(path).ok()
+    } else {
+        None
+    };
+    let mut entry = SelfTestEntry {
+        name,
+        path: path.into(),
+        present: true,
+        readable,
+        writable: sysfs::can_access(path, true),
+        typed: parser.is_some(),
+        parseOk: false,
+        value: String::new(),
+    };
+    if let Some(mut v) = value {
+        entry.parseOk = parser.is_some_and(|p| p(&v));
+        if v.len() > MAX_VALUE_LEN {
+            let mut end = MAX_VALUE_LEN;
+            while !v.is_char_boundary(end) {
+                end -= 1;
+            }
+            v.truncate(end);
+        }
+        entry.value = v;
+    }
+    entry
+}
+
+pub fn run() -> SelfTestReport {
+    let started = Instant::now();
+    let mut report = SelfTestReport::default();
+
+    for (feature, prop, path) in sysfs::duplicate_property_paths() {
+        warn!("Property {feature:?}/{prop} maps to {path}, already used by another property");
+    }
+
+    let path_entries = paths::ALL
+        .iter()
+        .map(|e| (e.name.to_string(), e.path.resolve(), parser_for(e.format)));
+    let property_entries = paths::ALL.iter().flat_map(|e| {
+        e.properties.iter().map(|(f, prop)| {
+            let present = sysfs::exists(e.path.primary);
+            (
+                format!("{f:?}/{prop}"),
+                present.then_some(e.path.primary),
+                parser_for(e.format),
+            )
+        })
+    });
+
+    for (name, path, parser) in path_entries.chain(property_entries) {
+        if started.elapsed() > TIME_BUDGET {
+            report.truncated = true;
+            break;
+        }
+        report.entries.push(probe(name, path, parser));
+    }
+    report.durationMs = started.elapsed().as_millis() as i64;
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::Feature::Feature;
+
+    use super::*;
+    use crate::{sysfs::property, testing::FakeSysfs};
+
+    fn entry<'a>(report: &'a SelfTestReport, name: &str) -> &'a SelfTestEntry {
+        report.entries.iter().find(|e| e.name == name).unwrap()
+    }
+
+    #[test]
+    fn nodes_are_classified_without_writing() {
+        let env = FakeSysfs::builder()
+            .node(paths::CHARGING_POLICY, "1\n")
+            .read_only(paths::HEALTH_INDEX, 85)
+            .read_only(paths::CSI_STATS, "bogus")
+            .read_only(paths::CHARGE_STATS, "x".repeat(2 * MAX_VALUE_LEN))
+            .node(paths::aacr::STATE, 1)
+            .node(paths::aacr::PROFILE, "1,2")
+            .install();
+        let report = run();
+        assert!(!report.truncated);
+        assert!(env.write_log().is_empty());
+
+        let policy = entry(&report, "CHARGING_POLICY");
+        assert_eq!(policy.path, paths::CHARGING_POLICY.primary);
+        assert!(policy.present && policy.readable && policy.writable);
+        assert!(policy.typed && policy.parseOk);
+        assert_eq!(policy.value, "1");
+
+        let index = entry(&report, "HEALTH_INDEX");
+        assert!(index.present && index.readable && !index.writable);
+        assert!(index.parseOk);
+
+        let csi = entry(&report, "CSI_STATS");
+        assert!(csi.typed && !csi.parseOk);
+        assert_eq!(csi.value, "bogus");
+
+        let stats = entry(&report, "CHARGE_STATS");
+        assert_eq!(stats.value.len(), MAX_VALUE_LEN);
+
+        let absent = entry(&report, "AACC_CHG_PROFILE");
+        assert!(!absent.present && !absent.readable && !absent.writable);
+        assert!(absent.path.is_empty() && absent.value.is_empty());
+
+        let state = entry(&report, "AACR_STATE");
+        assert!(state.present && state.typed && state.parseOk);
+        let untyped = entry(&report, "AACR_PROFILE");
+        assert!(untyped.present && !untyped.typed && !untyped.parseOk);
+
+        // The property table is walked too, under feature/property names, with
+        // the node's format.
+        let name = format!("{:?}/{}", Feature::AACR, property::STATE);
+        let state = entry(&report, &name);
+        assert_eq!(state.path, paths::aacr::STATE.primary);
+        assert!(state.typed && state.parseOk);
+        let name = format!("{:?}/{}", Feature::HEALTH, property::ALGO);
+        assert!(!entry(&report, &name).present);
+    }
+}
@@ -1,7 +1,26 @@
 // Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
 // SPDX-License-Identifier: Apache-2.0
 
-use std::sync::Mutex;
+use std::{
+   collections::BTreeMap,
+   ffi::CStr,
+   fmt,
+   io::{
+      self,
+      Write,
+   },
+   sync::{
+      atomic::{
+         AtomicI32,
+         Ordering,
+      },
+      Arc,
+      Mutex,
+      MutexGuard,
+      OnceLock,
+   },
+   thread,
+};
 
 use binder::{
    BinderFeatures,
@@ -9,39 +28,216 @@ use binder::{
    Interface,
    Result,
    Status,
+   StatusCode,
    Strong,
 };
-use log::{error, info};
+use log::{
+   debug,
+   error,
+   info,
+   warn,
+};
 use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
    BnBattery,
    IBattery,
+   AacpOptOut::AacpOptOut,
+   AaccProfileInfo::AaccProfileInfo,
+   AactProfile::AactProfile,
+   BatteryDefenderSummary::BatteryDefenderSummary,
+   BatteryTechnology::BatteryTechnology,
    CalibrationMode::CalibrationMode,
    CalibrationState::CalibrationState,
+   ChargeLimitSchedule::ChargeLimitSchedule,
+   ChargeSessionStats::ChargeSessionStats,
    ChargingPolicy::ChargingPolicy,
+   ChargingSession::ChargingSession,
+   ChargingSpeedLevel::ChargingSpeedLevel,
+   ChargeStage::ChargeStage,
    ChargingStage::ChargingStage,
    ChargingStatus::ChargingStatus,
    ChargingType::ChargingType,
+   CsiStats::CsiStats,
+   DefenderEvent::DefenderEvent,
    DefenderType::DefenderType,
+   DeratingStatus::DeratingStatus,
+   DetailedHealthScore::DetailedHealthScore,
    DockDefendStatus::DockDefendStatus,
+   EffectiveChargeLimit::EffectiveChargeLimit,
    Feature::Feature,
+   HealthAlert::HealthAlert,
    HealthAlgo::HealthAlgo,
    HealthStats::HealthStats,
    HealthStatus::HealthStatus,
+   SelfTestReport::SelfTestReport,
+   TempDefendConfig::TempDefendConfig,
+   TrickleDefendConfig::TrickleDefendConfig,
+   TemperatureSample::TemperatureSample,
 };
 // Feature is re-exported from sysfs module for get_property_sysfs
 
-use crate::sysfs::{
-   self,
-   paths,
+use crate::{
+   aacc::{
+      self,
+      AaccProfile,
+   },
+   aacr::AacrState,
+   aafv::AafvState,
+   adaptive_pause::{
+      self,
+      Pause,
+   },
+   arbitration::{
+      self,
+      Request,
+   },
+   audit::{
+      self,
+      AuditEntry,
+      AuditLog,
+   },
+   backoff,
+   call_counter::CallCounter,
+   charge_stats,
+   config,
+   crash,
+   csi,
+   deadline,
+   defender_stash::{
+      self,
+      Stash,
+   },
+   degraded,
+   derate::{
+      self,
+      Derating,
+   },
+   error::ErrorCode,
+   enums::{
+      BatteryTechnologyExt,
+      CalibrationStateExt,
+      ChargingSpeedLevelExt,
+      ChargingStatusExt,
+      ChargingTypeExt,
+      HealthStatusExt,
+   },
+   events::{
+      self,
+      DefenderLog,
+   },
+   features,
+   full_charge::{
+      self,
+      Bypass,
+   },
+   health_stats::{
+      self,
+      ParseError,
+   },
+   health_watch::{
+      self,
+      HealthWatch,
+   },
+   history::{
+      self,
+      TempHistory,
+   },
+   hot_cache,
+   journal::Journal,
+   lazy,
+   lock::MutexExt,
+   metrics::{
+      self,
+      Event as Metric,
+   },
+   missing::{
+      self,
+      Site,
+   },
+   monitor::{
+      self,
+      Monitor,
+      POLL_INTERVAL,
+   },
+   permission,
+   persist,
+   policy,
+   schedule::{
+      self,
+      LocalTime,
+      Window,
+   },
+   selftest,
+   session::{
+      self,
+      SessionTracker,
+   },
+   shutdown,
+   startup,
+   sysfs::{
+      self,
+      paths,
+      property,
+   },
+   trace::TracedBattery,
+   validate::{
+      self,
+      LowBoundaryFormat,
+   },
+   worker::{
+      AsyncOp,
+      Worker,
+   },
 };
 
-const DEFAULT_STOP: i32 = 80;
-const DEFAULT_START: i32 = 70;
+const SERVICE_NAME: &str = "vendor.benzeneos.battery.IBattery/default";
+// The interface version this binary is built against (the V2 rust library)
+// and that its VINTF fragment declares.
+const SERVICE_INTERFACE_VERSION: i32 = 2;
+
+/// Why the service couldn't be published.
+#[derive(Debug, Clone, Copy)]
+pub enum RegisterError {
+   /// servicemanager couldn't be reached, which is worth retrying.
+   Unavailable(StatusCode),
+   /// servicemanager answered and refused the service.
+   Rejected(StatusCode),
+}
+
+impl RegisterError {
+   fn from_status(code: StatusCode) -> Self {
+      match code {
+         StatusCode::DEAD_OBJECT
+         | StatusCode::FAILED_TRANSACTION
+         | StatusCode::NO_INIT
+         | StatusCode::TIMED_OUT
+         | StatusCode::WOULD_BLOCK => Self::Unavailable(code),
+         _ => Self::Rejected(code),
+      }
+   }
+}
+
+impl fmt::Display for RegisterError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      match self {
+         Self::Unavailable(code) => write!(f, "servicemanager unavailable: {code:?}"),
+         Self::Rejected(code) => write!(f, "servicemanager rejected the service: {code:?}"),
+      }
+   }
+}
+
+const AACP_MAX_CUTOFF_CYCLES: i32 = 3000;
+
+const MIN_CHARGE_CURRENT_MA: i32 = 100;
+
+const REVERSE_WIRELESS_MIN_CAPACITY: i32 = 20;
+
+// bd_trickle_cnt_thr first appears in this trickle defend version.
+const TRICKLE_CNT_THR_VERSION: i32 = 2;
 
 fn sysfs_err(e: sysfs::Error, ctx: &str) -> Status {
    let msg = format!("{ctx}: {e}");
    error!("{msg}");
-   Status::new_service_specific_error_str(1, Some(&msg))
+   Status::new_service_specific_error_str(ErrorCode::from(&e).code(), Some(&msg))
 }
 
 fn bad_arg(msg: &str) -> Status {
@@ -52,134 +248,1298 @@ fn unsupported(msg: &str) -> Status {
    Status::new_exception_str(ExceptionCode::UNSUPPORTED_OPERATION, Some(msg))
 }
 
+/// Warns when maxfg's charge limit bypass would let charging run past
+/// `stop`, and refuses the request if configured to.
+fn check_limit_bypass(stop: i32) -> Result<()> {
+   let mode = paths::CHARGE_LIMIT_BYPASS_MODE.read_int_or(0);
+   if mode == 0 {
+      return Ok(());
+   }
+   warn!("Charge limit bypass mode {mode} is active, stop level {stop} won't hold");
+   if config::refuse_in_bypass() {
+      return Err(Status::new_service_specific_error_str(
+         ErrorCode::BypassActive.code(),
+         Some("bypass mode active"),
+      ));
+   }
+   Ok(())
+}
+
+/// Writes back the trickle values a defender clear zeroed and drops the stash.
+fn restore_stash(stash: Stash) {
+   let writes = [
+      (paths::BD_TRICKLE_RESET_SEC, stash.reset_sec, "trickle reset sec"),
+      (paths::BD_TRICKLE_RATE, stash.rate, "trickle rate"),
+   ];
+   for (path, value, name) in writes {
+      if value == 0 {
+         continue;
+      }
+      match path.write_int(value) {
+         Ok(()) => info!("Restored {name} to {value}"),
+         Err(e) => warn!("Failed to restore {name} to {value}: {e}"),
+      }
+   }
+   if let Err(e) = defender_stash::clear(&persist::data_path(defender_stash::STATE_FILE)) {
+      warn!("Failed to clear defender stash: {e}");
+   }
+}
+
+/// The battery's state of charge, clamped to 0-100.
+fn capacity_percent() -> sysfs::Result<i32> {
+   let raw = paths::BATTERY_CAPACITY.read_int()?;
+   if !(0..=100).contains(&raw) {
+      error!("Battery capacity {raw}% out of range, clamping");
+   }
+   Ok(raw.clamp(0, 100))
+}
+
+/// For a setter whose node is missing: UNSUPPORTED in strict mode, otherwise
+/// the old silent success, counted for dump.
+fn missing_node(site: Site, path: sysfs::SysfsPath) -> Result<()> {
+   if missing::is_strict() {
+      return Err(unsupported(&format!("{} not found", path.primary)));
+   }
+   missing::record(site, path.primary);
+   Ok(())
+}
+
+/// Rejects an `aacr_state` write the driver can't take from its current state.
+/// An unreadable or unknown current state lets the write through.
+fn check_aacr_transition(value: &str) -> Result<()> {
+   let Some(target) = value.parse().ok().and_then(AacrState::from_i32) else {
+      return Ok(());
+   };
+   let current = sysfs::get_property_sysfs(Feature::AACR, property::STATE)
+      .and_then(|path| sysfs::read_int(path).ok())
+      .and_then(AacrState::from_i32);
+   match current {
+      Some(current) if !current.can_transition_to(target) => Err(bad_arg(&format!(
+         "AACR cannot go from {current:?} to {target:?}, only to {:?}",
+         current.valid_transitions()
+      ))),
+      _ => Ok(()),
+   }
+}
+
+/// Composite health weights, in percent. Capacity fade is what users notice as
+/// shorter runtime, so it dominates; impedance growth mostly shows up as
+/// slower charging and voltage sag under load.
+const HEALTH_CAPACITY_WEIGHT: i32 = 70;
+const HEALTH_IMPEDANCE_WEIGHT: i32 = 30;
+
+#[derive(Debug, Clone, Copy)]
+struct HealthScore {
+   composite:   i32,
+   capacity:    i32,
+   impedance:   i32,
+   temp_bucket: i32,
+}
+
+impl From<HealthScore> for DetailedHealthScore {
+   fn from(s: HealthScore) -> Self {
+      Self {
+         composite:      s.composite,
+         capacityScore:  s.capacity,
+         impedanceScore: s.impedance,
+         tempBucket:     s.temp_bucket,
+      }
+   }
+}
+
 struct Limits {
-   stop:  i32,
-   start: i32,
+   stop:        i32,
+   start:       i32,
+   // Applied when no client has a request.
+   base:        Request,
+   // Per-client requests; the applied levels above are the arbitration winner.
+   clients:     BTreeMap<String, Request>,
+   winner:      String,
+   schedule:    Vec<Window>,
+   // The window in effect, which stands in for the default client's request.
+   scheduled:   Option<Window>,
+   // While set, the limit is lifted and the levels above only take effect once it ends.
+   full_charge: Option<Bypass>,
+   // While set, adaptive charging is held off at DEFAULT and its deadline kept here.
+   paused:      Option<Pause>,
+   // What the last setChargingPolicy asked for, until the node is changed under us.
+   policy:      Option<ChargingPolicy>,
+   // While set, adaptive charging was turned on over this policy and restores it when turned off.
+   adaptive:    Option<ChargingPolicy>,
+   // While set, charge_to_limit replaced this policy with LONGLIFE and restores it when turned off.
+   forced:      Option<ChargingPolicy>,
+}
+
+/// Locks the limits. A panic while they were held may have left the levels
+/// and client requests half updated, so on a poisoned lock those are reset to
+/// the base levels and reapplied, and the persisted state is reloaded.
+fn recover_limits(limits: &Mutex<Limits>) -> MutexGuard<'_, Limits> {
+   let poisoned = match limits.lock() {
+      Ok(l) => return l,
+      Err(e) => e,
+   };
+   limits.clear_poison();
+   let mut l = poisoned.into_inner();
+   error!(
+      "Limits lock poisoned; resetting to base {}/{} and dropping {} client requests",
+      l.base.stop,
+      l.base.start,
+      l.clients.len()
+   );
+   l.clients.clear();
+   l.scheduled = None;
+   l.schedule = schedule::load(&persist::data_path(schedule::SCHEDULE_FILE));
+   l.full_charge = full_charge::load(&persist::data_path(full_charge::STATE_FILE));
+   l.paused = adaptive_pause::load(&persist::data_path(adaptive_pause::STATE_FILE));
+   l.policy = policy::load(&persist::data_path(policy::STATE_FILE));
+   l.adaptive = policy::load(&persist::data_path(policy::OVERRIDE_FILE));
+   l.forced = policy::load(&persist::data_path(policy::FORCED_FILE));
+   if let Err(e) = l.reapply() {
+      warn!("Failed to reapply limits after recovery: {e:?}");
+   }
+   l
+}
+
+impl Limits {
+   /// Writes every persisted field out from memory. Each is saved when it
+   /// changes, so on shutdown this only makes up for a write that failed.
+   fn flush(&self) {
+      let path = persist::data_path;
+      let results = [
+         ("schedule", schedule::save(&path(schedule::SCHEDULE_FILE), &self.schedule)),
+         ("full charge", match &self.full_charge {
+            Some(b) => full_charge::save(&path(full_charge::STATE_FILE), b),
+            None => full_charge::clear(&path(full_charge::STATE_FILE)),
+         }),
+         ("adaptive pause", match &self.paused {
+            Some(p) => adaptive_pause::save(&path(adaptive_pause::STATE_FILE), p),
+            None => adaptive_pause::clear(&path(adaptive_pause::STATE_FILE)),
+         }),
+         ("charging policy", match self.policy {
+            Some(p) => policy::save(&path(policy::STATE_FILE), p),
+            None => policy::clear(&path(policy::STATE_FILE)),
+         }),
+         ("adaptive override", match self.adaptive {
+            Some(p) => policy::save(&path(policy::OVERRIDE_FILE), p),
+            None => policy::clear(&path(policy::OVERRIDE_FILE)),
+         }),
+         ("charge_to_limit policy", match self.forced {
+            Some(p) => policy::save(&path(policy::FORCED_FILE), p),
+            None => policy::clear(&path(policy::FORCED_FILE)),
+         }),
+      ];
+      for (name, result) in results {
+         if let Err(e) = result {
+            warn!("Failed to flush {name}: {e}");
+         }
+      }
+   }
+
+   /// Applies whichever request wins, falling back to the base levels once no
+   /// client has one.
+   fn reapply(&mut self) -> Result<()> {
+      let mut requests = self.clients.clone();
+      if let Some(w) = self.scheduled {
+         requests.insert(arbitration::DEFAULT_CLIENT.to_owned(), Request {
+            stop:  w.stop,
+            start: w.start,
+         });
+      }
+      let (winner, r) = match arbitration::resolve(&requests) {
+         Some((tag, r)) => (tag.to_owned(), r),
+         None => (String::new(), self.base),
+      };
+      if !winner.is_empty() {
+         info!("Charge limit winner: {winner} ({}/{})", r.stop, r.start);
+      }
+      self.stop = r.stop;
+      self.start = r.start;
+      self.winner = winner;
+      if self.full_charge.is_some() {
+         return lift_limit();
+      }
+      apply_levels(r.stop, r.start)
+   }
+
+   /// Writes a raw policy value, or records it for later while a full charge
+   /// bypass holds the policy at DEFAULT. Returns whether the kernel saw it.
+   fn write_policy(&mut self, val: i32) -> Result<bool> {
+      if let Some(b) = self.full_charge.as_mut() {
+         b.policy = val;
+         save_full_charge(b);
+         return Ok(false);
+      }
+      paths::CHARGING_POLICY
+         .write_int(val)
+         .map_err(|e| sysfs_err(e, "write policy"))?;
+      Ok(true)
+   }
+
+   fn record_policy(&mut self, p: ChargingPolicy) {
+      self.policy = Some(p);
+      if let Err(e) = policy::save(&persist::data_path(policy::STATE_FILE), p) {
+         warn!("Failed to persist charging policy: {e}");
+      }
+   }
+
+   fn forget_policy(&mut self) {
+      if self.policy.take().is_none() {
+         return;
+      }
+      if let Err(e) = policy::clear(&persist::data_path(policy::STATE_FILE)) {
+         warn!("Failed to clear charging policy: {e}");
+      }
+   }
+
+   fn set_adaptive_override(&mut self, underlying: Option<ChargingPolicy>) {
+      if self.adaptive == underlying {
+         return;
+      }
+      self.adaptive = underlying;
+      let path = persist::data_path(policy::OVERRIDE_FILE);
+      let result = match underlying {
+         Some(p) => policy::save(&path, p),
+         None => policy::clear(&path),
+      };
+      if let Err(e) = result {
+         warn!("Failed to persist adaptive override: {e}");
+      }
+   }
+
+   fn set_forced_policy(&mut self, underlying: Option<ChargingPolicy>) {
+      if self.forced == underlying {
+         return;
+      }
+      self.forced = underlying;
+      let path = persist::data_path(policy::FORCED_FILE);
+      let result = match underlying {
+         Some(p) => policy::save(&path, p),
+         None => policy::clear(&path),
+      };
+      if let Err(e) = result {
+         warn!("Failed to persist charge_to_limit policy: {e}");
+      }
+   }
+
+   /// Puts the policy at LONGLIFE, under which the kernel enforces
+   /// charge_to_limit, remembering the one it replaced for release_longlife.
+   fn force_longlife(&mut self) -> Result<()> {
+      let node = match self.full_charge {
+         Some(b) => b.policy,
+         None => paths::CHARGING_POLICY.read_int_or(1),
+      };
+      if node == 2 {
+         return Ok(());
+      }
+      let underlying = policy::reported(node, self.policy);
+      self.write_policy(2)?;
+      self.set_forced_policy(Some(underlying));
+      info!("charge_to_limit replaced {underlying:?} with LONGLIFE");
+      Ok(())
+   }
+
+   /// Puts back the policy force_longlife replaced.
+   fn release_longlife(&mut self) -> Result<()> {
+      let Some(p) = self.forced else {
+         return Ok(());
+      };
+      self.write_policy(policy::kernel_value(p).unwrap_or(1))?;
+      self.set_forced_policy(None);
+      info!("charge_to_limit off, back to {p:?}");
+      Ok(())
+   }
+
+   /// Turns adaptive charging on without giving up the current policy, which
+   /// end_adaptive_override puts back.
+   fn start_adaptive_override(&mut self) -> Result<()> {
+      // A full charge bypass holds the real policy back; that is the one that counts.
+      let node = match self.full_charge {
+         Some(b) => b.policy,
+         None => paths::CHARGING_POLICY.read_int_or(1),
+      };
+      // Already adaptive, or adaptive and paused.
+      if node == 3 || self.paused.is_some() {
+         return Ok(());
+      }
+      let underlying = policy::reported(node, self.policy);
+      self.write_policy(3)?;
+      self.set_adaptive_override(Some(underlying));
+      info!("Adaptive charging enabled over {underlying:?}");
+      Ok(())
+   }
+
+   /// Turns adaptive charging off, back to the policy it was enabled over, or
+   /// DEFAULT when it was the policy itself.
+   fn end_adaptive_override(&mut self) -> Result<()> {
+      let node = match self.full_charge {
+         Some(b) => b.policy,
+         None => paths::CHARGING_POLICY.read_int_or(1),
+      };
+      let restore = match self.adaptive {
+         Some(p) => p,
+         None if node == 3 || self.paused.is_some() => ChargingPolicy::DEFAULT,
+         None => return Ok(()),
+      };
+      self.forget_pause();
+      let written = self.write_policy(policy::kernel_value(restore).unwrap_or(1))?;
+      if self.adaptive.is_some() {
+         self.set_adaptive_override(None);
+      } else {
+         self.record_policy(restore);
+      }
+      info!("Adaptive charging disabled, back to {restore:?}");
+      if written && restore == ChargingPolicy::CUSTOM {
+         apply_levels(self.stop, self.start)?;
+      }
+      Ok(())
+   }
+
+   /// Drops a pause without restoring anything, e.g. once a client picks a policy.
+   fn forget_pause(&mut self) {
+      if self.paused.take().is_none() {
+         return;
+      }
+      if let Err(e) = adaptive_pause::clear(&persist::data_path(adaptive_pause::STATE_FILE)) {
+         warn!("Failed to clear adaptive pause state: {e}");
+      }
+   }
+
+   /// Holds adaptive charging off, remembering the deadline it was working to.
+   fn pause_adaptive(&mut self) -> Result<()> {
+      if self.paused.is_some() {
+         return Ok(());
+      }
+      // A full charge bypass holds the real policy back; that is the one that counts.
+      let policy = match self.full_charge {
+         Some(b) => b.policy,
+         None => paths::CHARGING_POLICY.read_int_or(1),
+      };
+      if policy != 3 {
+         return Err(Status::new_exception_str(
+            ExceptionCode::ILLEGAL_STATE,
+            Some("adaptive charging is not active"),
+         ));
+      }
+      let raw = i64::from(paths::CHARGE_DEADLINE.read_int_or(0));
+      let remaining = match deadline::boottime_sec() {
+         Some(now) => deadline::seconds_remaining(raw, now),
+         None => raw,
+      };
+      let p = Pause::new(remaining, persist::now_ms());
+      save_pause(&p);
+      self.paused = Some(p);
+      info!("Adaptive charging paused, deadline target {:?}", p.target_ms);
+      self.write_policy(1).map(|_| ())
+   }
+
+   /// Puts ADAPTIVE back and re-arms the deadline with the time left on it.
+   fn resume_adaptive(&mut self) -> Result<()> {
+      let Some(p) = self.paused else {
+         return Ok(());
+      };
+      self.forget_pause();
+      self.write_policy(3)?;
+      match p.remaining_sec(persist::now_ms()) {
+         Some(left) => {
+            info!("Adaptive charging resumed, {left}s to deadline");
+            paths::CHARGE_DEADLINE
+               .write_int(left.try_into().unwrap_or(i32::MAX))
+               .map_err(|e| sysfs_err(e, "write deadline"))
+         },
+         None => {
+            info!("Adaptive charging resumed, no deadline left");
+            Ok(())
+         },
+      }
+   }
+
+   /// Ends a full charge bypass, putting back the policy and levels it replaced.
+   fn restore_after_full_charge(&mut self) -> Result<()> {
+      let Some(b) = self.full_charge.take() else {
+         return Ok(());
+      };
+      if let Err(e) = full_charge::clear(&persist::data_path(full_charge::STATE_FILE)) {
+         warn!("Failed to clear full charge state: {e}");
+      }
+      if paths::CHARGING_POLICY.exists() {
+         paths::CHARGING_POLICY
+            .write_int(b.policy)
+            .map_err(|e| sysfs_err(e, "restore policy"))?;
+      }
+      // After a restart mid-bypass no client has re-sent its request yet, so
+      // the saved levels are the only record of what was in effect.
+      if self.clients.is_empty() && self.scheduled.is_none() {
+         self.stop = b.stop;
+         self.start = b.start;
+         return apply_levels(b.stop, b.start);
+      }
+      self.reapply()
+   }
+}
+
+fn save_full_charge(b: &Bypass) {
+   if let Err(e) = full_charge::save(&persist::data_path(full_charge::STATE_FILE), b) {
+      warn!("Failed to persist full charge state: {e}");
+   }
+}
+
+fn save_pause(p: &Pause) {
+   if let Err(e) = adaptive_pause::save(&persist::data_path(adaptive_pause::STATE_FILE), p) {
+      warn!("Failed to persist adaptive pause state: {e}");
+   }
+}
+
+/// Whether a charger is attached.
+fn read_plugged() -> bool {
+   paths::BATTERY_STATUS
+      .read_string()
+      .is_ok_and(|s| s != "Discharging")
+}
+
+/// Lets the battery charge to 100%: the policy goes to DEFAULT so neither
+/// LONGLIFE nor adaptive charging holds it back, and the user levels are opened up.
+fn lift_limit() -> Result<()> {
+   if paths::CHARGING_POLICY.exists() {
+      paths::CHARGING_POLICY
+         .write_int(1)
+         .map_err(|e| sysfs_err(e, "write policy"))?;
+   }
+   apply_levels(100, 0)
+}
+
+/// Held across the two level writes, so a reader never sees one request's
+/// stop with another's start.
+static LEVELS: Mutex<()> = Mutex::new(());
+
+/// Writes the user charge levels. The kernel stores these and applies them to
+/// the active limit only under LONGLIFE. It takes a stop level only above the
+/// current start level and a start level only below the current stop level,
+/// and some drivers reject rewriting the value a node holds, so the order
+/// depends on the levels already there and unchanged ones aren't written.
+fn apply_levels(stop: i32, start: i32) -> Result<()> {
+   let _levels = LEVELS.lock_recover();
+   let (stop_node, start_node) = (paths::USER_CHARGE_STOP_LEVEL, paths::USER_CHARGE_START_LEVEL);
+   // None for a missing node, which is skipped; an unreadable one is written.
+   let read = |path: sysfs::SysfsPath| match path.read_int() {
+      Err(sysfs::Error::NotFound { .. }) => None,
+      current => Some(current.ok()),
+   };
+   let (current_stop, current_start) = (read(stop_node), read(start_node));
+   let write = |path: sysfs::SysfsPath, current: Option<Option<i32>>, value, ctx| match current {
+      None => Ok(()),
+      Some(Some(c)) if c == value => Ok(()),
+      // Past the read, so a stale cached write mustn't skip it.
+      Some(_) => path.write_int_uncached(value).map_err(|e| sysfs_err(e, ctx)),
+   };
+   // The new start is below the new stop, so when the new stop isn't above the
+   // current start, writing start first satisfies both bounds.
+   if current_start.flatten().is_some_and(|s| stop <= s) {
+      write(start_node, current_start, start, "write start")?;
+      write(stop_node, current_stop, stop, "write stop")?;
+   } else {
+      write(stop_node, current_stop, stop, "write stop")?;
+      write(start_node, current_start, start, "write start")?;
+   }
+   info!("Set charge levels: {stop}/{start}");
+   Ok(())
+}
+
+/// Ends a full charge bypass once the battery is full or the charger is unplugged.
+fn full_charge_tick(limits: &Mutex<Limits>) {
+   if recover_limits(limits).full_charge.is_none() {
+      return;
+   }
+   // Read outside the lock so binder callers never wait on sysfs.
+   let soc = paths::BATTERY_CAPACITY.read_int_or(-1);
+   let plugged = read_plugged();
+   let mut l = recover_limits(limits);
+   let Some(b) = l.full_charge.as_mut() else {
+      return;
+   };
+   let seen = b.plugged_seen;
+   match b.step(soc, plugged) {
+      Some(done) => {
+         info!("Full charge bypass ended: {done:?}");
+         if let Err(e) = l.restore_after_full_charge() {
+            warn!("Failed to restore charge limit: {e:?}");
+         }
+      },
+      None if b.plugged_seen != seen => save_full_charge(b),
+      None => {},
+   }
+}
+
+/// Resumes adaptive charging once the charger that the pause was for is unplugged.
+fn adaptive_pause_tick(limits: &Mutex<Limits>) {
+   if recover_limits(limits).paused.is_none() {
+      return;
+   }
+   let plugged = read_plugged();
+   let mut l = recover_limits(limits);
+   let Some(p) = l.paused.as_mut() else {
+      return;
+   };
+   let seen = p.plugged_seen;
+   if p.step(plugged) {
+      info!("Charger unplugged while adaptive charging paused");
+      if let Err(e) = l.resume_adaptive() {
+         warn!("Failed to resume adaptive charging: {e:?}");
+      }
+   } else if p.plugged_seen != seen {
+      save_pause(p);
+   }
+}
+
+/// Follows a scheduled calibration until the kernel reports it finished.
+fn calibration_tick(calibration: &Mutex<Option<CalibrationState>>) {
+   let Some(last) = *calibration.lock_recover() else {
+      return;
+   };
+   let state = match paths::HEALTH_GET_CAL_STATE.read_int_timeout(sysfs::SLOW_READ_TIMEOUT) {
+      // Keep following; the next tick may get through.
+      Err(sysfs::Error::Timeout { .. }) => return,
+      result => CalibrationState(result.unwrap_or(0)),
+   };
+   if state != last {
+      info!("Calibration {}", state.description());
+      if state.is_terminal() {
+         metrics::emit(Metric::CalibrationDone { state });
+      }
+   }
+   *calibration.lock_recover() = (!state.is_terminal()).then_some(state);
+}
+
+/// Whether anything is waiting on this process to keep running, see lazy.rs.
+fn has_pending_work(
+   limits: &Mutex<Limits>,
+   calibration: &Mutex<Option<CalibrationState>>,
+   temp_history: &TempHistory,
+   derating: &Derating,
+) -> bool {
+   let l = recover_limits(limits);
+   !l.clients.is_empty()
+      || !l.schedule.is_empty()
+      || l.full_charge.is_some()
+      || l.paused.is_some()
+      || calibration.lock_recover().is_some()
+      || temp_history.settings().enabled
+      || derating.is_enabled()
+}
+
+/// Emits the daily metrics snapshot when one is due.
+fn metrics_tick(limits: &Mutex<Limits>, defender_log: &DefenderLog) {
+   if !metrics::daily_due() {
+      return;
+   }
+   let algo = paths::HEALTH_ALGO.read_int_or(1);
+   let cycle_count = match paths::HEALTH_INDEX_STATS
+      .read_string_raw_timeout(sysfs::SLOW_READ_TIMEOUT)
+      .map_err(ParseError::Read)
+      .and_then(|c| health_stats::parse(&c, algo))
+   {
+      Ok(stats) | Err(ParseError::PartialData { stats, .. }) => stats.cycleCount,
+      Err(_) => -1,
+   };
+   let since = persist::now_ms() - metrics::DAILY_INTERVAL.as_millis() as i64;
+   let l = recover_limits(limits);
+   metrics::emit(Metric::Daily {
+      health_index: paths::HEALTH_INDEX.read_int_or(-1),
+      cycle_count,
+      defender_activations: defender_log.activations_since(since),
+      stop: l.stop,
+      start: l.start,
+      clients: l.clients.len(),
+      policy: paths::CHARGING_POLICY.read_int_or(-1),
+   });
+}
+
+/// Takes the daily health sample when one is due.
+fn health_watch_tick(watch: &HealthWatch) {
+   let now = persist::now_ms();
+   if !watch.due(now) {
+      return;
+   }
+   let sample = health_watch::Sample {
+      at_ms:     now,
+      index:     paths::HEALTH_INDEX.read_int_or(-1),
+      capacity:  paths::HEALTH_CAPACITY_INDEX.read_int_or(-1),
+      impedance: paths::HEALTH_IMPEDANCE_INDEX.read_int_or(-1),
+   };
+   if sample.index < 0 && sample.capacity < 0 && sample.impedance < 0 {
+      return;
+   }
+   watch.record(sample, config::health_thresholds());
+}
+
+/// Forgets the recorded policy once something else rewrites the node, so a
+/// stale CUSTOM isn't reported for a policy someone else chose.
+fn policy_tick(limits: &Mutex<Limits>) {
+   let Ok(node) = paths::CHARGING_POLICY.read_int() else {
+      return;
+   };
+   let mut l = recover_limits(limits);
+   // A bypass or pause holds the node away from the request on purpose.
+   if l.full_charge.is_some() || l.paused.is_some() {
+      return;
+   }
+   // charge_to_limit holds the node at LONGLIFE over the recorded policy.
+   if let Some(p) = l.forced {
+      if node == 2 {
+         return;
+      }
+      info!("Charging policy changed externally to {node}, no longer restoring {p:?}");
+      l.set_forced_policy(None);
+   }
+   // An adaptive override holds the node at ADAPTIVE over the recorded policy.
+   if let Some(p) = l.adaptive {
+      if node == 3 {
+         return;
+      }
+      info!("Charging policy changed externally to {node}, dropping adaptive override of {p:?}");
+      l.set_adaptive_override(None);
+   }
+   if let Some(p) = l.policy {
+      if policy::kernel_value(p) != Some(node) {
+         info!("Charging policy changed externally to {node}, dropping recorded {p:?}");
+         l.forget_policy();
+      }
+   }
+}
+
+/// Re-evaluates the schedule; only a change of window touches the kernel.
+fn schedule_tick(limits: &Mutex<Limits>, now: LocalTime) {
+   let mut l = recover_limits(limits);
+   let active = schedule::active_at(&l.schedule, now);
+   if active == l.scheduled {
+      return;
+   }
+   info!("Charge limit window: {active:?}");
+   l.scheduled = active;
+   if let Err(e) = l.reapply() {
+      warn!("Failed to apply scheduled charge limit: {e:?}");
+   }
 }
 
 pub struct BatteryService {
-   limits: Mutex<Limits>,
+   limits:       Arc<Mutex<Limits>>,
+   // Fixed by the kernel build, so read once.
+   aacp_version: OnceLock<i32>,
+   defender_log: Arc<DefenderLog>,
+   temp_history: Arc<TempHistory>,
+   health_watch: Arc<HealthWatch>,
+   // Last health snapshot; the lock is held across all of its reads.
+   health_score: Mutex<Option<HealthScore>>,
+   worker:       Worker,
+   // Last state of a scheduled calibration; None once it ends or none is running.
+   calibration:  Arc<Mutex<Option<CalibrationState>>>,
+   call_counts:  CallCounter,
+   audit:        AuditLog,
+   monitor:      Monitor,
+   session:      Arc<SessionTracker>,
+   derating:     Arc<Derating>,
 }
 
-impl Interface for BatteryService {}
+impl Interface for BatteryService {
+   fn dump(&self, writer: &mut dyn Write, _args: &[&CStr]) -> std::result::Result<(), StatusCode> {
+      self.dump_state(writer).map_err(|e| {
+         error!("dump failed: {e}");
+         StatusCode::UNKNOWN_ERROR
+      })
+   }
+}
 
 impl BatteryService {
-   pub fn new() -> Self {
+   /// `base` overrides the config file's levels, e.g. from startup properties.
+   pub fn new(base: Option<Request>) -> Self {
       info!("Creating BatteryService");
+      let base = base.unwrap_or(Request {
+         stop:  config::get().default_stop,
+         start: config::get().default_start,
+      });
       Self {
-         limits: Mutex::new(Limits {
-            stop:  DEFAULT_STOP,
-            start: DEFAULT_START,
-         }),
+         limits: Arc::new(Mutex::new(Limits {
+            stop:        base.stop,
+            start:       base.start,
+            base,
+            clients:     BTreeMap::new(),
+            winner:      String::new(),
+            schedule:    schedule::load(&persist::data_path(schedule::SCHEDULE_FILE)),
+            scheduled:   None,
+            full_charge: full_charge::load(&persist::data_path(full_charge::STATE_FILE)),
+            paused:      adaptive_pause::load(&persist::data_path(adaptive_pause::STATE_FILE)),
+            policy:      policy::load(&persist::data_path(policy::STATE_FILE)),
+            adaptive:    policy::load(&persist::data_path(policy::OVERRIDE_FILE)),
+            forced:      policy::load(&persist::data_path(policy::FORCED_FILE)),
+         })),
+         aacp_version: OnceLock::new(),
+         defender_log: Arc::new(DefenderLog::load(persist::data_path(events::EVENTS_FILE))),
+         temp_history: Arc::new(TempHistory::load(persist::data_path(history::SETTINGS_FILE))),
+         health_watch: Arc::new(HealthWatch::load(persist::data_path(health_watch::HISTORY_FILE))),
+         health_score: Mutex::new(None),
+         worker: Worker::spawn(),
+         calibration: Arc::new(Mutex::new(None)),
+         call_counts: CallCounter::new(),
+         audit: AuditLog::new(),
+         monitor: Monitor::new(),
+         session: Arc::new(SessionTracker::new()),
+         derating: Arc::new(Derating::load(persist::data_path(derate::SETTINGS_FILE))),
       }
    }
 
-   fn apply_levels(&self, stop: i32, start: i32) -> Result<()> {
-      // Write stop first so the kernel's start-level bound checks against the new ceiling.
-      // The kernel stores these and applies them to the active limit only under LONGLIFE.
-      if paths::USER_CHARGE_STOP_LEVEL.exists() {
-         paths::USER_CHARGE_STOP_LEVEL
-            .write_int(stop)
-            .map_err(|e| sysfs_err(e, "write stop"))?;
-      }
-      if paths::USER_CHARGE_START_LEVEL.exists() {
-         paths::USER_CHARGE_START_LEVEL
-            .write_int(start)
-            .map_err(|e| sysfs_err(e, "write start"))?;
+   /// Counts a call; TracedBattery sees every call on its way in.
+   pub fn record_call(&self, uid: u32, method: &'static str) {
+      self.call_counts.record(uid, method);
+   }
+
+   /// Re-checks the lazy service hold; TracedBattery calls this after every
+   /// call, since any of them may have started or ended pending work.
+   pub fn update_lazy_hold(&self) {
+      lazy::hold(|| {
+         has_pending_work(&self.limits, &self.calibration, &self.temp_history, &self.derating)
+      });
+   }
+
+   /// Records or drops `tag`'s request and applies whichever request now wins.
+   fn update_client_limit(&self, tag: &str, request: Option<Request>) -> Result<()> {
+      let mut l = recover_limits(&self.limits);
+      match request {
+         Some(r) => {
+            l.clients.insert(tag.to_owned(), r);
+         },
+         None => {
+            l.clients.remove(tag);
+         },
       }
-      info!("Set charge levels: {stop}/{start}");
-      Ok(())
+      l.reapply()
    }
 
-   fn parse_health_stats(&self, algo: i32) -> Option<HealthStats> {
-      let content = paths::HEALTH_INDEX_STATS.read_string().ok()?;
-      for line in content.lines() {
-         let (a, rest) = line.split_once(':')?;
-         if a.trim().parse::<i32>().ok()? != algo {
-            continue;
+   fn dump_state(&self, w: &mut dyn Write) -> io::Result<()> {
+      {
+         let l = recover_limits(&self.limits);
+         writeln!(w, "Charge limit: {}/{}", l.stop, l.start)?;
+         for (tag, r) in &l.clients {
+            writeln!(w, "  client {tag}: {}/{}", r.stop, r.start)?;
          }
-         let v = rest
-            .split(|c: char| c == ',' || c.is_whitespace())
-            .filter_map(|s| s.parse().ok())
-            .collect::<Vec<_>>();
-
-         if v.len() >= 10 {
-            return Some(HealthStats {
-               algo,
-               healthIndex: v[0],
-               capacityFcc: v[1],
-               capacityRaw: v[2],
-               capacityDesign: v[3],
-               impedanceRaw: v[4],
-               impedanceAvg: v[5],
-               impedanceDesign: v[6],
-               cycleCount: v[7],
-               cycleCountDesign: v[8],
-               tempBucket: v[9],
-            });
+         for win in &l.schedule {
+            let mark = if l.scheduled == Some(*win) { " (active)" } else { "" };
+            writeln!(w, "  window {win:?}{mark}")?;
          }
+         if let Some(b) = l.full_charge {
+            writeln!(w, "  full charge bypass active, restores {b:?}")?;
+         }
+         if let Some(p) = l.paused {
+            writeln!(w, "  adaptive charging paused: {p:?}")?;
+         }
+         writeln!(
+            w,
+            "Charging policy: {} (requested {:?}, adaptive over {:?}, charge_to_limit over {:?})",
+            paths::CHARGING_POLICY.read_int_or(-1),
+            l.policy,
+            l.adaptive,
+            l.forced
+         )?;
+      }
+      writeln!(
+         w,
+         "Charge limit bypass mode: {}",
+         paths::CHARGE_LIMIT_BYPASS_MODE.read_int_or(0)
+      )?;
+      let raw = i64::from(paths::CHARGE_DEADLINE.read_int_or(0));
+      writeln!(
+         w,
+         "Charge deadline: raw={raw} ({:?}), boottime={:?}",
+         deadline::Convention::detect(raw),
+         deadline::boottime_sec()
+      )?;
+      let raw = paths::CHARGING_STATUS.read_int_or(-1);
+      let status = ChargingStatus::from_kernel(raw);
+      let held = if status.is_defender() { ", held by defender" } else { "" };
+      writeln!(w, "Charging status: {status:?} (raw {raw}{held})")?;
+      let kind = ChargingType(paths::CHARGING_TYPE.read_int_or(-1));
+      let link = if kind.is_wireless() {
+         "wireless"
+      } else if kind.is_wired() {
+         "wired"
+      } else {
+         "unknown"
+      };
+      writeln!(w, "Charging type: {} ({}, {link})", kind.label(), kind.0)?;
+      let speed = ChargingSpeedLevel::from_raw(paths::CHARGING_SPEED.read_int_or(-1));
+      match speed.typical_power_mw() {
+         Some(mw) => writeln!(w, "Charging speed: {speed:?} (~{mw} mW)")?,
+         None => writeln!(w, "Charging speed: {speed:?}")?,
+      }
+
+      let recent = self.defender_log.recent(5);
+      writeln!(w, "Recent defender events:")?;
+      for e in &recent {
+         writeln!(
+            w,
+            "  {} {:?} {:?} ({} ms)",
+            e.timestamp_ms, e.defender, e.kind, e.duration_ms
+         )?;
+      }
+
+      let sampling = self.temp_history.settings();
+      let samples = self.temp_history.samples();
+      writeln!(w, "Temperature history ({sampling:?}, {} samples):", samples.len())?;
+      for s in &samples {
+         writeln!(
+            w,
+            "  {} temp={} soc={} status={}",
+            s.timestamp_ms, s.temp_deci_c, s.soc, s.status
+         )?;
+      }
+
+      writeln!(w, "Panics recorded: {}", crash::count())?;
+      degraded::dump(w)?;
+      self.audit.dump(w)?;
+      self.monitor.dump(w)?;
+      writeln!(w, "Temperature derating: {:?}", self.derating.status())?;
+      let (hits, misses) = hot_cache::stats();
+      writeln!(w, "Hot getter cache: {hits} hits, {misses} misses")?;
+      writeln!(w, "Missing node calls (strict: {}):", missing::is_strict())?;
+      for (site, count) in missing::counts() {
+         writeln!(w, "  {}: {count}", site.method())?;
+      }
+
+      if let Some(score) = *self.health_score.lock_recover() {
+         writeln!(w, "Last health score: {score:?}")?;
+      }
+      for a in self.health_watch.alerts() {
+         writeln!(w, "Health alert: {a:?}")?;
+      }
+      if let Ok(status) = paths::HEALTH_STATUS.read_int() {
+         writeln!(w, "Health status: {}", HealthStatus(status).description())?;
+      }
+      let cal = CalibrationState(
+         paths::HEALTH_GET_CAL_STATE
+            .read_int_timeout(sysfs::SLOW_READ_TIMEOUT)
+            .unwrap_or(0),
+      );
+      let polling = self.calibration.lock_recover().is_some();
+      writeln!(w, "Calibration: {} (polling: {polling})", cal.description())?;
+
+      writeln!(w, "Features:")?;
+      if paths::AACC_CHG_PROFILE.exists() {
+         writeln!(
+            w,
+            "  AACC profile: {} of {}",
+            paths::AACC_CHG_PROFILE.read_int_or(-1),
+            paths::AACC_CHG_PROFILE_CNT.read_int_or(-1)
+         )?;
+      } else {
+         writeln!(w, "  AACC profile: unsupported")?;
+      }
+
+      let report = selftest::run();
+      writeln!(
+         w,
+         "Self test ({} ms{}):",
+         report.durationMs,
+         if report.truncated { ", truncated" } else { "" }
+      )?;
+      for e in &report.entries {
+         if !e.present {
+            writeln!(w, "  {}: absent", e.name)?;
+            continue;
+         }
+         let parse = match (e.typed, e.parseOk) {
+            (false, _) => "",
+            (true, true) => " parse=ok",
+            (true, false) => " parse=FAIL",
+         };
+         writeln!(
+            w,
+            "  {}: {} r={} w={}{parse} value='{}'",
+            e.name, e.path, e.readable as i32, e.writable as i32, e.value
+         )?;
+      }
+      Ok(())
+   }
+
+   fn low_boundary_format(&self) -> Result<LowBoundaryFormat> {
+      if !paths::HEALTH_SET_LOW_BOUNDARY.exists() {
+         return Err(unsupported("health low boundary not supported"));
+      }
+      let content = paths::HEALTH_SET_LOW_BOUNDARY
+         .read_string()
+         .map_err(|e| sysfs_err(e, "health low boundary"))?;
+      LowBoundaryFormat::detect(&content).ok_or_else(|| {
+         sysfs_err(
+            sysfs::Error::Parse {
+               path: paths::HEALTH_SET_LOW_BOUNDARY.primary.into(),
+               content,
+            },
+            "health low boundary",
+         )
+      })
+   }
+
+   fn apply_enable(&self, feature: Feature, enabled: bool) -> Result<()> {
+      match feature {
+         Feature::DOCK_DEFEND if paths::DD_SETTINGS.exists() => {
+            use features::DockDefendSetting;
+            let target = if enabled { DockDefendSetting::On } else { DockDefendSetting::Off };
+            let current = paths::DD_SETTINGS.read_string().ok();
+            if current.and_then(|v| DockDefendSetting::parse(&v)) == Some(target) {
+               return Ok(());
+            }
+            paths::DD_SETTINGS
+               .write_string_uncached(target.as_str())
+               .map_err(|e| sysfs_err(e, "dock defend"))
+         },
+         Feature::FG_CYCLE if paths::MAXFG_FIX_CYCLE_COUNT.exists() => {
+            // Some maxfg versions redo the correction on every write, so skip no-op writes.
+            let target = enabled as i32;
+            let current = paths::MAXFG_FIX_CYCLE_COUNT
+               .read_int()
+               .map_err(|e| sysfs_err(e, "fix cycle count"))?;
+            if current == target {
+               return Ok(());
+            }
+            paths::MAXFG_FIX_CYCLE_COUNT
+               .write_int_uncached(target)
+               .map_err(|e| sysfs_err(e, "fix cycle count"))
+         },
+         _ => Err(unsupported("feature not controllable")),
+      }
+   }
+
+   /// Applies the startup policy from properties. It is kept in memory only,
+   /// so a policy a client sets later is what the next start restores.
+   fn apply_default_policy(&self, p: ChargingPolicy) -> Result<()> {
+      let Some(val) = policy::kernel_value(p) else {
+         return Ok(());
+      };
+      let mut l = recover_limits(&self.limits);
+      // A persisted policy, pause or override wins, as does one a client set
+      // since start. A pause already restores ADAPTIVE when it ends.
+      if l.policy.is_some() || l.paused.is_some() || l.adaptive.is_some() || l.forced.is_some() {
+         info!("Skipping charging policy {p:?} from properties, keeping {:?}", l.policy);
+         return Ok(());
+      }
+      // Already in place from an earlier start, so a restart writes nothing.
+      if l.full_charge.is_none() && paths::CHARGING_POLICY.read_int().ok() == Some(val) {
+         l.policy = Some(p);
+         return Ok(());
+      }
+      let written = l.write_policy(val)?;
+      l.policy = Some(p);
+      if written && p == ChargingPolicy::CUSTOM {
+         apply_levels(l.stop, l.start)?;
+      }
+      Ok(())
+   }
+
+   /// Applies a startup feature state from properties. AACR isn't a setEnable
+   /// feature, so it goes through its state node; RUNNING counts as enabled.
+   fn apply_default_feature(&self, feature: Feature, enabled: bool) -> Result<()> {
+      if feature != Feature::AACR {
+         return self.apply_enable(feature, enabled);
+      }
+      let Some(path) = sysfs::get_property_sysfs(Feature::AACR, property::STATE)
+         .filter(|p| sysfs::exists(p))
+      else {
+         return Err(unsupported("AACR not supported"));
+      };
+      let target = if enabled { AacrState::Enabled } else { AacrState::Disabled };
+      let current = sysfs::read_int(path).ok().and_then(AacrState::from_i32);
+      if current == Some(target) || (enabled && current == Some(AacrState::Running)) {
+         return Ok(());
+      }
+      sysfs::write_string_uncached(path, &target.as_i32().to_string())
+         .map_err(|e| sysfs_err(e, "aacr state"))
+   }
+
+   /// Battery identity string from `path`, else the uevent `key`, else empty.
+   fn battery_info(&self, path: sysfs::SysfsPath, key: &str) -> String {
+      if let Ok(s) = path.read_string() {
+         return s;
+      }
+      paths::BATTERY_UEVENT
+         .read_string()
+         .ok()
+         .and_then(|u| sysfs::uevent_value(&u, key).map(str::to_owned))
+         .unwrap_or_default()
+   }
+
+   /// Maximum current the attached adapter advertises, if it reports one.
+   fn adapter_max_current_ma(&self) -> Option<i32> {
+      match paths::USB_CURRENT_MAX.read_int() {
+         Ok(ua) if ua > 0 => Some(ua / 1000),
+         _ => None,
+      }
+   }
+
+   fn parse_health_stats(&self, algo: i32) -> std::result::Result<HealthStats, ParseError> {
+      let content = self
+         .worker
+         .read(AsyncOp::HealthStatsPoll)
+         .map_err(ParseError::Read)?;
+      health_stats::parse(&content, algo)
+   }
+
+   /// Stats for `algo`, keeping whatever a short line did have.
+   fn health_stats_lenient(&self, algo: i32) -> Option<HealthStats> {
+      match self.parse_health_stats(algo) {
+         Ok(stats) | Err(ParseError::PartialData { stats, .. }) => Some(stats),
+         Err(e) => {
+            debug!("No health stats for algo {algo}: {e}");
+            None
+         },
       }
-      None
    }
 }
 
 impl IBattery for BatteryService {
    fn setChargingPolicy(&self, policy: ChargingPolicy) -> Result<()> {
-      let val = match policy {
-         ChargingPolicy::DEFAULT => 1,
-         ChargingPolicy::LONGLIFE | ChargingPolicy::CUSTOM => 2,
-         ChargingPolicy::ADAPTIVE => 3,
-         _ => return Err(bad_arg("invalid policy")),
+      let Some(val) = policy::kernel_value(policy) else {
+         return Err(bad_arg("invalid policy"));
       };
       if !paths::CHARGING_POLICY.exists() {
+         return missing_node(Site::ChargingPolicy, paths::CHARGING_POLICY);
+      }
+      let mut l = recover_limits(&self.limits);
+      // Rewriting an unchanged policy restarts the driver's state machine. A
+      // pause or bypass holds the node away from the request, so while one is
+      // active the call always goes through to end or update it.
+      let node = paths::CHARGING_POLICY.read_int_or(-1);
+      let held = l.paused.is_some()
+         || l.full_charge.is_some()
+         || l.adaptive.is_some()
+         || l.forced.is_some();
+      if policy::reported(node, l.policy) == policy && !held {
+         debug!("Charging policy already {policy:?}, skipping write");
          return Ok(());
       }
-      paths::CHARGING_POLICY
-         .write_int(val)
-         .map_err(|e| sysfs_err(e, "write policy"))?;
-      if policy == ChargingPolicy::CUSTOM {
-         let l = self.limits.lock().unwrap();
-         self.apply_levels(l.stop, l.start)?;
+      l.forget_pause();
+      // A pause can leave the node at the policy already, so that one is
+      // ended without the write too.
+      let written = match l.full_charge {
+         None if node == val => true,
+         _ => l.write_policy(val)?,
+      };
+      l.set_adaptive_override(None);
+      l.set_forced_policy(None);
+      l.record_policy(policy);
+      if written && policy == ChargingPolicy::CUSTOM {
+         apply_levels(l.stop, l.start)?;
       }
       Ok(())
    }
 
    fn getChargingPolicy(&self) -> Result<ChargingPolicy> {
-      Ok(ChargingPolicy(paths::CHARGING_POLICY.read_int_or(1)))
+      let node = paths::CHARGING_POLICY.read_int_or(1);
+      Ok(policy::reported(node, recover_limits(&self.limits).policy))
+   }
+
+   fn setChargeLimit(&self, stop: i32, start: i32) -> Result<()> {
+      self.setChargeLimitForClient(arbitration::DEFAULT_CLIENT, stop, start)
+   }
+
+   fn getChargeLimit(&self) -> Result<Vec<i32>> {
+      // The kernel is authoritative; the in-memory copy only covers devices without the
+      // nodes, and is refreshed here so a later CUSTOM re-apply doesn't undo an external write.
+      // The nodes are read without the limits lock, only LEVELS, so the copy is only refreshed if
+      // nothing changed it meanwhile, and not during a full charge bypass, whose lifted levels
+      // aren't the limit.
+      let seen = {
+         let l = recover_limits(&self.limits);
+         (l.stop, l.start)
+      };
+      let read = |path: sysfs::SysfsPath, what| {
+         if !path.exists() {
+            return Ok(None);
+         }
+         path.read_int().map(Some).map_err(|e| sysfs_err(e, what))
+      };
+      let (stop, start) = {
+         let _levels = LEVELS.lock_recover();
+         let stop = read(paths::USER_CHARGE_STOP_LEVEL, "read stop")?;
+         (stop, read(paths::USER_CHARGE_START_LEVEL, "read start")?)
+      };
+      let mut l = recover_limits(&self.limits);
+      if l.full_charge.is_none() && (l.stop, l.start) == seen {
+         l.stop = stop.unwrap_or(l.stop);
+         l.start = start.unwrap_or(l.start);
+      }
+      Ok(vec![stop.unwrap_or(seen.0), start.unwrap_or(seen.1)])
+   }
+
+   fn setChargeLimitForClient(&self, tag: &str, stop: i32, start: i32) -> Result<()> {
+      if tag.is_empty() {
+         return Err(bad_arg("client tag must not be empty"));
+      }
+      if tag == arbitration::CHARGE_TO_LIMIT_CLIENT {
+         return Err(bad_arg("client tag is reserved for setChargeToLimit"));
+      }
+      if stop == 0 {
+         return self.update_client_limit(tag, None);
+      }
+      validate::charge_limit(stop, start).map_err(|e| bad_arg(&e))?;
+      check_limit_bypass(stop)?;
+      self.update_client_limit(tag, Some(Request { stop, start }))
+   }
+
+   fn clearChargeLimitForClient(&self, tag: &str) -> Result<()> {
+      self.update_client_limit(tag, None)
+   }
+
+   fn getEffectiveChargeLimit(&self) -> Result<EffectiveChargeLimit> {
+      let l = recover_limits(&self.limits);
+      Ok(EffectiveChargeLimit {
+         stopLevel:  l.stop,
+         startLevel: l.start,
+         clientTag:  l.winner.clone(),
+      })
+   }
+
+   fn setChargeLimitSchedule(&self, schedule: &ChargeLimitSchedule) -> Result<()> {
+      let windows = schedule.windows.iter().map(Window::from).collect::<Vec<_>>();
+      schedule::validate(&windows).map_err(|e| bad_arg(&e))?;
+      for w in &windows {
+         validate::charge_limit(w.stop, w.start).map_err(|e| bad_arg(&e))?;
+      }
+      if let Err(e) = schedule::save(&persist::data_path(schedule::SCHEDULE_FILE), &windows) {
+         warn!("Failed to persist charge limit schedule: {e}");
+      }
+      info!("Set charge limit schedule: {} windows", windows.len());
+      {
+         let mut l = recover_limits(&self.limits);
+         l.schedule = windows;
+         // Force the next evaluation to apply, even if the same window is active.
+         l.scheduled = None;
+         l.reapply()?;
+      }
+      if let Some(now) = LocalTime::now() {
+         schedule_tick(&self.limits, now);
+      }
+      Ok(())
+   }
+
+   fn setChargeToLimit(&self, enabled: bool) -> Result<()> {
+      if !paths::CHARGE_TO_LIMIT.exists() {
+         return Err(unsupported("charge_to_limit not supported"));
+      }
+      let mut l = recover_limits(&self.limits);
+      let was_enabled = paths::CHARGE_TO_LIMIT
+         .read_int()
+         .map_err(|e| sysfs_err(e, "read charge_to_limit"))?
+         != 0;
+      paths::CHARGE_TO_LIMIT
+         .write_int(enabled as i32)
+         .map_err(|e| sysfs_err(e, "write charge_to_limit"))?;
+      if enabled {
+         l.clients.insert(
+            arbitration::CHARGE_TO_LIMIT_CLIENT.to_owned(),
+            arbitration::CHARGE_TO_LIMIT_REQUEST,
+         );
+         if paths::CHARGING_POLICY.exists() {
+            l.force_longlife()?;
+         }
+      } else {
+         l.clients.remove(arbitration::CHARGE_TO_LIMIT_CLIENT);
+         l.release_longlife()?;
+      }
+      l.reapply()?;
+      // Have the fuel gauge re-learn full capacity against the new ceiling.
+      if was_enabled != enabled && paths::FORCE_FCR_UPDATE_OPS.exists() {
+         paths::FORCE_FCR_UPDATE_OPS
+            .write_int(1)
+            .map_err(|e| sysfs_err(e, "force fcr update"))?;
+      }
+      info!("charge_to_limit: {enabled}");
+      Ok(())
+   }
+
+   fn getChargeToLimit(&self) -> Result<bool> {
+      if !paths::CHARGE_TO_LIMIT.exists() {
+         return Ok(false);
+      }
+      let node = paths::CHARGE_TO_LIMIT
+         .read_int()
+         .map_err(|e| sysfs_err(e, "read charge_to_limit"))?;
+      Ok(node != 0 && paths::CHARGING_POLICY.read_int_or(2) == 2)
+   }
+
+   fn requestFullChargeOnce(&self) -> Result<()> {
+      let mut l = recover_limits(&self.limits);
+      if l.full_charge.is_some() {
+         return Ok(());
+      }
+      let b = Bypass {
+         stop:         l.stop,
+         start:        l.start,
+         policy:       paths::CHARGING_POLICY.read_int_or(1),
+         plugged_seen: false,
+      };
+      save_full_charge(&b);
+      l.full_charge = Some(b);
+      info!("Full charge bypass requested, will restore {b:?}");
+      lift_limit()
    }
 
-   fn setChargeLimit(&self, stop: i32, start: i32) -> Result<()> {
-      if !(50..=100).contains(&stop) {
-         return Err(bad_arg("stop must be 50-100"));
-      }
-      if stop - start < 5 {
-         return Err(bad_arg("gap must be >= 5"));
+   fn setEnable(&self, feature: Feature, enabled: bool) -> Result<()> {
+      if !features::is_controllable(feature) {
+         return Err(unsupported(&format!("{feature:?} not controllable")));
       }
-      {
-         let mut l = self.limits.lock().unwrap();
-         l.stop = stop;
-         l.start = start;
+      if enabled {
+         if let Some(dep) = features::check_feature_deps(feature) {
+            return Err(Status::new_exception_str(
+               ExceptionCode::ILLEGAL_STATE,
+               Some(&format!("{feature:?} requires {dep:?} to be enabled first")),
+            ));
+         }
       }
-      self.apply_levels(stop, start)
+      self.apply_enable(feature, enabled)
    }
 
-   fn getChargeLimit(&self) -> Result<Vec<i32>> {
-      let l = self.limits.lock().unwrap();
-      Ok(vec![
-         paths::USER_CHARGE_STOP_LEVEL.read_int_or(l.stop),
-         paths::USER_CHARGE_START_LEVEL.read_int_or(l.start),
-      ])
-   }
+   fn setEnableFeatures(&self, list: &[Feature], enabled: bool) -> Result<()> {
+      if let Some(f) = list.iter().find(|f| !features::is_controllable(**f)) {
+         return Err(unsupported(&format!("{f:?} not controllable")));
+      }
+      if enabled {
+         // Prerequisites in the same batch are enabled first, so only outside ones must be on.
+         for f in list {
+            if let Some(dep) = features::check_feature_deps(*f).filter(|d| !list.contains(d)) {
+               return Err(Status::new_exception_str(
+                  ExceptionCode::ILLEGAL_STATE,
+                  Some(&format!("{f:?} requires {dep:?} to be enabled first")),
+               ));
+            }
+         }
+      }
 
-   fn setEnable(&self, feature: Feature, enabled: bool) -> Result<()> {
-      match feature {
-         Feature::DOCK_DEFEND if paths::DD_SETTINGS.exists() => {
-            paths::DD_SETTINGS
-               .write_string(if enabled { "B2" } else { "1M" })
-               .map_err(|e| sysfs_err(e, "dock defend"))
-         },
-         _ => Err(unsupported("feature not controllable")),
+      let mut applied = Vec::new();
+      for f in features::apply_order(list, enabled) {
+         let was_enabled = features::is_enabled(f);
+         if let Err(e) = self.apply_enable(f, enabled) {
+            let mut rolled_back = Vec::new();
+            let mut rollback_failed = Vec::new();
+            for (done, prev) in applied.iter().rev() {
+               match self.apply_enable(*done, *prev) {
+                  Ok(()) => rolled_back.push(*done),
+                  Err(_) => rollback_failed.push(*done),
+               }
+            }
+            let msg = format!(
+               "{f:?} failed: {}; succeeded {:?}, rolled back {rolled_back:?}, rollback failed \
+                {rollback_failed:?}",
+               e.get_description(),
+               applied.iter().map(|(f, _)| *f).collect::<Vec<_>>()
+            );
+            error!("setEnableFeatures: {msg}");
+            return Err(match e.exception_code() {
+               ExceptionCode::SERVICE_SPECIFIC => {
+                  Status::new_service_specific_error_str(e.service_specific_error(), Some(&msg))
+               },
+               code => Status::new_exception_str(code, Some(&msg)),
+            });
+         }
+         applied.push((f, was_enabled));
       }
+      Ok(())
    }
 
    fn clearBatteryDefenders(&self, kind: DefenderType) -> Result<()> {
@@ -201,82 +1561,500 @@ impl IBattery for BatteryService {
       let clear_dock = || -> Result<()> {
          if paths::DD_STATE.read_int_or(0) == 1 && paths::DD_SETTINGS.exists() {
             paths::DD_SETTINGS
-               .write_string("02")
+               .write_string(features::DOCK_DEFEND_CLEAR)
                .map_err(|e| sysfs_err(e, "clear dock"))?;
          }
          Ok(())
       };
-      match kind {
-         DefenderType::ALL => {
-            clear_temp()?;
-            clear_trickle()?;
-            clear_dwell()?;
-            clear_dock()?;
+      let stash = Stash {
+         reset_sec: match kind {
+            DefenderType::ALL | DefenderType::TRICKLE => paths::BD_TRICKLE_RESET_SEC.read_int_or(0),
+            _ => 0,
          },
-         DefenderType::TEMP => clear_temp()?,
-         DefenderType::TRICKLE => clear_trickle()?,
-         DefenderType::DWELL => clear_dwell()?,
-         DefenderType::DOCK => clear_dock()?,
-         _ => {},
+         rate:      match kind {
+            DefenderType::ALL | DefenderType::DWELL => paths::BD_TRICKLE_RATE.read_int_or(0),
+            _ => 0,
+         },
+      };
+      if !stash.is_empty() {
+         let path = persist::data_path(defender_stash::STATE_FILE);
+         if let Err(e) = defender_stash::save(&path, &stash) {
+            warn!("Failed to persist defender stash: {e}");
+         }
+      }
+      let cleared = match kind {
+         DefenderType::ALL => clear_temp()
+            .and_then(|()| clear_trickle())
+            .and_then(|()| clear_dwell())
+            .and_then(|()| clear_dock()),
+         DefenderType::TEMP => clear_temp(),
+         DefenderType::TRICKLE => clear_trickle(),
+         DefenderType::DWELL => clear_dwell(),
+         DefenderType::DOCK => clear_dock(),
+         _ => return Ok(()),
+      };
+      if !stash.is_empty() {
+         if !defender_stash::wait_for_drop() {
+            warn!("Trickle defender still active after clear, restoring anyway");
+         }
+         restore_stash(stash);
+      }
+      cleared?;
+      self.defender_log.user_cleared(kind, persist::now_ms());
+      Ok(())
+   }
+
+   fn getBatteryDefenderSummary(&self) -> Result<BatteryDefenderSummary> {
+      let [(_, temp), (_, trickle), _] = monitor::defender_states();
+      let summary = BatteryDefenderSummary {
+         tempActive:    temp,
+         trickleActive: trickle,
+         trickleCnt:    paths::BD_TRICKLE_CNT.read_int_or(0),
+         dwellRate:     paths::BD_TRICKLE_RATE.read_int_or(0),
+         dockStatus:    dock_defend_status(),
+      };
+      let active = [
+         ("temp", summary.tempActive),
+         ("trickle", summary.trickleActive),
+         ("dock", summary.dockStatus == DockDefendStatus::ACTIVE),
+      ];
+      debug!(
+         "Active defenders: {:?}",
+         active.iter().filter(|(_, a)| *a).map(|(name, _)| *name).collect::<Vec<_>>()
+      );
+      Ok(summary)
+   }
+
+   fn getDefenderEvents(&self, max_count: i32) -> Result<Vec<DefenderEvent>> {
+      let max = usize::try_from(max_count).map_err(|_| bad_arg("maxCount must be >= 0"))?;
+      Ok(self
+         .defender_log
+         .recent(max)
+         .into_iter()
+         .map(DefenderEvent::from)
+         .collect())
+   }
+
+   fn setTempDefendConfig(&self, config: &TempDefendConfig) -> Result<()> {
+      validate::temp_defend_config(config).map_err(|e| bad_arg(&e))?;
+      if !paths::BD_TRIGGER_TEMP.exists() {
+         return Err(unsupported("temp defend not supported"));
+      }
+      // Resume thresholds go first and the trigger temperature last, so the
+      // defender never arms against resume values from the old set.
+      let writes = [
+         (paths::BD_RESUME_TEMP, config.resumeTempDeciC, "resume temp"),
+         (paths::BD_RESUME_SOC, config.resumeSoc, "resume soc"),
+         (paths::BD_RESUME_TIME, config.resumeTimeSec, "resume time"),
+         (paths::BD_RECHARGE_SOC, config.rechargeSoc, "recharge soc"),
+         (paths::BD_TRIGGER_VOLTAGE, config.triggerVoltageUv, "trigger voltage"),
+         (paths::BD_TRIGGER_TIME, config.triggerTimeSec, "trigger time"),
+         (paths::BD_TRIGGER_TEMP, config.triggerTempDeciC, "trigger temp"),
+      ];
+      let mut journal = Journal::new();
+      for (path, value, ctx) in writes {
+         if let Err(e) = journal.write_int_verified(path, value) {
+            let failed = journal.rollback();
+            if !failed.is_empty() {
+               error!("Temp defend left partially written: {failed:?}");
+            }
+            return Err(sysfs_err(e, ctx));
+         }
+      }
+      info!("Set temp defend config: {config:?}");
+      Ok(())
+   }
+
+   fn getTempDefendConfig(&self) -> Result<TempDefendConfig> {
+      if !paths::BD_TRIGGER_TEMP.exists() {
+         return Err(unsupported("temp defend not supported"));
+      }
+      let read = |path: sysfs::SysfsPath, ctx| path.read_int().map_err(|e| sysfs_err(e, ctx));
+      Ok(TempDefendConfig {
+         triggerTempDeciC: read(paths::BD_TRIGGER_TEMP, "trigger temp")?,
+         triggerTimeSec:   read(paths::BD_TRIGGER_TIME, "trigger time")?,
+         triggerVoltageUv: read(paths::BD_TRIGGER_VOLTAGE, "trigger voltage")?,
+         rechargeSoc:      read(paths::BD_RECHARGE_SOC, "recharge soc")?,
+         resumeSoc:        read(paths::BD_RESUME_SOC, "resume soc")?,
+         resumeTempDeciC:  read(paths::BD_RESUME_TEMP, "resume temp")?,
+         resumeTimeSec:    read(paths::BD_RESUME_TIME, "resume time")?,
+      })
+   }
+
+   fn setTrickleDefendConfig(&self, config: &TrickleDefendConfig) -> Result<()> {
+      let stop = recover_limits(&self.limits).stop;
+      validate::trickle_defend_config(config, stop).map_err(|e| bad_arg(&e))?;
+      if !paths::BD_TRICKLE_RATE.exists() {
+         return Err(unsupported("trickle defend not supported"));
+      }
+      let version = paths::BD_TRICKLE_VERSION.read_int_or(1);
+      let mut writes = vec![
+         (paths::BD_TRICKLE_RATE, config.rate, "trickle rate"),
+         (paths::BD_TRICKLE_RECHARGE_SOC, config.rechargeSoc, "trickle recharge soc"),
+         (paths::BD_TRICKLE_RESET_SEC, config.resetSec, "trickle reset sec"),
+      ];
+      if version >= TRICKLE_CNT_THR_VERSION {
+         writes.push((paths::BD_TRICKLE_CNT_THR, config.cntThreshold, "trickle cnt thr"));
+      } else if config.cntThreshold != TrickleDefendConfig::default().cntThreshold {
+         info!("Trickle version {version} has no count threshold, ignoring it");
       }
+      let mut journal = Journal::new();
+      for (path, value, ctx) in writes {
+         if !path.exists() {
+            continue;
+         }
+         if let Err(e) = journal.write_int_verified(path, value) {
+            let failed = journal.rollback();
+            if !failed.is_empty() {
+               error!("Trickle defend left partially written: {failed:?}");
+            }
+            return Err(sysfs_err(e, ctx));
+         }
+      }
+      info!("Set trickle defend config (version {version}): {config:?}");
       Ok(())
    }
 
+   fn getTrickleDefendConfig(&self) -> Result<TrickleDefendConfig> {
+      if !paths::BD_TRICKLE_RATE.exists() {
+         return Err(unsupported("trickle defend not supported"));
+      }
+      let defaults = TrickleDefendConfig::default();
+      let mut config = TrickleDefendConfig {
+         rate:        paths::BD_TRICKLE_RATE.read_int_or(defaults.rate),
+         rechargeSoc: paths::BD_TRICKLE_RECHARGE_SOC.read_int_or(defaults.rechargeSoc),
+         resetSec:    paths::BD_TRICKLE_RESET_SEC.read_int_or(defaults.resetSec),
+         ..defaults
+      };
+      if paths::BD_TRICKLE_VERSION.read_int_or(1) >= TRICKLE_CNT_THR_VERSION {
+         config.cntThreshold = paths::BD_TRICKLE_CNT_THR.read_int_or(config.cntThreshold);
+      }
+      Ok(config)
+   }
+
    fn getStringProperty(&self, feature: Feature, prop: i32) -> Result<String> {
-      if prop >= 51 {
+      if prop > property::MAX_PROPERTY_ID {
          return Err(bad_arg("property out of range"));
       }
       match sysfs::get_property_sysfs(feature, prop) {
-         Some(path) if std::path::Path::new(path).exists() => {
-            sysfs::read_string(path).map_err(|e| sysfs_err(e, "getStringProperty"))
-         }
-         _ => Ok(String::new()),
+         Some(_) if feature == Feature::FW_UPDATE && prop == property::UPDATE_FIRMWARE => {
+            Ok(self.worker.read(AsyncOp::FirmwareStatusPoll).unwrap_or_default())
+         },
+         Some(path) => match sysfs::read_string(path) {
+            Err(sysfs::Error::NotFound { .. }) => Ok(String::new()),
+            result => result.map_err(|e| sysfs_err(e, "getStringProperty")),
+         },
+         None => Ok(String::new()),
       }
    }
 
    fn setStringProperty(&self, feature: Feature, prop: i32, value: &str) -> Result<()> {
-      if prop >= 51 {
+      if prop > property::MAX_PROPERTY_ID {
          return Err(bad_arg("property out of range"));
       }
-      match sysfs::get_property_sysfs(feature, prop) {
-         Some(path) if std::path::Path::new(path).exists() => {
-            sysfs::write_string(path, value).map_err(|e| sysfs_err(e, "setStringProperty"))
-         }
-         _ => Ok(()), // Silently succeed if path not configured (matches Google behavior)
+      let value = validate::property(feature, prop, value).map_err(|e| bad_arg(&e))?;
+      if feature == Feature::AACR && prop == property::STATE {
+         check_aacr_transition(&value)?;
+      }
+      // Silently succeed if the path isn't configured or the node is missing
+      // (matches Google behavior).
+      let Some(path) = sysfs::get_property_sysfs(feature, prop) else {
+         return Ok(());
+      };
+      let old_value = audit::old_value(path);
+      let written = sysfs::write_string(path, &value);
+      if let Err(sysfs::Error::NotFound { .. }) = written {
+         return Ok(());
       }
+      self.audit.record(AuditEntry {
+         at_ms: 0,
+         uid: permission::calling_uid(),
+         feature,
+         prop,
+         path,
+         old_value,
+         new_value: value.clone(),
+         ok: written.is_ok(),
+      });
+      if feature == Feature::FW_UPDATE && prop == property::UPDATE_FIRMWARE {
+         metrics::emit(Metric::FirmwareUpdate { ok: written.is_ok() });
+      }
+      written.map_err(|e| sysfs_err(e, "setStringProperty"))
    }
 
    fn getChargingStatus(&self) -> Result<ChargingStatus> {
-      Ok(ChargingStatus(paths::CHARGING_STATUS.read_int_or(-1)))
+      Ok(ChargingStatus(hot_cache::get(hot_cache::Key::Status, || {
+         let raw = paths::CHARGING_STATUS.read_int_or(-1);
+         let status = ChargingStatus::from_kernel(raw);
+         // Once per new code, since SystemUI polls this constantly.
+         static LAST_UNMAPPED: AtomicI32 = AtomicI32::new(-1);
+         if status == ChargingStatus::UNKNOWN && LAST_UNMAPPED.swap(raw, Ordering::Relaxed) != raw {
+            warn!("Unmapped charging_status {raw}");
+         }
+         status.0
+      })))
    }
 
    fn getChargingType(&self) -> Result<ChargingType> {
-      Ok(ChargingType(paths::CHARGING_TYPE.read_int_or(-1)))
+      Ok(ChargingType(hot_cache::get(hot_cache::Key::Type, || {
+         paths::CHARGING_TYPE.read_int_or(-1)
+      })))
    }
 
    fn getChargingSpeed(&self) -> Result<i32> {
-      Ok(paths::CHARGING_SPEED.read_int_or(0))
+      Ok(hot_cache::get(hot_cache::Key::Speed, || {
+         paths::CHARGING_SPEED.read_int_or(0)
+      }))
+   }
+
+   fn getChargeStats(&self) -> Result<Vec<ChargeSessionStats>> {
+      if !paths::CHARGE_STATS.exists() {
+         return Err(unsupported("charge stats not supported"));
+      }
+      let content = self
+         .worker
+         .read(AsyncOp::SessionSample)
+         .map_err(|e| sysfs_err(e, "charge stats"))?;
+      charge_stats::parse(&content).map_err(|msg| {
+         sysfs_err(
+            sysfs::Error::Parse {
+               path: paths::CHARGE_STATS.primary.into(),
+               content: msg,
+            },
+            "charge stats",
+         )
+      })
+   }
+
+   fn getChargingSession(&self) -> Result<ChargingSession> {
+      Ok(self.session.current())
+   }
+
+   fn getChargingSpeedLevel(&self) -> Result<ChargingSpeedLevel> {
+      Ok(ChargingSpeedLevel::from_raw(paths::CHARGING_SPEED.read_int_or(-1)))
+   }
+
+   fn getMaxChargingSpeed(&self) -> Result<i32> {
+      // The config file wins, for kernels that report it wrong or not at all.
+      match config::get().max_charging_speed {
+         -1 if !paths::MAX_CHARGING_SPEED.exists() => Ok(ChargingSpeedLevel::UNKNOWN.0),
+         -1 => {
+            let raw = paths::MAX_CHARGING_SPEED
+               .read_int()
+               .map_err(|e| sysfs_err(e, "max charging speed"))?;
+            Ok(ChargingSpeedLevel::from_raw(raw).0)
+         },
+         level => Ok(level),
+      }
+   }
+
+   fn setChargingCurrentLimit(&self, ma: i32) -> Result<()> {
+      if ma < MIN_CHARGE_CURRENT_MA {
+         return Err(bad_arg(&format!("current must be >= {MIN_CHARGE_CURRENT_MA} mA")));
+      }
+      if let Some(max) = self.adapter_max_current_ma() {
+         if ma > max {
+            return Err(bad_arg(&format!("{ma} mA exceeds adapter capability of {max} mA")));
+         }
+      }
+      let ua = ma
+         .checked_mul(1000)
+         .ok_or_else(|| bad_arg("current out of range"))?;
+      if !paths::CONSTANT_CHARGE_CURRENT_MAX.exists() {
+         return Err(unsupported("charge current control not supported"));
+      }
+      paths::CONSTANT_CHARGE_CURRENT_MAX
+         .write_int_verified(ua)
+         .map_err(|e| sysfs_err(e, "write charge current"))?;
+      info!("Set charging current limit: {ma} mA");
+      Ok(())
+   }
+
+   fn getChargingCurrentLimit(&self) -> Result<i32> {
+      paths::CONSTANT_CHARGE_CURRENT_MAX
+         .read_int()
+         .map(|ua| ua / 1000)
+         .map_err(|e| sysfs_err(e, "read charge current"))
+   }
+
+   fn setTemperatureDerating(
+      &self,
+      enabled: bool,
+      threshold_deci_c: i32,
+      reduction_percent: i32,
+   ) -> Result<()> {
+      if !derate::THRESHOLD_RANGE.contains(&threshold_deci_c) {
+         return Err(bad_arg(&format!(
+            "thresholdDeciC {threshold_deci_c} outside {:?}",
+            derate::THRESHOLD_RANGE
+         )));
+      }
+      if !derate::REDUCTION_RANGE.contains(&reduction_percent) {
+         return Err(bad_arg(&format!(
+            "reductionPercent {reduction_percent} outside {:?}",
+            derate::REDUCTION_RANGE
+         )));
+      }
+      if enabled && !paths::INPUT_CURRENT_LIMIT.exists() {
+         return Err(unsupported("input current limit not supported"));
+      }
+      self
+         .derating
+         .configure(derate::Settings {
+            enabled,
+            threshold_deci_c,
+            reduction_pct: reduction_percent,
+         })
+         .map_err(|e| sysfs_err(e, "restore input current limit"))
+   }
+
+   fn getTemperatureDeratingStatus(&self) -> Result<DeratingStatus> {
+      Ok(self.derating.status())
+   }
+
+   fn setReverseWirelessCharging(&self, enabled: bool) -> Result<()> {
+      if !paths::REVERSE_WIRELESS_ENABLE.exists() {
+         return Err(unsupported("reverse wireless charging not supported"));
+      }
+      if enabled {
+         let capacity = capacity_percent().map_err(|e| sysfs_err(e, "battery capacity"))?;
+         if capacity <= REVERSE_WIRELESS_MIN_CAPACITY {
+            return Err(bad_arg(&format!(
+               "battery at {capacity}%, must be above {REVERSE_WIRELESS_MIN_CAPACITY}%"
+            )));
+         }
+      }
+      paths::REVERSE_WIRELESS_ENABLE
+         .write_int(enabled as i32)
+         .map_err(|e| sysfs_err(e, "reverse wireless"))?;
+      info!("Reverse wireless charging: {enabled}");
+      Ok(())
+   }
+
+   fn isReverseWirelessChargingSupported(&self) -> Result<bool> {
+      Ok(paths::REVERSE_WIRELESS_ENABLE.exists())
    }
 
    fn setChargingDeadline(&self, deadline: i32) -> Result<()> {
+      if let Some(now) = deadline::boottime_sec() {
+         deadline::validate(i64::from(deadline), now).map_err(|e| bad_arg(&e))?;
+      }
+      let mut l = recover_limits(&self.limits);
+      if let Some(p) = l.paused.as_mut() {
+         // Kept for the resume; the kernel isn't running adaptive charging right now.
+         let remaining = match deadline::boottime_sec() {
+            Some(now) => deadline::seconds_remaining(i64::from(deadline), now),
+            None => i64::from(deadline),
+         };
+         *p = Pause {
+            plugged_seen: p.plugged_seen,
+            ..Pause::new(remaining, persist::now_ms())
+         };
+         save_pause(p);
+         return Ok(());
+      }
       paths::CHARGE_DEADLINE
          .write_int(deadline)
          .map_err(|e| sysfs_err(e, "write deadline"))
    }
 
+   fn setChargingDeadlineLocal(&self, seconds_from_now: i32) -> Result<()> {
+      if !(deadline::LOCAL_MIN_SEC..=deadline::LOCAL_MAX_SEC).contains(&seconds_from_now) {
+         return Err(bad_arg(&format!(
+            "seconds_from_now {seconds_from_now} outside {}-{}",
+            deadline::LOCAL_MIN_SEC,
+            deadline::LOCAL_MAX_SEC
+         )));
+      }
+      // Relative values are the kernel's own convention, so no clock is involved.
+      self.setChargingDeadline(seconds_from_now)
+   }
+
+   fn setAdaptiveChargingPaused(&self, paused: bool) -> Result<()> {
+      if !paths::CHARGING_POLICY.exists() {
+         return Err(unsupported("charging policy not supported"));
+      }
+      let mut l = recover_limits(&self.limits);
+      if paused {
+         l.pause_adaptive()
+      } else {
+         l.resume_adaptive()
+      }
+   }
+
+   fn setAdaptiveChargingEnabled(&self, enabled: bool) -> Result<()> {
+      if !paths::CHARGING_POLICY.exists() {
+         return missing_node(Site::ChargingPolicy, paths::CHARGING_POLICY);
+      }
+      let mut l = recover_limits(&self.limits);
+      if enabled {
+         l.start_adaptive_override()
+      } else {
+         l.end_adaptive_override()
+      }
+   }
+
+   fn isAdaptiveChargingPaused(&self) -> Result<bool> {
+      Ok(recover_limits(&self.limits).paused.is_some())
+   }
+
+   fn getChargeStageEnum(&self) -> Result<ChargeStage> {
+      let raw = paths::CHARGE_STAGE.read_string().unwrap_or_default();
+      Ok(ChargeStage::from(&deadline::check_stage(&raw)))
+   }
+
    fn getChargingStageAndDeadline(&self) -> Result<ChargingStage> {
+      let stage = paths::CHARGE_STAGE.read_string().unwrap_or_default();
+      deadline::check_stage(&stage);
+      let raw = i64::from(paths::CHARGE_DEADLINE.read_int_or(0));
+      let remaining = match deadline::boottime_sec() {
+         Some(now) => deadline::seconds_remaining(raw, now),
+         None => raw,
+      };
       Ok(ChargingStage {
-         stage:    paths::CHARGE_STAGE.read_string().unwrap_or_default(),
-         deadline: paths::CHARGE_DEADLINE.read_int_or(0),
+         stage,
+         deadline: remaining.try_into().unwrap_or(i32::MAX),
       })
    }
 
+   fn setHealthSafetyMargin(&self, seconds: i32) -> Result<()> {
+      validate::health_safety_margin(seconds).map_err(|e| bad_arg(&e))?;
+      if !paths::HEALTH_SAFETY_MARGIN.exists() {
+         return Err(unsupported("health safety margin not supported"));
+      }
+      paths::HEALTH_SAFETY_MARGIN
+         .write_int_verified(seconds)
+         .map_err(|e| sysfs_err(e, "health safety margin"))
+   }
+
+   fn getHealthSafetyMargin(&self) -> Result<i32> {
+      if !paths::HEALTH_SAFETY_MARGIN.exists() {
+         return Err(unsupported("health safety margin not supported"));
+      }
+      paths::HEALTH_SAFETY_MARGIN
+         .read_int()
+         .map_err(|e| sysfs_err(e, "health safety margin"))
+   }
+
    fn getHealthIndex(&self) -> Result<i32> {
-      Ok(paths::HEALTH_INDEX.read_int_or(100))
+      // Without the health driver there is no index; reporting 100 would show
+      // a perfect battery.
+      if !paths::HEALTH_INDEX.exists() {
+         return Ok(-1);
+      }
+      paths::HEALTH_INDEX
+         .read_int()
+         .map_err(|e| sysfs_err(e, "health index"))
    }
 
    fn getHealthStatus(&self) -> Result<HealthStatus> {
-      Ok(HealthStatus(paths::HEALTH_STATUS.read_int_or(0)))
+      if !paths::HEALTH_STATUS.exists() {
+         return Ok(HealthStatus::UNSUPPORTED);
+      }
+      paths::HEALTH_STATUS
+         .read_int()
+         .map(HealthStatus)
+         .map_err(|e| sysfs_err(e, "health status"))
    }
 
    fn getHealthCapacityIndex(&self) -> Result<i32> {
@@ -292,12 +2070,57 @@ impl IBattery for BatteryService {
    }
 
    fn getHealthStats(&self, algo: HealthAlgo) -> Result<HealthStats> {
-      let algo_int = match algo {
-         HealthAlgo::GOOGLE => 1,
-         HealthAlgo::MAXIM => 2,
-         _ => 0,
+      // HealthAlgo values are the kernel's algo IDs, so new algos need no mapping here.
+      Ok(self.health_stats_lenient(algo.0).unwrap_or_default())
+   }
+
+   fn getDetailedHealthScore(&self) -> Result<DetailedHealthScore> {
+      let mut cached = self.health_score.lock_recover();
+      let capacity = paths::HEALTH_CAPACITY_INDEX
+         .read_int()
+         .map_err(|e| sysfs_err(e, "capacity index"))?;
+      let impedance = paths::HEALTH_IMPEDANCE_INDEX
+         .read_int()
+         .map_err(|e| sysfs_err(e, "impedance index"))?;
+      let algo = paths::HEALTH_ALGO.read_int_or(1);
+      let temp_bucket = self
+         .health_stats_lenient(algo)
+         .map_or(0, |s| s.tempBucket);
+      let score = HealthScore {
+         composite: (capacity * HEALTH_CAPACITY_WEIGHT + impedance * HEALTH_IMPEDANCE_WEIGHT) / 100,
+         capacity,
+         impedance,
+         temp_bucket,
       };
-      Ok(self.parse_health_stats(algo_int).unwrap_or_default())
+      *cached = Some(score);
+      Ok(score.into())
+   }
+
+   fn getCsiStats(&self) -> Result<CsiStats> {
+      if !paths::CSI_STATS.exists() {
+         return Err(unsupported("CSI stats not supported"));
+      }
+      let content = paths::CSI_STATS
+         .read_string()
+         .map_err(|e| sysfs_err(e, "csi stats"))?;
+      csi::parse(&content).map_err(|msg| {
+         sysfs_err(
+            sysfs::Error::Parse {
+               path: paths::CSI_STATS.primary.into(),
+               content: msg,
+            },
+            "csi stats",
+         )
+      })
+   }
+
+   fn getHealthAlerts(&self) -> Result<Vec<HealthAlert>> {
+      Ok(self
+         .health_watch
+         .alerts()
+         .into_iter()
+         .map(HealthAlert::from)
+         .collect())
    }
 
    fn setHealthAlwaysOn(&self, value: i32) -> Result<()> {
@@ -306,9 +2129,46 @@ impl IBattery for BatteryService {
          .map_err(|e| sysfs_err(e, "write charge_limit"))
    }
 
+   fn setHealthTrendPoints(&self, points: &[i32]) -> Result<()> {
+      validate::health_trend_points(points).map_err(|e| bad_arg(&e))?;
+      if !paths::HEALTH_SET_TREND_POINTS.exists() {
+         return Err(unsupported("health trend points not supported"));
+      }
+      paths::HEALTH_SET_TREND_POINTS
+         .write_ints_verified(points)
+         .map_err(|e| sysfs_err(e, "health trend points"))
+   }
+
+   fn setHealthLowBoundary(&self, boundary_pct: i32) -> Result<()> {
+      validate::health_low_boundary(boundary_pct).map_err(|e| bad_arg(&e))?;
+      match self.low_boundary_format()? {
+         LowBoundaryFormat::Percent => {
+            paths::HEALTH_SET_LOW_BOUNDARY.write_int_verified(boundary_pct)
+         },
+         // Keep whichever boundary the kernel currently has selected.
+         LowBoundaryFormat::Indexed { index } => {
+            paths::HEALTH_SET_LOW_BOUNDARY.write_ints_verified(&[index, boundary_pct])
+         },
+      }
+      .map_err(|e| sysfs_err(e, "health low boundary"))
+   }
+
+   fn setHealthLowBoundaryIndexed(&self, index: i32, boundary_pct: i32) -> Result<()> {
+      if index < 0 {
+         return Err(bad_arg("index must be >= 0"));
+      }
+      validate::health_low_boundary(boundary_pct).map_err(|e| bad_arg(&e))?;
+      match self.low_boundary_format()? {
+         LowBoundaryFormat::Percent => Err(unsupported("kernel takes a plain percentage")),
+         LowBoundaryFormat::Indexed { .. } => paths::HEALTH_SET_LOW_BOUNDARY
+            .write_ints_verified(&[index, boundary_pct])
+            .map_err(|e| sysfs_err(e, "health low boundary")),
+      }
+   }
+
    fn scheduleCalibration(&self, mode: CalibrationMode) -> Result<()> {
       if !paths::HEALTH_SET_CAL_MODE.exists() {
-         return Ok(());
+         return missing_node(Site::Calibration, paths::HEALTH_SET_CAL_MODE);
       }
       let v = match mode {
          CalibrationMode::DISABLED => 0,
@@ -318,37 +2178,448 @@ impl IBattery for BatteryService {
       };
       paths::HEALTH_SET_CAL_MODE
          .write_int(v)
-         .map_err(|e| sysfs_err(e, "calibration"))
+         .map_err(|e| sysfs_err(e, "calibration"))?;
+      *self.calibration.lock_recover() =
+         (mode != CalibrationMode::DISABLED).then_some(CalibrationState::NOT_CALIBRATING);
+      Ok(())
    }
 
    fn getCalibrationState(&self) -> Result<CalibrationState> {
-      Ok(CalibrationState(paths::HEALTH_GET_CAL_STATE.read_int_or(0)))
+      match paths::HEALTH_GET_CAL_STATE.read_int_timeout(sysfs::SLOW_READ_TIMEOUT) {
+         Err(e @ sysfs::Error::Timeout { .. }) => Err(sysfs_err(e, "getCalibrationState")),
+         result => Ok(CalibrationState(result.unwrap_or(0))),
+      }
    }
 
    fn getDockDefendStatus(&self) -> Result<DockDefendStatus> {
-      if !paths::DD_STATE.exists() {
-         return Ok(DockDefendStatus::DISABLED);
-      }
-      let state = paths::DD_STATE.read_int_or(-1);
-      let settings = paths::DD_SETTINGS.read_int_or(-1);
-      Ok(match (state, settings) {
-         (-1, _) => DockDefendStatus::DISABLED,
-         (_, -1) => DockDefendStatus::ERROR,
-         (1, 1) => DockDefendStatus::ACTIVE,
-         _ => DockDefendStatus::INACTIVE,
+      Ok(dock_defend_status())
+   }
+
+   fn setAactProfile(&self, profile: &AactProfile) -> Result<()> {
+      validate::aact_profile(profile).map_err(|e| bad_arg(&e))?;
+      if !paths::AACT_STATE.exists() {
+         return Err(unsupported("AACT not supported"));
+      }
+      // Keep AACT off while the table is inconsistent. The limits go first since the
+      // kernel sizes the ECC matrix from them.
+      paths::AACT_STATE
+         .write_int(0)
+         .map_err(|e| sysfs_err(e, "disable aact"))?;
+      paths::AACT_TEMP_LIMITS
+         .write_ints_verified(&profile.tempLimitsDeciC)
+         .map_err(|e| sysfs_err(e, "aact temp limits"))?;
+      paths::AACT_CV_LIMITS
+         .write_ints_verified(&profile.cvLimitsUv)
+         .map_err(|e| sysfs_err(e, "aact cv limits"))?;
+      paths::AACT_CHG_ECC
+         .write_ints_verified(&profile.eccMatrix)
+         .map_err(|e| sysfs_err(e, "aact chg ecc"))?;
+      paths::AACT_STATE
+         .write_int_verified(1)
+         .map_err(|e| sysfs_err(e, "enable aact"))?;
+      info!(
+         "Set AACT profile: {} temp bands x {} cv limits",
+         profile.tempLimitsDeciC.len() - 1,
+         profile.cvLimitsUv.len()
+      );
+      Ok(())
+   }
+
+   fn getAactProfile(&self) -> Result<AactProfile> {
+      if !paths::AACT_STATE.exists() {
+         return Err(unsupported("AACT not supported"));
+      }
+      Ok(AactProfile {
+         cvLimitsUv:      paths::AACT_CV_LIMITS
+            .read_ints()
+            .map_err(|e| sysfs_err(e, "aact cv limits"))?,
+         tempLimitsDeciC: paths::AACT_TEMP_LIMITS
+            .read_ints()
+            .map_err(|e| sysfs_err(e, "aact temp limits"))?,
+         eccMatrix:       paths::AACT_CHG_ECC
+            .read_ints()
+            .map_err(|e| sysfs_err(e, "aact chg ecc"))?,
+      })
+   }
+
+   fn setAaccProfile(&self, index: i32) -> Result<()> {
+      if !paths::AACC_CHG_PROFILE.exists() || !paths::AACC_CHG_PROFILE_CNT.exists() {
+         return Err(unsupported("AACC not supported"));
+      }
+      let count = paths::AACC_CHG_PROFILE_CNT
+         .read_int()
+         .map_err(|e| sysfs_err(e, "aacc profile count"))?;
+      if !(0..count).contains(&index) {
+         return Err(bad_arg(&format!(
+            "profile index {index} out of range, {count} profiles available"
+         )));
+      }
+      paths::AACC_CHG_PROFILE
+         .write_int_verified(index)
+         .map_err(|e| sysfs_err(e, "aacc profile"))?;
+      info!("Set AACC profile: {index}");
+      Ok(())
+   }
+
+   fn getAaccProfile(&self) -> Result<i32> {
+      if !paths::AACC_CHG_PROFILE.exists() {
+         return Err(unsupported("AACC not supported"));
+      }
+      let content = paths::AACC_CHG_PROFILE
+         .read_string()
+         .map_err(|e| sysfs_err(e, "aacc profile"))?;
+      AaccProfile::parse(&content).map(|p| p.index).map_err(|_| {
+         sysfs_err(
+            sysfs::Error::Parse {
+               path: paths::AACC_CHG_PROFILE.primary.into(),
+               content,
+            },
+            "aacc profile",
+         )
+      })
+   }
+
+   fn setAaccProfileInfo(&self, profile: &AaccProfileInfo) -> Result<()> {
+      if profile.index < 0 {
+         return Err(bad_arg(&format!(
+            "bad AACC profile index {}: expected {}",
+            profile.index,
+            aacc::EXPECTED_FORMAT
+         )));
+      }
+      self.setAaccProfile(profile.index)
+   }
+
+   fn getAaccProfileInfo(&self) -> Result<AaccProfileInfo> {
+      Ok(AaccProfileInfo {
+         index:        self.getAaccProfile()?,
+         profileCount: paths::AACC_CHG_PROFILE_CNT
+            .read_int()
+            .map_err(|e| sysfs_err(e, "aacc profile count"))?,
+      })
+   }
+
+   fn getAacpVersion(&self) -> Result<i32> {
+      if let Some(v) = self.aacp_version.get() {
+         return Ok(*v);
+      }
+      if !paths::AACP_VERSION.exists() {
+         return Err(unsupported("AACP not supported"));
+      }
+      let v = paths::AACP_VERSION
+         .read_int()
+         .map_err(|e| sysfs_err(e, "aacp version"))?;
+      Ok(*self.aacp_version.get_or_init(|| v))
+   }
+
+   fn setAacpOptOut(&self, opt_out: bool, cutoff_cycles: i32) -> Result<()> {
+      if !(0..=AACP_MAX_CUTOFF_CYCLES).contains(&cutoff_cycles) {
+         return Err(bad_arg(&format!(
+            "cutoffCycles must be 0-{AACP_MAX_CUTOFF_CYCLES}, got {cutoff_cycles}"
+         )));
+      }
+      if !paths::AACP_OPT_OUT.exists() || !paths::AACP_OPT_OUT_CUTOFF_CYCLES.exists() {
+         return Err(unsupported("AACP opt-out not supported"));
+      }
+      // Cutoff first so the flag never takes effect against a stale cutoff.
+      paths::AACP_OPT_OUT_CUTOFF_CYCLES
+         .write_int_verified(cutoff_cycles)
+         .map_err(|e| sysfs_err(e, "aacp opt-out cutoff"))?;
+      paths::AACP_OPT_OUT
+         .write_int_verified(opt_out as i32)
+         .map_err(|e| sysfs_err(e, "aacp opt-out"))?;
+      info!("Set AACP opt-out: {opt_out} (cutoff {cutoff_cycles} cycles)");
+      Ok(())
+   }
+
+   fn getAacpOptOut(&self) -> Result<AacpOptOut> {
+      if !paths::AACP_OPT_OUT.exists() || !paths::AACP_OPT_OUT_CUTOFF_CYCLES.exists() {
+         return Err(unsupported("AACP opt-out not supported"));
+      }
+      Ok(AacpOptOut {
+         optOut:       paths::AACP_OPT_OUT
+            .read_int()
+            .map_err(|e| sysfs_err(e, "aacp opt-out"))?
+            != 0,
+         cutoffCycles: paths::AACP_OPT_OUT_CUTOFF_CYCLES
+            .read_int()
+            .map_err(|e| sysfs_err(e, "aacp opt-out cutoff"))?,
       })
    }
 
+   fn setAafvEnabled(&self, enabled: bool) -> Result<()> {
+      if !paths::aafv::STATE.exists() {
+         return Err(unsupported("AAFV not supported"));
+      }
+      // Through setStringProperty so the write is validated and audited alike.
+      let state = AafvState::from(enabled);
+      self.setStringProperty(Feature::AAFV, property::STATE, &state.as_i32().to_string())
+   }
+
+   fn setTemperatureSampling(
+      &self,
+      enabled: bool,
+      interval_minutes: i32,
+      pause_on_discharge: bool,
+   ) -> Result<()> {
+      if !(1..=24 * 60).contains(&interval_minutes) {
+         return Err(bad_arg("intervalMinutes must be 1-1440"));
+      }
+      self.temp_history.configure(history::Settings {
+         enabled,
+         interval_min: interval_minutes,
+         pause_on_discharge,
+      });
+      Ok(())
+   }
+
+   fn getTemperatureHistory(&self) -> Result<Vec<TemperatureSample>> {
+      Ok(self
+         .temp_history
+         .samples()
+         .into_iter()
+         .map(TemperatureSample::from)
+         .collect())
+   }
+
+   fn getBatteryManufacturer(&self) -> Result<String> {
+      Ok(self.battery_info(paths::BATTERY_MANUFACTURER, "MANUFACTURER"))
+   }
+
+   fn getBatteryModelName(&self) -> Result<String> {
+      Ok(self.battery_info(paths::BATTERY_MODEL_NAME, "MODEL_NAME"))
+   }
+
+   fn getBatterySerialNumber(&self) -> Result<String> {
+      Ok(self.battery_info(paths::BATTERY_SERIAL_NUMBER, "SERIAL_NUMBER"))
+   }
+
+   fn getBatteryTechnology(&self) -> Result<String> {
+      Ok(paths::BATTERY_TECHNOLOGY
+         .read_string()
+         .unwrap_or_else(|_| "Unknown".into()))
+   }
+
+   fn getBatteryTechnologyEnum(&self) -> Result<BatteryTechnology> {
+      Ok(BatteryTechnology::from_label(&self.getBatteryTechnology()?))
+   }
+
+   fn getCapacityPercent(&self) -> Result<i32> {
+      capacity_percent().map_err(|e| sysfs_err(e, "battery capacity"))
+   }
+
+   fn runSelfTest(&self) -> Result<SelfTestReport> {
+      Ok(selftest::run())
+   }
+
+   fn getCallCountsByUid(&self) -> Result<String> {
+      Ok(self.call_counts.to_json())
+   }
+
    fn getAdapterId(&self) -> Result<i32> {
       Err(unsupported("getAdapterId not supported"))
    }
 }
 
-pub fn register() -> Result<Strong<dyn IBattery>> {
-   let svc = BatteryService::new();
-   let binder = BnBattery::new_binder(svc, BinderFeatures::default());
-   binder::add_service("vendor.benzeneos.battery.IBattery/default", binder.as_binder())
-      .map_err(|_| Status::new_exception_str(ExceptionCode::SERVICE_SPECIFIC, Some("register failed")))?;
-   info!("Registered vendor.benzeneos.battery.IBattery/default");
+fn publish(binder: &Strong<dyn IBattery>) -> std::result::Result<(), RegisterError> {
+   let registered = if lazy::is_enabled() {
+      let registered = binder::register_lazy_service(SERVICE_NAME, binder.as_binder());
+      #[cfg(not(test))]
+      if registered.is_ok() {
+         lazy::watch_clients();
+      }
+      registered
+   } else {
+      binder::add_service(SERVICE_NAME, binder.as_binder())
+   };
+   registered.map_err(RegisterError::from_status)
+}
+
+/// Brings the kernel back in line with the persisted state and startup
+/// properties, then starts the background threads. Runs once, after the
+/// service is published; see startup.rs.
+fn reconcile(svc: &BatteryService, levels: Option<Request>) {
+   let missing = startup::wait_for_nodes();
+   if !missing.is_empty() {
+      warn!("Reconciling without {missing:?}");
+   }
+   let summary = restore(svc, levels);
+   add_monitor_tasks(svc);
+   match svc.monitor.start() {
+      // The monitor revalidates cached writes, so only while it runs can an
+      // unchanged write be skipped.
+      Ok(()) => {
+         sysfs::set_write_cache_enabled(true);
+         shutdown::on_flush(|| sysfs::set_write_cache_enabled(false));
+      },
+      Err(e) => warn!("Failed to start monitor thread, not caching writes: {e}"),
+   }
+   let limits = svc.limits.clone();
+   match schedule::spawn(move |now| schedule_tick(&limits, now)) {
+      Ok(handle) => shutdown::track(handle),
+      Err(e) => warn!("Failed to start charge schedule thread: {e}"),
+   }
+   summary.finish(&missing);
+   if lazy::is_enabled() {
+      // A lazy service exits once idle, often well before the healthy uptime,
+      // so getting through reconciliation is what counts as a good start.
+      degraded::clear();
+   }
+}
+
+/// The restore steps of reconcile, in the order they have to run.
+fn restore(svc: &BatteryService, levels: Option<Request>) -> startup::Summary {
+   let mut summary = startup::Summary::new();
+   if let Some(p) = config::default_policy().filter(|_| paths::CHARGING_POLICY.exists()) {
+      summary.record("charging policy", svc.apply_default_policy(p));
+   }
+   for (feature, enabled) in config::feature_defaults() {
+      summary.record("feature", svc.apply_default_feature(feature, enabled));
+   }
+   if levels.is_some() {
+      summary.record("charge levels", recover_limits(&svc.limits).reapply());
+   }
+   if recover_limits(&svc.limits).full_charge.is_some() {
+      // Restarted mid-bypass; the kernel may have been reset under us.
+      summary.record("full charge bypass", lift_limit());
+   }
+   if recover_limits(&svc.limits).paused.is_some() {
+      // Restarted mid-pause; keep adaptive charging held off until resumed.
+      summary.record("adaptive pause", recover_limits(&svc.limits).write_policy(1).map(drop));
+   }
+   if let Some(stash) = defender_stash::load(&persist::data_path(defender_stash::STATE_FILE)) {
+      // Restarted mid-clear; the tuned trickle values were never written back.
+      restore_stash(stash);
+      summary.record("defender stash", Ok::<_, Status>(()));
+   }
+   summary
+}
+
+fn add_monitor_tasks(svc: &BatteryService) {
+   let m = &svc.monitor;
+   let observe_defenders = |defender_log: Arc<DefenderLog>| {
+      move || {
+         let now = persist::now_ms();
+         for (defender, active) in monitor::defender_states() {
+            defender_log.observe(defender, active, now);
+         }
+      }
+   };
+   m.add_task("defender states", POLL_INTERVAL, observe_defenders(svc.defender_log.clone()));
+   // The driver notifies some of the nodes, so a change is logged when it
+   // happens rather than up to POLL_INTERVAL later.
+   let observe = observe_defenders(svc.defender_log.clone());
+   m.watch("defender nodes", monitor::DEFENDER_NODES, move |_| observe());
+   let temp_history = svc.temp_history.clone();
+   m.add_task("temp history", POLL_INTERVAL, move || temp_history.tick(persist::now_ms()));
+   // A node changed behind the HAL's back has to be rewritten next time, not
+   // skipped as unchanged.
+   let cached = paths::ALL.iter().map(|e| e.path).filter(|p| !p.always_write);
+   m.watch("write cache", cached, sysfs::revalidate_write);
+   m.on_uevent("write cache events", monitor::POWER_SUPPLY, |_| sysfs::revalidate_write_cache());
+   m.on_uevent("hot cache", monitor::POWER_SUPPLY, |_| hot_cache::invalidate());
+   let charge_limits = |limits: Arc<Mutex<Limits>>| {
+      move || {
+         full_charge_tick(&limits);
+         adaptive_pause_tick(&limits);
+         policy_tick(&limits);
+      }
+   };
+   m.add_task("charge limits", POLL_INTERVAL, charge_limits(svc.limits.clone()));
+   // Plugging in, unplugging and reaching full all come with a power_supply
+   // uevent, and a bypass or pause waiting on one of them ends right away.
+   let tick = charge_limits(svc.limits.clone());
+   m.on_uevent("charge limit events", monitor::POWER_SUPPLY, move |_| tick());
+   let calibration = svc.calibration.clone();
+   m.add_task("calibration", POLL_INTERVAL, move || calibration_tick(&calibration));
+   let (limits, defender_log) = (svc.limits.clone(), svc.defender_log.clone());
+   m.add_task("metrics", POLL_INTERVAL, move || metrics_tick(&limits, &defender_log));
+   let session = svc.session.clone();
+   m.add_task("charging session", session::SAMPLE_INTERVAL, move || {
+      session.tick(persist::now_ms());
+   });
+   let derating = svc.derating.clone();
+   m.add_task("temp derating", derate::TICK_INTERVAL, move || derating.tick());
+   let health_watch = svc.health_watch.clone();
+   m.add_task("health watch", POLL_INTERVAL, move || health_watch_tick(&health_watch));
+   // Last, so the hold reflects what the tasks above just did.
+   let (limits, calibration) = (svc.limits.clone(), svc.calibration.clone());
+   let (temp_history, derating) = (svc.temp_history.clone(), svc.derating.clone());
+   m.add_task("lazy hold", POLL_INTERVAL, move || {
+      lazy::hold(|| has_pending_work(&limits, &calibration, &temp_history, &derating));
+   });
+}
+
+fn dock_defend_status() -> DockDefendStatus {
+   if !paths::DD_STATE.exists() {
+      return DockDefendStatus::DISABLED;
+   }
+   let state = paths::DD_STATE.read_int_or(-1);
+   let settings = paths::DD_SETTINGS.read_int_or(-1);
+   match (state, settings) {
+      (-1, _) => DockDefendStatus::DISABLED,
+      (_, -1) => DockDefendStatus::ERROR,
+      (1, 1) => DockDefendStatus::ACTIVE,
+      _ => DockDefendStatus::INACTIVE,
+   }
+}
+
+/// Publishes the service, retrying with backoff while servicemanager is
+/// unavailable, then reconciles the kernel state on a thread of its own
+/// unless this start is degraded.
+pub fn register() -> std::result::Result<Strong<dyn IBattery>, RegisterError> {
+   let start_degraded = degraded::on_start();
+   let levels = config::charge_levels().map(|(stop, start)| Request { stop, start });
+   let svc = Arc::new(BatteryService::new(levels));
+   let limits = svc.limits.clone();
+   shutdown::on_flush(move || recover_limits(&limits).flush());
+   let derating = svc.derating.clone();
+   shutdown::on_flush(move || derating.flush());
+   // Restored from persistence, so hold on to it before clients can bind.
+   svc.update_lazy_hold();
+   let binder = BnBattery::new_binder(TracedBattery::new(svc.clone()), BinderFeatures::default());
+   let published = backoff::retry(
+      || publish(&binder),
+      |e| matches!(e, RegisterError::Unavailable(_)),
+      |attempt, e, delay| {
+         warn!("Registering {SERVICE_NAME} failed ({e}), attempt {attempt}, retry in {delay:?}");
+         thread::sleep(delay);
+      },
+   );
+   if let Err(e) = published {
+      if let RegisterError::Rejected(code) = e {
+         // servicemanager only accepts instances the VINTF manifest declares, so
+         // a binary built against another interface version than the manifest
+         // lists fails here rather than at the first call.
+         error!(
+            "servicemanager rejected {SERVICE_NAME} V{SERVICE_INTERFACE_VERSION}: {code:?}. \
+             Check that vendor.benzeneos.battery-service.xml declares version \
+             {SERVICE_INTERFACE_VERSION} and that the framework compatibility matrix accepts it."
+         );
+      }
+      return Err(e);
+   }
+   info!(
+      "Registered {SERVICE_NAME} V{SERVICE_INTERFACE_VERSION}{}",
+      if lazy::is_enabled() { " (lazy)" } else { "" }
+   );
+   if let Err(e) = degraded::spawn_healthy_timer() {
+      warn!("Failed to start healthy uptime timer: {e}");
+   }
+   if start_degraded {
+      warn!("Degraded, skipping startup reconciliation and background threads");
+      return Ok(binder);
+   }
+   let startup_svc = svc.clone();
+   let spawned = thread::Builder::new()
+      .name("battery_startup".into())
+      .spawn(move || reconcile(&startup_svc, levels));
+   if let Err(e) = spawned {
+      warn!("Failed to start reconciliation, reconciling inline: {e}");
+      reconcile(&svc, levels);
+   }
    Ok(binder)
 }
+
+#[cfg(test)]
+mod tests;
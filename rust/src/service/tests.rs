@@ -0,0 +1,2115 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
+   ChargeLimitWindow::ChargeLimitWindow,
+};
+
+use super::*;
+use crate::testing::FakeSysfs;
+
+fn service() -> BatteryService {
+   BatteryService::new(None)
+}
+
+fn exception(r: Result<()>) -> ExceptionCode {
+   r.expect_err("call should fail").exception_code()
+}
+
+/// Runs apply_levels from the levels in `current`, None for a missing node,
+/// and returns the writes it made in order as ("stop" | "start", value).
+fn levels_writes(
+   current: (Option<&str>, Option<&str>),
+   target: (i32, i32),
+) -> Vec<(&'static str, i32)> {
+   let mut fake = FakeSysfs::builder();
+   if let Some(stop) = current.0 {
+      fake = fake.node(paths::USER_CHARGE_STOP_LEVEL, stop);
+   }
+   if let Some(start) = current.1 {
+      fake = fake.node(paths::USER_CHARGE_START_LEVEL, start);
+   }
+   let env = fake.install();
+   apply_levels(target.0, target.1).unwrap();
+   env.write_log()
+      .into_iter()
+      .map(|(path, value)| {
+         let node = if path == paths::USER_CHARGE_STOP_LEVEL.primary { "stop" } else { "start" };
+         (node, value.parse().unwrap())
+      })
+      .collect()
+}
+
+#[test]
+fn apply_levels_orders_writes_by_kernel_bounds() {
+   type Case = ((&'static str, &'static str), (i32, i32), &'static [(&'static str, i32)]);
+   let cases: &[Case] = &[
+      // New stop above the current start: stop first.
+      (("80", "70"), (90, 85), &[("stop", 90), ("start", 85)]),
+      (("80", "70"), (75, 65), &[("stop", 75), ("start", 65)]),
+      (("60", "50"), (80, 55), &[("stop", 80), ("start", 55)]),
+      // Restoring after a full charge bypass.
+      (("100", "0"), (80, 70), &[("stop", 80), ("start", 70)]),
+      // Lifting the limit for one.
+      (("80", "70"), (100, 0), &[("stop", 100), ("start", 0)]),
+      // New stop at the current start: start has to move down first.
+      (("80", "70"), (70, 60), &[("start", 60), ("stop", 70)]),
+      // New stop below the current start.
+      (("80", "70"), (60, 50), &[("start", 50), ("stop", 60)]),
+      (("80", "70"), (65, 60), &[("start", 60), ("stop", 65)]),
+      // Unchanged levels aren't written.
+      (("80", "70"), (90, 70), &[("stop", 90)]),
+      (("80", "70"), (80, 60), &[("start", 60)]),
+      (("80", "70"), (80, 70), &[]),
+      (("80\n", "70\n"), (80, 70), &[]),
+      // An unreadable start is no bound to order against.
+      (("80", "busy"), (60, 50), &[("stop", 60), ("start", 50)]),
+   ];
+   for &((stop, start), target, expected) in cases {
+      assert_eq!(
+         levels_writes((Some(stop), Some(start)), target),
+         expected,
+         "from {stop:?}/{start:?} to {target:?}"
+      );
+   }
+}
+
+#[test]
+fn apply_levels_skips_a_missing_node() {
+   assert_eq!(levels_writes((Some("80"), None), (90, 85)), [("stop", 90)]);
+   assert_eq!(levels_writes((None, Some("70")), (60, 50)), [("start", 50)]);
+}
+
+#[test]
+fn apply_levels_reaches_every_target_under_kernel_bounds() {
+   const LEVELS: [i32; 11] = [0, 20, 50, 60, 65, 70, 75, 80, 85, 95, 100];
+   let pairs = || {
+      LEVELS
+         .into_iter()
+         .flat_map(|stop| LEVELS.into_iter().map(move |start| (stop, start)))
+         .filter(|(stop, start)| start < stop)
+   };
+   for (from_stop, from_start) in pairs() {
+      for (stop, start) in pairs() {
+         let env = FakeSysfs::builder().charge_levels(from_stop, from_start).install();
+         let result = apply_levels(stop, start);
+         let case = format!("{from_stop}/{from_start} to {stop}/{start}");
+         assert!(result.is_ok(), "{case}: {result:?}");
+         assert!(env.rejected(paths::USER_CHARGE_STOP_LEVEL).is_empty(), "{case}");
+         assert!(env.rejected(paths::USER_CHARGE_START_LEVEL).is_empty(), "{case}");
+         assert_eq!(env.int(paths::USER_CHARGE_STOP_LEVEL), stop, "{case}");
+         assert_eq!(env.int(paths::USER_CHARGE_START_LEVEL), start, "{case}");
+      }
+   }
+}
+
+#[test]
+fn apply_levels_without_nodes_does_nothing() {
+   let env = FakeSysfs::builder().install();
+   apply_levels(80, 70).unwrap();
+   assert!(env.write_log().is_empty());
+}
+
+#[test]
+fn apply_levels_reports_a_rejected_write() {
+   let env = FakeSysfs::builder()
+      .node(paths::USER_CHARGE_STOP_LEVEL, 80)
+      .range(paths::USER_CHARGE_STOP_LEVEL, 50, 100)
+      .node(paths::USER_CHARGE_START_LEVEL, 70)
+      .install();
+   let e = apply_levels(101, 70).unwrap_err();
+   assert_eq!(e.service_specific_error(), ErrorCode::KernelRejected.code());
+   assert_eq!(env.rejected(paths::USER_CHARGE_STOP_LEVEL), ["101"]);
+   assert!(env.writes(paths::USER_CHARGE_START_LEVEL).is_empty());
+}
+
+#[test]
+fn set_enable_dock_defend() {
+   let env = FakeSysfs::builder().node(paths::DD_SETTINGS, "1M").install();
+   let svc = service();
+   svc.setEnable(Feature::DOCK_DEFEND, true).unwrap();
+   svc.setEnable(Feature::DOCK_DEFEND, false).unwrap();
+   assert_eq!(env.writes(paths::DD_SETTINGS), ["B2", "1M"]);
+   assert_eq!(env.value(paths::DD_SETTINGS), "1M");
+}
+
+#[test]
+fn set_enable_read_only_node_fails() {
+   let env = FakeSysfs::builder().read_only(paths::DD_SETTINGS, "1M").install();
+   let e = service().setEnable(Feature::DOCK_DEFEND, true).unwrap_err();
+   assert_eq!(e.service_specific_error(), ErrorCode::Io.code());
+   assert_eq!(env.value(paths::DD_SETTINGS), "1M");
+}
+
+#[test]
+fn set_enable_fg_cycle_skips_unchanged() {
+   let env = FakeSysfs::builder()
+      .node(paths::MAXFG_FIX_CYCLE_COUNT, 1)
+      .reject_equal(paths::MAXFG_FIX_CYCLE_COUNT)
+      .install();
+   let svc = service();
+   svc.setEnable(Feature::FG_CYCLE, true).unwrap();
+   assert!(env.writes(paths::MAXFG_FIX_CYCLE_COUNT).is_empty());
+   svc.setEnable(Feature::FG_CYCLE, false).unwrap();
+   assert_eq!(env.writes(paths::MAXFG_FIX_CYCLE_COUNT), ["0"]);
+   assert_eq!(env.int(paths::MAXFG_FIX_CYCLE_COUNT), 0);
+}
+
+#[test]
+fn set_enable_missing_node_is_unsupported() {
+   let _env = FakeSysfs::builder().install();
+   let svc = service();
+   for feature in [Feature::DOCK_DEFEND, Feature::FG_CYCLE, Feature::WIRELESS] {
+      assert_eq!(exception(svc.setEnable(feature, true)), ExceptionCode::UNSUPPORTED_OPERATION);
+   }
+}
+
+#[test]
+fn set_enable_of_a_dependent_feature_is_unsupported() {
+   for &(feature, dep) in features::FEATURE_DEPS {
+      for dep_on in [0, 1] {
+         let env = FakeSysfs::builder()
+            .node(paths::AACR_STATE, dep_on)
+            .node(paths::HEALTH_ALGO, dep_on)
+            .install();
+         let svc = service();
+         let e = svc.setEnable(feature, true).unwrap_err();
+         assert_eq!(e.exception_code(), ExceptionCode::UNSUPPORTED_OPERATION, "{feature:?}");
+         assert!(!e.get_description().contains(&format!("{dep:?}")), "{e:?}");
+         assert_eq!(exception(svc.setEnable(feature, false)), ExceptionCode::UNSUPPORTED_OPERATION);
+         assert!(env.write_log().is_empty());
+      }
+   }
+}
+
+/// Dock defend and a fix cycle count node whose writes fail with EIO.
+fn batch_env(dock_defend: &str) -> crate::testing::Env {
+   FakeSysfs::builder()
+      .node(paths::DD_SETTINGS, dock_defend)
+      .node(paths::MAXFG_FIX_CYCLE_COUNT, 0)
+      .rule(paths::MAXFG_FIX_CYCLE_COUNT, |_, _| Err(libc::EIO))
+      .install()
+}
+
+#[test]
+fn set_enable_features_rolls_back_on_a_partial_failure() {
+   let env = batch_env("1M");
+   let e = service()
+      .setEnableFeatures(&[Feature::DOCK_DEFEND, Feature::FG_CYCLE], true)
+      .unwrap_err();
+   assert_eq!(env.writes(paths::DD_SETTINGS), ["B2", "1M"]);
+   assert_eq!(env.value(paths::DD_SETTINGS), "1M");
+   let (msg, dd) = (e.get_description(), [Feature::DOCK_DEFEND]);
+   assert!(msg.starts_with(&format!("{:?} failed", Feature::FG_CYCLE)), "{msg}");
+   assert!(msg.contains(&format!("succeeded {dd:?}, rolled back {dd:?}")), "{msg}");
+   assert!(msg.contains("rollback failed []"), "{msg}");
+}
+
+#[test]
+fn set_enable_features_restores_what_was_on_before() {
+   let env = batch_env("B2");
+   env.set(paths::MAXFG_FIX_CYCLE_COUNT, 1);
+   service()
+      .setEnableFeatures(&[Feature::DOCK_DEFEND, Feature::FG_CYCLE], false)
+      .unwrap_err();
+   assert_eq!(env.writes(paths::DD_SETTINGS), ["1M", "B2"]);
+   assert_eq!(env.value(paths::DD_SETTINGS), "B2");
+}
+
+#[test]
+fn set_enable_features_reports_a_failed_rollback() {
+   let env = FakeSysfs::builder()
+      .node(paths::DD_SETTINGS, "1M")
+      .node(paths::MAXFG_FIX_CYCLE_COUNT, 0)
+      .rule(paths::MAXFG_FIX_CYCLE_COUNT, |_, _| Err(libc::EIO))
+      // Once on, the driver won't take dock defend back off.
+      .rule(paths::DD_SETTINGS, |k, v| match k.value(paths::DD_SETTINGS) {
+         Some("B2") if v == "1M" => Err(libc::EBUSY),
+         _ => Ok(()),
+      })
+      .install();
+   let e = service()
+      .setEnableFeatures(&[Feature::DOCK_DEFEND, Feature::FG_CYCLE], true)
+      .unwrap_err();
+   assert_eq!(env.value(paths::DD_SETTINGS), "B2");
+   let (msg, dd) = (e.get_description(), [Feature::DOCK_DEFEND]);
+   assert!(msg.contains(&format!("rolled back [], rollback failed {dd:?}")), "{msg}");
+}
+
+#[test]
+fn set_enable_features_checks_every_feature_before_writing() {
+   let env = batch_env("1M");
+   let svc = service();
+   let e = svc.setEnableFeatures(&[Feature::DOCK_DEFEND, Feature::AAFV], true).unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::UNSUPPORTED_OPERATION);
+   assert!(e.get_description().contains(&format!("{:?}", Feature::AAFV)));
+   assert!(env.write_log().is_empty());
+}
+
+#[test]
+fn health_safety_margin_is_validated_and_verified() {
+   let env = FakeSysfs::builder().node(paths::HEALTH_SAFETY_MARGIN, 600).install();
+   let svc = service();
+   svc.setHealthSafetyMargin(validate::HEALTH_SAFETY_MARGIN_MAX_SEC).unwrap();
+   assert_eq!(svc.getHealthSafetyMargin().unwrap(), 14400);
+   for seconds in [0, -60, 14401, 24 * 60 * 60] {
+      assert_eq!(exception(svc.setHealthSafetyMargin(seconds)), ExceptionCode::ILLEGAL_ARGUMENT);
+      let r = svc.setStringProperty(
+         Feature::CHARGE_DEADLINE,
+         property::HEALTH_SAFETY_MARGIN,
+         &seconds.to_string(),
+      );
+      assert_eq!(exception(r), ExceptionCode::ILLEGAL_ARGUMENT);
+   }
+   assert_eq!(env.writes(paths::HEALTH_SAFETY_MARGIN), ["14400"]);
+}
+
+#[test]
+fn a_zero_safety_margin_leaves_the_node_alone() {
+   let env = FakeSysfs::builder().node(paths::HEALTH_SAFETY_MARGIN, 600).install();
+   let svc = service();
+   for seconds in [0, -1, i32::MIN] {
+      let e = svc.setHealthSafetyMargin(seconds).unwrap_err();
+      assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT);
+      assert!(e.get_description().contains("must be positive"), "{seconds}");
+   }
+   assert!(env.writes(paths::HEALTH_SAFETY_MARGIN).is_empty());
+   assert_eq!(svc.getHealthSafetyMargin().unwrap(), 600);
+   // The smallest positive margin is fine.
+   svc.setHealthSafetyMargin(1).unwrap();
+   assert_eq!(svc.getHealthSafetyMargin().unwrap(), 1);
+}
+
+#[test]
+fn health_safety_margin_needs_the_node() {
+   let _env = FakeSysfs::builder().install();
+   let svc = service();
+   assert_eq!(exception(svc.setHealthSafetyMargin(600)), ExceptionCode::UNSUPPORTED_OPERATION);
+   let e = svc.getHealthSafetyMargin().unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::UNSUPPORTED_OPERATION);
+   // A bad value is still reported as such.
+   assert_eq!(exception(svc.setHealthSafetyMargin(0)), ExceptionCode::ILLEGAL_ARGUMENT);
+}
+
+/// Temp defend nodes, in the order setTempDefendConfig writes them, with
+/// the driver defaults.
+const TEMP_DEFEND_NODES: [(sysfs::SysfsPath, i32); 7] = [
+   (paths::BD_RESUME_TEMP, 420),
+   (paths::BD_RESUME_SOC, 50),
+   (paths::BD_RESUME_TIME, 300),
+   (paths::BD_RECHARGE_SOC, 75),
+   (paths::BD_TRIGGER_VOLTAGE, 4_300_000),
+   (paths::BD_TRIGGER_TIME, 60),
+   (paths::BD_TRIGGER_TEMP, 450),
+];
+
+fn temp_defend_env() -> crate::testing::FakeSysfs {
+   TEMP_DEFEND_NODES
+      .iter()
+      .fold(FakeSysfs::builder(), |fake, (path, value)| fake.node(*path, value))
+}
+
+fn temp_defend_config() -> TempDefendConfig {
+   TempDefendConfig {
+      triggerTempDeciC: 400,
+      triggerTimeSec:   120,
+      triggerVoltageUv: 4_200_000,
+      rechargeSoc:      80,
+      resumeSoc:        60,
+      resumeTempDeciC:  350,
+      resumeTimeSec:    600,
+   }
+}
+
+#[test]
+fn temp_defend_config_round_trips_in_order() {
+   let env = temp_defend_env().install();
+   let svc = service();
+   let config = temp_defend_config();
+   svc.setTempDefendConfig(&config).unwrap();
+   let order: Vec<_> = env.write_log().into_iter().map(|(path, _)| path).collect();
+   let expected: Vec<_> = TEMP_DEFEND_NODES.iter().map(|(p, _)| p.primary).collect();
+   assert_eq!(order, expected);
+
+   let read = svc.getTempDefendConfig().unwrap();
+   assert_eq!(read.triggerTempDeciC, config.triggerTempDeciC);
+   assert_eq!(read.triggerTimeSec, config.triggerTimeSec);
+   assert_eq!(read.triggerVoltageUv, config.triggerVoltageUv);
+   assert_eq!(read.rechargeSoc, config.rechargeSoc);
+   assert_eq!(read.resumeSoc, config.resumeSoc);
+   assert_eq!(read.resumeTempDeciC, config.resumeTempDeciC);
+   assert_eq!(read.resumeTimeSec, config.resumeTimeSec);
+}
+
+#[test]
+fn temp_defend_config_rolls_back_a_partial_write() {
+   let env = temp_defend_env()
+      .rule(paths::BD_TRIGGER_TIME, |_, v| if v == "120" { Err(libc::EINVAL) } else { Ok(()) })
+      .install();
+   let e = service().setTempDefendConfig(&temp_defend_config()).unwrap_err();
+   assert!(e.get_description().contains("trigger time"), "{e:?}");
+   for (path, default) in TEMP_DEFEND_NODES {
+      assert_eq!(env.int(path), default, "{}", path.primary);
+   }
+   // The nodes before the failure were written, then put back.
+   assert_eq!(env.writes(paths::BD_RESUME_TEMP), ["350", "420"]);
+   assert_eq!(env.rejected(paths::BD_TRIGGER_TIME), ["120"]);
+   assert_eq!(env.writes(paths::BD_TRIGGER_TIME), ["60"]);
+   assert!(env.writes(paths::BD_TRIGGER_TEMP).is_empty());
+}
+
+#[test]
+fn temp_defend_config_is_validated_before_writing() {
+   let env = temp_defend_env().install();
+   let svc = service();
+   let bad: [fn(&mut TempDefendConfig); 6] = [
+      |c| c.triggerTempDeciC = 349,
+      |c| c.triggerTempDeciC = 501,
+      |c| c.resumeTempDeciC = c.triggerTempDeciC,
+      |c| c.triggerVoltageUv = 5_000_000,
+      |c| c.triggerTimeSec = -1,
+      |c| c.resumeSoc = 101,
+   ];
+   for (i, change) in bad.iter().enumerate() {
+      let mut config = temp_defend_config();
+      change(&mut config);
+      let r = svc.setTempDefendConfig(&config);
+      assert_eq!(exception(r), ExceptionCode::ILLEGAL_ARGUMENT, "{i}");
+   }
+   assert!(env.write_log().is_empty());
+
+   drop(env);
+   let _env = FakeSysfs::builder().install();
+   let r = svc.setTempDefendConfig(&temp_defend_config());
+   assert_eq!(exception(r), ExceptionCode::UNSUPPORTED_OPERATION);
+   let e = svc.getTempDefendConfig().unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::UNSUPPORTED_OPERATION);
+}
+
+fn trickle_env(version: i32) -> crate::testing::Env {
+   let fake = FakeSysfs::builder()
+      .charge_levels(80, 70)
+      .node(paths::BD_TRICKLE_RATE, 0)
+      .node(paths::BD_TRICKLE_RECHARGE_SOC, 0)
+      .node(paths::BD_TRICKLE_RESET_SEC, 0)
+      .read_only(paths::BD_TRICKLE_VERSION, version);
+   match version {
+      1 => fake.install(),
+      _ => fake.node(paths::BD_TRICKLE_CNT_THR, 1).install(),
+   }
+}
+
+fn trickle_config() -> TrickleDefendConfig {
+   TrickleDefendConfig { rate: 30, cntThreshold: 4, rechargeSoc: 60, resetSec: 600 }
+}
+
+#[test]
+fn trickle_defend_config_writes_the_count_threshold_from_version_2() {
+   let env = trickle_env(2);
+   let svc = BatteryService::new(Some(Request { stop: 80, start: 70 }));
+   svc.setTrickleDefendConfig(&trickle_config()).unwrap();
+   assert_eq!(env.writes(paths::BD_TRICKLE_CNT_THR), ["4"]);
+   let c = svc.getTrickleDefendConfig().unwrap();
+   assert_eq!((c.rate, c.cntThreshold, c.rechargeSoc, c.resetSec), (30, 4, 60, 600));
+}
+
+#[test]
+fn trickle_defend_config_on_version_1_defaults_the_count_threshold() {
+   let env = trickle_env(1);
+   let svc = BatteryService::new(Some(Request { stop: 80, start: 70 }));
+   svc.setTrickleDefendConfig(&trickle_config()).unwrap();
+   assert_eq!(env.write_log().len(), 3);
+   let c = svc.getTrickleDefendConfig().unwrap();
+   assert_eq!((c.rate, c.cntThreshold, c.rechargeSoc, c.resetSec), (30, 1, 60, 600));
+}
+
+#[test]
+fn trickle_defend_config_checks_fields_against_each_other() {
+   let env = trickle_env(2);
+   let svc = BatteryService::new(Some(Request { stop: 80, start: 70 }));
+   let bad: [fn(&mut TrickleDefendConfig); 5] = [
+      // Recharging at the stop level would never let the battery discharge.
+      |c| c.rechargeSoc = 80,
+      |c| c.rechargeSoc = -1,
+      |c| c.rate = 101,
+      |c| c.cntThreshold = 0,
+      |c| c.resetSec = -1,
+   ];
+   for (i, change) in bad.iter().enumerate() {
+      let mut config = trickle_config();
+      change(&mut config);
+      let r = svc.setTrickleDefendConfig(&config);
+      assert_eq!(exception(r), ExceptionCode::ILLEGAL_ARGUMENT, "{i}");
+   }
+   assert!(env.write_log().is_empty());
+   // The bound follows the stop level in effect.
+   svc.setChargeLimit(90, 80).unwrap();
+   svc.setTrickleDefendConfig(&TrickleDefendConfig { rechargeSoc: 85, ..trickle_config() })
+      .unwrap();
+   assert_eq!(env.int(paths::BD_TRICKLE_RECHARGE_SOC), 85);
+}
+
+#[test]
+fn clear_temp_defender() {
+   let env = FakeSysfs::builder().node(paths::BD_CLEAR, "").install();
+   service().clearBatteryDefenders(DefenderType::TEMP).unwrap();
+   service().clearBatteryDefenders(DefenderType::TEMP).unwrap();
+   assert_eq!(env.writes(paths::BD_CLEAR), ["B2", "B2"]);
+}
+
+#[test]
+fn clear_trickle_defender_restores_reset_sec() {
+   let env = FakeSysfs::builder()
+      .node(paths::BD_TRICKLE_RESET_SEC, 600)
+      .node(paths::BD_TRICKLE_RATE, 30)
+      .node(paths::BD_TRICKLE_CNT, 0)
+      .install();
+   service().clearBatteryDefenders(DefenderType::TRICKLE).unwrap();
+   assert_eq!(env.writes(paths::BD_TRICKLE_RESET_SEC), ["0", "600"]);
+   assert!(env.writes(paths::BD_TRICKLE_RATE).is_empty());
+   assert!(defender_stash::load(&persist::data_path(defender_stash::STATE_FILE)).is_none());
+}
+
+fn stashed() -> Option<Stash> {
+   defender_stash::load(&persist::data_path(defender_stash::STATE_FILE))
+}
+
+#[test]
+fn clear_dwell_defender_restores_the_rate_once_it_drops() {
+   let env = FakeSysfs::builder()
+      .node(paths::BD_TRICKLE_RESET_SEC, 600)
+      .node(paths::BD_TRICKLE_RATE, 30)
+      .node(paths::BD_TRICKLE_CNT, 2)
+      .rule(paths::BD_TRICKLE_RATE, |k, v| {
+         match v {
+            // The stash is on disk before anything is zeroed.
+            "0" => {
+               assert_eq!(stashed(), Some(Stash { reset_sec: 0, rate: 30 }));
+               k.set(paths::BD_TRICKLE_CNT, 0);
+            },
+            // And the rate only comes back once the defender is off.
+            _ => assert_eq!(k.int(paths::BD_TRICKLE_CNT), Some(0)),
+         }
+         Ok(())
+      })
+      .install();
+   service().clearBatteryDefenders(DefenderType::DWELL).unwrap();
+   assert_eq!(env.writes(paths::BD_TRICKLE_RATE), ["0", "30"]);
+   assert!(env.writes(paths::BD_TRICKLE_RESET_SEC).is_empty());
+   assert_eq!(stashed(), None);
+}
+
+#[test]
+fn clear_defender_already_off_is_not_restored() {
+   let env = FakeSysfs::builder()
+      .node(paths::BD_TRICKLE_RESET_SEC, 0)
+      .node(paths::BD_TRICKLE_RATE, 0)
+      .install();
+   service().clearBatteryDefenders(DefenderType::DWELL).unwrap();
+   service().clearBatteryDefenders(DefenderType::TRICKLE).unwrap();
+   assert_eq!(env.writes(paths::BD_TRICKLE_RATE), ["0"]);
+   assert_eq!(env.writes(paths::BD_TRICKLE_RESET_SEC), ["0"]);
+   assert!(!persist::data_path(defender_stash::STATE_FILE).exists());
+}
+
+#[test]
+fn a_stash_left_by_a_crash_is_restored_at_startup() {
+   let env = FakeSysfs::builder()
+      .node(paths::BD_TRICKLE_RESET_SEC, 0)
+      .node(paths::BD_TRICKLE_RATE, 0)
+      .install();
+   // The last process stashed both values and died after zeroing them.
+   let stash = Stash { reset_sec: 600, rate: 30 };
+   defender_stash::save(&persist::data_path(defender_stash::STATE_FILE), &stash).unwrap();
+   let svc = service();
+   restore(&svc, None);
+   assert_eq!(env.int(paths::BD_TRICKLE_RESET_SEC), 600);
+   assert_eq!(env.int(paths::BD_TRICKLE_RATE), 30);
+   assert_eq!(stashed(), None);
+   // Nothing is left to restore on the next start.
+   restore(&svc, None);
+   assert_eq!(env.writes(paths::BD_TRICKLE_RATE), ["30"]);
+}
+
+#[test]
+fn clear_dock_defender_only_while_active() {
+   let env = FakeSysfs::builder()
+      .node(paths::DD_STATE, 0)
+      .node(paths::DD_SETTINGS, "B2")
+      // The driver drops out of the dock defend state on a clear.
+      .rule(paths::DD_SETTINGS, |k, v| {
+         if v == "02" && k.int(paths::DD_STATE) == Some(1) {
+            k.set(paths::DD_STATE, 0);
+         }
+         Ok(())
+      })
+      .install();
+   service().clearBatteryDefenders(DefenderType::DOCK).unwrap();
+   assert!(env.writes(paths::DD_SETTINGS).is_empty());
+   env.set(paths::DD_STATE, 1);
+   service().clearBatteryDefenders(DefenderType::DOCK).unwrap();
+   assert_eq!(env.writes(paths::DD_SETTINGS), ["02"]);
+   assert_eq!(env.int(paths::DD_STATE), 0);
+}
+
+#[test]
+fn clear_all_defenders_in_order() {
+   let env = FakeSysfs::builder()
+      .node(paths::BD_CLEAR, "")
+      .node(paths::BD_TRICKLE_RESET_SEC, 600)
+      .node(paths::BD_TRICKLE_RATE, 30)
+      .node(paths::DD_STATE, 1)
+      .node(paths::DD_SETTINGS, "B2")
+      .install();
+   service().clearBatteryDefenders(DefenderType::ALL).unwrap();
+   let log = env.write_log();
+   let written = log.iter().map(|(p, v)| (p.as_str(), v.as_str())).collect::<Vec<_>>();
+   assert_eq!(written, [
+      (paths::BD_CLEAR.primary, "B2"),
+      (paths::BD_TRICKLE_RESET_SEC.primary, "0"),
+      (paths::BD_TRICKLE_RATE.primary, "0"),
+      (paths::DD_SETTINGS.primary, "02"),
+      (paths::BD_TRICKLE_RESET_SEC.primary, "600"),
+      (paths::BD_TRICKLE_RATE.primary, "30"),
+   ]);
+}
+
+#[test]
+fn clear_missing_defender_fails() {
+   let _env = FakeSysfs::builder().install();
+   let e = service().clearBatteryDefenders(DefenderType::TEMP).unwrap_err();
+   assert_eq!(e.service_specific_error(), ErrorCode::NodeMissing.code());
+}
+
+/// The service as register() publishes it, seen from the client side.
+fn client() -> Strong<dyn IBattery> {
+   BnBattery::new_binder(TracedBattery::new(Arc::new(service())), BinderFeatures::default())
+}
+
+#[test]
+fn binder_round_trip() {
+   let env = FakeSysfs::builder()
+      .node(paths::USER_CHARGE_STOP_LEVEL, 100)
+      .node(paths::USER_CHARGE_START_LEVEL, 0)
+      .node(paths::HEALTH_INDEX_STATS, "1: 96, 4521, 4600, 4700, 150, 148, 140, 312, 800, 1\n")
+      .node(paths::BD_TRICKLE_RATE, 30)
+      .install();
+   let battery = client();
+
+   for (stop, start) in [(101, 70), (40, 30), (70, 80), (80, 80)] {
+      let e = battery.setChargeLimit(stop, start).unwrap_err();
+      assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT, "{stop}/{start}");
+   }
+   assert!(env.write_log().is_empty());
+
+   let stats = battery.getHealthStats(HealthAlgo::GOOGLE).unwrap();
+   assert_eq!(
+      (stats.algo, stats.healthIndex, stats.capacityFcc, stats.cycleCount, stats.tempBucket),
+      (1, 96, 4521, 312, 1)
+   );
+   assert_eq!(battery.getHealthStats(HealthAlgo::MAXIM).unwrap().healthIndex, 0);
+
+   let trickle_rate = battery.getStringProperty(Feature::TRICKLE_DEFEND, property::TRICKLE_RATE);
+   assert_eq!(trickle_rate.unwrap(), "30");
+   let unmapped = battery.getStringProperty(Feature::TRICKLE_DEFEND, property::DC_CTL);
+   assert_eq!(unmapped.unwrap(), "");
+   let out_of_range = property::MAX_PROPERTY_ID + 1;
+   let e = battery.getStringProperty(Feature::TRICKLE_DEFEND, out_of_range).unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT);
+}
+
+#[test]
+fn property_ids_up_to_the_max_are_accepted() {
+   let env = FakeSysfs::builder().node(paths::AACP_OPT_OUT_CUTOFF_CYCLES, 500).install();
+   let svc = service();
+   assert_eq!(property::OPT_OUT_CUTOFF, 44);
+   assert_eq!(svc.getStringProperty(Feature::AACP, 44).unwrap(), "500");
+   svc.setStringProperty(Feature::AACP, 44, "400").unwrap();
+   assert_eq!(env.writes(paths::AACP_OPT_OUT_CUTOFF_CYCLES), ["400"]);
+   // Unmapped IDs up to the max read empty and ignore writes.
+   for prop in [51, property::MAX_PROPERTY_ID] {
+      assert_eq!(svc.getStringProperty(Feature::AACP, prop).unwrap(), "", "{prop}");
+      svc.setStringProperty(Feature::AACP, prop, "1").unwrap();
+   }
+   for prop in [property::MAX_PROPERTY_ID + 1, 65, i32::MAX] {
+      let e = svc.getStringProperty(Feature::AACP, prop).unwrap_err();
+      assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT, "{prop}");
+      let r = svc.setStringProperty(Feature::AACP, prop, "1");
+      assert_eq!(exception(r), ExceptionCode::ILLEGAL_ARGUMENT, "{prop}");
+   }
+   assert_eq!(env.write_log().len(), 1);
+}
+
+#[test]
+fn reading_a_present_property_costs_one_open() {
+   let env = FakeSysfs::builder().node(paths::BD_TRICKLE_RATE, 30).install();
+   let svc = service();
+   let before = (env.opens(), env.stats());
+   let rate = svc.getStringProperty(Feature::TRICKLE_DEFEND, property::TRICKLE_RATE);
+   assert_eq!(rate.unwrap(), "30");
+   // No stat ahead of the open: a missing node shows up as the open's NotFound.
+   assert_eq!((env.opens() - before.0, env.stats() - before.1), (1, 0));
+}
+
+/// The audit log's dump.
+fn audit_dump(svc: &BatteryService) -> String {
+   let mut out = Vec::new();
+   svc.audit.dump(&mut out).unwrap();
+   String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn missing_property_nodes_read_empty_and_ignore_writes() {
+   let (feature, prop) = (Feature::TRICKLE_DEFEND, property::TRICKLE_RATE);
+   let path = sysfs::get_property_sysfs(feature, prop).unwrap();
+   let env = FakeSysfs::builder().install();
+   let svc = service();
+   assert_eq!(svc.getStringProperty(feature, prop).unwrap(), "");
+   svc.setStringProperty(feature, prop, "30").unwrap();
+   assert!(env.write_log().is_empty());
+   assert_eq!(svc.getStringProperty(feature, prop).unwrap(), "");
+   // Nothing reached a node, so there is nothing to audit.
+   assert!(audit_dump(&svc).starts_with("Property writes (0):"));
+
+   // A node that is there but refuses the write is still an error.
+   drop(env);
+   let _env = FakeSysfs::builder().read_only(path, 10).install();
+   assert_eq!(svc.getStringProperty(feature, prop).unwrap(), "10");
+   let e = svc.setStringProperty(feature, prop, "30").unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::SERVICE_SPECIFIC);
+   let dump = audit_dump(&svc);
+   assert!(dump.starts_with("Property writes (1):"), "{dump}");
+   assert!(dump.contains(&format!("{path}: '10' -> '30'")), "{dump}");
+}
+
+/// A client whose calls come from the uid `calling_uid` returns.
+fn client_as(calling_uid: fn() -> u32) -> Strong<dyn IBattery> {
+   let battery = TracedBattery::with_calling_uid(Arc::new(service()), calling_uid);
+   BnBattery::new_binder(battery, BinderFeatures::default())
+}
+
+type Call = fn(&dyn IBattery) -> Result<()>;
+
+/// Every IBattery method that isn't a getter, with arguments it would accept.
+const MUTATORS: &[(&str, Call)] = &[
+   ("setChargingPolicy", |b| b.setChargingPolicy(ChargingPolicy::LONGLIFE)),
+   ("setChargeLimit", |b| b.setChargeLimit(80, 70)),
+   ("setChargeLimitForClient", |b| b.setChargeLimitForClient("test", 80, 70)),
+   ("clearChargeLimitForClient", |b| b.clearChargeLimitForClient("test")),
+   ("setChargeLimitSchedule", |b| b.setChargeLimitSchedule(&Default::default())),
+   ("setChargeToLimit", |b| b.setChargeToLimit(true)),
+   ("requestFullChargeOnce", |b| b.requestFullChargeOnce()),
+   ("setEnable", |b| b.setEnable(Feature::DOCK_DEFEND, true)),
+   ("setEnableFeatures", |b| b.setEnableFeatures(&[Feature::DOCK_DEFEND], true)),
+   ("clearBatteryDefenders", |b| b.clearBatteryDefenders(DefenderType::DOCK)),
+   ("setTempDefendConfig", |b| b.setTempDefendConfig(&Default::default())),
+   ("setTrickleDefendConfig", |b| b.setTrickleDefendConfig(&Default::default())),
+   ("setStringProperty", |b| {
+      b.setStringProperty(Feature::TRICKLE_DEFEND, property::TRICKLE_RATE, "30")
+   }),
+   ("setChargingCurrentLimit", |b| b.setChargingCurrentLimit(1500)),
+   ("setTemperatureDerating", |b| b.setTemperatureDerating(true, 400, 50)),
+   ("setReverseWirelessCharging", |b| b.setReverseWirelessCharging(true)),
+   ("setChargingDeadline", |b| b.setChargingDeadline(3600)),
+   ("setChargingDeadlineLocal", |b| b.setChargingDeadlineLocal(3600)),
+   ("setAdaptiveChargingPaused", |b| b.setAdaptiveChargingPaused(true)),
+   ("setAdaptiveChargingEnabled", |b| b.setAdaptiveChargingEnabled(true)),
+   ("setHealthSafetyMargin", |b| b.setHealthSafetyMargin(600)),
+   ("setHealthAlwaysOn", |b| b.setHealthAlwaysOn(1)),
+   ("setHealthTrendPoints", |b| b.setHealthTrendPoints(&[80, 90])),
+   ("setHealthLowBoundary", |b| b.setHealthLowBoundary(80)),
+   ("setHealthLowBoundaryIndexed", |b| b.setHealthLowBoundaryIndexed(0, 80)),
+   ("scheduleCalibration", |b| b.scheduleCalibration(Default::default())),
+   ("setAactProfile", |b| b.setAactProfile(&Default::default())),
+   ("setAaccProfile", |b| b.setAaccProfile(1)),
+   ("setAaccProfileInfo", |b| b.setAaccProfileInfo(&Default::default())),
+   ("setAacpOptOut", |b| b.setAacpOptOut(true, 500)),
+   ("setAafvEnabled", |b| b.setAafvEnabled(true)),
+   ("setTemperatureSampling", |b| b.setTemperatureSampling(true, 5, false)),
+   ("runSelfTest", |b| b.runSelfTest().map(drop)),
+];
+
+#[test]
+fn mutators_cover_every_non_getter() {
+   let mut listed = MUTATORS.iter().map(|&(name, _)| name).collect::<Vec<_>>();
+   let mut expected = crate::trace::METHODS
+      .iter()
+      .copied()
+      .filter(|m| !permission::is_getter(m))
+      .collect::<Vec<_>>();
+   listed.sort_unstable();
+   expected.sort_unstable();
+   assert_eq!(listed, expected);
+}
+
+#[test]
+fn unprivileged_callers_cannot_mutate() {
+   let env = FakeSysfs::builder()
+      .charge_levels(100, 0)
+      .node(paths::CHARGING_POLICY, 1)
+      .node(paths::DD_SETTINGS, "1M")
+      .node(paths::DD_STATE, 1)
+      .node(paths::BD_TRICKLE_RATE, 0)
+      .install();
+   for calling_uid in [|| 10123, || permission::AID_SHELL] {
+      let battery = client_as(calling_uid);
+      for (name, call) in MUTATORS {
+         let e = call(&*battery).expect_err(name);
+         assert_eq!(e.exception_code(), ExceptionCode::SECURITY, "{name}");
+      }
+      assert_eq!(battery.getChargeLimit().unwrap(), [100, 0]);
+   }
+   assert!(env.write_log().is_empty());
+}
+
+#[test]
+fn privileged_callers_pass_the_gate() {
+   let env = FakeSysfs::builder().charge_levels(100, 0).install();
+   for calling_uid in [|| permission::AID_ROOT, || permission::AID_SYSTEM] {
+      let battery = client_as(calling_uid);
+      for (name, call) in MUTATORS {
+         if let Err(e) = call(&*battery) {
+            assert_ne!(e.exception_code(), ExceptionCode::SECURITY, "{name}");
+         }
+      }
+   }
+   assert!(!env.writes(paths::USER_CHARGE_STOP_LEVEL).is_empty());
+}
+
+#[test]
+fn set_string_property_then_charge_to_limit() {
+   let env = FakeSysfs::builder().node(paths::CHARGE_TO_LIMIT, 0).install();
+   let svc = service();
+   svc.setChargeToLimit(true).unwrap();
+   svc.setStringProperty(Feature::CHARGE_LIMIT, property::CHARGE_TO_LIMIT, "0").unwrap();
+   svc.setChargeToLimit(true).unwrap();
+   assert_eq!(env.writes(paths::CHARGE_TO_LIMIT), ["1", "0", "1"]);
+   assert_eq!(env.int(paths::CHARGE_TO_LIMIT), 1);
+}
+
+#[test]
+fn get_charge_limit_adopts_an_external_write() {
+   let env = FakeSysfs::builder()
+      .charge_levels(100, 0)
+      .node(paths::CHARGING_POLICY, 1)
+      .install();
+   let svc = service();
+   svc.setChargeLimit(80, 70).unwrap();
+   env.set(paths::USER_CHARGE_STOP_LEVEL, 90);
+   assert_eq!(svc.getChargeLimit().unwrap(), [90, 70]);
+   assert_eq!(svc.getEffectiveChargeLimit().unwrap().stopLevel, 90);
+   svc.setChargingPolicy(ChargingPolicy::CUSTOM).unwrap();
+   assert_eq!(env.int(paths::USER_CHARGE_STOP_LEVEL), 90);
+   assert_eq!(env.writes(paths::USER_CHARGE_STOP_LEVEL), ["80"]);
+}
+
+#[test]
+fn a_level_changed_behind_the_hals_back_is_rewritten() {
+   let env = FakeSysfs::builder()
+      .charge_levels(100, 0)
+      .node(paths::CHARGING_POLICY, 1)
+      .install();
+   // As while the monitor runs, which misses a driver changing a node silently.
+   sysfs::set_write_cache_enabled(true);
+   let svc = service();
+   svc.setChargeLimit(80, 70).unwrap();
+   env.set(paths::USER_CHARGE_STOP_LEVEL, 90);
+   svc.setChargeLimit(80, 70).unwrap();
+   assert_eq!(env.int(paths::USER_CHARGE_STOP_LEVEL), 80);
+   assert_eq!(env.writes(paths::USER_CHARGE_STOP_LEVEL), ["80", "80"]);
+}
+
+#[test]
+fn get_charge_limit_keeps_the_limit_during_a_full_charge() {
+   let env = FakeSysfs::builder()
+      .charge_levels(100, 0)
+      .node(paths::CHARGING_POLICY, 2)
+      .node(paths::BATTERY_CAPACITY, 60)
+      .install();
+   let svc = service();
+   svc.setChargeLimitForClient("app", 80, 70).unwrap();
+   svc.requestFullChargeOnce().unwrap();
+   assert_eq!(svc.getChargeLimit().unwrap(), [100, 0]);
+   let effective = svc.getEffectiveChargeLimit().unwrap();
+   assert_eq!((effective.stopLevel, effective.startLevel), (80, 70));
+
+   env.set(paths::BATTERY_CAPACITY, 100);
+   full_charge_tick(&svc.limits);
+   assert_eq!(svc.getChargeLimit().unwrap(), [80, 70]);
+   assert_eq!(env.int(paths::CHARGING_POLICY), 2);
+}
+
+#[test]
+fn reconcile_waits_for_nodes_that_probe_late() {
+   let env = FakeSysfs::builder().install();
+   let svc = BatteryService::new(Some(Request { stop: 80, start: 70 }));
+   thread::scope(|scope| {
+      scope.spawn(|| {
+         thread::sleep(startup::NODE_POLL * 2);
+         env.set(paths::USER_CHARGE_STOP_LEVEL, 100);
+         env.set(paths::USER_CHARGE_START_LEVEL, 0);
+         env.set(paths::CHARGING_POLICY, 2);
+      });
+      // Calls before the nodes appear get the defaults.
+      assert_eq!(svc.getChargeLimit().unwrap(), [80, 70]);
+      assert!(startup::wait_for_nodes().is_empty());
+   });
+   restore(&svc, Some(Request { stop: 80, start: 70 }));
+   assert_eq!(env.int(paths::USER_CHARGE_STOP_LEVEL), 80);
+   assert_eq!(env.int(paths::USER_CHARGE_START_LEVEL), 70);
+   assert_eq!(svc.getChargeLimit().unwrap(), [80, 70]);
+}
+
+#[test]
+fn power_supply_uevents_refresh_the_charging_getters() {
+   let env = FakeSysfs::builder()
+      .node(paths::CHARGING_STATUS, 1)
+      .node(paths::CHARGING_SPEED, 5)
+      .install();
+   let svc = service();
+   add_monitor_tasks(&svc);
+   let status = svc.getChargingStatus().unwrap();
+   assert_eq!(svc.getChargingSpeed().unwrap(), 5);
+   env.set(paths::CHARGING_STATUS, 2);
+   env.set(paths::CHARGING_SPEED, 7);
+   assert_eq!(svc.getChargingStatus().unwrap(), status);
+   assert_eq!(svc.getChargingSpeed().unwrap(), 5);
+   svc.monitor.deliver(
+      b"change@/devices/platform/google,charger/power_supply/usb\0\
+        ACTION=change\0SUBSYSTEM=power_supply\0POWER_SUPPLY_NAME=usb\0",
+   );
+   assert_eq!(svc.getChargingStatus().unwrap(), ChargingStatus::from_kernel(2));
+   assert_eq!(svc.getChargingSpeed().unwrap(), 7);
+}
+
+fn aact_profile(cv: &[i32], temp: &[i32], ecc: &[i32]) -> AactProfile {
+   AactProfile { cvLimitsUv: cv.to_vec(), tempLimitsDeciC: temp.to_vec(), eccMatrix: ecc.to_vec() }
+}
+
+fn aact_env() -> crate::testing::Env {
+   FakeSysfs::builder()
+      .node(paths::AACT_STATE, 1)
+      .node(paths::AACT_CV_LIMITS, "")
+      .node(paths::AACT_TEMP_LIMITS, "")
+      .node(paths::AACT_CHG_ECC, "")
+      .install()
+}
+
+#[test]
+fn aact_profile_is_written_in_order_and_read_back() {
+   let env = aact_env();
+   let svc = service();
+   let profile = aact_profile(&[4_200_000, 4_400_000], &[100, 250, 450], &[10, 20, 30, 40]);
+   svc.setAactProfile(&profile).unwrap();
+   let nodes = [
+      paths::AACT_STATE,
+      paths::AACT_TEMP_LIMITS,
+      paths::AACT_CV_LIMITS,
+      paths::AACT_CHG_ECC,
+      paths::AACT_STATE,
+   ];
+   let order = env.write_log().into_iter().map(|(path, _)| path).collect::<Vec<_>>();
+   assert_eq!(order, nodes.map(|p| p.primary));
+   assert_eq!(env.value(paths::AACT_CHG_ECC), "10,20,30,40");
+   assert_eq!(env.int(paths::AACT_STATE), 1);
+   let read = svc.getAactProfile().unwrap();
+   assert_eq!(read.cvLimitsUv, profile.cvLimitsUv);
+   assert_eq!(read.tempLimitsDeciC, profile.tempLimitsDeciC);
+   assert_eq!(read.eccMatrix, profile.eccMatrix);
+}
+
+#[test]
+fn aact_profile_rejects_bad_tables_with_specifics() {
+   let env = aact_env();
+   let svc = service();
+   let cases = [
+      (aact_profile(&[], &[100, 250], &[]), "cvLimitsUv is empty"),
+      (aact_profile(&[4_200_000], &[100], &[]), "tempLimitsDeciC needs at least 2 entries, got 1"),
+      (
+         aact_profile(&[4_400_000, 4_200_000], &[100, 250], &[1, 2]),
+         "cvLimitsUv must be strictly increasing: [0]=4400000 >= [1]=4200000",
+      ),
+      (
+         aact_profile(&[4_200_000], &[250, 250], &[1]),
+         "tempLimitsDeciC must be strictly increasing: [0]=250 >= [1]=250",
+      ),
+      (
+         aact_profile(&[4_200_000, 4_400_000], &[100, 250, 450], &[1, 2, 3]),
+         "eccMatrix has 3 entries, expected 2x2=4",
+      ),
+   ];
+   for (profile, message) in cases {
+      let e = svc.setAactProfile(&profile).unwrap_err();
+      assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT, "{message}");
+      assert_eq!(e.get_description(), message);
+   }
+   assert!(env.write_log().is_empty());
+}
+
+#[test]
+fn aact_stays_off_when_a_table_does_not_verify() {
+   let env = FakeSysfs::builder()
+      .node(paths::AACT_STATE, 1)
+      .node(paths::AACT_CV_LIMITS, "")
+      .node(paths::AACT_TEMP_LIMITS, "")
+      .node(paths::AACT_CHG_ECC, "")
+      // A driver that keeps only the first row.
+      .rule(paths::AACT_CHG_ECC, |k, v| {
+         let first = v.split(',').take(2).collect::<Vec<_>>().join(",");
+         k.set(paths::AACT_CHG_ECC, first);
+         Ok(())
+      })
+      .install();
+   let profile = aact_profile(&[4_200_000, 4_400_000], &[100, 250, 450], &[10, 20, 30, 40]);
+   let e = service().setAactProfile(&profile).unwrap_err();
+   assert_eq!(e.service_specific_error(), ErrorCode::VerifyFailed.code());
+   assert_eq!(env.int(paths::AACT_STATE), 0);
+}
+
+#[test]
+fn aact_profile_needs_the_nodes() {
+   let _env = FakeSysfs::builder().install();
+   let profile = aact_profile(&[4_200_000], &[100, 250], &[10]);
+   assert_eq!(exception(service().setAactProfile(&profile)), ExceptionCode::UNSUPPORTED_OPERATION);
+   let e = service().getAactProfile().unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::UNSUPPORTED_OPERATION);
+}
+
+#[test]
+fn aacc_profile_selects_an_available_profile() {
+   let env = FakeSysfs::builder()
+      .node(paths::AACC_CHG_PROFILE, "0\n")
+      .read_only(paths::AACC_CHG_PROFILE_CNT, 3)
+      .install();
+   let svc = service();
+   svc.setAaccProfile(2).unwrap();
+   assert_eq!(env.writes(paths::AACC_CHG_PROFILE), ["2"]);
+   assert_eq!(svc.getAaccProfile().unwrap(), 2);
+   let info = svc.getAaccProfileInfo().unwrap();
+   assert_eq!((info.index, info.profileCount), (2, 3));
+   let mut dump = Vec::new();
+   svc.dump_state(&mut dump).unwrap();
+   assert!(String::from_utf8(dump).unwrap().contains("  AACC profile: 2 of 3\n"));
+}
+
+#[test]
+fn aacc_profile_rejects_an_index_out_of_range() {
+   let env = FakeSysfs::builder()
+      .node(paths::AACC_CHG_PROFILE, 0)
+      .read_only(paths::AACC_CHG_PROFILE_CNT, 3)
+      .install();
+   let svc = service();
+   for index in [-1, 3, i32::MAX] {
+      let e = svc.setAaccProfile(index).unwrap_err();
+      assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT, "{index}");
+      assert!(e.get_description().contains("3 profiles available"), "{index}");
+   }
+   let info = AaccProfileInfo { index: -1, profileCount: 3 };
+   assert_eq!(exception(svc.setAaccProfileInfo(&info)), ExceptionCode::ILLEGAL_ARGUMENT);
+   assert!(env.write_log().is_empty());
+}
+
+#[test]
+fn aacc_profile_round_trips_and_rejects_a_malformed_node() {
+   let env = FakeSysfs::builder()
+      .node(paths::AACC_CHG_PROFILE, 0)
+      .read_only(paths::AACC_CHG_PROFILE_CNT, 3)
+      .install();
+   let svc = service();
+   for index in 0..3 {
+      svc.setAaccProfileInfo(&AaccProfileInfo { index, profileCount: 3 }).unwrap();
+      let info = svc.getAaccProfileInfo().unwrap();
+      assert_eq!((info.index, info.profileCount), (index, 3));
+   }
+   let info = AaccProfileInfo { index: -1, profileCount: 3 };
+   let e = svc.setAaccProfileInfo(&info).unwrap_err();
+   assert!(e.get_description().contains(aacc::EXPECTED_FORMAT), "{e:?}");
+
+   env.set(paths::AACC_CHG_PROFILE, "1 2\n");
+   let e = svc.getAaccProfile().unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::SERVICE_SPECIFIC);
+}
+
+#[test]
+fn aacc_profile_needs_both_nodes() {
+   for with_count in [false, true] {
+      let mut fake = FakeSysfs::builder();
+      if with_count {
+         fake = fake.read_only(paths::AACC_CHG_PROFILE_CNT, 3);
+      }
+      let _env = fake.install();
+      let svc = service();
+      assert_eq!(exception(svc.setAaccProfile(0)), ExceptionCode::UNSUPPORTED_OPERATION);
+      let e = svc.getAaccProfile().unwrap_err();
+      assert_eq!(e.exception_code(), ExceptionCode::UNSUPPORTED_OPERATION);
+   }
+   let _env = FakeSysfs::builder().node(paths::AACC_CHG_PROFILE, 0).install();
+   assert_eq!(exception(service().setAaccProfile(0)), ExceptionCode::UNSUPPORTED_OPERATION);
+}
+
+#[test]
+fn aacp_version_is_read_once() {
+   let env = FakeSysfs::builder().read_only(paths::AACP_VERSION, "2\n").install();
+   let svc = service();
+   assert_eq!(svc.getAacpVersion().unwrap(), 2);
+   env.set(paths::AACP_VERSION, 3);
+   assert_eq!(svc.getAacpVersion().unwrap(), 2);
+
+   env.set(paths::AACP_VERSION, "v3");
+   let e = service().getAacpVersion().unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::SERVICE_SPECIFIC);
+}
+
+#[test]
+fn aacp_opt_out_round_trips() {
+   let env = FakeSysfs::builder()
+      .node(paths::AACP_OPT_OUT, 0)
+      .node(paths::AACP_OPT_OUT_CUTOFF_CYCLES, 0)
+      .install();
+   let svc = service();
+   svc.setAacpOptOut(true, 800).unwrap();
+   let order = env.write_log().into_iter().map(|(path, _)| path).collect::<Vec<_>>();
+   assert_eq!(order, [paths::AACP_OPT_OUT_CUTOFF_CYCLES.primary, paths::AACP_OPT_OUT.primary]);
+   let opt_out = svc.getAacpOptOut().unwrap();
+   assert_eq!((opt_out.optOut, opt_out.cutoffCycles), (true, 800));
+   svc.setAacpOptOut(false, 0).unwrap();
+   let opt_out = svc.getAacpOptOut().unwrap();
+   assert_eq!((opt_out.optOut, opt_out.cutoffCycles), (false, 0));
+}
+
+#[test]
+fn aacp_cutoff_is_validated() {
+   let env = FakeSysfs::builder()
+      .node(paths::AACP_OPT_OUT, 0)
+      .node(paths::AACP_OPT_OUT_CUTOFF_CYCLES, 0)
+      .install();
+   let svc = service();
+   for cutoff in [-1, AACP_MAX_CUTOFF_CYCLES + 1] {
+      let e = svc.setAacpOptOut(true, cutoff).unwrap_err();
+      assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT, "{cutoff}");
+      assert_eq!(e.get_description(), format!("cutoffCycles must be 0-3000, got {cutoff}"));
+   }
+   assert!(env.write_log().is_empty());
+   svc.setAacpOptOut(true, AACP_MAX_CUTOFF_CYCLES).unwrap();
+}
+
+#[test]
+fn aacp_opt_out_needs_both_nodes() {
+   let _env = FakeSysfs::builder().node(paths::AACP_OPT_OUT, 0).install();
+   let svc = service();
+   assert_eq!(exception(svc.setAacpOptOut(true, 10)), ExceptionCode::UNSUPPORTED_OPERATION);
+   let e = svc.getAacpOptOut().unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::UNSUPPORTED_OPERATION);
+   let e = svc.getAacpVersion().unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::UNSUPPORTED_OPERATION);
+}
+
+const TREND_POINTS: [i32; 10] = [0, 100, 200, 300, 400, 500, 600, 700, 800, 900];
+
+#[test]
+fn health_trend_points_are_written_and_verified() {
+   let env = FakeSysfs::builder().node(paths::HEALTH_SET_TREND_POINTS, "").install();
+   service().setHealthTrendPoints(&TREND_POINTS).unwrap();
+   let written = env.writes(paths::HEALTH_SET_TREND_POINTS);
+   assert_eq!(written, ["0,100,200,300,400,500,600,700,800,900"]);
+}
+
+#[test]
+fn health_trend_points_are_validated() {
+   let env = FakeSysfs::builder().node(paths::HEALTH_SET_TREND_POINTS, "").install();
+   let svc = service();
+   let mut swapped = TREND_POINTS;
+   swapped.swap(3, 4);
+   let mut high = TREND_POINTS;
+   high[9] = validate::HEALTH_TREND_POINT_MAX + 1;
+   let cases: [(&[i32], &str); 4] = [
+      (&TREND_POINTS[..9], "expected 10 trend points, got 9"),
+      (&[0; 11], "expected 10 trend points, got 11"),
+      (&swapped, "trend points must be strictly increasing: [3]=400 >= [4]=300"),
+      (&high, "trend point 10001 outside 0-10000"),
+   ];
+   for (points, message) in cases {
+      let e = svc.setHealthTrendPoints(points).unwrap_err();
+      assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT, "{message}");
+      assert_eq!(e.get_description(), message);
+      // The string property path goes through the same check.
+      let value = points.iter().map(i32::to_string).collect::<Vec<_>>().join(",");
+      let e = svc.setStringProperty(Feature::HEALTH, property::TREND_POINTS, &value).unwrap_err();
+      assert_eq!(e.get_description(), message);
+   }
+   assert!(env.write_log().is_empty());
+}
+
+#[test]
+fn health_trend_points_the_kernel_drops_fail_to_verify() {
+   let env = FakeSysfs::builder()
+      .node(paths::HEALTH_SET_TREND_POINTS, "")
+      // The driver ignores what it doesn't like and keeps nothing.
+      .rule(paths::HEALTH_SET_TREND_POINTS, |k, _| {
+         k.set(paths::HEALTH_SET_TREND_POINTS, "0");
+         Ok(())
+      })
+      .install();
+   let e = service().setHealthTrendPoints(&TREND_POINTS).unwrap_err();
+   assert_eq!(e.service_specific_error(), ErrorCode::VerifyFailed.code());
+   assert_eq!(env.writes(paths::HEALTH_SET_TREND_POINTS).len(), 1);
+}
+
+#[test]
+fn health_low_boundary_follows_the_kernel_format() {
+   let env = FakeSysfs::builder().node(paths::HEALTH_SET_LOW_BOUNDARY, "60\n").install();
+   let svc = service();
+   svc.setHealthLowBoundary(70).unwrap();
+   let indexed = svc.setHealthLowBoundaryIndexed(1, 70);
+   assert_eq!(exception(indexed), ExceptionCode::UNSUPPORTED_OPERATION);
+   assert_eq!(env.writes(paths::HEALTH_SET_LOW_BOUNDARY), ["70"]);
+
+   env.set(paths::HEALTH_SET_LOW_BOUNDARY, "2,60\n");
+   svc.setHealthLowBoundary(75).unwrap();
+   svc.setHealthLowBoundaryIndexed(0, 55).unwrap();
+   assert_eq!(env.writes(paths::HEALTH_SET_LOW_BOUNDARY), ["70", "2,75", "0,55"]);
+}
+
+#[test]
+fn health_low_boundary_rejects_out_of_range_input() {
+   let env = FakeSysfs::builder().node(paths::HEALTH_SET_LOW_BOUNDARY, "1,60").install();
+   let svc = service();
+   let e = svc.setHealthLowBoundary(45).unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT);
+   assert_eq!(e.get_description(), "boundaryPct 45 below minimum 50");
+   let e = svc.setHealthLowBoundaryIndexed(1, 90).unwrap_err();
+   assert_eq!(e.get_description(), "boundaryPct 90 above maximum 80");
+   let e = svc.setHealthLowBoundaryIndexed(-1, 60).unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT);
+   assert!(env.write_log().is_empty());
+
+   env.set(paths::HEALTH_SET_LOW_BOUNDARY, "garbage");
+   let e = svc.setHealthLowBoundary(60).unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::SERVICE_SPECIFIC);
+}
+
+#[test]
+fn csi_stats_are_parsed_from_the_node() {
+   let env = FakeSysfs::builder().read_only(paths::CSI_STATS, "status=1 type=2 speed=40").install();
+   let stats = service().getCsiStats().unwrap();
+   assert_eq!((stats.status, stats.kind, stats.speed, stats.ssocIn), (1, 2, 40, -1));
+   env.set(paths::CSI_STATS, "type=2");
+   let e = service().getCsiStats().unwrap_err();
+   assert_eq!(e.service_specific_error(), ErrorCode::ParseError.code());
+   assert!(e.get_description().contains("missing status"));
+}
+
+#[test]
+fn charge_stats_fall_back_to_debugfs() {
+   let capture = include_str!("../../testdata/charge_stats/android14-5.15-reset.txt");
+   let _env = FakeSysfs::builder()
+      .read_only(paths::CHARGE_STATS.alternate.unwrap(), capture)
+      .install();
+   let sessions = service().getChargeStats().unwrap();
+   let summary = sessions
+      .iter()
+      .map(|s| (s.adapterType, s.tiers.len(), s.inProgress))
+      .collect::<Vec<_>>();
+   assert_eq!(summary, [(4, 2, false), (9, 1, true)]);
+}
+
+#[test]
+fn charge_stats_need_the_node() {
+   let _env = FakeSysfs::builder().install();
+   let e = service().getChargeStats().unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::UNSUPPORTED_OPERATION);
+}
+
+#[test]
+fn temperature_sampling_interval_is_validated() {
+   let _env = FakeSysfs::builder().install();
+   let svc = service();
+   for minutes in [0, 24 * 60 + 1] {
+      let e = svc.setTemperatureSampling(true, minutes, false).unwrap_err();
+      assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT);
+   }
+   assert!(!svc.temp_history.settings().enabled);
+   svc.setTemperatureSampling(true, 24 * 60, false).unwrap();
+   assert!(svc.temp_history.settings().enabled);
+   assert!(svc.getTemperatureHistory().unwrap().is_empty());
+}
+
+#[test]
+fn health_stats_accept_any_algo() {
+   let stats = "1: 98 4630 4688 4700 132 131 140 187 800 2\n3: 91 4400\n";
+   let _env = FakeSysfs::builder().read_only(paths::HEALTH_INDEX_STATS, stats).install();
+   let svc = service();
+   let custom = svc.getHealthStats(HealthAlgo::CUSTOM).unwrap();
+   assert_eq!((custom.algo, custom.healthIndex, custom.capacityFcc), (3, 91, 4400));
+   assert_eq!(svc.getHealthStats(HealthAlgo::GOOGLE).unwrap().cycleCount, 187);
+   for algo in [HealthAlgo::MAXIM, HealthAlgo(9)] {
+      let stats = svc.getHealthStats(algo).unwrap();
+      assert_eq!((stats.algo, stats.healthIndex, stats.cycleCount), (0, 0, 0));
+   }
+}
+
+fn effective(svc: &BatteryService) -> (i32, i32, String) {
+   let e = svc.getEffectiveChargeLimit().unwrap();
+   (e.stopLevel, e.startLevel, e.clientTag)
+}
+
+#[test]
+fn the_most_restrictive_client_wins() {
+   let env = FakeSysfs::builder().charge_levels(100, 0).install();
+   let svc = BatteryService::new(Some(Request { stop: 100, start: 0 }));
+   svc.setChargeLimit(90, 80).unwrap();
+   assert_eq!(effective(&svc), (90, 80, arbitration::DEFAULT_CLIENT.to_owned()));
+   svc.setChargeLimitForClient("policy", 70, 60).unwrap();
+   // A later, looser request doesn't undo the policy's limit.
+   svc.setChargeLimit(85, 75).unwrap();
+   assert_eq!(effective(&svc), (70, 60, "policy".to_owned()));
+   assert_eq!(env.int(paths::USER_CHARGE_STOP_LEVEL), 70);
+   assert_eq!(env.int(paths::USER_CHARGE_START_LEVEL), 60);
+
+   svc.clearChargeLimitForClient("policy").unwrap();
+   assert_eq!(effective(&svc), (85, 75, arbitration::DEFAULT_CLIENT.to_owned()));
+   svc.setChargeLimitForClient(arbitration::DEFAULT_CLIENT, 0, 0).unwrap();
+   assert_eq!(effective(&svc), (100, 0, String::new()));
+   assert_eq!(svc.getChargeLimit().unwrap(), [100, 0]);
+}
+
+#[test]
+fn client_tags_are_checked() {
+   let env = FakeSysfs::builder().charge_levels(100, 0).install();
+   let svc = BatteryService::new(Some(Request { stop: 100, start: 0 }));
+   let rejected = [("", 80, 70), (arbitration::CHARGE_TO_LIMIT_CLIENT, 80, 70), ("app", 40, 30)];
+   for (tag, stop, start) in rejected {
+      let e = svc.setChargeLimitForClient(tag, stop, start);
+      assert_eq!(exception(e), ExceptionCode::ILLEGAL_ARGUMENT, "{tag}");
+   }
+   // Clearing a client without a request is a no-op.
+   svc.clearChargeLimitForClient("nobody").unwrap();
+   assert!(env.write_log().is_empty());
+}
+
+#[test]
+fn bypass_modes_outside_the_allowed_set_are_rejected() {
+   let env = FakeSysfs::builder().node(paths::charge_limit::BYPASS_MODE, 0).install();
+   let svc = service();
+   let set = |v: &str| svc.setStringProperty(Feature::CHARGE_LIMIT, property::BYPASS_MODE, v);
+   assert_eq!(exception(set("3")), ExceptionCode::ILLEGAL_ARGUMENT);
+   assert_eq!(exception(set("on")), ExceptionCode::ILLEGAL_ARGUMENT);
+   assert!(env.write_log().is_empty());
+   set("2").unwrap();
+   assert_eq!(env.writes(paths::charge_limit::BYPASS_MODE), ["2"]);
+}
+
+fn friday_night() -> ChargeLimitSchedule {
+   ChargeLimitSchedule {
+      windows: vec![ChargeLimitWindow {
+         day:         5,
+         startMinute: 18 * 60,
+         endMinute:   schedule::MINUTES_PER_DAY,
+         stopLevel:   100,
+         startLevel:  90,
+      }],
+   }
+}
+
+#[test]
+fn schedule_windows_follow_a_synthetic_clock() {
+   let env = FakeSysfs::builder().charge_levels(80, 70).install();
+   let svc = BatteryService::new(Some(Request { stop: 80, start: 70 }));
+   svc.setChargeLimitSchedule(&friday_night()).unwrap();
+   let levels = || {
+      let stop = env.int(paths::USER_CHARGE_STOP_LEVEL);
+      (stop, env.int(paths::USER_CHARGE_START_LEVEL))
+   };
+   let tick = |day, minute| schedule_tick(&svc.limits, LocalTime { day, minute });
+   tick(5, 18 * 60 - 1);
+   assert_eq!(levels(), (80, 70));
+   tick(5, 18 * 60);
+   assert_eq!(levels(), (100, 90));
+   tick(6, 0);
+   assert_eq!(levels(), (80, 70));
+   // The clock set back into the window applies it again.
+   tick(5, 20 * 60);
+   assert_eq!(levels(), (100, 90));
+   assert_eq!(schedule::load(&persist::data_path(schedule::SCHEDULE_FILE)).len(), 1);
+
+   svc.setChargeLimitSchedule(&ChargeLimitSchedule { windows: Vec::new() }).unwrap();
+   assert_eq!(levels(), (80, 70));
+}
+
+#[test]
+fn overlapping_windows_are_rejected() {
+   let env = FakeSysfs::builder().charge_levels(80, 70).install();
+   let mut schedule = friday_night();
+   let mut overlap = friday_night().windows.remove(0);
+   overlap.startMinute = 23 * 60;
+   overlap.stopLevel = 90;
+   overlap.startLevel = 80;
+   schedule.windows.push(overlap);
+   let e = service().setChargeLimitSchedule(&schedule).unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::ILLEGAL_ARGUMENT);
+   assert_eq!(e.get_description(), "window 1 overlaps window 0");
+   let mut bad_levels = friday_night();
+   bad_levels.windows[0].stopLevel = 40;
+   let e = service().setChargeLimitSchedule(&bad_levels);
+   assert_eq!(exception(e), ExceptionCode::ILLEGAL_ARGUMENT);
+   assert!(env.write_log().is_empty());
+}
+
+fn bypass_env(status: &str) -> crate::testing::Env {
+   FakeSysfs::builder()
+      .charge_levels(80, 70)
+      .node(paths::CHARGING_POLICY, 2)
+      .node(paths::BATTERY_CAPACITY, 60)
+      .node(paths::BATTERY_STATUS, status)
+      .install()
+}
+
+fn kernel_limit(env: &crate::testing::Env) -> (i32, i32, i32) {
+   (
+      env.int(paths::USER_CHARGE_STOP_LEVEL),
+      env.int(paths::USER_CHARGE_START_LEVEL),
+      env.int(paths::CHARGING_POLICY),
+   )
+}
+
+#[test]
+fn full_charge_ends_when_the_charger_is_unplugged() {
+   let env = bypass_env("Discharging");
+   let svc = BatteryService::new(Some(Request { stop: 80, start: 70 }));
+   svc.requestFullChargeOnce().unwrap();
+   assert_eq!(kernel_limit(&env), (100, 0, 1));
+   // Requested before plugging in, so being unplugged doesn't end it yet.
+   full_charge_tick(&svc.limits);
+   assert_eq!(kernel_limit(&env), (100, 0, 1));
+   env.set(paths::BATTERY_STATUS, "Charging");
+   full_charge_tick(&svc.limits);
+   env.set(paths::BATTERY_STATUS, "Discharging");
+   full_charge_tick(&svc.limits);
+   assert_eq!(kernel_limit(&env), (80, 70, 2));
+   assert!(full_charge::load(&persist::data_path(full_charge::STATE_FILE)).is_none());
+}
+
+#[test]
+fn full_charge_survives_a_restart() {
+   let env = bypass_env("Charging");
+   let levels = Some(Request { stop: 80, start: 70 });
+   let svc = BatteryService::new(levels);
+   svc.requestFullChargeOnce().unwrap();
+   full_charge_tick(&svc.limits);
+   // A new process starts without the write cache, and the kernel comes back
+   // up with the driver's defaults.
+   drop(svc);
+   sysfs::reset();
+   env.set(paths::USER_CHARGE_STOP_LEVEL, 100);
+   env.set(paths::USER_CHARGE_START_LEVEL, 0);
+   env.set(paths::CHARGING_POLICY, 2);
+
+   let svc = BatteryService::new(levels);
+   restore(&svc, levels);
+   assert_eq!(kernel_limit(&env), (100, 0, 1));
+   env.set(paths::BATTERY_CAPACITY, 100);
+   full_charge_tick(&svc.limits);
+   assert_eq!(kernel_limit(&env), (80, 70, 2));
+   assert_eq!(svc.getChargeLimit().unwrap(), [80, 70]);
+}
+
+fn charge_to_limit_env() -> crate::testing::Env {
+   FakeSysfs::builder()
+      .charge_levels(100, 0)
+      .node(paths::CHARGE_TO_LIMIT, 0)
+      .node(paths::CHARGING_POLICY, 1)
+      .node(paths::FORCE_FCR_UPDATE_OPS, 0)
+      .install()
+}
+
+#[test]
+fn charge_to_limit_sets_longlife_and_kicks_fcr_on_transitions() {
+   let env = charge_to_limit_env();
+   let svc = BatteryService::new(Some(Request { stop: 100, start: 0 }));
+   assert!(!svc.getChargeToLimit().unwrap());
+   svc.setChargeToLimit(true).unwrap();
+   svc.setChargeToLimit(true).unwrap();
+   assert_eq!(env.int(paths::CHARGING_POLICY), 2);
+   assert_eq!(kernel_limit(&env), (80, 75, 2));
+   assert!(svc.getChargeToLimit().unwrap());
+   assert_eq!(env.writes(paths::FORCE_FCR_UPDATE_OPS), ["1"]);
+
+   // Another policy means the kernel no longer enforces it.
+   env.set(paths::CHARGING_POLICY, 1);
+   assert!(!svc.getChargeToLimit().unwrap());
+   env.set(paths::CHARGING_POLICY, 2);
+
+   svc.setChargeToLimit(false).unwrap();
+   assert!(!svc.getChargeToLimit().unwrap());
+   assert_eq!(kernel_limit(&env), (100, 0, 1));
+   assert_eq!(svc.getChargingPolicy().unwrap(), ChargingPolicy::DEFAULT);
+   assert_eq!(env.writes(paths::FORCE_FCR_UPDATE_OPS), ["1", "1"]);
+}
+
+#[test]
+fn charge_to_limit_gives_the_policy_back() {
+   let env = charge_to_limit_env();
+   let svc = BatteryService::new(Some(Request { stop: 100, start: 0 }));
+   svc.setChargingPolicy(ChargingPolicy::ADAPTIVE).unwrap();
+   svc.setChargeToLimit(true).unwrap();
+   assert_eq!(env.int(paths::CHARGING_POLICY), 2);
+   // LONGLIFE is the HAL's own, not a change to forget the request over.
+   policy_tick(&svc.limits);
+   assert!(persist::data_path(policy::STATE_FILE).exists());
+   // And the policy it replaced survives a restart.
+   drop(svc);
+   sysfs::reset();
+   let svc = BatteryService::new(Some(Request { stop: 100, start: 0 }));
+   svc.setChargeToLimit(false).unwrap();
+   assert_eq!(env.int(paths::CHARGING_POLICY), 3);
+   assert_eq!(svc.getChargingPolicy().unwrap(), ChargingPolicy::ADAPTIVE);
+   assert!(!persist::data_path(policy::FORCED_FILE).exists());
+
+   // Already LONGLIFE, there is nothing to give back.
+   svc.setChargingPolicy(ChargingPolicy::CUSTOM).unwrap();
+   svc.setChargeToLimit(true).unwrap();
+   svc.setChargeToLimit(false).unwrap();
+   assert_eq!(env.writes(paths::CHARGING_POLICY), ["3", "2", "3", "2"]);
+   assert_eq!(svc.getChargingPolicy().unwrap(), ChargingPolicy::CUSTOM);
+}
+
+#[test]
+fn a_policy_picked_under_charge_to_limit_is_kept() {
+   let env = charge_to_limit_env();
+   let svc = service();
+   svc.setChargeToLimit(true).unwrap();
+   svc.setChargingPolicy(ChargingPolicy::LONGLIFE).unwrap();
+   svc.setChargeToLimit(false).unwrap();
+   assert_eq!(env.int(paths::CHARGING_POLICY), 2);
+   assert_eq!(svc.getChargingPolicy().unwrap(), ChargingPolicy::LONGLIFE);
+
+   // Nor is one set behind the HAL's back undone.
+   svc.setChargingPolicy(ChargingPolicy::DEFAULT).unwrap();
+   svc.setChargeToLimit(true).unwrap();
+   env.set(paths::CHARGING_POLICY, 3);
+   policy_tick(&svc.limits);
+   svc.setChargeToLimit(false).unwrap();
+   assert_eq!(env.int(paths::CHARGING_POLICY), 3);
+}
+
+#[test]
+fn charge_to_limit_and_custom_limits_resolve_to_the_lower_stop() {
+   let env = charge_to_limit_env();
+   let svc = BatteryService::new(Some(Request { stop: 100, start: 0 }));
+   svc.setChargeLimit(70, 60).unwrap();
+   svc.setChargeToLimit(true).unwrap();
+   assert_eq!(effective(&svc), (70, 60, arbitration::DEFAULT_CLIENT.to_owned()));
+   svc.setChargeLimit(90, 80).unwrap();
+   let winner = arbitration::CHARGE_TO_LIMIT_CLIENT.to_owned();
+   assert_eq!(effective(&svc), (80, 75, winner));
+   // The same calls in the other order end up in the same place.
+   svc.setChargeToLimit(false).unwrap();
+   svc.setChargeToLimit(true).unwrap();
+   svc.setChargeLimit(70, 60).unwrap();
+   assert_eq!(effective(&svc).0, 70);
+   assert_eq!(kernel_limit(&env).0, 70);
+}
+
+#[test]
+fn charge_to_limit_needs_the_node() {
+   let _env = FakeSysfs::builder().install();
+   let svc = service();
+   assert_eq!(exception(svc.setChargeToLimit(true)), ExceptionCode::UNSUPPORTED_OPERATION);
+   assert!(!svc.getChargeToLimit().unwrap());
+}
+
+#[test]
+fn set_charge_limit_works_after_the_limits_lock_is_poisoned() {
+   let env = FakeSysfs::builder().charge_levels(100, 0).install();
+   let svc = BatteryService::new(Some(Request { stop: 100, start: 0 }));
+   svc.setChargeLimitForClient("policy", 70, 60).unwrap();
+   let limits = svc.limits.clone();
+   let panicked = thread::spawn(move || {
+      let mut l = limits.lock().unwrap();
+      l.stop = -5;
+      panic!("poison the limits lock");
+   })
+   .join();
+   assert!(panicked.is_err() && svc.limits.is_poisoned());
+
+   // The half-updated state is dropped for the base levels.
+   assert_eq!(effective(&svc), (100, 0, String::new()));
+   assert!(!svc.limits.is_poisoned());
+   assert_eq!(env.int(paths::USER_CHARGE_STOP_LEVEL), 100);
+   svc.setChargeLimit(80, 70).unwrap();
+   assert_eq!(svc.getChargeLimit().unwrap(), [80, 70]);
+   assert_eq!(env.int(paths::USER_CHARGE_STOP_LEVEL), 80);
+}
+
+#[test]
+fn self_test_report_matches_the_dump() {
+   let env = FakeSysfs::builder()
+      .node(paths::CHARGING_POLICY, 1)
+      .read_only(paths::CSI_STATS, "bogus")
+      .install();
+   let svc = service();
+   let report = svc.runSelfTest().unwrap();
+   let mut dump = Vec::new();
+   svc.dump_state(&mut dump).unwrap();
+   let dump = String::from_utf8(dump).unwrap();
+   assert!(env.write_log().is_empty());
+   for e in &report.entries {
+      assert!(dump.contains(&format!("\n  {}: ", e.name)), "{}", e.name);
+   }
+   let policy = paths::CHARGING_POLICY.primary;
+   assert!(dump.contains(&format!("  CHARGING_POLICY: {policy} r=1 w=1 parse=ok value='1'\n")));
+   assert!(dump.contains(" parse=FAIL value='bogus'\n"));
+   assert!(dump.contains("  AACC_CHG_PROFILE: absent\n"));
+}
+
+/// Adaptive charging active with an hour to its deadline.
+fn adaptive_env(status: &str) -> crate::testing::Env {
+   FakeSysfs::builder()
+      .charge_levels(100, 0)
+      .node(paths::CHARGING_POLICY, 3)
+      .node(paths::CHARGE_DEADLINE, 3600)
+      .node(paths::BATTERY_STATUS, status)
+      .install()
+}
+
+fn deadline_rearmed(env: &crate::testing::Env) -> bool {
+   (3590..=3600).contains(&env.int(paths::CHARGE_DEADLINE))
+}
+
+#[test]
+fn adaptive_pause_keeps_the_deadline_until_resumed() {
+   let env = adaptive_env("Charging");
+   let svc = service();
+   svc.setAdaptiveChargingPaused(true).unwrap();
+   assert!(svc.isAdaptiveChargingPaused().unwrap());
+   assert_eq!(env.int(paths::CHARGING_POLICY), 1);
+   env.set(paths::CHARGE_DEADLINE, 0);
+
+   svc.setAdaptiveChargingPaused(false).unwrap();
+   assert!(!svc.isAdaptiveChargingPaused().unwrap());
+   assert_eq!(env.int(paths::CHARGING_POLICY), 3);
+   assert!(deadline_rearmed(&env), "{}", env.value(paths::CHARGE_DEADLINE));
+   assert!(!persist::data_path(adaptive_pause::STATE_FILE).exists());
+   // Resuming again is a no-op.
+   svc.setAdaptiveChargingPaused(false).unwrap();
+   assert_eq!(env.writes(paths::CHARGING_POLICY), ["1", "3"]);
+}
+
+#[test]
+fn adaptive_pause_needs_adaptive_charging() {
+   let env = adaptive_env("Charging");
+   env.set(paths::CHARGING_POLICY, 1);
+   let svc = service();
+   assert_eq!(exception(svc.setAdaptiveChargingPaused(true)), ExceptionCode::ILLEGAL_STATE);
+   assert!(!svc.isAdaptiveChargingPaused().unwrap());
+   assert!(env.write_log().is_empty());
+}
+
+#[test]
+fn adaptive_pause_ends_when_the_charger_is_unplugged() {
+   let env = adaptive_env("Discharging");
+   let svc = service();
+   svc.setAdaptiveChargingPaused(true).unwrap();
+   // Unplugged before the charger was ever seen: still paused.
+   adaptive_pause_tick(&svc.limits);
+   assert!(svc.isAdaptiveChargingPaused().unwrap());
+   env.set(paths::BATTERY_STATUS, "Charging");
+   adaptive_pause_tick(&svc.limits);
+   assert!(svc.isAdaptiveChargingPaused().unwrap());
+   env.set(paths::BATTERY_STATUS, "Discharging");
+   adaptive_pause_tick(&svc.limits);
+   assert!(!svc.isAdaptiveChargingPaused().unwrap());
+   assert_eq!(env.int(paths::CHARGING_POLICY), 3);
+   assert!(deadline_rearmed(&env));
+}
+
+#[test]
+fn adaptive_pause_survives_a_restart() {
+   let env = adaptive_env("Charging");
+   let svc = service();
+   svc.setAdaptiveChargingPaused(true).unwrap();
+   adaptive_pause_tick(&svc.limits);
+   drop(svc);
+   sysfs::reset();
+   env.set(paths::CHARGING_POLICY, 3);
+   env.set(paths::CHARGE_DEADLINE, 0);
+
+   let svc = service();
+   restore(&svc, None);
+   assert!(svc.isAdaptiveChargingPaused().unwrap());
+   assert_eq!(env.int(paths::CHARGING_POLICY), 1);
+   // The charger seen before the restart still counts.
+   env.set(paths::BATTERY_STATUS, "Discharging");
+   adaptive_pause_tick(&svc.limits);
+   assert_eq!(env.int(paths::CHARGING_POLICY), 3);
+   assert!(deadline_rearmed(&env));
+}
+
+#[test]
+fn partial_health_stats_are_served_zero_filled() {
+   let _env = FakeSysfs::builder()
+      .read_only(paths::HEALTH_INDEX_STATS, "1: 97 4500 4600 4700 120\n")
+      .install();
+   let stats = service().getHealthStats(HealthAlgo::GOOGLE).unwrap();
+   assert_eq!((stats.algo, stats.healthIndex, stats.impedanceRaw), (1, 97, 120));
+   assert_eq!((stats.impedanceAvg, stats.cycleCount, stats.tempBucket), (0, 0, 0));
+}
+
+#[test]
+fn charge_stage_is_decoded_from_the_node() {
+   let env = FakeSysfs::builder().read_only(paths::CHARGE_STAGE, "TF\n").install();
+   let svc = service();
+   assert_eq!(svc.getChargeStageEnum().unwrap(), ChargeStage::TAPER_FAST);
+   env.set(paths::CHARGE_STAGE, "Active");
+   assert_eq!(svc.getChargeStageEnum().unwrap(), ChargeStage::AC_CHARGING);
+   env.set(paths::CHARGE_STAGE, "ZZ");
+   assert_eq!(svc.getChargeStageEnum().unwrap(), ChargeStage::UNKNOWN);
+   // The raw string is passed on as is, so a new stage still reaches clients.
+   assert_eq!(svc.getChargingStageAndDeadline().unwrap().stage, "ZZ");
+   drop(env);
+   let _env = FakeSysfs::builder().install();
+   assert_eq!(svc.getChargeStageEnum().unwrap(), ChargeStage::NONE);
+}
+
+#[test]
+fn custom_and_longlife_are_reported_as_requested() {
+   let env = FakeSysfs::builder()
+      .charge_levels(100, 0)
+      .node(paths::CHARGING_POLICY, 1)
+      .install();
+   let svc = service();
+   // With nothing recorded, the shared node value reads as LONGLIFE.
+   env.set(paths::CHARGING_POLICY, 2);
+   assert_eq!(svc.getChargingPolicy().unwrap(), ChargingPolicy::LONGLIFE);
+   for policy in [ChargingPolicy::CUSTOM, ChargingPolicy::LONGLIFE, ChargingPolicy::CUSTOM] {
+      svc.setChargingPolicy(policy).unwrap();
+      assert_eq!(env.int(paths::CHARGING_POLICY), 2);
+      assert_eq!(svc.getChargingPolicy().unwrap(), policy);
+   }
+   // The request is persisted, so a new process still reports CUSTOM.
+   drop(svc);
+   sysfs::reset();
+   assert_eq!(service().getChargingPolicy().unwrap(), ChargingPolicy::CUSTOM);
+}
+
+#[test]
+fn an_external_policy_change_drops_the_recorded_policy() {
+   let env = FakeSysfs::builder()
+      .charge_levels(100, 0)
+      .node(paths::CHARGING_POLICY, 1)
+      .install();
+   let svc = service();
+   svc.setChargingPolicy(ChargingPolicy::CUSTOM).unwrap();
+   policy_tick(&svc.limits);
+   assert_eq!(svc.getChargingPolicy().unwrap(), ChargingPolicy::CUSTOM);
+
+   env.set(paths::CHARGING_POLICY, 1);
+   policy_tick(&svc.limits);
+   assert_eq!(svc.getChargingPolicy().unwrap(), ChargingPolicy::DEFAULT);
+   assert!(!persist::data_path(policy::STATE_FILE).exists());
+   // Back at 2 from someone else, it is no longer the user's CUSTOM.
+   env.set(paths::CHARGING_POLICY, 2);
+   policy_tick(&svc.limits);
+   assert_eq!(svc.getChargingPolicy().unwrap(), ChargingPolicy::LONGLIFE);
+}
+
+#[test]
+fn calibration_is_polled_until_a_terminal_state() {
+   for (end, state) in [(3, CalibrationState::COMPLETE), (4, CalibrationState::FAILED)] {
+      let env = FakeSysfs::builder()
+         .node(paths::HEALTH_SET_CAL_MODE, 0)
+         .read_only(paths::HEALTH_GET_CAL_STATE, 0)
+         .install();
+      let svc = service();
+      svc.scheduleCalibration(CalibrationMode::ENABLED).unwrap();
+      for raw in [1, 2] {
+         env.set(paths::HEALTH_GET_CAL_STATE, raw);
+         calibration_tick(&svc.calibration);
+         assert_eq!(*svc.calibration.lock_recover(), Some(CalibrationState(raw)));
+      }
+      env.set(paths::HEALTH_GET_CAL_STATE, end);
+      calibration_tick(&svc.calibration);
+      assert_eq!(*svc.calibration.lock_recover(), None);
+      assert_eq!(svc.getCalibrationState().unwrap(), state);
+      // Disabling doesn't start following a run.
+      svc.scheduleCalibration(CalibrationMode::DISABLED).unwrap();
+      assert_eq!(*svc.calibration.lock_recover(), None);
+   }
+}
+
+#[test]
+fn a_missing_node_fails_only_in_strict_mode() {
+   let _env = FakeSysfs::builder().install();
+   let svc = service();
+   let count = |site: Site| {
+      missing::counts().find(|(s, _)| s.method() == site.method()).unwrap().1
+   };
+
+   missing::set_strict(true);
+   let e = svc.setChargingPolicy(ChargingPolicy::LONGLIFE).unwrap_err();
+   assert_eq!(e.exception_code(), ExceptionCode::UNSUPPORTED_OPERATION);
+   assert!(e.get_description().contains(paths::CHARGING_POLICY.primary), "{e:?}");
+   let e = svc.scheduleCalibration(CalibrationMode::ENABLED).unwrap_err();
+   assert!(e.get_description().contains(paths::HEALTH_SET_CAL_MODE.primary), "{e:?}");
+
+   missing::set_strict(false);
+   let before = (count(Site::ChargingPolicy), count(Site::Calibration));
+   svc.setChargingPolicy(ChargingPolicy::LONGLIFE).unwrap();
+   svc.setAdaptiveChargingEnabled(true).unwrap();
+   svc.scheduleCalibration(CalibrationMode::ENABLED).unwrap();
+   assert_eq!(count(Site::ChargingPolicy), before.0 + 2);
+   assert_eq!(count(Site::Calibration), before.1 + 1);
+   let mut dump = Vec::new();
+   svc.dump_state(&mut dump).unwrap();
+   let dump = String::from_utf8(dump).unwrap();
+   assert!(dump.contains("Missing node calls (strict: false):\n"), "{dump}");
+   assert!(dump.contains(&format!("  setChargingPolicy: {}\n", before.0 + 2)), "{dump}");
+}
+
+#[test]
+fn health_stats_fall_back_to_the_alternate_node() {
+   let primary = paths::HEALTH_INDEX_STATS.primary;
+   let alternate = paths::HEALTH_INDEX_STATS.alternate.unwrap();
+   let line = |index| format!("1: {index} 4630 4688 4700 132 131 140 187 800 2\n");
+
+   let env = FakeSysfs::builder().read_only(alternate, line(93)).install();
+   assert_eq!(paths::HEALTH_INDEX_STATS.resolve(), Some(alternate));
+   assert_eq!(service().getHealthStats(HealthAlgo::GOOGLE).unwrap().healthIndex, 93);
+   drop(env);
+
+   let _env = FakeSysfs::builder()
+      .read_only(primary, line(97))
+      .read_only(alternate, line(93))
+      .install();
+   assert_eq!(service().getHealthStats(HealthAlgo::GOOGLE).unwrap().healthIndex, 97);
+}
+
+#[test]
+fn health_index_and_status_without_the_driver() {
+   let env = FakeSysfs::builder()
+      .read_only(paths::HEALTH_INDEX, "87\n")
+      .read_only(paths::HEALTH_STATUS, 1)
+      .install();
+   let svc = service();
+   assert_eq!(svc.getHealthIndex().unwrap(), 87);
+   assert_eq!(svc.getHealthStatus().unwrap(), HealthStatus::GOOD);
+
+   env.set(paths::HEALTH_INDEX, "n/a");
+   env.set(paths::HEALTH_STATUS, "");
+   let e = svc.getHealthIndex().unwrap_err();
+   assert_eq!(e.service_specific_error(), ErrorCode::ParseError.code());
+   let e = svc.getHealthStatus().unwrap_err();
+   assert_eq!(e.service_specific_error(), ErrorCode::ParseError.code());
+
+   drop(env);
+   let _env = FakeSysfs::builder().install();
+   // Absent, not a perfect battery.
+   assert_eq!(svc.getHealthIndex().unwrap(), -1);
+   assert_eq!(svc.getHealthStatus().unwrap(), HealthStatus::UNSUPPORTED);
+}
+
+#[test]
+fn firmware_updates_and_calibrations_emit_metrics() {
+   let env = FakeSysfs::builder()
+      .node(paths::FW_UPDATE_FIRMWARE, 0)
+      .rule(paths::FW_UPDATE_FIRMWARE, |_, v| if v == "2" { Err(libc::EBUSY) } else { Ok(()) })
+      .node(paths::HEALTH_SET_CAL_MODE, 0)
+      .read_only(paths::HEALTH_GET_CAL_STATE, 1)
+      .install();
+   let svc = service();
+   let update = |v: &str| {
+      svc.setStringProperty(Feature::FW_UPDATE, property::UPDATE_FIRMWARE, v).ok();
+   };
+   let events = metrics::capture(|| {
+      update("1");
+      update("2");
+      svc.scheduleCalibration(CalibrationMode::ENABLED).unwrap();
+      calibration_tick(&svc.calibration);
+      env.set(paths::HEALTH_GET_CAL_STATE, 3);
+      calibration_tick(&svc.calibration);
+   });
+   assert_eq!(events, [
+      Metric::FirmwareUpdate { ok: true },
+      Metric::FirmwareUpdate { ok: false },
+      Metric::CalibrationDone { state: CalibrationState::COMPLETE },
+   ]);
+}
+
+/// Sets the startup properties for restore on this thread.
+fn startup_props(props: &[(&str, &str)]) {
+   config::clear_properties();
+   for (key, value) in props {
+      config::set_property(key, value);
+   }
+}
+
+fn startup_env(policy: i32, dock_defend: &str, aacr: i32) -> crate::testing::Env {
+   FakeSysfs::builder()
+      .node(paths::CHARGING_POLICY, policy)
+      .node(paths::DD_SETTINGS, dock_defend)
+      .node(paths::AACR_STATE, aacr)
+      .install()
+}
+
+#[test]
+fn startup_properties_apply_policy_and_features_once() {
+   let env = startup_env(1, features::DOCK_DEFEND_OFF, 0);
+   startup_props(&[
+      (config::PROP_POLICY, "longlife"),
+      ("persist.vendor.benzene_battery.feature.dock_defend", "1"),
+      ("persist.vendor.benzene_battery.feature.aacr", "true"),
+   ]);
+   let svc = service();
+   restore(&svc, None);
+   assert_eq!(env.int(paths::CHARGING_POLICY), 2);
+   assert_eq!(env.value(paths::DD_SETTINGS), features::DOCK_DEFEND_ON);
+   assert_eq!(env.int(paths::AACR_STATE), 1);
+   // Idempotent: a second start writes nothing new.
+   restore(&service(), None);
+   assert_eq!(env.writes(paths::CHARGING_POLICY), ["2"]);
+   assert_eq!(env.writes(paths::DD_SETTINGS), [features::DOCK_DEFEND_ON]);
+   assert_eq!(env.writes(paths::AACR_STATE), ["1"]);
+   // The property policy is never persisted as a client choice.
+   assert_eq!(service().limits.lock_recover().policy, None);
+   config::clear_properties();
+}
+
+#[test]
+fn unset_or_malformed_startup_properties_leave_the_kernel_alone() {
+   let env = startup_env(1, features::DOCK_DEFEND_ON, 0);
+   for props in [
+      &[][..],
+      &[(config::PROP_POLICY, "turbo"), ("persist.vendor.benzene_battery.feature.aacr", "on")],
+   ] {
+      startup_props(props);
+      restore(&service(), None);
+   }
+   assert!(env.write_log().is_empty());
+   config::clear_properties();
+}
+
+#[test]
+fn enable_adaptive_is_used_only_without_a_policy_property() {
+   let env = startup_env(1, features::DOCK_DEFEND_OFF, 0);
+   startup_props(&[(config::PROP_ENABLE_ADAPTIVE, "1")]);
+   restore(&service(), None);
+   assert_eq!(env.int(paths::CHARGING_POLICY), 3);
+   drop(env);
+   let env = startup_env(1, features::DOCK_DEFEND_OFF, 0);
+   startup_props(&[(config::PROP_ENABLE_ADAPTIVE, "1"), (config::PROP_POLICY, "default")]);
+   restore(&service(), None);
+   assert!(env.writes(paths::CHARGING_POLICY).is_empty());
+   config::clear_properties();
+}
+
+#[test]
+fn a_client_policy_wins_over_the_startup_property() {
+   let env = startup_env(1, features::DOCK_DEFEND_OFF, 0);
+   service().setChargingPolicy(ChargingPolicy::LONGLIFE).unwrap();
+   startup_props(&[(config::PROP_POLICY, "adaptive")]);
+   restore(&service(), None);
+   assert_eq!(env.writes(paths::CHARGING_POLICY), ["2"]);
+   config::clear_properties();
+}
+
+#[test]
+fn a_running_aacr_counts_as_enabled_at_startup() {
+   let env = startup_env(1, features::DOCK_DEFEND_OFF, 2);
+   startup_props(&[("persist.vendor.benzene_battery.feature.aacr", "1")]);
+   restore(&service(), None);
+   assert!(env.writes(paths::AACR_STATE).is_empty());
+   startup_props(&[("persist.vendor.benzene_battery.feature.aacr", "0")]);
+   restore(&service(), None);
+   assert_eq!(env.writes(paths::AACR_STATE), ["0"]);
+   config::clear_properties();
+}
+
+#[test]
+fn an_unchanged_charging_policy_is_not_rewritten() {
+   let env = FakeSysfs::builder().node(paths::CHARGING_POLICY, 2).install();
+   let svc = service();
+   for _ in 0..3 {
+      svc.setChargingPolicy(ChargingPolicy::DEFAULT).unwrap();
+   }
+   assert_eq!(env.writes(paths::CHARGING_POLICY), ["1"]);
+   // Nor when the node already held it before the first call.
+   drop(svc);
+   sysfs::reset();
+   service().setChargingPolicy(ChargingPolicy::DEFAULT).unwrap();
+   assert_eq!(env.writes(paths::CHARGING_POLICY), ["1"]);
+}
+
+#[test]
+fn a_matching_policy_still_ends_an_adaptive_pause() {
+   let env = adaptive_env("Charging");
+   let svc = service();
+   svc.setAdaptiveChargingPaused(true).unwrap();
+   // The pause holds the node at DEFAULT, but the request has to end it.
+   svc.setChargingPolicy(ChargingPolicy::DEFAULT).unwrap();
+   assert!(!svc.isAdaptiveChargingPaused().unwrap());
+   assert!(!persist::data_path(adaptive_pause::STATE_FILE).exists());
+   assert_eq!(svc.getChargingPolicy().unwrap(), ChargingPolicy::DEFAULT);
+   svc.setChargingPolicy(ChargingPolicy::DEFAULT).unwrap();
+   assert_eq!(env.writes(paths::CHARGING_POLICY), ["1"]);
+}
+
+#[test]
+fn shutdown_flushes_state_and_joins_the_monitor() {
+   let env = FakeSysfs::builder().node(paths::CHARGING_POLICY, 2).install();
+   let svc = service();
+   // As if saving it failed at the time, so only memory has the policy.
+   recover_limits(&svc.limits).policy = Some(ChargingPolicy::LONGLIFE);
+   assert_eq!(policy::load(&persist::data_path(policy::STATE_FILE)), None);
+   let limits = svc.limits.clone();
+   shutdown::on_flush(move || recover_limits(&limits).flush());
+   svc.monitor.start().unwrap();
+   assert!(svc.monitor.is_running());
+
+   let started = std::time::Instant::now();
+   shutdown::shutdown();
+   shutdown::reset();
+   assert!(started.elapsed() < shutdown::DEADLINE);
+   assert!(!svc.monitor.is_running());
+   assert_eq!(
+      policy::load(&persist::data_path(policy::STATE_FILE)),
+      Some(ChargingPolicy::LONGLIFE)
+   );
+   drop(env);
+}
+
+fn pending(svc: &BatteryService) -> bool {
+   has_pending_work(&svc.limits, &svc.calibration, &svc.temp_history, &svc.derating)
+}
+
+#[test]
+fn a_relaunched_instance_restores_state_and_pending_work() {
+   let env = adaptive_env("Charging");
+   let svc = service();
+   assert!(!pending(&svc));
+   svc.setChargeLimitSchedule(&friday_night()).unwrap();
+   svc.setTemperatureSampling(true, 30, false).unwrap();
+   svc.setChargingPolicy(ChargingPolicy::LONGLIFE).unwrap();
+   let schedule = recover_limits(&svc.limits).schedule.clone();
+
+   // A lazy service exits with no clients; the next one starts from disk.
+   drop(svc);
+   sysfs::reset();
+   let svc = service();
+   assert_eq!(recover_limits(&svc.limits).schedule, schedule);
+   assert_eq!(svc.temp_history.settings().interval_min, 30);
+   assert_eq!(svc.getChargingPolicy().unwrap(), ChargingPolicy::LONGLIFE);
+   assert!(pending(&svc));
+
+   // Once the work is done, the next instance has nothing to hold on for.
+   svc.setChargeLimitSchedule(&ChargeLimitSchedule { windows: Vec::new() }).unwrap();
+   svc.setTemperatureSampling(false, 30, false).unwrap();
+   drop(svc);
+   sysfs::reset();
+   assert!(!pending(&service()));
+   drop(env);
+}
+
+#[test]
+fn a_relaunch_mid_pause_keeps_adaptive_charging_paused() {
+   let env = adaptive_env("Charging");
+   let svc = service();
+   svc.setAdaptiveChargingPaused(true).unwrap();
+   drop(svc);
+   sysfs::reset();
+   let svc = service();
+   restore(&svc, None);
+   assert!(svc.isAdaptiveChargingPaused().unwrap());
+   assert!(pending(&svc));
+   assert_eq!(env.int(paths::CHARGING_POLICY), 1);
+   svc.setAdaptiveChargingPaused(false).unwrap();
+   assert_eq!(env.int(paths::CHARGING_POLICY), 3);
+}
+
+#[test]
+fn concurrent_calls_never_interleave_level_writes() {
+   const PAIRS: [(i32, i32); 4] = [(90, 85), (60, 50), (75, 65), (100, 95)];
+   let env = FakeSysfs::builder()
+      .charge_levels(80, 70)
+      .node(paths::CHARGING_POLICY, 1)
+      .node(paths::HEALTH_INDEX, 90)
+      .install();
+   let svc = service();
+   let valid = |got: &[i32]| got == [80, 70] || PAIRS.iter().any(|&(s, t)| got == [s, t]);
+   thread::scope(|scope| {
+      for offset in 0..PAIRS.len() {
+         let svc = &svc;
+         scope.spawn(move || {
+            for i in 0..200 {
+               let (stop, start) = PAIRS[(i + offset) % PAIRS.len()];
+               svc.setChargeLimit(stop, start).unwrap();
+            }
+         });
+      }
+      for _ in 0..2 {
+         scope.spawn(|| {
+            for _ in 0..400 {
+               let got = svc.getChargeLimit().unwrap();
+               assert!(valid(&got), "torn read {got:?}");
+               svc.getChargingPolicy().unwrap();
+               svc.getHealthIndex().unwrap();
+            }
+         });
+      }
+   });
+   // Every write the kernel saw kept stop above start, and it ended on a
+   // whole request.
+   assert!(env.rejected(paths::USER_CHARGE_STOP_LEVEL).is_empty());
+   assert!(env.rejected(paths::USER_CHARGE_START_LEVEL).is_empty());
+   assert!(env.writes(paths::USER_CHARGE_STOP_LEVEL).len() > 100);
+   let end = [env.int(paths::USER_CHARGE_STOP_LEVEL), env.int(paths::USER_CHARGE_START_LEVEL)];
+   assert!(valid(&end), "{end:?}");
+   assert_eq!(svc.getChargeLimit().unwrap(), end);
+}
+
+#[test]
+fn only_an_unreachable_servicemanager_is_retried() {
+   let unavailable = [
+      StatusCode::DEAD_OBJECT,
+      StatusCode::FAILED_TRANSACTION,
+      StatusCode::NO_INIT,
+      StatusCode::TIMED_OUT,
+      StatusCode::WOULD_BLOCK,
+   ];
+   for code in unavailable {
+      assert!(matches!(RegisterError::from_status(code), RegisterError::Unavailable(_)));
+   }
+   for code in [StatusCode::PERMISSION_DENIED, StatusCode::BAD_VALUE, StatusCode::ALREADY_EXISTS] {
+      assert!(matches!(RegisterError::from_status(code), RegisterError::Rejected(_)));
+   }
+}
+
+#[test]
+fn defender_summary_without_nodes_is_all_inactive() {
+   let _env = FakeSysfs::builder().install();
+   let summary = service().getBatteryDefenderSummary().unwrap();
+   assert!(!summary.tempActive && !summary.trickleActive);
+   assert_eq!((summary.trickleCnt, summary.dwellRate), (0, 0));
+   assert_eq!(summary.dockStatus, DockDefendStatus::DISABLED);
+}
+
+#[test]
+fn defender_summary_reads_every_defender() {
+   let env = FakeSysfs::builder()
+      .node(paths::CHARGE_STAGE, crate::deadline::stage::BATTERY_DEFEND)
+      .node(paths::BD_TRICKLE_CNT, 3)
+      .node(paths::BD_TRICKLE_RATE, 40)
+      .node(paths::DD_STATE, 1)
+      .node(paths::DD_SETTINGS, 1)
+      .install();
+   let summary = service().getBatteryDefenderSummary().unwrap();
+   assert!(summary.tempActive && summary.trickleActive);
+   assert_eq!((summary.trickleCnt, summary.dwellRate), (3, 40));
+   assert_eq!(summary.dockStatus, DockDefendStatus::ACTIVE);
+
+   env.set(paths::CHARGE_STAGE, "Fast");
+   env.set(paths::BD_TRICKLE_CNT, 0);
+   env.set(paths::DD_STATE, 0);
+   let summary = service().getBatteryDefenderSummary().unwrap();
+   assert!(!summary.tempActive && !summary.trickleActive);
+   assert_eq!(summary.dockStatus, DockDefendStatus::INACTIVE);
+}
@@ -0,0 +1,126 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Progress of the charging session in progress, sampled from the battery
+//! uevent every SAMPLE_INTERVAL by a monitor task. A session starts when the
+//! battery leaves `Discharging` and ends when it goes back; a charge limit
+//! holding the battery at `Not charging` while plugged in is still the same
+//! session. Energy is integrated from voltage and current between samples,
+//! so it is an estimate that misses whatever happened between two samples.
+//! Completed sessions are in getChargeStats.
+
+use std::{sync::Mutex, time::Duration};
+
+use log::info;
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::ChargingSession::ChargingSession;
+
+use crate::{
+    lock::MutexExt,
+    sysfs::{self, paths},
+};
+
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+/// A gap longer than this between samples, e.g. across a suspend, isn't
+/// integrated.
+pub const MAX_GAP_MS: i64 = 60_000;
+
+struct Reading {
+    plugged: bool,
+    soc: i32,
+    current_ua: i64,
+    voltage_uv: i64,
+    temp_deci_c: i32,
+}
+
+fn read() -> Option<Reading> {
+    let uevent = paths::BATTERY_UEVENT.read_string().ok()?;
+    let value = |key| sysfs::uevent_value(&uevent, key);
+    let int = |key| value(key).and_then(|v| v.parse::<i64>().ok());
+    Some(Reading {
+        plugged: !matches!(value("STATUS")?, "Discharging" | "Unknown"),
+        soc: int("CAPACITY")? as i32,
+        current_ua: int("CURRENT_NOW").unwrap_or(0),
+        voltage_uv: int("VOLTAGE_NOW").unwrap_or(0),
+        temp_deci_c: int("TEMP").unwrap_or(0) as i32,
+    })
+}
+
+struct Live {
+    start_ms: i64,
+    start_soc: i32,
+    soc: i32,
+    energy_mwh: f64,
+    peak_current_ma: i32,
+    peak_temp_deci_c: i32,
+    last_ms: i64,
+}
+
+#[derive(Default)]
+pub struct SessionTracker {
+    live: Mutex<Option<Live>>,
+}
+
+impl SessionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called from the monitor every SAMPLE_INTERVAL.
+    pub fn tick(&self, now_ms: i64) {
+        // Read outside the lock so binder callers never wait on sysfs.
+        let Some(r) = read() else {
+            return;
+        };
+        let mut live = self.live.lock_recover();
+        if !r.plugged {
+            if let Some(ended) = live.take() {
+                info!(
+                    "Charging session ended: {}% -> {}%, ~{} mWh",
+                    ended.start_soc, ended.soc, ended.energy_mwh as i32
+                );
+            }
+            return;
+        }
+        let l = live.get_or_insert_with(|| {
+            info!("Charging session started at {}%", r.soc);
+            Live {
+                start_ms: now_ms,
+                start_soc: r.soc,
+                soc: r.soc,
+                energy_mwh: 0.0,
+                peak_current_ma: 0,
+                peak_temp_deci_c: r.temp_deci_c,
+                last_ms: now_ms,
+            }
+        });
+        // Kernels differ on the sign of current_now; only charging is
+        // sampled here, so the magnitude is what counts.
+        let current_ma = r.current_ua.abs() / 1000;
+        let gap_ms = now_ms - l.last_ms;
+        if gap_ms > 0 && gap_ms <= MAX_GAP_MS {
+            let power_mw = (r.voltage_uv / 1000 * current_ma) as f64 / 1000.0;
+            l.energy_mwh += power_mw * gap_ms as f64 / 3_600_000.0;
+        }
+        l.last_ms = now_ms;
+        l.soc = r.soc;
+        l.peak_current_ma = l.peak_current_ma.max(current_ma as i32);
+        l.peak_temp_deci_c = l.peak_temp_deci_c.max(r.temp_deci_c);
+    }
+
+    /// The session in progress, or all zero with `isActive` false.
+    pub fn current(&self) -> ChargingSession {
+        let live = self.live.lock_recover();
+        let Some(l) = live.as_ref() else {
+            return ChargingSession::default();
+        };
+        ChargingSession {
+            isActive: true,
+            startTimeMs: l.start_ms,
+            startSoc: l.start_soc,
+            currentSoc: l.soc,
+            energyDeliveredMwh: l.energy_mwh as i32,
+            peakCurrentMa: l.peak_current_ma,
+            peakTempDeciC: l.peak_temp_deci_c,
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Clean exit when init stops the service. SIGTERM and SIGINT are blocked in
+//! every thread and taken by a dedicated one with sigwait, so nothing runs in
+//! signal context. On a signal the background threads are told to stop, the
+//! flush hooks write state out, the threads are joined, and the process exits;
+//! if that takes longer than DEADLINE it exits anyway so shutdown never hangs.
+
+use std::{
+    io, process,
+    sync::{mpsc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::{error, info, warn};
+use nix::sys::signal::{SigSet, Signal};
+
+use crate::lock::MutexExt;
+
+pub const DEADLINE: Duration = Duration::from_secs(3);
+
+type Hook = Box<dyn FnOnce() + Send>;
+
+static STOPPING: Mutex<bool> = Mutex::new(false);
+static WAKE: Condvar = Condvar::new();
+static THREADS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+static HOOKS: Mutex<Vec<Hook>> = Mutex::new(Vec::new());
+
+fn signals() -> SigSet {
+    let mut set = SigSet::empty();
+    set.add(Signal::SIGTERM);
+    set.add(Signal::SIGINT);
+    set
+}
+
+/// Blocks the shutdown signals in this thread and every thread it starts
+/// afterwards, so call it first thing in main.
+pub fn block_signals() -> nix::Result<()> {
+    signals().thread_block()
+}
+
+/// Starts the thread that waits for a shutdown signal.
+pub fn spawn_handler() -> io::Result<()> {
+    thread::Builder::new()
+        .name("battery_shutdown".into())
+        .spawn(|| {
+            match signals().wait() {
+                Ok(sig) => info!("{sig:?} received, shutting down"),
+                Err(e) => {
+                    error!("sigwait failed: {e}");
+                    return;
+                }
+            }
+            let (tx, rx) = mpsc::channel();
+            thread::spawn(move || {
+                shutdown();
+                let _ = tx.send(());
+            });
+            if rx.recv_timeout(DEADLINE).is_err() {
+                warn!("Shutdown took longer than {DEADLINE:?}, exiting anyway");
+            }
+            process::exit(0);
+        })
+        .map(|_| ())
+}
+
+/// Registers a thread to stop and join on shutdown. It should wait with
+/// `sleep`, or check `is_stopping` when woken, so it notices promptly.
+pub fn track(handle: JoinHandle<()>) {
+    THREADS.lock_recover().push(handle);
+}
+
+/// Registers work to run on shutdown once the background threads are told
+/// to stop, e.g. writing out state.
+pub fn on_flush(hook: impl FnOnce() + Send + 'static) {
+    HOOKS.lock_recover().push(Box::new(hook));
+}
+
+/// Sleeps for `d` or until shutdown starts. Returns true on shutdown.
+pub fn sleep(d: Duration) -> bool {
+    let stopping = STOPPING.lock_recover();
+    let (stopping, _) = WAKE
+        .wait_timeout_while(stopping, d, |s| !*s)
+        .unwrap_or_else(|e| e.into_inner());
+    *stopping
+}
+
+/// Whether shutdown has started, for a thread that waits on something other
+/// than `sleep` and is woken by a flush hook.
+pub fn is_stopping() -> bool {
+    *STOPPING.lock_recover()
+}
+
+/// Stops the tracked threads, runs the flush hooks and joins the threads.
+pub fn shutdown() {
+    *STOPPING.lock_recover() = true;
+    WAKE.notify_all();
+    let hooks = std::mem::take(&mut *HOOKS.lock_recover());
+    for hook in hooks {
+        hook();
+    }
+    let threads = std::mem::take(&mut *THREADS.lock_recover());
+    for t in threads {
+        let name = t.thread().name().unwrap_or("?").to_owned();
+        if t.join().is_err() {
+            warn!("{name} panicked during shutdown");
+        }
+    }
+    info!("Shutdown complete");
+}
+
+/// Undoes `shutdown` so later tests in the same process can sleep again.
+#[cfg(test)]
+pub fn reset() {
+    *STOPPING.lock_recover() = false;
+}
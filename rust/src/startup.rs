@@ -0,0 +1,92 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Startup reconciliation. The service is published before the kernel is
+//! brought back in line with the persisted state, so servicemanager clients
+//! aren't held up by charger nodes that probe late. Calls that arrive in the
+//! meantime see what BatteryService::new loaded from disk.
+//!
+//! The reconciler waits up to NODE_TIMEOUT for KEY_NODES, runs its steps in
+//! order, logs a Summary and sets PROP_READY.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use rustutils::system_properties;
+
+use crate::{
+    shutdown,
+    sysfs::{paths, SysfsPath},
+};
+
+pub const PROP_READY: &str = "vendor.benzene_battery.ready";
+
+/// Nodes the restore steps write, which the charger driver may create late.
+pub const KEY_NODES: &[(&str, SysfsPath)] = &[
+    ("CHARGING_POLICY", paths::CHARGING_POLICY),
+    ("USER_CHARGE_STOP_LEVEL", paths::charge_limit::STOP_LEVEL),
+    ("USER_CHARGE_START_LEVEL", paths::charge_limit::START_LEVEL),
+];
+pub const NODE_TIMEOUT: Duration = Duration::from_secs(10);
+pub const NODE_POLL: Duration = Duration::from_millis(200);
+
+/// Waits for KEY_NODES, returning the ones still missing after NODE_TIMEOUT
+/// or once shutdown starts. Devices without some of them pay the full wait,
+/// off the binder threads.
+pub fn wait_for_nodes() -> Vec<&'static str> {
+    let started = Instant::now();
+    loop {
+        let missing = KEY_NODES
+            .iter()
+            .filter(|(_, p)| !p.exists())
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        if missing.is_empty() || started.elapsed() >= NODE_TIMEOUT || shutdown::sleep(NODE_POLL) {
+            return missing;
+        }
+    }
+}
+
+/// What each reconciliation step did.
+pub struct Summary {
+    started: Instant,
+    applied: Vec<&'static str>,
+    failed: Vec<&'static str>,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            applied: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    pub fn record<E: fmt::Debug>(&mut self, step: &'static str, result: Result<(), E>) {
+        match result {
+            Ok(()) => self.applied.push(step),
+            Err(e) => {
+                warn!("Startup step '{step}' failed: {e:?}");
+                self.failed.push(step);
+            }
+        }
+    }
+
+    /// Logs the summary and sets PROP_READY.
+    pub fn finish(self, missing: &[&str]) {
+        info!(
+            "Startup reconciliation done in {:?}: applied {:?}, failed {:?}, missing nodes {:?}",
+            self.started.elapsed(),
+            self.applied,
+            self.failed,
+            missing
+        );
+        if let Err(e) = system_properties::write(PROP_READY, "1") {
+            warn!("Failed to set {PROP_READY}: {e}");
+        }
+    }
+}
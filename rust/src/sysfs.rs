@@ -2,24 +2,50 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
-    fs,
-    io::{self, ErrorKind},
-    path::Path,
+    collections::{BTreeMap, BTreeSet},
+    io::{self, ErrorKind, Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+    time::Duration,
 };
 
+use crate::{hot_cache, lock::MutexExt};
+
+#[cfg(test)]
+use crate::testing::{node_access, node_exists, open_node, Node};
+
 #[derive(Debug)]
 pub enum Error {
     NotFound { path: String },
-    Io { path: String, source: io::Error },
+    /// `written` is set for writes the kernel rejected with EINVAL, so the
+    /// log shows which value it didn't like.
+    Io { path: String, source: io::Error, written: Option<String> },
     Parse { path: String, content: String },
+    Verify { path: String, expected: String, actual: String },
+    /// A timed read didn't complete in time; the driver may still be stuck.
+    Timeout { path: String },
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::NotFound { path } => write!(f, "sysfs path not found: {path}"),
-            Self::Io { path, source } => write!(f, "I/O error on {path}: {source}"),
+            Self::Io {
+                path,
+                source,
+                written: Some(value),
+            } => write!(f, "I/O error on {path}: {source} (wrote '{value}')"),
+            Self::Io { path, source, .. } => write!(f, "I/O error on {path}: {source}"),
             Self::Parse { path, content } => write!(f, "parse error: '{content}' from {path}"),
+            Self::Verify {
+                path,
+                expected,
+                actual,
+            } => write!(f, "verify failed on {path}: wrote '{expected}', read back '{actual}'"),
+            Self::Timeout { path } => write!(f, "timed out reading {path}"),
         }
     }
 }
@@ -35,10 +61,205 @@ impl std::error::Error for Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+fn io_error(path: &str, e: io::Error) -> Error {
+    match e.kind() {
+        ErrorKind::NotFound => Error::NotFound { path: path.into() },
+        _ => Error::Io {
+            path: path.into(),
+            source: e,
+            written: None,
+        },
+    }
+}
+
+/// A node opened by `open_node`.
+#[cfg(not(test))]
+type Node = std::fs::File;
+
+/// Opens a node for reading, or for writing with `write`. Unlike `fs::write`
+/// nothing is ever created. Test builds open FakeSysfs nodes instead.
+#[cfg(not(test))]
+fn open_node(path: &str, write: bool) -> io::Result<Node> {
+    std::fs::OpenOptions::new().read(!write).write(write).open(path)
+}
+
+#[cfg(not(test))]
+fn node_exists(path: &str) -> bool {
+    std::path::Path::new(path).exists()
+}
+
+/// Whether a node exists, for paths that aren't a SysfsPath.
+pub fn exists(path: &str) -> bool {
+    node_exists(path)
+}
+
+#[cfg(not(test))]
+fn node_access(path: &str, write: bool) -> bool {
+    let Ok(c) = std::ffi::CString::new(path) else {
+        return false;
+    };
+    let mode = if write { libc::W_OK } else { libc::R_OK };
+    // SAFETY: `c` is a valid NUL-terminated string that outlives the call.
+    unsafe { libc::access(c.as_ptr(), mode) == 0 }
+}
+
+/// Whether this process may read, or with `write` write, a node, without
+/// opening it.
+pub fn can_access(path: &str, write: bool) -> bool {
+    node_access(path, write)
+}
+
+/// Integer reads of nodes shorter than this use a stack buffer; longer
+/// content is read as a string instead.
+pub const SMALL_READ_LEN: usize = 64;
+
+/// Trims `s` without a second allocation.
+fn trim_in_place(s: &mut String) {
+    s.truncate(s.trim_end().len());
+    let start = s.len() - s.trim_start().len();
+    s.drain(..start);
+}
+
+/// Timeout for reads of nodes whose driver has been seen to stall, so a
+/// binder thread gives up instead of hanging with it.
+pub const SLOW_READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Nodes with a timed-out read still blocked in the driver. Another read
+/// would only queue behind it, so it fails straight away instead.
+static STUCK_READS: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// Runs `read` of `path`, giving up after `timeout`. Regular sysfs files
+/// always poll as readable and a driver stuck in its show() can't be
+/// interrupted, so the read runs on a helper thread that is left behind on
+/// timeout and exits whenever the driver returns.
+fn with_timeout<T: Send + 'static>(
+    path: &'static str,
+    timeout: Duration,
+    read: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let timed_out = || Error::Timeout { path: path.into() };
+    if STUCK_READS.lock_recover().contains(path) {
+        return Err(timed_out());
+    }
+    let (tx, rx) = mpsc::sync_channel(1);
+    thread::Builder::new()
+        .name("battery_sysfs_read".into())
+        .spawn(move || {
+            // Send before clearing, so a timeout racing the read either still
+            // receives the result or leaves the clearing to this thread.
+            let _ = tx.send(read());
+            STUCK_READS.lock_recover().remove(path);
+        })
+        .map_err(|e| io_error(path, e))?;
+    if let Ok(result) = rx.recv_timeout(timeout) {
+        return result;
+    }
+    STUCK_READS.lock_recover().insert(path);
+    match rx.try_recv() {
+        Ok(result) => {
+            STUCK_READS.lock_recover().remove(path);
+            result
+        }
+        Err(_) => Err(timed_out()),
+    }
+}
+
+/// Last value written through each SysfsPath, by resolved path. System
+/// server re-sends policy and limits on every boot phase and user switch, and
+/// the kernel rejects some equal-value writes, so unchanged writes are skipped.
+static WRITE_CACHE: Mutex<BTreeMap<&'static str, String>> = Mutex::new(BTreeMap::new());
+
+/// Whether unchanged writes may be skipped. Only the monitor notices a node
+/// changed behind the HAL's back, so until it runs every write is made.
+static WRITE_CACHE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns skipping unchanged writes on or off, forgetting what was cached
+/// either way: nothing watched the nodes while it was off.
+pub fn set_write_cache_enabled(enabled: bool) {
+    WRITE_CACHE_ENABLED.store(enabled, Ordering::Relaxed);
+    WRITE_CACHE.lock_recover().clear();
+}
+
+/// Drops the cached write to `path` if the node no longer holds it, so a
+/// change made behind the HAL's back is rewritten on the next write. The
+/// monitor calls this when a node changes.
+pub fn revalidate_write(path: &str) {
+    let Some(value) = WRITE_CACHE.lock_recover().get(path).cloned() else {
+        return;
+    };
+    if read_string(path).map_or(true, |v| v != value) {
+        let mut cache = WRITE_CACHE.lock_recover();
+        // Unless a write raced the read and cached something else.
+        if cache.get(path) == Some(&value) {
+            cache.remove(path);
+        }
+    }
+}
+
+/// `revalidate_write` for every cached write.
+pub fn revalidate_write_cache() {
+    let cached = WRITE_CACHE.lock_recover().keys().copied().collect::<Vec<_>>();
+    for path in cached {
+        revalidate_write(path);
+    }
+}
+
+/// Records the outcome of writing `value` to `path`. A failed write leaves
+/// the node in an unknown state, so it forgets what was cached.
+fn remember(path: &'static str, value: &str, written: bool) {
+    let mut cache = WRITE_CACHE.lock_recover();
+    if written {
+        cache.insert(path, value.to_owned());
+    } else {
+        cache.remove(path);
+    }
+}
+
+/// Forgets the write cache and stuck reads, so each test starts clean. The
+/// cache starts off, as it does before the monitor runs.
+#[cfg(test)]
+pub fn reset() {
+    set_write_cache_enabled(false);
+    STUCK_READS.lock_recover().clear();
+}
+
+/// What a node holds, so it can be checked without knowing the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A single integer.
+    Int,
+    /// Integers separated by commas and/or whitespace.
+    Ints,
+    /// `csi_stats` tokens.
+    CsiStats,
+    /// `charge_stats` sessions.
+    ChargeStats,
+    /// An `aacc_chg_profile` index.
+    AaccProfile,
+    /// A bare or indexed `health_set_low_boundary` value.
+    LowBoundary,
+    /// Free text, or a format nothing here parses.
+    Text,
+}
+
+/// A node in `paths::ALL`.
+#[derive(Debug, Clone, Copy)]
+pub struct PathEntry {
+    /// The constant's name, e.g. "CHARGING_POLICY".
+    pub name: &'static str,
+    pub path: SysfsPath,
+    pub format: Format,
+    /// Feature/property pairs that getStringProperty and setStringProperty
+    /// map to this node.
+    pub properties: &'static [(Feature, i32)],
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct SysfsPath {
     pub primary: &'static str,
     pub alternate: Option<&'static str>,
+    /// Writes trigger an action, so every write must reach the kernel.
+    pub always_write: bool,
 }
 
 impl SysfsPath {
@@ -46,6 +267,7 @@ impl SysfsPath {
         Self {
             primary,
             alternate: None,
+            always_write: false,
         }
     }
 
@@ -53,14 +275,22 @@ impl SysfsPath {
         Self {
             primary,
             alternate: Some(alternate),
+            always_write: false,
+        }
+    }
+
+    pub const fn always_write(self) -> Self {
+        Self {
+            always_write: true,
+            ..self
         }
     }
 
     pub fn resolve(&self) -> Option<&'static str> {
-        if Path::new(self.primary).exists() {
+        if node_exists(self.primary) {
             Some(self.primary)
         } else {
-            self.alternate.filter(|p| Path::new(p).exists())
+            self.alternate.filter(|p| node_exists(p))
         }
     }
 
@@ -68,49 +298,201 @@ impl SysfsPath {
         self.resolve().is_some()
     }
 
+    /// Opens primary, or alternate when primary doesn't exist, and says
+    /// which. When neither exists the error is for primary.
+    fn open_with(&self, write: bool) -> (&'static str, io::Result<Node>) {
+        match (open_node(self.primary, write), self.alternate) {
+            (Err(e), Some(alt)) if e.kind() == ErrorKind::NotFound => match open_node(alt, write) {
+                Err(e) if e.kind() == ErrorKind::NotFound => (self.primary, Err(e)),
+                result => (alt, result),
+            },
+            (result, _) => (self.primary, result),
+        }
+    }
+
+    fn open(&self) -> (&'static str, io::Result<Node>) {
+        self.open_with(false)
+    }
+
+    /// Reads the node into `buf` and returns it trimmed, or None when it
+    /// doesn't fit. The error comes with the path it is for.
+    fn read_small<'b>(
+        &self,
+        buf: &'b mut [u8; SMALL_READ_LEN],
+    ) -> std::result::Result<Option<&'b str>, (&'static str, io::Error)> {
+        let (path, file) = self.open();
+        let read = |buf: &mut [u8]| -> io::Result<usize> {
+            let mut file = file?;
+            let mut len = 0;
+            while len < buf.len() {
+                match file.read(&mut buf[len..])? {
+                    0 => break,
+                    n => len += n,
+                }
+            }
+            Ok(len)
+        };
+        let len = read(buf).map_err(|e| (path, e))?;
+        if len == buf.len() {
+            return Ok(None);
+        }
+        std::str::from_utf8(&buf[..len])
+            .map(|s| Some(s.trim()))
+            .map_err(|e| (path, io::Error::new(ErrorKind::InvalidData, e)))
+    }
+
     pub fn read_string(&self) -> Result<String> {
-        self.resolve()
-            .ok_or_else(|| Error::NotFound {
-                path: self.primary.into(),
-            })
-            .and_then(read_string)
+        let mut s = self.read_string_raw()?;
+        trim_in_place(&mut s);
+        Ok(s)
+    }
+
+    pub fn read_string_raw(&self) -> Result<String> {
+        let (path, file) = self.open();
+        let mut s = String::new();
+        file.and_then(|mut f| f.read_to_string(&mut s))
+            .map_err(|e| io_error(path, e))?;
+        Ok(s)
+    }
+
+    /// `read_string_raw`, failing with Error::Timeout after `timeout`.
+    pub fn read_string_raw_timeout(&self, timeout: Duration) -> Result<String> {
+        let path = *self;
+        with_timeout(self.primary, timeout, move || path.read_string_raw())
+    }
+
+    /// `read_int`, failing with Error::Timeout after `timeout`.
+    pub fn read_int_timeout(&self, timeout: Duration) -> Result<i32> {
+        let path = *self;
+        with_timeout(self.primary, timeout, move || path.read_int())
     }
 
     pub fn read_int(&self) -> Result<i32> {
-        self.resolve()
-            .ok_or_else(|| Error::NotFound {
+        let mut buf = [0; SMALL_READ_LEN];
+        match self.read_small(&mut buf) {
+            Ok(Some(s)) => s.parse().map_err(|_| Error::Parse {
                 path: self.primary.into(),
-            })
-            .and_then(read_int)
+                content: s.to_owned(),
+            }),
+            Ok(None) => self.read_string().and_then(|content| {
+                content.parse().map_err(|_| Error::Parse {
+                    path: self.primary.into(),
+                    content,
+                })
+            }),
+            Err((path, e)) => Err(io_error(path, e)),
+        }
     }
 
+    /// Writes `value`, unless it is what the node was last written, the node
+    /// isn't `always_write` and the write cache is on.
     pub fn write_string(&self, value: &str) -> Result<()> {
+        if !self.always_write && WRITE_CACHE_ENABLED.load(Ordering::Relaxed) {
+            // Only a path that was written is cached, so the first one found
+            // is the one that resolves, and a skipped write costs no syscall.
+            let cache = WRITE_CACHE.lock_recover();
+            let cached = [Some(self.primary), self.alternate]
+                .into_iter()
+                .flatten()
+                .find_map(|p| cache.get(p));
+            if cached.is_some_and(|v| v == value) {
+                return Ok(());
+            }
+        }
+        self.write_through(value).map(|_| ())
+    }
+
+    /// Writes `value` even if it is what the cache says the node holds. For
+    /// callers that have just read the node and found something else there.
+    pub fn write_string_uncached(&self, value: &str) -> Result<()> {
+        self.write_through(value).map(|_| ())
+    }
+
+    pub fn write_int_uncached(&self, value: i32) -> Result<()> {
+        self.write_string_uncached(&value.to_string())
+    }
+
+    /// Writes `value` and returns the path written. The outcome is recorded,
+    /// so later cached writes compare against it.
+    fn write_through(&self, value: &str) -> Result<&'static str> {
+        let (path, file) = self.open_with(true);
+        let result = write_to(path, file, value);
+        if !self.always_write {
+            remember(path, value, result.is_ok());
+        }
+        result.map(|()| path)
+    }
+
+    pub fn write_int(&self, value: i32) -> Result<()> {
+        self.write_string(&value.to_string())
+    }
+
+    pub fn read_int_or(&self, default: i32) -> i32 {
+        let mut buf = [0; SMALL_READ_LEN];
+        match self.read_small(&mut buf) {
+            Ok(Some(s)) => s.parse().unwrap_or(default),
+            Ok(None) => self.read_int().unwrap_or(default),
+            Err(_) => default,
+        }
+    }
+
+    pub fn read_ints(&self) -> Result<Vec<i32>> {
         self.resolve()
             .ok_or_else(|| Error::NotFound {
                 path: self.primary.into(),
             })
-            .and_then(|p| write_string(p, value))
+            .and_then(read_ints)
     }
 
-    pub fn write_int(&self, value: i32) -> Result<()> {
-        self.write_string(&value.to_string())
+    /// Writes a comma-separated list and reads it back, failing if the kernel
+    /// dropped or rewrote any entry. Verified writes always reach the kernel,
+    /// since the read back is their check.
+    pub fn write_ints_verified(&self, values: &[i32]) -> Result<()> {
+        let expected = format_ints(values);
+        let path = self.write_through(&expected)?;
+        let actual = read_ints(path)?;
+        if actual != values {
+            remember(path, &expected, false);
+            return Err(Error::Verify {
+                path: path.into(),
+                expected,
+                actual: format_ints(&actual),
+            });
+        }
+        Ok(())
     }
 
-    pub fn read_int_or(&self, default: i32) -> i32 {
-        self.read_int().unwrap_or(default)
+    pub fn write_int_verified(&self, value: i32) -> Result<()> {
+        let expected = value.to_string();
+        let path = self.write_through(&expected)?;
+        let actual = read_int(path)?;
+        if actual != value {
+            remember(path, &expected, false);
+            return Err(Error::Verify {
+                path: path.into(),
+                expected,
+                actual: actual.to_string(),
+            });
+        }
+        Ok(())
     }
 }
 
 pub fn read_string(path: &str) -> Result<String> {
-    fs::read_to_string(path)
-        .map(|s| s.trim().to_string())
-        .map_err(|e| match e.kind() {
-            ErrorKind::NotFound => Error::NotFound { path: path.into() },
-            _ => Error::Io {
-                path: path.into(),
-                source: e,
-            },
-        })
+    let mut s = read_string_raw(path)?;
+    trim_in_place(&mut s);
+    Ok(s)
+}
+
+/// The node's content as is. Compound nodes such as `health_index_stats` can
+/// carry layout in leading or trailing whitespace that `read_string` would
+/// drop.
+pub fn read_string_raw(path: &str) -> Result<String> {
+    let mut s = String::new();
+    open_node(path, false)
+        .and_then(|mut f| f.read_to_string(&mut s))
+        .map_err(|e| io_error(path, e))?;
+    Ok(s)
 }
 
 pub fn read_int(path: &str) -> Result<i32> {
@@ -121,75 +503,551 @@ pub fn read_int(path: &str) -> Result<i32> {
     })
 }
 
-pub fn write_string(path: &str, value: &str) -> Result<()> {
-    fs::write(path, value).map_err(|e| match e.kind() {
+pub fn read_ints(path: &str) -> Result<Vec<i32>> {
+    let content = read_string(path)?;
+    parse_ints(&content).ok_or_else(|| Error::Parse {
+        path: path.into(),
+        content,
+    })
+}
+
+/// Parses a list of integers separated by commas and/or whitespace.
+pub fn parse_ints(s: &str) -> Option<Vec<i32>> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().ok())
+        .collect()
+}
+
+pub fn format_ints(values: &[i32]) -> String {
+    values
+        .iter()
+        .map(i32::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Looks up `key` in power_supply uevent content (`POWER_SUPPLY_<KEY>=value` lines).
+pub fn uevent_value<'a>(content: &'a str, key: &str) -> Option<&'a str> {
+    content
+        .lines()
+        .filter_map(|l| l.split_once('='))
+        .find(|(k, _)| k.strip_prefix("POWER_SUPPLY_") == Some(key))
+        .map(|(_, v)| v.trim())
+}
+
+/// Written values are cut to this many bytes in error messages.
+pub const MAX_WRITTEN_LEN: usize = 64;
+
+fn truncated(value: &str) -> String {
+    let mut end = value.len().min(MAX_WRITTEN_LEN);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value[..end].to_owned()
+}
+
+/// Writes to a node by path, which must exist: unlike `fs::write`, nothing is
+/// created. Goes through the node's SysfsPath when it has one, so the write
+/// cache and `always_write` apply as they do to every other write.
+pub fn write_string(path: &'static str, value: &str) -> Result<()> {
+    node(path).write_string(value)
+}
+
+/// `write_string` that is made even if the value is cached.
+pub fn write_string_uncached(path: &'static str, value: &str) -> Result<()> {
+    node(path).write_string_uncached(value)
+}
+
+/// `path` as a SysfsPath, with the flags of the constant it belongs to.
+fn node(path: &'static str) -> SysfsPath {
+    paths::ALL
+        .iter()
+        .map(|e| e.path)
+        .find(|p| p.primary == path || p.alternate == Some(path))
+        .map_or(SysfsPath::new(path), |p| SysfsPath {
+            primary: path,
+            alternate: None,
+            ..p
+        })
+}
+
+fn write_to(path: &str, file: io::Result<Node>, value: &str) -> Result<()> {
+    let result = file.and_then(|mut f| f.write_all(value.as_bytes()));
+    // Any write may move the charging state the hot getters report. After the
+    // write, so a getter on another thread can't cache the value from before.
+    hot_cache::invalidate();
+    result.map_err(|e| match e.kind() {
         ErrorKind::NotFound => Error::NotFound { path: path.into() },
         _ => Error::Io {
             path: path.into(),
+            written: (e.raw_os_error() == Some(libc::EINVAL)).then(|| truncated(value)),
             source: e,
         },
     })
 }
 
 pub mod paths {
-    use super::SysfsPath;
-
-    pub const CHARGING_POLICY: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/charging_policy");
-
-    // Charge limit control nodes
-    pub const USER_CHARGE_STOP_LEVEL: SysfsPath =
-        SysfsPath::new("/sys/devices/platform/google,charger/user_charge_stop_level");
-    pub const USER_CHARGE_START_LEVEL: SysfsPath =
-        SysfsPath::new("/sys/devices/platform/google,charger/user_charge_start_level");
-
-    pub const BD_CLEAR: SysfsPath = SysfsPath::with_alt(
-        "/sys/devices/platform/google,charger/bd_clear",
-        "/sys/devices/platform/soc/soc:google,charger/bd_clear",
-    );
-    pub const BD_TRICKLE_RESET_SEC: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/bd_trickle_reset_sec");
-
-    pub const BD_TRICKLE_RATE: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/bd_trickle_rate");
-
-    pub const DD_STATE: SysfsPath = SysfsPath::new("/sys/devices/platform/google,charger/dd_state");
-
-    pub const DD_SETTINGS: SysfsPath =
-        SysfsPath::new("/sys/devices/platform/google,charger/dd_settings");
-
-    pub const CHARGING_STATUS: SysfsPath = SysfsPath::with_alt(
-        "/sys/devices/platform/google,charger/charging_status",
-        "/sys/devices/platform/soc/soc:google,charger/charging_status",
-    );
-    pub const CHARGING_TYPE: SysfsPath = SysfsPath::with_alt(
-        "/sys/devices/platform/google,charger/charging_type",
-        "/sys/devices/platform/soc/soc:google,charger/charging_type",
-    );
-    pub const CHARGING_SPEED: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/charging_speed");
-
-    pub const CHARGE_DEADLINE: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/charge_deadline");
-    pub const CHARGE_STAGE: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/charge_stage");
-    pub const CHARGE_LIMIT: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/charge_limit");
-
-    pub const HEALTH_INDEX: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/health_index");
-    pub const HEALTH_STATUS: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/health_status");
-    pub const HEALTH_CAPACITY_INDEX: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/health_capacity_index");
-    pub const HEALTH_IMPEDANCE_INDEX: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/health_impedance_index");
-    pub const HEALTH_INDEX_STATS: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/health_index_stats");
-    pub const HEALTH_SET_CAL_MODE: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/health_set_cal_mode");
-    pub const HEALTH_GET_CAL_STATE: SysfsPath =
-        SysfsPath::new("/sys/class/power_supply/battery/health_get_cal_state");
+    use super::{property, Feature, Format, PathEntry, SysfsPath};
+
+    /// Defines each path constant once and lists it in `ALL` under its name, so
+    /// the two can't drift apart. `as group::NAME` marks a node that is also
+    /// re-exported in one of the per-feature groups below, `: Format` is what
+    /// the node holds, and `for FEATURE / PROP, ...` the getStringProperty and
+    /// setStringProperty pairs that reach it.
+    macro_rules! sysfs_paths {
+        ($(
+            $(#[$doc:meta])*
+            $name:ident $(as $group:ident::$short:ident)?: $format:ident
+                $(for $($feature:ident / $prop:ident),+)? = $path:expr;
+        )*) => {
+            $($(#[$doc])* pub const $name: SysfsPath = $path;)*
+
+            /// Every path constant, by name, in definition order.
+            pub const ALL: &[PathEntry] = &[$(PathEntry {
+                name: stringify!($name),
+                path: sysfs_paths!(@entry $name $($group::$short)?),
+                format: Format::$format,
+                properties: &[$($((Feature::$feature, property::$prop)),+)?],
+            }),*];
+        };
+        (@entry $name:ident) => {
+            $name
+        };
+        (@entry $name:ident $group:ident::$short:ident) => {
+            $group::$short
+        };
+    }
+
+    sysfs_paths! {
+        CHARGING_POLICY: Int = SysfsPath::new("/sys/class/power_supply/battery/charging_policy");
+
+        // Charge limit control nodes
+        USER_CHARGE_STOP_LEVEL as charge_limit::STOP_LEVEL: Int =
+            SysfsPath::new("/sys/devices/platform/google,charger/user_charge_stop_level");
+        USER_CHARGE_START_LEVEL as charge_limit::START_LEVEL: Int =
+            SysfsPath::new("/sys/devices/platform/google,charger/user_charge_start_level");
+
+        BD_CLEAR: Text = SysfsPath::with_alt(
+            "/sys/devices/platform/google,charger/bd_clear",
+            "/sys/devices/platform/soc/soc:google,charger/bd_clear",
+        )
+        .always_write();
+
+        // Trickle defender nodes
+        /// Trickle defend on/off, from the google_battery driver.
+        BD_TRICKLE_ENABLE as trickle_defend::ENABLE: Int for TRICKLE_DEFEND / ENABLE =
+            SysfsPath::new("/sys/class/power_supply/battery/bd_trickle_enable");
+        /// Evaluates trickle defend without acting on it, from the google_battery driver.
+        BD_TRICKLE_DRY_RUN as trickle_defend::DRY_RUN: Int for TRICKLE_DEFEND / DRY_RUN =
+            SysfsPath::new("/sys/class/power_supply/battery/bd_trickle_dry_run");
+        /// Written 0 and back to clear the trickle defender, so a rewrite of the
+        /// same value is an action too.
+        BD_TRICKLE_RESET_SEC as trickle_defend::RESET_SEC: Int
+            for TRICKLE_DEFEND / TRICKLE_RESET_SEC =
+            SysfsPath::new("/sys/class/power_supply/battery/bd_trickle_reset_sec")
+                .always_write();
+        /// Written 0 and back, like BD_TRICKLE_RESET_SEC.
+        BD_TRICKLE_RATE as trickle_defend::RATE: Int for TRICKLE_DEFEND / TRICKLE_RATE =
+            SysfsPath::new("/sys/class/power_supply/battery/bd_trickle_rate").always_write();
+        /// Trickle events counted so far, from the google_battery driver.
+        BD_TRICKLE_CNT as trickle_defend::CNT: Int for TRICKLE_DEFEND / TRICKLE_CNT =
+            SysfsPath::new("/sys/class/power_supply/battery/bd_trickle_cnt");
+        BD_TRICKLE_RECHARGE_SOC as trickle_defend::RECHARGE_SOC: Int
+            for TRICKLE_DEFEND / TRICKLE_RECHARGE_SOC =
+            SysfsPath::new("/sys/class/power_supply/battery/bd_trickle_recharge_soc");
+        BD_TRICKLE_VERSION as trickle_defend::VERSION: Int for TRICKLE_DEFEND / TRICKLE_VERSION =
+            SysfsPath::new("/sys/class/power_supply/battery/bd_trickle_version");
+        BD_TRICKLE_CNT_THR as trickle_defend::CNT_THR: Int for TRICKLE_DEFEND / TRICKLE_CNT_THR =
+            SysfsPath::new("/sys/class/power_supply/battery/bd_trickle_cnt_thr");
+
+        // Temperature defender thresholds
+        BD_TRIGGER_TEMP: Int = SysfsPath::new("/sys/class/power_supply/battery/bd_trigger_temp");
+        BD_TRIGGER_TIME: Int = SysfsPath::new("/sys/class/power_supply/battery/bd_trigger_time");
+        BD_TRIGGER_VOLTAGE: Int =
+            SysfsPath::new("/sys/class/power_supply/battery/bd_trigger_voltage");
+        BD_RECHARGE_SOC: Int = SysfsPath::new("/sys/class/power_supply/battery/bd_recharge_soc");
+        BD_RESUME_SOC: Int = SysfsPath::new("/sys/class/power_supply/battery/bd_resume_soc");
+        BD_RESUME_TEMP: Int = SysfsPath::new("/sys/class/power_supply/battery/bd_resume_temp");
+        BD_RESUME_TIME: Int = SysfsPath::new("/sys/class/power_supply/battery/bd_resume_time");
+
+        DD_STATE as dock_defend::STATE: Int =
+            SysfsPath::new("/sys/devices/platform/google,charger/dd_state");
+
+        /// "02" clears an active dock defend, however often it is written.
+        DD_SETTINGS as dock_defend::SETTINGS: Text =
+            SysfsPath::new("/sys/devices/platform/google,charger/dd_settings").always_write();
+
+        CHARGING_STATUS: Int = SysfsPath::with_alt(
+            "/sys/devices/platform/google,charger/charging_status",
+            "/sys/devices/platform/soc/soc:google,charger/charging_status",
+        );
+        CHARGING_TYPE: Int = SysfsPath::with_alt(
+            "/sys/devices/platform/google,charger/charging_type",
+            "/sys/devices/platform/soc/soc:google,charger/charging_type",
+        );
+        CHARGING_SPEED: Int = SysfsPath::new("/sys/class/power_supply/battery/charging_speed");
+        /// Fastest `charging_speed` level the hardware supports, on kernels that report it.
+        MAX_CHARGING_SPEED: Int =
+            SysfsPath::new("/sys/class/power_supply/battery/max_charging_speed");
+
+        BATTERY_CAPACITY: Int = SysfsPath::new("/sys/class/power_supply/battery/capacity");
+        BATTERY_UEVENT: Text = SysfsPath::new("/sys/class/power_supply/battery/uevent");
+        BATTERY_STATUS: Text = SysfsPath::new("/sys/class/power_supply/battery/status");
+        BATTERY_TECHNOLOGY: Text = SysfsPath::new("/sys/class/power_supply/battery/technology");
+
+        // Pack identity. Older kernels only expose these on the fuel gauge; the
+        // battery uevent is the last fallback.
+        BATTERY_MANUFACTURER: Text = SysfsPath::with_alt(
+            "/sys/class/power_supply/battery/manufacturer",
+            "/sys/class/power_supply/maxfg/manufacturer",
+        );
+        BATTERY_MODEL_NAME: Text = SysfsPath::with_alt(
+            "/sys/class/power_supply/battery/model_name",
+            "/sys/class/power_supply/maxfg/model_name",
+        );
+        BATTERY_SERIAL_NUMBER: Text = SysfsPath::with_alt(
+            "/sys/class/power_supply/battery/serial_number",
+            "/sys/class/power_supply/maxfg/serial_number",
+        );
+
+        REVERSE_WIRELESS_ENABLE: Int = SysfsPath::with_alt(
+            "/sys/class/power_supply/dc/enable",
+            "/sys/class/power_supply/wireless/device/rtx",
+        );
+
+        // Wireless charger nodes
+        WLC_MITIGATE_THRESHOLD: Int for WIRELESS / MITIGATE_THRESHOLD =
+            SysfsPath::new("/sys/class/power_supply/wireless/device/mitigate_threshold");
+        /// Wireless charger firmware update trigger.
+        WLC_RX_FWUPDATE: Int for WLC_FW / ENABLE =
+            SysfsPath::new("/sys/class/power_supply/wireless/device/rx_fwupdate");
+        /// Wireless charger firmware version tag.
+        WLC_RX_VERTAG: Text for WLC_FW / UPDATE_FIRMWARE =
+            SysfsPath::new("/sys/class/power_supply/wireless/device/rx_vertag");
+        QI22_EN_GPIO: Int for QI22 / ENABLE =
+            SysfsPath::new("/sys/class/power_supply/wireless/device/qi22_en_gpio");
+
+        // Charge current control, in µA. Kernels without the power_supply
+        // attributes have them on the google,charger platform device.
+        CONSTANT_CHARGE_CURRENT_MAX: Int = SysfsPath::with_alt(
+            "/sys/class/power_supply/battery/constant_charge_current_max",
+            "/sys/devices/platform/google,charger/constant_charge_current_max",
+        );
+        INPUT_CURRENT_LIMIT: Int = SysfsPath::with_alt(
+            "/sys/class/power_supply/battery/input_current_limit",
+            "/sys/devices/platform/google,charger/input_current_limit",
+        );
+        USB_CURRENT_MAX: Int = SysfsPath::new("/sys/class/power_supply/usb/current_max");
+
+        // Charge pump module nodes
+        CPM_DC_CTL: Int for CPM / DC_CTL =
+            SysfsPath::new("/sys/devices/platform/google,cpm/dc_ctl");
+        THERMAL_DC_FAN_ALARM: Int for CPM / THERMAL_DC_FAN_ALARM =
+            SysfsPath::new("/sys/devices/platform/google,charger/thermal_dc_fan_alarm");
+        CPM_THERMAL_MDIS_FAN_ALARM: Int for CPM / THERMAL_MDIS_FAN_ALARM =
+            SysfsPath::new("/sys/devices/platform/google,cpm/thermal_mdis_fan_alarm");
+
+        /// Each write restarts the deadline countdown from now.
+        CHARGE_DEADLINE: Int =
+            SysfsPath::new("/sys/class/power_supply/battery/charge_deadline").always_write();
+        /// Evaluates the deadline without acting on it.
+        CHARGE_DEADLINE_DRYRUN: Int for CHARGE_DEADLINE / DEADLINE_DRYRUN =
+            SysfsPath::new("/sys/class/power_supply/battery/charge_deadline_dryrun");
+        HEALTH_SAFETY_MARGIN: Int for CHARGE_DEADLINE / HEALTH_SAFETY_MARGIN =
+            SysfsPath::new("/sys/class/power_supply/battery/health_safety_margin");
+        CHARGE_STAGE: Text = SysfsPath::new("/sys/class/power_supply/battery/charge_stage");
+        CHARGE_LIMIT as charge_limit::LIMIT: Int =
+            SysfsPath::new("/sys/class/power_supply/battery/charge_limit");
+        CHARGE_TO_LIMIT as charge_limit::TO_LIMIT: Int for CHARGE_LIMIT / CHARGE_TO_LIMIT =
+            SysfsPath::new("/sys/class/power_supply/battery/charge_to_limit");
+        FORCE_FCR_UPDATE_OPS as charge_limit::FORCE_FCR_UPDATE: Int
+            for CHARGE_LIMIT / FORCE_FCR_UPDATE =
+            SysfsPath::new("/sys/class/power_supply/battery/force_fcr_update_ops").always_write();
+        /// Nonzero while maxfg lets charging run past the stop level, see
+        /// property::charge_limit_bypass_mode_allowed_values.
+        CHARGE_LIMIT_BYPASS_MODE as charge_limit::BYPASS_MODE: Int for CHARGE_LIMIT / BYPASS_MODE =
+            SysfsPath::new("/sys/class/power_supply/maxfg/bypass_chargelimit_mode");
+        CHARGE_LIMIT_BYPASS_FCN_DELTA: Int for CHARGE_LIMIT / BYPASS_FCN_DELTA =
+            SysfsPath::new("/sys/class/power_supply/maxfg/bypass_chargelimit_fcn_delta");
+        CHARGE_LIMIT_BYPASS_CYCLE_DELTA: Int for CHARGE_LIMIT / BYPASS_CYCLE_DELTA =
+            SysfsPath::new("/sys/class/power_supply/maxfg/bypass_chargelimit_cycle_delta");
+        CHARGE_STATS: ChargeStats = SysfsPath::with_alt(
+            "/sys/class/power_supply/battery/charge_stats",
+            "/sys/kernel/debug/google_battery/charge_stats",
+        );
+
+        HEALTH_INDEX as health::INDEX: Int =
+            SysfsPath::new("/sys/class/power_supply/battery/health_index");
+        HEALTH_STATUS as health::STATUS: Int =
+            SysfsPath::new("/sys/class/power_supply/battery/health_status");
+        HEALTH_CAPACITY_INDEX as health::CAPACITY_INDEX: Int =
+            SysfsPath::new("/sys/class/power_supply/battery/health_capacity_index");
+        HEALTH_IMPEDANCE_INDEX as health::IMPEDANCE_INDEX: Int =
+            SysfsPath::new("/sys/class/power_supply/battery/health_impedance_index");
+        HEALTH_INDEX_STATS as health::INDEX_STATS: Text = SysfsPath::with_alt(
+            "/sys/class/power_supply/battery/health_index_stats",
+            "/sys/class/power_supply/maxfg/health_index_stats",
+        );
+        HEALTH_ALGO as health::ALGO: Int for HEALTH / ALGO =
+            SysfsPath::new("/sys/class/power_supply/battery/health_algo");
+        HEALTH_SET_CAL_MODE as health::SET_CAL_MODE: Text =
+            SysfsPath::new("/sys/class/power_supply/battery/health_set_cal_mode").always_write();
+        HEALTH_GET_CAL_STATE as health::GET_CAL_STATE: Int =
+            SysfsPath::new("/sys/class/power_supply/battery/health_get_cal_state");
+        HEALTH_SET_TREND_POINTS as health::SET_TREND_POINTS: Ints for HEALTH / TREND_POINTS =
+            SysfsPath::new("/sys/class/power_supply/battery/health_set_trend_points");
+        HEALTH_SET_LOW_BOUNDARY as health::SET_LOW_BOUNDARY: LowBoundary for HEALTH / LOW_BOUNDARY =
+            SysfsPath::new("/sys/class/power_supply/battery/health_set_low_boundary");
+
+        CSI_STATS: CsiStats for CSI_STATS / CSI_STATS =
+            SysfsPath::new("/sys/class/power_supply/battery/csi_stats");
+
+        // AACT charge table nodes
+        AACT_STATE: Int for AACT / STATE =
+            SysfsPath::new("/sys/class/power_supply/battery/aact_state");
+        AACT_CV_LIMITS: Ints for AACT / CV_LIMITS =
+            SysfsPath::new("/sys/class/power_supply/battery/aact_cv_limits");
+        AACT_TEMP_LIMITS: Ints for AACT / TEMP_LIMITS =
+            SysfsPath::new("/sys/class/power_supply/battery/aact_temp_limits");
+        AACT_CHG_ECC: Ints for AACT / CHG_ECC =
+            SysfsPath::new("/sys/class/power_supply/battery/aact_chg_ecc");
+        AACT_PROFILE: Text for AACT / PROFILE =
+            SysfsPath::new("/sys/class/power_supply/battery/aact_profile");
+
+        // AACC charging profile selection
+        AACC_CHG_PROFILE: AaccProfile for AACC / PROFILE =
+            SysfsPath::new("/sys/class/power_supply/battery/aacc_chg_profile");
+        AACC_CHG_PROFILE_CNT: Int for AACC / PROFILE_COUNT =
+            SysfsPath::new("/sys/class/power_supply/battery/aacc_chg_profile_cnt");
+
+        // AACP version and opt-out
+        AACP_VERSION: Int for AACP / VERSION =
+            SysfsPath::new("/sys/class/power_supply/battery/aacp_version");
+        AACP_OPT_OUT: Int for AACP / OPT_OUT =
+            SysfsPath::new("/sys/class/power_supply/battery/aacp_opt_out");
+        AACP_OPT_OUT_CUTOFF_CYCLES: Int for AACP / OPT_OUT_CUTOFF =
+            SysfsPath::new("/sys/class/power_supply/battery/aacp_opt_out_cutoff_cycles");
+
+        // Fuel gauge firmware update status
+        FW_UPDATE_FIRMWARE: Text for FW_UPDATE / UPDATE_FIRMWARE =
+            SysfsPath::new("/sys/devices/platform/maxim,max77779fwu/update_firmware")
+                .always_write();
+        FW_ENABLE_UPDATE: Int for FW_UPDATE / ENABLE =
+            SysfsPath::new("/sys/devices/platform/maxim,max77779fwu/enable_update");
+
+        // Fuel gauge cycle count correction. Enabling it makes maxfg adjust its
+        // reported cycle count in place; the learned capacity data is untouched.
+        MAXFG_FIX_CYCLE_COUNT: Int for FG_CYCLE / ENABLE =
+            SysfsPath::new("/sys/class/power_supply/maxfg/fix_cycle_count");
+
+        // AACR capacity-based charge rate nodes
+        AACR_CYCLE_GRACE as aacr::CYCLE_GRACE: Int for AACR / CYCLE_GRACE =
+            SysfsPath::new("/sys/class/power_supply/battery/aacr_cycle_grace");
+        AACR_STATE as aacr::STATE: Int for AACR / STATE =
+            SysfsPath::new("/sys/class/power_supply/battery/aacr_state");
+        AACR_CYCLE_MAX as aacr::CYCLE_MAX: Int for AACR / CYCLE_MAX =
+            SysfsPath::new("/sys/class/power_supply/battery/aacr_cycle_max");
+        AACR_MIN_CAPACITY_RATE as aacr::MIN_CAPACITY_RATE: Int for AACR / MIN_CAPACITY_RATE =
+            SysfsPath::new("/sys/class/power_supply/battery/aacr_min_capacity_rate");
+        AACR_CLIFF_CAPACITY_RATE as aacr::CLIFF_CAPACITY_RATE: Int for AACR / CLIFF_CAPACITY_RATE =
+            SysfsPath::new("/sys/class/power_supply/battery/aacr_cliff_capacity_rate");
+        AACR_PROFILE as aacr::PROFILE: Text for AACR / PROFILE =
+            SysfsPath::new("/sys/class/power_supply/battery/aacr_profile");
+
+        // AAFV float voltage adjustment nodes
+        AAFV_STATE as aafv::STATE: Int for AAFV / STATE =
+            SysfsPath::new("/sys/class/power_supply/battery/aafv_state");
+        AAFV_APPLY_MAX as aafv::APPLY_MAX: Int for AAFV / APPLY_MAX =
+            SysfsPath::new("/sys/class/power_supply/battery/aafv_apply_max");
+        AAFV_MAX_OFFSET as aafv::MAX_OFFSET: Int for AAFV / MAX_OFFSET =
+            SysfsPath::new("/sys/class/power_supply/battery/aafv_max_offset");
+        AAFV_CLIFF_CYCLE as aafv::CLIFF_CYCLE: Int for AAFV / CLIFF_CYCLE =
+            SysfsPath::new("/sys/class/power_supply/battery/aafv_cliff_cycle");
+        AAFV_CLIFF_OFFSET as aafv::CLIFF_OFFSET: Int for AAFV / CLIFF_OFFSET =
+            SysfsPath::new("/sys/class/power_supply/battery/aafv_cliff_offset");
+        AAFV_PROFILE as aafv::PROFILE: Text for AAFV / PROFILE =
+            SysfsPath::new("/sys/class/power_supply/battery/aafv_profile");
+        AAFV_CONFIG as aafv::CONFIG: Text for AAFV / AAFV_CONFIG =
+            SysfsPath::new("/sys/class/power_supply/maxfg/aafv_config");
+    }
+
+    // Per-feature groups of the nodes above under shorter names, e.g.
+    // `health::INDEX_STATS` for `HEALTH_INDEX_STATS`. The flat names stay.
+
+    /// Charge limit nodes.
+    pub mod charge_limit {
+        pub use super::{
+            CHARGE_LIMIT as LIMIT, CHARGE_LIMIT_BYPASS_MODE as BYPASS_MODE,
+            CHARGE_TO_LIMIT as TO_LIMIT, FORCE_FCR_UPDATE_OPS as FORCE_FCR_UPDATE,
+            USER_CHARGE_START_LEVEL as START_LEVEL, USER_CHARGE_STOP_LEVEL as STOP_LEVEL,
+        };
+    }
+
+    /// Trickle defender nodes.
+    pub mod trickle_defend {
+        pub use super::{
+            BD_TRICKLE_CNT as CNT, BD_TRICKLE_CNT_THR as CNT_THR, BD_TRICKLE_DRY_RUN as DRY_RUN,
+            BD_TRICKLE_ENABLE as ENABLE, BD_TRICKLE_RATE as RATE,
+            BD_TRICKLE_RECHARGE_SOC as RECHARGE_SOC, BD_TRICKLE_RESET_SEC as RESET_SEC,
+            BD_TRICKLE_VERSION as VERSION,
+        };
+    }
+
+    /// Battery health nodes.
+    pub mod health {
+        pub use super::{
+            HEALTH_ALGO as ALGO, HEALTH_CAPACITY_INDEX as CAPACITY_INDEX,
+            HEALTH_GET_CAL_STATE as GET_CAL_STATE, HEALTH_IMPEDANCE_INDEX as IMPEDANCE_INDEX,
+            HEALTH_INDEX as INDEX, HEALTH_INDEX_STATS as INDEX_STATS,
+            HEALTH_SET_CAL_MODE as SET_CAL_MODE, HEALTH_SET_LOW_BOUNDARY as SET_LOW_BOUNDARY,
+            HEALTH_SET_TREND_POINTS as SET_TREND_POINTS, HEALTH_STATUS as STATUS,
+        };
+    }
+
+    /// Dock defender nodes.
+    pub mod dock_defend {
+        pub use super::{DD_SETTINGS as SETTINGS, DD_STATE as STATE};
+    }
+
+    /// AACR nodes.
+    pub mod aacr {
+        pub use super::{
+            AACR_CLIFF_CAPACITY_RATE as CLIFF_CAPACITY_RATE, AACR_CYCLE_GRACE as CYCLE_GRACE,
+            AACR_CYCLE_MAX as CYCLE_MAX, AACR_MIN_CAPACITY_RATE as MIN_CAPACITY_RATE,
+            AACR_PROFILE as PROFILE, AACR_STATE as STATE,
+        };
+    }
+
+    /// AAFV nodes.
+    pub mod aafv {
+        pub use super::{
+            AAFV_APPLY_MAX as APPLY_MAX, AAFV_CLIFF_CYCLE as CLIFF_CYCLE,
+            AAFV_CLIFF_OFFSET as CLIFF_OFFSET, AAFV_CONFIG as CONFIG, AAFV_MAX_OFFSET as MAX_OFFSET,
+            AAFV_PROFILE as PROFILE, AAFV_STATE as STATE,
+        };
+    }
+
+    const fn str_eq(a: &str, b: &str) -> bool {
+        let (a, b) = (a.as_bytes(), b.as_bytes());
+        if a.len() != b.len() {
+            return false;
+        }
+        let mut i = 0;
+        while i < a.len() {
+            if a[i] != b[i] {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+
+    /// Whether no two entries share a primary path, which a copy-paste slip
+    /// would otherwise turn into two names silently driving one node.
+    pub(super) const fn primaries_unique(all: &[PathEntry]) -> bool {
+        let mut i = 0;
+        while i < all.len() {
+            let mut j = i + 1;
+            while j < all.len() {
+                if str_eq(all[i].path.primary, all[j].path.primary) {
+                    return false;
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+        true
+    }
+
+    const _: () = assert!(primaries_unique(ALL), "two paths::ALL entries share a primary path");
+
+    const fn starts_with(s: &str, prefix: &str) -> bool {
+        let (s, prefix) = (s.as_bytes(), prefix.as_bytes());
+        if s.len() < prefix.len() {
+            return false;
+        }
+        let mut i = 0;
+        while i < prefix.len() {
+            if s[i] != prefix[i] {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+
+    /// Whether a node lives under sysfs or debugfs (`/d`), catching a lost
+    /// leading slash or a typo in the mount point.
+    const fn is_node(path: &str) -> bool {
+        starts_with(path, "/sys/") || starts_with(path, "/d/")
+    }
+
+    const fn nodes_valid(all: &[PathEntry]) -> bool {
+        let mut i = 0;
+        while i < all.len() {
+            let path = all[i].path;
+            if !is_node(path.primary) {
+                return false;
+            }
+            if let Some(alt) = path.alternate {
+                if !is_node(alt) {
+                    return false;
+                }
+            }
+            i += 1;
+        }
+        true
+    }
+
+    const _: () = assert!(nodes_valid(ALL), "a paths::ALL entry is outside /sys and /d");
+
+    /// Whether every property pair is in range and listed on one node only,
+    /// so a lookup never has to pick between two.
+    pub(super) const fn properties_valid(all: &[PathEntry]) -> bool {
+        let mut i = 0;
+        while i < all.len() {
+            let mut k = 0;
+            while k < all[i].properties.len() {
+                let (feature, prop) = all[i].properties[k];
+                if prop < 0 || prop > property::MAX_PROPERTY_ID {
+                    return false;
+                }
+                // Every later pair, on this node and the ones after it.
+                let (mut j, mut l) = (i, k + 1);
+                while j < all.len() {
+                    while l < all[j].properties.len() {
+                        let (f, p) = all[j].properties[l];
+                        if f.0 == feature.0 && p == prop {
+                            return false;
+                        }
+                        l += 1;
+                    }
+                    (j, l) = (j + 1, 0);
+                }
+                k += 1;
+            }
+            i += 1;
+        }
+        true
+    }
+
+    const _: () = assert!(properties_valid(ALL), "a property pair is out of range or repeated");
+
+    /// Logs one table of every known path, where it resolved, and whether it exists.
+    pub fn log_all_resolved(log_level: log::Level) {
+        let mut table = String::from("Resolved sysfs paths:");
+        for PathEntry { name, path, .. } in ALL {
+            let line = match path.resolve() {
+                Some(p) if p == path.primary => format!("{name}: {p} [present] (primary)"),
+                Some(p) => format!("{name}: {p} [present] (alternate)"),
+                None => format!("{name}: {} [absent]", path.primary),
+            };
+            table.push_str("\n  ");
+            table.push_str(&line);
+        }
+        log::log!(log_level, "{table}");
+    }
 }
 
 use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::Feature::Feature;
@@ -197,6 +1055,10 @@ use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::Featur
 /// Property IDs used with getStringProperty/setStringProperty.
 /// These are per-feature and based on reverse engineering of Google's HAL.
 pub mod property {
+    /// Highest ID getStringProperty/setStringProperty accept, per the IBattery
+    /// Property Access section. Leaves room above the current IDs for new features.
+    pub const MAX_PROPERTY_ID: i32 = 63;
+
     // Common properties across features
     pub const ENABLE: i32 = 0;
     pub const DRY_RUN: i32 = 1;
@@ -261,121 +1123,387 @@ pub mod property {
     pub const VERSION: i32 = 33;
     pub const OPT_OUT: i32 = 37;
     pub const OPT_OUT_CUTOFF: i32 = 44;
+
+    // AACC properties
+    pub const PROFILE_COUNT: i32 = 45;
+
+    /// Modes `bypass_chargelimit_mode` accepts; maxfg rejects anything else.
+    /// 0 disables the bypass, 1 and 2 select the FCN and cycle delta checks.
+    pub fn charge_limit_bypass_mode_allowed_values() -> &'static [i32] {
+        &[0, 1, 2]
+    }
+}
+
+/// Feature/property pairs that map to the same node as an earlier pair. Each
+/// node should have one pair; a duplicate is most likely a copy-paste slip.
+pub fn duplicate_property_paths() -> Vec<(Feature, i32, &'static str)> {
+    let mut seen = std::collections::HashSet::new();
+    Feature::enum_values()
+        .into_iter()
+        .flat_map(|f| (0..=property::MAX_PROPERTY_ID).map(move |prop| (f, prop)))
+        .filter_map(|(f, prop)| get_property_sysfs(f, prop).map(|path| (f, prop, path)))
+        .filter(|(_, _, path)| !seen.insert(*path))
+        .collect()
 }
 
-/// Get sysfs path for a feature/property combination.
-/// Based on reverse engineering of vendor.google.google_battery-service.
+/// Get sysfs path for a feature/property combination, from the `for` lists
+/// in `paths::ALL`. Based on reverse engineering of
+/// vendor.google.google_battery-service.
 pub fn get_property_sysfs(feature: Feature, prop: i32) -> Option<&'static str> {
-    use property::*;
+    paths::ALL
+        .iter()
+        .find(|e| e.properties.contains(&(feature, prop)))
+        .map(|e| e.path.primary)
+}
 
-    match feature {
-        Feature::CHARGE_DEADLINE => match prop {
-            DEADLINE_DRYRUN => Some("/sys/class/power_supply/battery/charge_deadline_dryrun"),
-            HEALTH_SAFETY_MARGIN => Some("/sys/class/power_supply/battery/health_safety_margin"),
-            _ => None,
-        },
-        Feature::TRICKLE_DEFEND => match prop {
-            ENABLE => Some("/sys/class/power_supply/battery/bd_trickle_enable"),
-            DRY_RUN => Some("/sys/class/power_supply/battery/bd_trickle_dry_run"),
-            TRICKLE_RATE => Some("/sys/class/power_supply/battery/bd_trickle_rate"),
-            TRICKLE_CNT => Some("/sys/class/power_supply/battery/bd_trickle_cnt"),
-            TRICKLE_RESET_SEC => Some("/sys/class/power_supply/battery/bd_trickle_reset_sec"),
-            TRICKLE_RECHARGE_SOC => Some("/sys/class/power_supply/battery/bd_trickle_recharge_soc"),
-            TRICKLE_VERSION => Some("/sys/class/power_supply/battery/bd_trickle_version"),
-            TRICKLE_CNT_THR => Some("/sys/class/power_supply/battery/bd_trickle_cnt_thr"),
-            _ => None,
-        },
-        Feature::WIRELESS => match prop {
-            MITIGATE_THRESHOLD => {
-                Some("/sys/class/power_supply/wireless/device/mitigate_threshold")
-            }
-            _ => None,
-        },
-        Feature::CPM => match prop {
-            DC_CTL => Some("/sys/devices/platform/google,cpm/dc_ctl"),
-            THERMAL_DC_FAN_ALARM => {
-                Some("/sys/devices/platform/google,charger/thermal_dc_fan_alarm")
-            }
-            THERMAL_MDIS_FAN_ALARM => {
-                Some("/sys/devices/platform/google,cpm/thermal_mdis_fan_alarm")
-            }
-            _ => None,
-        },
-        Feature::AACR => match prop {
-            CYCLE_GRACE => Some("/sys/class/power_supply/battery/aacr_cycle_grace"),
-            STATE => Some("/sys/class/power_supply/battery/aacr_state"),
-            CYCLE_MAX => Some("/sys/class/power_supply/battery/aacr_cycle_max"),
-            MIN_CAPACITY_RATE => Some("/sys/class/power_supply/battery/aacr_min_capacity_rate"),
-            CLIFF_CAPACITY_RATE => Some("/sys/class/power_supply/battery/aacr_cliff_capacity_rate"),
-            PROFILE => Some("/sys/class/power_supply/battery/aacr_profile"),
-            _ => None,
-        },
-        Feature::HEALTH => match prop {
-            ALGO => Some("/sys/class/power_supply/battery/health_algo"),
-            TREND_POINTS => Some("/sys/class/power_supply/battery/health_set_trend_points"),
-            LOW_BOUNDARY => Some("/sys/class/power_supply/battery/health_set_low_boundary"),
-            _ => None,
-        },
-        Feature::CSI_STATS => match prop {
-            CSI_STATS => Some("/sys/class/power_supply/battery/csi_stats"),
-            _ => None,
-        },
-        Feature::FW_UPDATE => match prop {
-            ENABLE => Some("/sys/devices/platform/maxim,max77779fwu/enable_update"),
-            UPDATE_FIRMWARE => Some("/sys/devices/platform/maxim,max77779fwu/update_firmware"),
-            _ => None,
-        },
-        Feature::CHARGE_LIMIT => match prop {
-            CHARGE_TO_LIMIT => Some("/sys/class/power_supply/battery/charge_to_limit"),
-            FORCE_FCR_UPDATE => Some("/sys/class/power_supply/battery/force_fcr_update_ops"),
-            BYPASS_FCN_DELTA => Some("/sys/class/power_supply/maxfg/bypass_chargelimit_fcn_delta"),
-            BYPASS_CYCLE_DELTA => {
-                Some("/sys/class/power_supply/maxfg/bypass_chargelimit_cycle_delta")
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeSysfs;
+
+    #[test]
+    fn reads_are_the_same_at_any_length() {
+        const NODE: SysfsPath = SysfsPath::new("/sys/fake/node");
+        for pad in [0, SMALL_READ_LEN - 4, SMALL_READ_LEN - 3, SMALL_READ_LEN, 200] {
+            let content = format!("{}42\n", " ".repeat(pad));
+            let env = FakeSysfs::builder().node(NODE, &content).install();
+            assert_eq!(NODE.read_int().unwrap(), 42, "{pad}");
+            assert_eq!(NODE.read_int_or(-1), 42, "{pad}");
+            assert_eq!(NODE.read_string().unwrap(), "42", "{pad}");
+            assert_eq!(NODE.read_string_raw().unwrap(), content, "{pad}");
+            env.set(NODE, format!("{}4x2\n", " ".repeat(pad)));
+            let Err(Error::Parse { content, .. }) = NODE.read_int() else {
+                panic!("{pad}: 4x2 isn't a number");
+            };
+            assert_eq!(content, "4x2", "{pad}");
+            assert_eq!(NODE.read_int_or(-1), -1, "{pad}");
+        }
+    }
+
+    #[test]
+    fn reads_fall_back_to_the_alternate() {
+        const NODE: SysfsPath = SysfsPath::with_alt("/sys/fake/primary", "/sys/fake/alternate");
+        let env = FakeSysfs::builder().node(NODE.alternate.unwrap(), 7).install();
+        assert_eq!((NODE.read_int().unwrap(), NODE.read_int_or(-1)), (7, 7));
+        drop(env);
+        let _env = FakeSysfs::builder().install();
+        let Err(Error::NotFound { path }) = NODE.read_int() else {
+            panic!("neither node exists");
+        };
+        assert_eq!(path, NODE.primary);
+        assert_eq!(NODE.read_int_or(-1), -1);
+        let missing = |r| matches!(r, Err(Error::NotFound { path }) if path == NODE.primary);
+        assert!(missing(NODE.read_string()));
+    }
+
+    #[test]
+    fn writes_fall_back_to_the_alternate() {
+        const NODE: SysfsPath = SysfsPath::with_alt("/sys/fake/primary", "/sys/fake/alternate");
+        let alt = NODE.alternate.unwrap();
+        let env = FakeSysfs::builder().node(alt, 1).install();
+        NODE.write_int(5).unwrap();
+        assert_eq!(env.writes(alt), ["5"]);
+        drop(env);
+        let env = FakeSysfs::builder().install();
+        let Err(Error::NotFound { path }) = NODE.write_int(6) else {
+            panic!("neither node exists");
+        };
+        assert_eq!(path, NODE.primary);
+        // The free write doesn't create a node that isn't there either.
+        assert!(matches!(write_string(alt, "7"), Err(Error::NotFound { .. })));
+        assert!(env.write_log().is_empty());
+    }
+
+    fn entry(name: &'static str, path: SysfsPath) -> PathEntry {
+        PathEntry {
+            name,
+            path,
+            format: Format::Text,
+            properties: &[],
+        }
+    }
+
+    #[test]
+    fn shared_primaries_are_caught() {
+        assert!(paths::primaries_unique(paths::ALL));
+        let (a, b) = (SysfsPath::new("/sys/fake/a"), SysfsPath::new("/sys/fake/b"));
+        assert!(paths::primaries_unique(&[entry("A", a), entry("B", b)]));
+        let copy = SysfsPath::with_alt("/sys/fake/a", "/sys/fake/c");
+        assert!(!paths::primaries_unique(&[entry("A", a), entry("B", b), entry("COPY", copy)]));
+        // Same length, different bytes.
+        let upper = SysfsPath::new("/sys/fake/A");
+        assert!(paths::primaries_unique(&[entry("A", a), entry("UPPER", upper)]));
+    }
+
+    #[test]
+    fn property_pairs_are_checked() {
+        assert!(paths::properties_valid(paths::ALL));
+        let node = |properties| PathEntry {
+            properties,
+            ..entry("A", SysfsPath::new("/sys/fake/a"))
+        };
+        let ok: &[(Feature, i32)] = &[(Feature::AACR, 0), (Feature::AAFV, 0)];
+        assert!(paths::properties_valid(&[node(ok), node(&[(Feature::AACR, 1)])]));
+        assert!(!paths::properties_valid(&[node(&[(Feature::AACR, -1)])]));
+        let past_max = &[(Feature::AACR, property::MAX_PROPERTY_ID + 1)];
+        assert!(!paths::properties_valid(&[node(past_max)]));
+        // The same pair twice, on one node or on two.
+        assert!(!paths::properties_valid(&[node(&[(Feature::AACR, 1), (Feature::AACR, 1)])]));
+        assert!(!paths::properties_valid(&[node(ok), node(&[(Feature::AAFV, 0)])]));
+    }
+
+    /// The feature/property table as it stood as a hand-written match, before
+    /// it moved into `paths::ALL`.
+    const PROPERTY_TABLE: &[(Feature, i32, &str)] = &[
+        (Feature::CHARGE_DEADLINE, 1, "/sys/class/power_supply/battery/charge_deadline_dryrun"),
+        (Feature::CHARGE_DEADLINE, 12, "/sys/class/power_supply/battery/health_safety_margin"),
+        (Feature::TRICKLE_DEFEND, 0, "/sys/class/power_supply/battery/bd_trickle_enable"),
+        (Feature::TRICKLE_DEFEND, 1, "/sys/class/power_supply/battery/bd_trickle_dry_run"),
+        (Feature::TRICKLE_DEFEND, 3, "/sys/class/power_supply/battery/bd_trickle_rate"),
+        (Feature::TRICKLE_DEFEND, 8, "/sys/class/power_supply/battery/bd_trickle_cnt"),
+        (Feature::TRICKLE_DEFEND, 12, "/sys/class/power_supply/battery/bd_trickle_reset_sec"),
+        (Feature::TRICKLE_DEFEND, 15, "/sys/class/power_supply/battery/bd_trickle_recharge_soc"),
+        (Feature::TRICKLE_DEFEND, 33, "/sys/class/power_supply/battery/bd_trickle_version"),
+        (Feature::TRICKLE_DEFEND, 50, "/sys/class/power_supply/battery/bd_trickle_cnt_thr"),
+        (Feature::WIRELESS, 5, "/sys/class/power_supply/wireless/device/mitigate_threshold"),
+        (Feature::CPM, 2, "/sys/devices/platform/google,cpm/dc_ctl"),
+        (Feature::CPM, 19, "/sys/devices/platform/google,charger/thermal_dc_fan_alarm"),
+        (Feature::CPM, 20, "/sys/devices/platform/google,cpm/thermal_mdis_fan_alarm"),
+        (Feature::AACR, 8, "/sys/class/power_supply/battery/aacr_cycle_grace"),
+        (Feature::AACR, 18, "/sys/class/power_supply/battery/aacr_state"),
+        (Feature::AACR, 21, "/sys/class/power_supply/battery/aacr_cycle_max"),
+        (Feature::AACR, 24, "/sys/class/power_supply/battery/aacr_min_capacity_rate"),
+        (Feature::AACR, 27, "/sys/class/power_supply/battery/aacr_cliff_capacity_rate"),
+        (Feature::AACR, 32, "/sys/class/power_supply/battery/aacr_profile"),
+        (Feature::HEALTH, 2, "/sys/class/power_supply/battery/health_algo"),
+        (Feature::HEALTH, 23, "/sys/class/power_supply/battery/health_set_trend_points"),
+        (Feature::HEALTH, 24, "/sys/class/power_supply/battery/health_set_low_boundary"),
+        (Feature::CSI_STATS, 25, "/sys/class/power_supply/battery/csi_stats"),
+        (Feature::FW_UPDATE, 0, "/sys/devices/platform/maxim,max77779fwu/enable_update"),
+        (Feature::FW_UPDATE, 26, "/sys/devices/platform/maxim,max77779fwu/update_firmware"),
+        (Feature::CHARGE_LIMIT, 5, "/sys/class/power_supply/battery/charge_to_limit"),
+        (Feature::CHARGE_LIMIT, 39, "/sys/class/power_supply/battery/force_fcr_update_ops"),
+        (Feature::CHARGE_LIMIT, 41, "/sys/class/power_supply/maxfg/bypass_chargelimit_fcn_delta"),
+        (Feature::CHARGE_LIMIT, 42, "/sys/class/power_supply/maxfg/bypass_chargelimit_cycle_delta"),
+        (Feature::CHARGE_LIMIT, 43, "/sys/class/power_supply/maxfg/bypass_chargelimit_mode"),
+        (Feature::FG_CYCLE, 0, "/sys/class/power_supply/maxfg/fix_cycle_count"),
+        (Feature::AAFV, 18, "/sys/class/power_supply/battery/aafv_state"),
+        (Feature::AAFV, 28, "/sys/class/power_supply/battery/aafv_apply_max"),
+        (Feature::AAFV, 29, "/sys/class/power_supply/battery/aafv_max_offset"),
+        (Feature::AAFV, 30, "/sys/class/power_supply/battery/aafv_cliff_cycle"),
+        (Feature::AAFV, 31, "/sys/class/power_supply/battery/aafv_cliff_offset"),
+        (Feature::AAFV, 32, "/sys/class/power_supply/battery/aafv_profile"),
+        (Feature::AAFV, 38, "/sys/class/power_supply/maxfg/aafv_config"),
+        (Feature::AACT, 18, "/sys/class/power_supply/battery/aact_state"),
+        (Feature::AACT, 32, "/sys/class/power_supply/battery/aact_profile"),
+        (Feature::AACT, 34, "/sys/class/power_supply/battery/aact_cv_limits"),
+        (Feature::AACT, 35, "/sys/class/power_supply/battery/aact_temp_limits"),
+        (Feature::AACT, 36, "/sys/class/power_supply/battery/aact_chg_ecc"),
+        (Feature::AACP, 33, "/sys/class/power_supply/battery/aacp_version"),
+        (Feature::AACP, 37, "/sys/class/power_supply/battery/aacp_opt_out"),
+        (Feature::AACP, 44, "/sys/class/power_supply/battery/aacp_opt_out_cutoff_cycles"),
+        (Feature::WLC_FW, 0, "/sys/class/power_supply/wireless/device/rx_fwupdate"),
+        (Feature::WLC_FW, 26, "/sys/class/power_supply/wireless/device/rx_vertag"),
+        (Feature::QI22, 0, "/sys/class/power_supply/wireless/device/qi22_en_gpio"),
+        (Feature::AACC, 32, "/sys/class/power_supply/battery/aacc_chg_profile"),
+        (Feature::AACC, 45, "/sys/class/power_supply/battery/aacc_chg_profile_cnt"),
+    ];
+
+    #[test]
+    fn property_mapping_is_unchanged() {
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for feature in Feature::enum_values() {
+            for prop in -1..=property::MAX_PROPERTY_ID + 1 {
+                let want = PROPERTY_TABLE
+                    .iter()
+                    .find(|&&(f, p, _)| (f, p) == (feature, prop))
+                    .map(|&(_, _, path)| path);
+                match (get_property_sysfs(feature, prop), want) {
+                    (got, want) if got == want => {}
+                    (Some(got), None) => added.push((feature, prop, got)),
+                    (got, want) => changed.push((feature, prop, got, want)),
+                }
             }
-            BYPASS_MODE => Some("/sys/class/power_supply/maxfg/bypass_chargelimit_mode"),
-            _ => None,
-        },
-        Feature::FG_CYCLE => match prop {
-            ENABLE => Some("/sys/class/power_supply/maxfg/fix_cycle_count"),
-            _ => None,
-        },
-        Feature::AAFV => match prop {
-            STATE => Some("/sys/class/power_supply/battery/aafv_state"),
-            APPLY_MAX => Some("/sys/class/power_supply/battery/aafv_apply_max"),
-            MAX_OFFSET => Some("/sys/class/power_supply/battery/aafv_max_offset"),
-            CLIFF_CYCLE => Some("/sys/class/power_supply/battery/aafv_cliff_cycle"),
-            CLIFF_OFFSET => Some("/sys/class/power_supply/battery/aafv_cliff_offset"),
-            PROFILE => Some("/sys/class/power_supply/battery/aafv_profile"),
-            AAFV_CONFIG => Some("/sys/class/power_supply/maxfg/aafv_config"),
-            _ => None,
-        },
-        Feature::AACT => match prop {
-            STATE => Some("/sys/class/power_supply/battery/aact_state"),
-            PROFILE => Some("/sys/class/power_supply/battery/aact_profile"),
-            CV_LIMITS => Some("/sys/class/power_supply/battery/aact_cv_limits"),
-            TEMP_LIMITS => Some("/sys/class/power_supply/battery/aact_temp_limits"),
-            CHG_ECC => Some("/sys/class/power_supply/battery/aact_chg_ecc"),
-            _ => None,
-        },
-        Feature::AACP => match prop {
-            VERSION => Some("/sys/class/power_supply/battery/aacp_version"),
-            OPT_OUT => Some("/sys/class/power_supply/battery/aacp_opt_out"),
-            OPT_OUT_CUTOFF => Some("/sys/class/power_supply/battery/aacp_opt_out_cutoff_cycles"),
-            _ => None,
-        },
-        Feature::WLC_FW => match prop {
-            ENABLE => Some("/sys/class/power_supply/wireless/device/rx_fwupdate"),
-            UPDATE_FIRMWARE => Some("/sys/class/power_supply/wireless/device/rx_vertag"),
-            _ => None,
-        },
-        Feature::QI22 => match prop {
-            ENABLE => Some("/sys/class/power_supply/wireless/device/qi22_en_gpio"),
-            _ => None,
-        },
-        Feature::AACC => match prop {
-            PROFILE => Some("/sys/class/power_supply/battery/aacc_chg_profile"),
-            _ => None,
-        },
-        _ => None,
+        }
+        assert_eq!(added, [], "pairs that weren't mapped before");
+        assert_eq!(changed, [], "pairs mapped elsewhere, or no longer mapped");
+    }
+
+    #[test]
+    fn no_two_properties_share_a_node() {
+        assert_eq!(duplicate_property_paths(), []);
+    }
+
+    #[test]
+    fn unchanged_writes_are_skipped() {
+        let env = FakeSysfs::builder()
+            .node(paths::CHARGE_TO_LIMIT, 0)
+            .reject_equal(paths::CHARGE_TO_LIMIT)
+            .install();
+        set_write_cache_enabled(true);
+        for value in [1, 1, 0, 0, 1] {
+            paths::CHARGE_TO_LIMIT.write_int(value).unwrap();
+        }
+        assert_eq!(env.writes(paths::CHARGE_TO_LIMIT), ["1", "0", "1"]);
+    }
+
+    #[test]
+    fn writes_are_made_while_the_cache_is_off() {
+        let env = FakeSysfs::builder().node(paths::CHARGE_TO_LIMIT, 0).install();
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        env.set(paths::CHARGE_TO_LIMIT, 0);
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        // Nor does turning it on trust what was written before.
+        env.set(paths::CHARGE_TO_LIMIT, 0);
+        set_write_cache_enabled(true);
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        assert_eq!(env.writes(paths::CHARGE_TO_LIMIT), ["1", "1", "1"]);
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        assert_eq!(env.writes(paths::CHARGE_TO_LIMIT).len(), 3);
+    }
+
+    #[test]
+    fn uncached_writes_skip_nothing() {
+        let env = FakeSysfs::builder().node(paths::CHARGE_TO_LIMIT, 0).install();
+        set_write_cache_enabled(true);
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        paths::CHARGE_TO_LIMIT.write_int_uncached(1).unwrap();
+        write_string_uncached(paths::CHARGE_TO_LIMIT.primary, "1").unwrap();
+        assert_eq!(env.writes(paths::CHARGE_TO_LIMIT), ["1", "1", "1"]);
+    }
+
+    #[test]
+    fn rejected_writes_name_the_value() {
+        let _env = FakeSysfs::builder()
+            .node(paths::USER_CHARGE_STOP_LEVEL, 80)
+            .range(paths::USER_CHARGE_STOP_LEVEL, 0, 100)
+            .node(paths::CHARGE_STATS, "")
+            .rule(paths::CHARGE_STATS, |_, _| Err(libc::EINVAL))
+            .node(paths::CHARGE_TO_LIMIT, 0)
+            .rule(paths::CHARGE_TO_LIMIT, |_, _| Err(libc::EBUSY))
+            .install();
+        let e = paths::USER_CHARGE_STOP_LEVEL.write_int(105).unwrap_err();
+        let (stop, einval) = (
+            paths::USER_CHARGE_STOP_LEVEL.primary,
+            io::Error::from_raw_os_error(libc::EINVAL),
+        );
+        assert_eq!(e.to_string(), format!("I/O error on {stop}: {einval} (wrote '105')"));
+
+        // Long values are cut to MAX_WRITTEN_LEN bytes, on a char boundary.
+        let long = "é".repeat(MAX_WRITTEN_LEN);
+        let Err(Error::Io { written: Some(value), .. }) = paths::CHARGE_STATS.write_string(&long)
+        else {
+            panic!("EINVAL should keep the written value");
+        };
+        assert!(long.starts_with(&value));
+        assert_eq!(value.len(), MAX_WRITTEN_LEN);
+
+        // Other errors don't blame the value.
+        let e = paths::CHARGE_TO_LIMIT.write_int(1).unwrap_err();
+        assert!(matches!(e, Error::Io { written: None, .. }), "{e}");
+        assert!(!e.to_string().contains("wrote"), "{e}");
+    }
+
+    #[test]
+    fn action_nodes_are_always_written() {
+        let env = FakeSysfs::builder()
+            .node(paths::DD_SETTINGS, "02")
+            .node(paths::BD_TRICKLE_RESET_SEC, 0)
+            .node(paths::BD_TRICKLE_RATE, 0)
+            .node(paths::CHARGE_DEADLINE, 3600)
+            .install();
+        for node in [
+            paths::DD_SETTINGS,
+            paths::BD_TRICKLE_RESET_SEC,
+            paths::BD_TRICKLE_RATE,
+            paths::CHARGE_DEADLINE,
+        ] {
+            assert!(node.always_write, "{}", node.primary);
+            let value = env.value(node);
+            node.write_string(&value).unwrap();
+            write_string(node.primary, &value).unwrap();
+            assert_eq!(env.writes(node), [value.clone(), value]);
+        }
+    }
+
+    #[test]
+    fn writes_by_path_share_the_cache() {
+        let env = FakeSysfs::builder().node(paths::CHARGE_TO_LIMIT, 1).install();
+        set_write_cache_enabled(true);
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        // A write by path must not leave "1" cached for the next one.
+        write_string(paths::CHARGE_TO_LIMIT.primary, "0").unwrap();
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        write_string(paths::CHARGE_TO_LIMIT.primary, "1").unwrap();
+        assert_eq!(env.writes(paths::CHARGE_TO_LIMIT), ["1", "0", "1"]);
+        assert_eq!(env.int(paths::CHARGE_TO_LIMIT), 1);
+    }
+
+    #[test]
+    fn verified_writes_always_reach_the_kernel() {
+        let env = FakeSysfs::builder()
+            .node(paths::HEALTH_SAFETY_MARGIN, 0)
+            .install();
+        set_write_cache_enabled(true);
+        paths::HEALTH_SAFETY_MARGIN.write_int_verified(600).unwrap();
+        paths::HEALTH_SAFETY_MARGIN.write_int_verified(600).unwrap();
+        assert_eq!(env.writes(paths::HEALTH_SAFETY_MARGIN), ["600", "600"]);
+        // And are cached, so a plain write of the same value is skipped.
+        paths::HEALTH_SAFETY_MARGIN.write_int(600).unwrap();
+        assert_eq!(env.writes(paths::HEALTH_SAFETY_MARGIN).len(), 2);
+    }
+
+    #[test]
+    fn failed_and_rewritten_writes_are_forgotten() {
+        let margin = paths::HEALTH_SAFETY_MARGIN;
+        let env = FakeSysfs::builder()
+            .node(paths::CHARGE_TO_LIMIT, 0)
+            .range(paths::CHARGE_TO_LIMIT, 0, 1)
+            .node(margin, 0)
+            // The driver clamps the margin it is given.
+            .rule(margin, move |k, v| {
+                if v.parse::<i32>().is_ok_and(|v| v > 300) {
+                    k.set(margin, 300);
+                }
+                Ok(())
+            })
+            .install();
+        set_write_cache_enabled(true);
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        assert!(paths::CHARGE_TO_LIMIT.write_int(2).is_err());
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        assert_eq!(env.writes(paths::CHARGE_TO_LIMIT), ["1", "1"]);
+
+        assert!(matches!(margin.write_int_verified(600), Err(Error::Verify { .. })));
+        assert_eq!(env.int(margin), 300);
+        margin.write_int(600).unwrap();
+        assert_eq!(env.writes(margin), ["600", "600"]);
+    }
+
+    #[test]
+    fn an_external_change_invalidates_the_cached_write() {
+        let env = FakeSysfs::builder()
+            .node(paths::CHARGE_TO_LIMIT, 0)
+            .node(paths::CHARGING_POLICY, 1)
+            .install();
+        set_write_cache_enabled(true);
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        paths::CHARGING_POLICY.write_int(2).unwrap();
+
+        // A change notification for a node still holding its value keeps it.
+        revalidate_write(paths::CHARGE_TO_LIMIT.primary);
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        assert_eq!(env.writes(paths::CHARGE_TO_LIMIT), ["1"]);
+
+        env.set(paths::CHARGE_TO_LIMIT, 0);
+        revalidate_write(paths::CHARGE_TO_LIMIT.primary);
+        paths::CHARGE_TO_LIMIT.write_int(1).unwrap();
+        assert_eq!(env.writes(paths::CHARGE_TO_LIMIT), ["1", "1"]);
+
+        env.set(paths::CHARGING_POLICY, 1);
+        revalidate_write_cache();
+        paths::CHARGING_POLICY.write_int(2).unwrap();
+        assert_eq!(env.writes(paths::CHARGING_POLICY), ["2", "2"]);
     }
 }
@@ -0,0 +1,415 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Test support. FakeSysfs stands in for the kernel: in test builds sysfs.rs
+//! opens and checks nodes here instead of on disk, so service code runs
+//! unchanged against the nodes a test declares. Each node can carry the rules
+//! its driver applies to a write, and every write is recorded.
+//!
+//! Installing a FakeSysfs also points persist at a scratch directory and
+//! clears the process-wide caches. The returned Env holds a lock for the rest
+//! of the test, so tests that share that state run one at a time.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Cursor, ErrorKind, Read, Write},
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, OnceLock,
+    },
+};
+
+use crate::{
+    hot_cache,
+    lock::MutexExt,
+    sysfs::{self, paths, SysfsPath},
+};
+
+/// Checks a write the way a driver's store() would, returning an errno such
+/// as `libc::EINVAL` to reject it. It may also change other nodes, or set the
+/// node being written to store something other than what was written, as a
+/// driver that clamps does.
+pub type Rule = Box<dyn Fn(&mut Kernel<'_>, &str) -> Result<(), i32> + Send>;
+
+/// A node given by SysfsPath (its primary) or by path.
+pub trait AsNode {
+    fn node_path(&self) -> &str;
+}
+
+impl AsNode for SysfsPath {
+    fn node_path(&self) -> &str {
+        self.primary
+    }
+}
+
+impl AsNode for &str {
+    fn node_path(&self) -> &str {
+        self
+    }
+}
+
+struct NodeState {
+    value: String,
+    read_only: bool,
+    rules: Vec<Rule>,
+    writes: Vec<String>,
+    rejected: Vec<String>,
+}
+
+impl NodeState {
+    fn new(value: String) -> Self {
+        Self {
+            value,
+            read_only: false,
+            rules: Vec::new(),
+            writes: Vec::new(),
+            rejected: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    nodes: BTreeMap<String, NodeState>,
+    // Accepted writes to every node, in order.
+    log: Vec<(String, String)>,
+    // Opens, found or not, and existence or access checks, each a syscall
+    // on a device.
+    opens: usize,
+    stats: usize,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+static DATA_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// What a Rule sees: the other nodes, as the driver would.
+pub struct Kernel<'a> {
+    nodes: &'a mut BTreeMap<String, NodeState>,
+}
+
+impl Kernel<'_> {
+    pub fn value(&self, path: impl AsNode) -> Option<&str> {
+        self.nodes.get(path.node_path()).map(|n| n.value.trim())
+    }
+
+    pub fn int(&self, path: impl AsNode) -> Option<i32> {
+        self.value(path)?.parse().ok()
+    }
+
+    /// Changes a node as the driver would, without recording a write.
+    pub fn set(&mut self, path: impl AsNode, value: impl ToString) {
+        if let Some(n) = self.nodes.get_mut(path.node_path()) {
+            n.value = value.to_string();
+        }
+    }
+}
+
+/// Declares the nodes of a FakeSysfs.
+pub struct FakeSysfs {
+    nodes: BTreeMap<String, NodeState>,
+}
+
+impl FakeSysfs {
+    pub fn builder() -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+        }
+    }
+
+    /// A writable node holding `value`.
+    pub fn node(mut self, path: impl AsNode, value: impl ToString) -> Self {
+        self.nodes.insert(
+            path.node_path().to_owned(),
+            NodeState::new(value.to_string()),
+        );
+        self
+    }
+
+    /// A node that can't be opened for writing, like a 0444 attribute.
+    pub fn read_only(mut self, path: impl AsNode, value: impl ToString) -> Self {
+        let mut node = NodeState::new(value.to_string());
+        node.read_only = true;
+        self.nodes.insert(path.node_path().to_owned(), node);
+        self
+    }
+
+    /// Adds a rule to a node declared earlier. Every rule must pass.
+    pub fn rule(
+        mut self,
+        path: impl AsNode,
+        rule: impl Fn(&mut Kernel<'_>, &str) -> Result<(), i32> + Send + 'static,
+    ) -> Self {
+        let path = path.node_path();
+        self.nodes
+            .get_mut(path)
+            .unwrap_or_else(|| panic!("rule for undeclared node {path}"))
+            .rules
+            .push(Box::new(rule));
+        self
+    }
+
+    /// Rejects writing the value a node already holds, as some charger
+    /// drivers do.
+    pub fn reject_equal(self, path: impl AsNode) -> Self {
+        let node = path.node_path().to_owned();
+        self.rule(path, move |k, v| match k.value(node.as_str()) {
+            Some(cur) if cur == v.trim() => Err(libc::EINVAL),
+            _ => Ok(()),
+        })
+    }
+
+    /// Rejects integers outside `min..=max` and anything that isn't one.
+    pub fn range(self, path: impl AsNode, min: i32, max: i32) -> Self {
+        self.rule(path, move |_, v| match v.trim().parse::<i32>() {
+            Ok(v) if (min..=max).contains(&v) => Ok(()),
+            _ => Err(libc::EINVAL),
+        })
+    }
+
+    /// The charger's user charge level nodes at `stop`/`start`, with the
+    /// checks google_charger makes on a write: a level from 0 to 100, a stop
+    /// level above the current start level, a start level below the current
+    /// stop level, and no rewriting the value already held.
+    pub fn charge_levels(self, stop: i32, start: i32) -> Self {
+        let (stop_node, start_node) = (
+            paths::USER_CHARGE_STOP_LEVEL,
+            paths::USER_CHARGE_START_LEVEL,
+        );
+        self.node(stop_node, stop)
+            .range(stop_node, 0, 100)
+            .reject_equal(stop_node)
+            .rule(stop_node, move |k, v| {
+                match (v.trim().parse::<i32>(), k.int(start_node)) {
+                    (Ok(v), Some(start)) if v <= start => Err(libc::EINVAL),
+                    _ => Ok(()),
+                }
+            })
+            .node(start_node, start)
+            .range(start_node, 0, 100)
+            .reject_equal(start_node)
+            .rule(start_node, move |k, v| {
+                match (v.trim().parse::<i32>(), k.int(stop_node)) {
+                    (Ok(v), Some(stop)) if v >= stop => Err(libc::EINVAL),
+                    _ => Ok(()),
+                }
+            })
+    }
+
+    /// Makes these the nodes every sysfs call sees until the Env is dropped.
+    pub fn install(self) -> Env {
+        let serial = SERIAL.lock_recover();
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "benzene_battery_test.{}.{}",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).expect("create test data dir");
+        *DATA_DIR.lock_recover() = Some(dir.clone());
+        *STATE.lock_recover() = Some(State {
+            nodes: self.nodes,
+            ..State::default()
+        });
+        sysfs::reset();
+        hot_cache::invalidate();
+        Env {
+            dir,
+            _serial: serial,
+        }
+    }
+}
+
+/// An installed FakeSysfs, for inspecting and changing it mid-test.
+pub struct Env {
+    dir: PathBuf,
+    _serial: MutexGuard<'static, ()>,
+}
+
+impl Env {
+    fn with<T>(&self, f: impl FnOnce(&mut State) -> T) -> T {
+        f(STATE.lock_recover().as_mut().expect("FakeSysfs installed"))
+    }
+
+    fn with_node<T>(&self, path: impl AsNode, f: impl FnOnce(&mut NodeState) -> T) -> T {
+        let path = path.node_path();
+        self.with(|s| {
+            f(s.nodes
+                .get_mut(path)
+                .unwrap_or_else(|| panic!("no node {path}")))
+        })
+    }
+
+    /// The node's content, trimmed.
+    pub fn value(&self, path: impl AsNode) -> String {
+        self.with_node(path, |n| n.value.trim().to_owned())
+    }
+
+    pub fn int(&self, path: impl AsNode) -> i32 {
+        let value = self.value(path);
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("'{value}' is not an integer"))
+    }
+
+    /// Writes the node accepted, oldest first.
+    pub fn writes(&self, path: impl AsNode) -> Vec<String> {
+        self.with_node(path, |n| n.writes.clone())
+    }
+
+    /// Writes a rule rejected, oldest first.
+    pub fn rejected(&self, path: impl AsNode) -> Vec<String> {
+        self.with_node(path, |n| n.rejected.clone())
+    }
+
+    /// Accepted writes to every node, oldest first.
+    pub fn write_log(&self) -> Vec<(String, String)> {
+        self.with(|s| s.log.clone())
+    }
+
+    /// Nodes opened since install, including opens that found nothing.
+    pub fn opens(&self) -> usize {
+        self.with(|s| s.opens)
+    }
+
+    /// Existence and access checks made since install.
+    pub fn stats(&self) -> usize {
+        self.with(|s| s.stats)
+    }
+
+    /// Changes a node behind the HAL's back, or creates it as a driver that
+    /// probes late would. Not recorded as a write.
+    pub fn set(&self, path: impl AsNode, value: impl ToString) {
+        let (path, value) = (path.node_path().to_owned(), value.to_string());
+        self.with(|s| match s.nodes.get_mut(&path) {
+            Some(n) => n.value = value,
+            None => {
+                s.nodes.insert(path, NodeState::new(value));
+            }
+        });
+    }
+}
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        *STATE.lock_recover() = None;
+        *DATA_DIR.lock_recover() = None;
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// persist's directory: the installed Env's, else one shared by the tests
+/// that don't install one.
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = DATA_DIR.lock_recover().clone() {
+        return dir;
+    }
+    static SHARED: OnceLock<PathBuf> = OnceLock::new();
+    SHARED
+        .get_or_init(|| {
+            let dir =
+                std::env::temp_dir().join(format!("benzene_battery_test.{}.shared", process::id()));
+            fs::create_dir_all(&dir).expect("create test data dir");
+            dir
+        })
+        .clone()
+}
+
+/// A node opened through `open_node`: a snapshot to read, or a handle whose
+/// every write() is one store(), as with sysfs.
+pub enum Node {
+    Read(Cursor<Vec<u8>>),
+    Write(String),
+}
+
+impl Read for Node {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Read(c) => c.read(buf),
+            Self::Write(_) => Err(io::Error::from_raw_os_error(libc::EBADF)),
+        }
+    }
+}
+
+impl Write for Node {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Self::Write(path) = self else {
+            return Err(io::Error::from_raw_os_error(libc::EBADF));
+        };
+        let value =
+            std::str::from_utf8(buf).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let mut state = STATE.lock_recover();
+        let state = state.as_mut().ok_or(ErrorKind::NotFound)?;
+        let node = state
+            .nodes
+            .get_mut(path.as_str())
+            .ok_or(ErrorKind::NotFound)?;
+        // Taken out while they run, so a rule can look at every node.
+        let rules = std::mem::take(&mut node.rules);
+        let before = node.value.clone();
+        let verdict = rules.iter().try_for_each(|r| {
+            r(
+                &mut Kernel {
+                    nodes: &mut state.nodes,
+                },
+                value,
+            )
+        });
+        let node = state
+            .nodes
+            .get_mut(path.as_str())
+            .expect("node removed by its own rule");
+        node.rules = rules;
+        match verdict {
+            Ok(()) => {
+                if node.value == before {
+                    node.value = value.to_owned();
+                }
+                node.writes.push(value.to_owned());
+                state.log.push((path.clone(), value.to_owned()));
+                Ok(buf.len())
+            }
+            Err(errno) => {
+                node.rejected.push(value.to_owned());
+                Err(io::Error::from_raw_os_error(errno))
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn open_node(path: &str, write: bool) -> io::Result<Node> {
+    let mut state = STATE.lock_recover();
+    let Some(state) = state.as_mut() else {
+        return Err(ErrorKind::NotFound.into());
+    };
+    state.opens += 1;
+    let node = state.nodes.get(path).ok_or(ErrorKind::NotFound)?;
+    if write {
+        if node.read_only {
+            return Err(io::Error::from_raw_os_error(libc::EACCES));
+        }
+        return Ok(Node::Write(path.to_owned()));
+    }
+    Ok(Node::Read(Cursor::new(node.value.clone().into_bytes())))
+}
+
+pub fn node_access(path: &str, write: bool) -> bool {
+    STATE.lock_recover().as_mut().is_some_and(|s| {
+        s.stats += 1;
+        s.nodes.get(path).is_some_and(|n| !write || !n.read_only)
+    })
+}
+
+pub fn node_exists(path: &str) -> bool {
+    STATE.lock_recover().as_mut().is_some_and(|s| {
+        s.stats += 1;
+        s.nodes.contains_key(path)
+    })
+}
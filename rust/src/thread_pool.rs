@@ -0,0 +1,103 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Binder thread pool sizing under load. The pool starts at the configured
+//! `binder_threads`. Binder doesn't expose its queue depth, so calls in
+//! flight stand in for it: when a call arrives with every pool thread already
+//! busy SUSTAINED_BUSY times within BUSY_WINDOW, the pool grows by one, up to
+//! `binder_threads_max`. After IDLE_AFTER without that it shrinks by one, but
+//! not below where it started. The kernel only stops asking for new threads past the limit, so a
+//! shrink leaves threads it already spawned idle rather than ending them.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use binder::ProcessState;
+use log::info;
+
+use crate::lock::MutexExt;
+
+pub const SUSTAINED_BUSY: u32 = 5;
+pub const BUSY_WINDOW: Duration = Duration::from_secs(1);
+pub const IDLE_AFTER: Duration = Duration::from_secs(60);
+
+struct Load {
+    busy: u32,
+    window_start: Instant,
+    last_busy: Instant,
+}
+
+pub struct ThreadPoolAdjuster {
+    min: usize,
+    max: usize,
+    size: AtomicUsize,
+    in_flight: AtomicUsize,
+    load: Mutex<Load>,
+}
+
+/// Marks a call finished when dropped.
+pub struct InFlight<'a>(&'a ThreadPoolAdjuster);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ThreadPoolAdjuster {
+    /// Expects the pool to have been started at `min`.
+    pub fn new(min: usize, max: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            min,
+            max,
+            size: AtomicUsize::new(min),
+            in_flight: AtomicUsize::new(0),
+            load: Mutex::new(Load {
+                busy: 0,
+                window_start: now,
+                last_busy: now,
+            }),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Call as a binder call starts and keep the guard until it returns.
+    pub fn enter(&self) -> InFlight<'_> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+        let size = self.size();
+        let now = Instant::now();
+        let mut load = self.load.lock_recover();
+        if now.duration_since(load.window_start) >= BUSY_WINDOW {
+            load.window_start = now;
+            load.busy = 0;
+        }
+        if in_flight > size {
+            load.busy += 1;
+            load.last_busy = now;
+            if load.busy >= SUSTAINED_BUSY && size < self.max {
+                self.resize(&mut load, size + 1, now);
+            }
+        } else if now.duration_since(load.last_busy) >= IDLE_AFTER && size > self.min {
+            self.resize(&mut load, size - 1, now);
+        }
+        InFlight(self)
+    }
+
+    fn resize(&self, load: &mut Load, size: usize, now: Instant) {
+        ProcessState::set_thread_pool_max_thread_count(size as u32);
+        self.size.store(size, Ordering::Relaxed);
+        load.busy = 0;
+        // A shrink waits a full IDLE_AFTER before the next one.
+        load.last_busy = now;
+        info!("Binder thread pool max set to {size}");
+    }
+}
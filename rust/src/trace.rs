@@ -0,0 +1,457 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Recent IBattery calls, for working out from a bugreport which sequence of
+//! calls left the HAL in a bad state. Each slot has its own lock and the write
+//! position is an atomic counter, so two calls only contend when they land on
+//! the same slot.
+
+use std::{
+    ffi::CStr,
+    fmt,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use binder::{ExceptionCode, Interface, Result, Status, StatusCode};
+use log::error;
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
+    AaccProfileInfo::AaccProfileInfo, AacpOptOut::AacpOptOut, AactProfile::AactProfile,
+    BatteryDefenderSummary::BatteryDefenderSummary, BatteryTechnology::BatteryTechnology,
+    CalibrationMode::CalibrationMode, CalibrationState::CalibrationState,
+    ChargeLimitSchedule::ChargeLimitSchedule, ChargeSessionStats::ChargeSessionStats,
+    ChargeStage::ChargeStage, ChargingPolicy::ChargingPolicy, ChargingSession::ChargingSession,
+    ChargingSpeedLevel::ChargingSpeedLevel, ChargingStage::ChargingStage,
+    ChargingStatus::ChargingStatus, ChargingType::ChargingType, CsiStats::CsiStats,
+    DefenderEvent::DefenderEvent, DefenderType::DefenderType, DeratingStatus::DeratingStatus,
+    DetailedHealthScore::DetailedHealthScore, DockDefendStatus::DockDefendStatus,
+    EffectiveChargeLimit::EffectiveChargeLimit, Feature::Feature, HealthAlgo::HealthAlgo,
+    HealthAlert::HealthAlert, HealthStats::HealthStats, HealthStatus::HealthStatus, IBattery,
+    SelfTestReport::SelfTestReport, TempDefendConfig::TempDefendConfig,
+    TemperatureSample::TemperatureSample, TrickleDefendConfig::TrickleDefendConfig,
+};
+
+use crate::{
+    config, degraded, lock::MutexExt, log_level, permission, persist, service::BatteryService,
+    thread_pool::ThreadPoolAdjuster,
+};
+
+pub const CAPACITY: usize = 128;
+/// Argument summaries are cut to this many bytes.
+pub const MAX_ARGS_LEN: usize = 96;
+/// The same at debug log level and below.
+pub const MAX_ARGS_LEN_VERBOSE: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Outcome {
+    Ok,
+    Exception(ExceptionCode),
+    ServiceSpecific(i32),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => write!(f, "ok"),
+            Self::Exception(code) => write!(f, "{code:?}"),
+            Self::ServiceSpecific(code) => write!(f, "service error {code}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub seq: u64,
+    pub at_ms: i64,
+    pub method: &'static str,
+    pub args: String,
+    pub uid: u32,
+    pub outcome: Outcome,
+    pub duration: Duration,
+}
+
+pub struct CallTrace {
+    next: AtomicU64,
+    slots: Vec<Mutex<Option<Call>>>,
+}
+
+impl CallTrace {
+    pub fn new() -> Self {
+        Self {
+            next: AtomicU64::new(0),
+            slots: (0..CAPACITY).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    fn record(&self, mut call: Call) {
+        call.seq = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut slot = self.slots[call.seq as usize % CAPACITY].lock_recover();
+        // A call that took a full lap longer than a newer one may arrive late.
+        match &*slot {
+            Some(newer) if newer.seq > call.seq => {}
+            _ => *slot = Some(call),
+        }
+    }
+
+    /// Recorded calls, oldest first.
+    pub fn snapshot(&self) -> Vec<Call> {
+        let mut calls: Vec<Call> = self
+            .slots
+            .iter()
+            .filter_map(|s| s.lock_recover().clone())
+            .collect();
+        calls.sort_by_key(|c| c.seq);
+        calls
+    }
+
+    fn dump(&self, w: &mut dyn Write) -> io::Result<()> {
+        let calls = self.snapshot();
+        writeln!(
+            w,
+            "Recent calls ({} of {}):",
+            calls.len(),
+            self.next.load(Ordering::Relaxed)
+        )?;
+        for c in calls {
+            writeln!(
+                w,
+                "  #{} {} uid={} {}({}) -> {} in {:?}",
+                c.seq, c.at_ms, c.uid, c.method, c.args, c.outcome, c.duration
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn summarize(args: &[&dyn fmt::Debug]) -> String {
+    let mut s = args
+        .iter()
+        .map(|a| format!("{a:?}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let max = if log_level::is_verbose() {
+        MAX_ARGS_LEN_VERBOSE
+    } else {
+        MAX_ARGS_LEN
+    };
+    if s.len() > max {
+        let mut end = max;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        s.truncate(end);
+        s.push_str("...");
+    }
+    s
+}
+
+/// The service as registered: every call is checked against the caller's
+/// uid, goes through to BatteryService and is recorded on the way out.
+pub struct TracedBattery {
+    svc: Arc<BatteryService>,
+    calls: CallTrace,
+    pool: ThreadPoolAdjuster,
+    calling_uid: fn() -> u32,
+}
+
+impl TracedBattery {
+    pub fn new(svc: Arc<BatteryService>) -> Self {
+        Self::with_calling_uid(svc, permission::calling_uid)
+    }
+
+    /// With the caller's uid from `calling_uid` rather than binder, so tests
+    /// can call as any uid.
+    pub fn with_calling_uid(svc: Arc<BatteryService>, calling_uid: fn() -> u32) -> Self {
+        let (min, max) = config::binder_threads();
+        Self {
+            svc,
+            calls: CallTrace::new(),
+            pool: ThreadPoolAdjuster::new(min, max),
+            calling_uid,
+        }
+    }
+
+    fn traced<T>(
+        &self,
+        method: &'static str,
+        args: String,
+        f: impl FnOnce(&BatteryService) -> Result<T>,
+    ) -> Result<T> {
+        let uid = (self.calling_uid)();
+        self.svc.record_call(uid, method);
+        let _in_flight = self.pool.enter();
+        let started = Instant::now();
+        let result = permission::enforce(method, uid).and_then(|()| {
+            if degraded::allows(method) {
+                f(&self.svc)
+            } else {
+                Err(Status::new_exception_str(
+                    ExceptionCode::ILLEGAL_STATE,
+                    Some("degraded mode, only getters are served"),
+                ))
+            }
+        });
+        self.svc.update_lazy_hold();
+        let outcome = match &result {
+            Ok(_) => Outcome::Ok,
+            Err(e) if e.exception_code() == ExceptionCode::SERVICE_SPECIFIC => {
+                Outcome::ServiceSpecific(e.service_specific_error())
+            }
+            Err(e) => Outcome::Exception(e.exception_code()),
+        };
+        self.calls.record(Call {
+            seq: 0,
+            at_ms: persist::now_ms(),
+            method,
+            args,
+            uid,
+            outcome,
+            duration: started.elapsed(),
+        });
+        result
+    }
+}
+
+impl Interface for TracedBattery {
+    fn dump(&self, writer: &mut dyn Write, args: &[&CStr]) -> std::result::Result<(), StatusCode> {
+        match degraded::handle_dump_args(writer, args)
+            .and_then(|done| Ok(done || log_level::handle_dump_args(writer, args)?))
+        {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(e) => {
+                error!("dump failed: {e}");
+                return Err(StatusCode::UNKNOWN_ERROR);
+            }
+        }
+        self.svc.dump(writer, args)?;
+        writeln!(writer, "Log level: {}", log::max_level())
+            .and_then(|()| writeln!(writer, "Binder thread pool max: {}", self.pool.size()))
+            .and_then(|()| self.calls.dump(writer))
+            .map_err(|e| {
+                error!("dump failed: {e}");
+                StatusCode::UNKNOWN_ERROR
+            })
+    }
+}
+
+macro_rules! traced_methods {
+    ($($name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        /// Every IBattery method, for tests that must cover them all.
+        #[cfg(test)]
+        pub const METHODS: &[&str] = &[$(stringify!($name)),*];
+
+        impl IBattery for TracedBattery {
+            $(
+                fn $name(&self, $($arg: $ty),*) -> Result<$ret> {
+                    let args = summarize(&[$(&$arg as &dyn fmt::Debug),*]);
+                    self.traced(stringify!($name), args, |svc| svc.$name($($arg),*))
+                }
+            )*
+        }
+    };
+}
+
+traced_methods! {
+    setChargingPolicy(policy: ChargingPolicy) -> ();
+    getChargingPolicy() -> ChargingPolicy;
+    setChargeLimit(stop: i32, start: i32) -> ();
+    getChargeLimit() -> Vec<i32>;
+    setChargeLimitForClient(tag: &str, stop: i32, start: i32) -> ();
+    clearChargeLimitForClient(tag: &str) -> ();
+    getEffectiveChargeLimit() -> EffectiveChargeLimit;
+    setChargeLimitSchedule(schedule: &ChargeLimitSchedule) -> ();
+    setChargeToLimit(enabled: bool) -> ();
+    getChargeToLimit() -> bool;
+    requestFullChargeOnce() -> ();
+    setEnable(feature: Feature, enabled: bool) -> ();
+    setEnableFeatures(features: &[Feature], enabled: bool) -> ();
+    clearBatteryDefenders(kind: DefenderType) -> ();
+    getDefenderEvents(max_count: i32) -> Vec<DefenderEvent>;
+    getBatteryDefenderSummary() -> BatteryDefenderSummary;
+    setTempDefendConfig(config: &TempDefendConfig) -> ();
+    getTempDefendConfig() -> TempDefendConfig;
+    setTrickleDefendConfig(config: &TrickleDefendConfig) -> ();
+    getTrickleDefendConfig() -> TrickleDefendConfig;
+    getStringProperty(feature: Feature, prop: i32) -> String;
+    setStringProperty(feature: Feature, prop: i32, value: &str) -> ();
+    getChargingStatus() -> ChargingStatus;
+    getChargingType() -> ChargingType;
+    getChargingSpeed() -> i32;
+    getChargeStats() -> Vec<ChargeSessionStats>;
+    getChargingSession() -> ChargingSession;
+    getChargingSpeedLevel() -> ChargingSpeedLevel;
+    getMaxChargingSpeed() -> i32;
+    setChargingCurrentLimit(ma: i32) -> ();
+    getChargingCurrentLimit() -> i32;
+    setTemperatureDerating(enabled: bool, threshold_deci_c: i32, reduction_percent: i32) -> ();
+    getTemperatureDeratingStatus() -> DeratingStatus;
+    setReverseWirelessCharging(enabled: bool) -> ();
+    isReverseWirelessChargingSupported() -> bool;
+    setChargingDeadline(deadline: i32) -> ();
+    setChargingDeadlineLocal(seconds_from_now: i32) -> ();
+    setAdaptiveChargingPaused(paused: bool) -> ();
+    setAdaptiveChargingEnabled(enabled: bool) -> ();
+    isAdaptiveChargingPaused() -> bool;
+    getChargingStageAndDeadline() -> ChargingStage;
+    getChargeStageEnum() -> ChargeStage;
+    setHealthSafetyMargin(seconds: i32) -> ();
+    getHealthSafetyMargin() -> i32;
+    getHealthIndex() -> i32;
+    getHealthStatus() -> HealthStatus;
+    getHealthCapacityIndex() -> i32;
+    getHealthImpedanceIndex() -> i32;
+    getHealthStats(algo: HealthAlgo) -> HealthStats;
+    getDetailedHealthScore() -> DetailedHealthScore;
+    getCsiStats() -> CsiStats;
+    getHealthAlerts() -> Vec<HealthAlert>;
+    setHealthAlwaysOn(value: i32) -> ();
+    setHealthTrendPoints(points: &[i32]) -> ();
+    setHealthLowBoundary(boundary_pct: i32) -> ();
+    setHealthLowBoundaryIndexed(index: i32, boundary_pct: i32) -> ();
+    scheduleCalibration(mode: CalibrationMode) -> ();
+    getCalibrationState() -> CalibrationState;
+    getDockDefendStatus() -> DockDefendStatus;
+    setAactProfile(profile: &AactProfile) -> ();
+    getAactProfile() -> AactProfile;
+    setAaccProfile(index: i32) -> ();
+    getAaccProfile() -> i32;
+    setAaccProfileInfo(profile: &AaccProfileInfo) -> ();
+    getAaccProfileInfo() -> AaccProfileInfo;
+    getAacpVersion() -> i32;
+    setAacpOptOut(opt_out: bool, cutoff_cycles: i32) -> ();
+    getAacpOptOut() -> AacpOptOut;
+    setAafvEnabled(enabled: bool) -> ();
+    setTemperatureSampling(enabled: bool, interval_minutes: i32, pause_on_discharge: bool) -> ();
+    getTemperatureHistory() -> Vec<TemperatureSample>;
+    getBatteryManufacturer() -> String;
+    getBatteryModelName() -> String;
+    getBatterySerialNumber() -> String;
+    getBatteryTechnology() -> String;
+    getBatteryTechnologyEnum() -> BatteryTechnology;
+    getCapacityPercent() -> i32;
+    runSelfTest() -> SelfTestReport;
+    getCallCountsByUid() -> String;
+    getAdapterId() -> i32;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{sysfs::paths, testing::FakeSysfs};
+
+    fn call(method: &'static str) -> Call {
+        Call {
+            seq: 0,
+            at_ms: 0,
+            method,
+            args: String::new(),
+            uid: 0,
+            outcome: Outcome::Ok,
+            duration: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn calls_are_recorded_in_order_with_their_outcome() {
+        let _env = FakeSysfs::builder().charge_levels(100, 0).install();
+        let battery = TracedBattery::with_calling_uid(
+            Arc::new(BatteryService::new(None)),
+            || permission::AID_SYSTEM,
+        );
+        battery.setChargeLimit(80, 70).unwrap();
+        battery.getChargeLimit().unwrap();
+        battery.setChargeLimit(120, 70).unwrap_err();
+        battery.setChargeLimitForClient("policy", 90, 80).unwrap();
+
+        let calls = battery.calls.snapshot();
+        let seen: Vec<_> = calls.iter().map(|c| (c.seq, c.method, c.args.as_str())).collect();
+        assert_eq!(
+            seen,
+            [
+                (0, "setChargeLimit", "80, 70"),
+                (1, "getChargeLimit", ""),
+                (2, "setChargeLimit", "120, 70"),
+                (3, "setChargeLimitForClient", "\"policy\", 90, 80"),
+            ]
+        );
+        assert!(calls.iter().all(|c| c.uid == permission::AID_SYSTEM));
+        assert!(matches!(calls[2].outcome, Outcome::Exception(ExceptionCode::ILLEGAL_ARGUMENT)));
+        assert!(matches!(calls[3].outcome, Outcome::Ok));
+
+        let mut dump = Vec::new();
+        battery.calls.dump(&mut dump).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        assert!(dump.starts_with("Recent calls (4 of 4):\n"), "{dump}");
+        assert!(dump.contains(" uid=1000 setChargeLimit(120, 70) -> "), "{dump}");
+        let (first, last) = (dump.find("#0 ").unwrap(), dump.find("#3 ").unwrap());
+        assert!(first < last);
+    }
+
+    #[test]
+    fn rejected_callers_are_recorded_too() {
+        let env = FakeSysfs::builder().charge_levels(100, 0).install();
+        let battery =
+            TracedBattery::with_calling_uid(Arc::new(BatteryService::new(None)), || 10123);
+        battery.setChargeLimit(80, 70).unwrap_err();
+        let calls = battery.calls.snapshot();
+        assert_eq!((calls[0].method, calls[0].uid), ("setChargeLimit", 10123));
+        assert!(matches!(calls[0].outcome, Outcome::Exception(ExceptionCode::SECURITY)));
+        assert!(env.write_log().is_empty());
+        assert_eq!(paths::USER_CHARGE_STOP_LEVEL.read_int().unwrap(), 100);
+    }
+
+    #[test]
+    fn the_ring_keeps_the_newest_calls() {
+        let trace = Arc::new(CallTrace::new());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let trace = trace.clone();
+                thread::spawn(move || {
+                    for _ in 0..CAPACITY {
+                        trace.record(call("getChargeLimit"));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        let seqs: Vec<_> = trace.snapshot().iter().map(|c| c.seq).collect();
+        let total = 4 * CAPACITY as u64;
+        assert_eq!(seqs, (total - CAPACITY as u64..total).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn a_late_call_does_not_replace_a_newer_one() {
+        let trace = CallTrace::new();
+        trace.next.store(CAPACITY as u64, Ordering::Relaxed);
+        trace.record(call("newer"));
+        // A call that took its seq a lap earlier lands on the same slot after.
+        trace.next.store(0, Ordering::Relaxed);
+        trace.record(call("late"));
+        let calls = trace.snapshot();
+        assert_eq!(calls.len(), 1);
+        assert_eq!((calls[0].seq, calls[0].method), (CAPACITY as u64, "newer"));
+    }
+
+    #[test]
+    fn long_arguments_are_cut() {
+        let _lock = log_level::TEST_LOCK.lock_recover();
+        log::set_max_level(log::LevelFilter::Info);
+        let long = "x".repeat(4 * MAX_ARGS_LEN);
+        let s = summarize(&[&long]);
+        assert!(s.len() <= MAX_ARGS_LEN + 3 && s.ends_with("..."), "{s}");
+        assert_eq!(summarize(&[&1, &"a"]), "1, \"a\"");
+        // Debug keeps more of them.
+        log::set_max_level(log::LevelFilter::Debug);
+        assert_eq!(summarize(&[&long]).len(), long.len() + 2);
+        log::set_max_level(log::LevelFilter::Info);
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Value checks shared by the typed setters and `setStringProperty`, so both
+//! paths reject the same input before it reaches the kernel.
+
+use vendor_benzeneos_battery::aidl::vendor::benzeneos::battery::IBattery::{
+    AactProfile::AactProfile, Feature::Feature, TempDefendConfig::TempDefendConfig,
+    TrickleDefendConfig::TrickleDefendConfig,
+};
+
+use crate::{
+    aacc::AaccProfile,
+    aacr::AacrState,
+    aafv::AafvState,
+    config,
+    sysfs::{self, property},
+};
+
+pub type Result<T> = std::result::Result<T, String>;
+
+fn check_increasing(name: &str, v: &[i32]) -> Result<()> {
+    match v.windows(2).position(|w| w[1] <= w[0]) {
+        Some(i) => Err(format!(
+            "{name} must be strictly increasing: [{i}]={} >= [{}]={}",
+            v[i],
+            i + 1,
+            v[i + 1]
+        )),
+        None => Ok(()),
+    }
+}
+
+pub fn charge_limit(stop: i32, start: i32) -> Result<()> {
+    levels(stop, start, config::get().min_gap)
+}
+
+/// charge_limit with an explicit gap, for checking the config file itself.
+pub fn levels(stop: i32, start: i32, min_gap: i32) -> Result<()> {
+    if !(50..=100).contains(&stop) {
+        return Err("stop must be 50-100".into());
+    }
+    if stop - start < min_gap {
+        return Err(format!("gap must be >= {min_gap}"));
+    }
+    Ok(())
+}
+
+pub fn aact_profile(p: &AactProfile) -> Result<()> {
+    if p.cvLimitsUv.is_empty() {
+        return Err("cvLimitsUv is empty".into());
+    }
+    if p.tempLimitsDeciC.len() < 2 {
+        return Err(format!(
+            "tempLimitsDeciC needs at least 2 entries, got {}",
+            p.tempLimitsDeciC.len()
+        ));
+    }
+    check_increasing("cvLimitsUv", &p.cvLimitsUv)?;
+    check_increasing("tempLimitsDeciC", &p.tempLimitsDeciC)?;
+
+    let rows = p.tempLimitsDeciC.len() - 1;
+    let cols = p.cvLimitsUv.len();
+    if p.eccMatrix.len() != rows * cols {
+        return Err(format!(
+            "eccMatrix has {} entries, expected {rows}x{cols}={}",
+            p.eccMatrix.len(),
+            rows * cols
+        ));
+    }
+    Ok(())
+}
+
+pub const TEMP_DEFEND_TRIGGER_TEMP_RANGE: std::ops::RangeInclusive<i32> = 350..=500;
+/// The trigger has to hold this long at most; longer never fires in practice.
+pub const TEMP_DEFEND_MAX_TIME_SEC: i32 = 24 * 60 * 60;
+/// 0 leaves the voltage trigger off.
+pub const TEMP_DEFEND_TRIGGER_VOLTAGE_RANGE: std::ops::RangeInclusive<i32> = 3_800_000..=4_600_000;
+pub const TEMP_DEFEND_MIN_RESUME_TEMP: i32 = 200;
+
+pub fn temp_defend_config(c: &TempDefendConfig) -> Result<()> {
+    if !TEMP_DEFEND_TRIGGER_TEMP_RANGE.contains(&c.triggerTempDeciC) {
+        return Err(format!(
+            "triggerTempDeciC {} outside {TEMP_DEFEND_TRIGGER_TEMP_RANGE:?}",
+            c.triggerTempDeciC
+        ));
+    }
+    if !(TEMP_DEFEND_MIN_RESUME_TEMP..c.triggerTempDeciC).contains(&c.resumeTempDeciC) {
+        return Err(format!(
+            "resumeTempDeciC {} must be {TEMP_DEFEND_MIN_RESUME_TEMP}-{}",
+            c.resumeTempDeciC,
+            c.triggerTempDeciC - 1
+        ));
+    }
+    if c.triggerVoltageUv != 0 && !TEMP_DEFEND_TRIGGER_VOLTAGE_RANGE.contains(&c.triggerVoltageUv) {
+        return Err(format!(
+            "triggerVoltageUv {} outside {TEMP_DEFEND_TRIGGER_VOLTAGE_RANGE:?}",
+            c.triggerVoltageUv
+        ));
+    }
+    for (name, v) in [("triggerTimeSec", c.triggerTimeSec), ("resumeTimeSec", c.resumeTimeSec)] {
+        if !(0..=TEMP_DEFEND_MAX_TIME_SEC).contains(&v) {
+            return Err(format!("{name} {v} outside 0-{TEMP_DEFEND_MAX_TIME_SEC}"));
+        }
+    }
+    for (name, v) in [("rechargeSoc", c.rechargeSoc), ("resumeSoc", c.resumeSoc)] {
+        if !(0..=100).contains(&v) {
+            return Err(format!("{name} {v} outside 0-100"));
+        }
+    }
+    Ok(())
+}
+
+/// Recharging at or above the stop level would never let trickle defend
+/// discharge, so `rechargeSoc` has to sit below `stop`.
+pub fn trickle_defend_config(c: &TrickleDefendConfig, stop: i32) -> Result<()> {
+    if !(0..=100).contains(&c.rate) {
+        return Err(format!("rate {} outside 0-100", c.rate));
+    }
+    if c.cntThreshold < 1 {
+        return Err(format!("cntThreshold {} must be >= 1", c.cntThreshold));
+    }
+    if !(0..stop).contains(&c.rechargeSoc) {
+        return Err(format!(
+            "rechargeSoc {} must be 0 or more and below the stop level {stop}",
+            c.rechargeSoc
+        ));
+    }
+    if c.resetSec < 0 {
+        return Err(format!("resetSec {} must be >= 0", c.resetSec));
+    }
+    Ok(())
+}
+
+/// The health driver expects exactly this many trend points.
+pub const HEALTH_TREND_POINT_COUNT: usize = 10;
+/// Trend points are cycle counts.
+pub const HEALTH_TREND_POINT_MAX: i32 = 10000;
+
+pub fn health_trend_points(points: &[i32]) -> Result<()> {
+    if points.len() != HEALTH_TREND_POINT_COUNT {
+        return Err(format!(
+            "expected {HEALTH_TREND_POINT_COUNT} trend points, got {}",
+            points.len()
+        ));
+    }
+    if let Some(p) = points
+        .iter()
+        .find(|p| !(0..=HEALTH_TREND_POINT_MAX).contains(*p))
+    {
+        return Err(format!("trend point {p} outside 0-{HEALTH_TREND_POINT_MAX}"));
+    }
+    check_increasing("trend points", points)
+}
+
+/// The margin is in seconds; four hours is well past any real alarm lead.
+pub const HEALTH_SAFETY_MARGIN_MAX_SEC: i32 = 4 * 60 * 60;
+
+/// The margin pads the predicted time-to-full, so it has to be positive: a
+/// zero margin leaves prediction error nowhere to go.
+pub fn health_safety_margin(seconds: i32) -> Result<()> {
+    if seconds <= 0 {
+        return Err(format!("safety margin {seconds}s must be positive"));
+    }
+    if seconds > HEALTH_SAFETY_MARGIN_MAX_SEC {
+        return Err(format!("safety margin {seconds}s above {HEALTH_SAFETY_MARGIN_MAX_SEC}s"));
+    }
+    Ok(())
+}
+
+pub const HEALTH_LOW_BOUNDARY_MIN: i32 = 50;
+pub const HEALTH_LOW_BOUNDARY_MAX: i32 = 80;
+
+pub fn health_low_boundary(pct: i32) -> Result<()> {
+    if pct < HEALTH_LOW_BOUNDARY_MIN {
+        return Err(format!("boundaryPct {pct} below minimum {HEALTH_LOW_BOUNDARY_MIN}"));
+    }
+    if pct > HEALTH_LOW_BOUNDARY_MAX {
+        return Err(format!("boundaryPct {pct} above maximum {HEALTH_LOW_BOUNDARY_MAX}"));
+    }
+    Ok(())
+}
+
+/// How `health_set_low_boundary` wants its value: older kernels take a bare
+/// percentage, newer ones an `index,percentage` pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LowBoundaryFormat {
+    Percent,
+    Indexed { index: i32 },
+}
+
+impl LowBoundaryFormat {
+    /// Detects the format from the node's current content.
+    pub fn detect(content: &str) -> Option<Self> {
+        match sysfs::parse_ints(content)?.as_slice() {
+            [_] => Some(Self::Percent),
+            [index, _] => Some(Self::Indexed { index: *index }),
+            _ => None,
+        }
+    }
+}
+
+fn health_trend_points_str(value: &str) -> Result<String> {
+    let points = sysfs::parse_ints(value).ok_or("trend points must be integers")?;
+    health_trend_points(&points)?;
+    Ok(sysfs::format_ints(&points))
+}
+
+fn health_safety_margin_str(value: &str) -> Result<String> {
+    let seconds = value
+        .trim()
+        .parse::<i32>()
+        .map_err(|_| format!("safety margin '{value}' must be whole seconds"))?;
+    health_safety_margin(seconds)?;
+    Ok(seconds.to_string())
+}
+
+fn aacr_state_str(value: &str) -> Result<String> {
+    value
+        .trim()
+        .parse::<i32>()
+        .ok()
+        .and_then(AacrState::from_i32)
+        .map(|s| s.as_i32().to_string())
+        .ok_or_else(|| format!("unknown AACR state '{value}'"))
+}
+
+fn aafv_state_str(value: &str) -> Result<String> {
+    value.parse::<AafvState>().map(|s| s.as_i32().to_string())
+}
+
+fn aacc_profile_str(value: &str) -> Result<String> {
+    AaccProfile::parse(value).map(|p| p.to_string())
+}
+
+fn charge_limit_bypass_mode_str(value: &str) -> Result<String> {
+    let allowed = property::charge_limit_bypass_mode_allowed_values();
+    match value.trim().parse::<i32>() {
+        Ok(mode) if allowed.contains(&mode) => Ok(mode.to_string()),
+        _ => Err(format!("bypass mode '{value}' not one of {allowed:?}")),
+    }
+}
+
+/// Validates a raw property value and returns the string to write.
+pub struct PropertyValidator {
+    pub feature: Feature,
+    pub prop: i32,
+    pub validate: fn(&str) -> Result<String>,
+}
+
+pub const PROPERTY_VALIDATORS: &[PropertyValidator] = &[
+    PropertyValidator {
+        feature: Feature::HEALTH,
+        prop: property::TREND_POINTS,
+        validate: health_trend_points_str,
+    },
+    PropertyValidator {
+        feature: Feature::CHARGE_LIMIT,
+        prop: property::BYPASS_MODE,
+        validate: charge_limit_bypass_mode_str,
+    },
+    PropertyValidator {
+        feature: Feature::CHARGE_DEADLINE,
+        prop: property::HEALTH_SAFETY_MARGIN,
+        validate: health_safety_margin_str,
+    },
+    PropertyValidator {
+        feature: Feature::AACR,
+        prop: property::STATE,
+        validate: aacr_state_str,
+    },
+    PropertyValidator {
+        feature: Feature::AAFV,
+        prop: property::STATE,
+        validate: aafv_state_str,
+    },
+    PropertyValidator {
+        feature: Feature::AACC,
+        prop: property::PROFILE,
+        validate: aacc_profile_str,
+    },
+];
+
+/// sysfs takes at most a page less its terminator per write and silently
+/// drops the rest.
+pub const MAX_PROPERTY_VALUE_LEN: usize = 4095;
+
+/// Checks every property value has to pass: short enough for one sysfs
+/// write, and no control characters besides whitespace, which no node
+/// takes and which would otherwise reach the kernel as part of the value.
+fn property_raw(value: &str) -> Result<()> {
+    if value.len() > MAX_PROPERTY_VALUE_LEN {
+        return Err(format!(
+            "value is {} bytes, more than {MAX_PROPERTY_VALUE_LEN}",
+            value.len()
+        ));
+    }
+    if let Some(c) = value
+        .chars()
+        .find(|c| c.is_control() && !c.is_ascii_whitespace())
+    {
+        return Err(format!("value contains control character {c:?}"));
+    }
+    Ok(())
+}
+
+/// Runs the validator registered for `(feature, prop)`, if any. Properties
+/// without one are passed through unchanged once property_raw accepts them.
+pub fn property(feature: Feature, prop: i32, value: &str) -> Result<String> {
+    property_raw(value)?;
+    match PROPERTY_VALIDATORS
+        .iter()
+        .find(|v| v.feature == feature && v.prop == prop)
+    {
+        Some(v) => (v.validate)(value),
+        None => Ok(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_boundary_format_follows_the_node() {
+        assert_eq!(LowBoundaryFormat::detect("70\n"), Some(LowBoundaryFormat::Percent));
+        assert_eq!(
+            LowBoundaryFormat::detect("2,70\n"),
+            Some(LowBoundaryFormat::Indexed { index: 2 })
+        );
+        assert_eq!(
+            LowBoundaryFormat::detect("1 65"),
+            Some(LowBoundaryFormat::Indexed { index: 1 })
+        );
+        for content in ["", "\n", "1,2,3", "seventy", "2,x"] {
+            assert_eq!(LowBoundaryFormat::detect(content), None, "{content:?}");
+        }
+    }
+
+    #[test]
+    fn safety_margin_is_in_seconds_up_to_four_hours() {
+        for seconds in [1, 600, HEALTH_SAFETY_MARGIN_MAX_SEC] {
+            assert_eq!(health_safety_margin(seconds), Ok(()), "{seconds}");
+        }
+        for seconds in [i32::MIN, -1, 0, HEALTH_SAFETY_MARGIN_MAX_SEC + 1, i32::MAX] {
+            assert!(health_safety_margin(seconds).is_err(), "{seconds}");
+        }
+        // The property path goes through the same check.
+        let check =
+            |v: &str| property(Feature::CHARGE_DEADLINE, property::HEALTH_SAFETY_MARGIN, v);
+        assert_eq!(check("14400\n"), Ok("14400".to_owned()));
+        for value in ["0", "14401", "240m", "4h", ""] {
+            assert!(check(value).is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn bypass_mode_must_be_an_allowed_value() {
+        let check = |v: &str| property(Feature::CHARGE_LIMIT, property::BYPASS_MODE, v);
+        for &mode in property::charge_limit_bypass_mode_allowed_values() {
+            assert_eq!(check(&mode.to_string()), Ok(mode.to_string()));
+        }
+        assert_eq!(check(" 2\n"), Ok("2".to_owned()));
+        for value in ["3", "-1", "", "fcn", "1.0"] {
+            let e = check(value).unwrap_err();
+            assert!(e.contains("not one of [0, 1, 2]"), "{value:?}: {e}");
+        }
+    }
+
+    #[test]
+    fn low_boundary_names_the_bound() {
+        assert!(health_low_boundary(HEALTH_LOW_BOUNDARY_MIN).is_ok());
+        assert!(health_low_boundary(HEALTH_LOW_BOUNDARY_MAX).is_ok());
+        assert_eq!(health_low_boundary(49).unwrap_err(), "boundaryPct 49 below minimum 50");
+        assert_eq!(health_low_boundary(81).unwrap_err(), "boundaryPct 81 above maximum 80");
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright (C) 2025 Amaan Qureshi <contact@amaanq.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Background refresh of slow sysfs reads. Health stats and firmware status
+//! can take hundreds of milliseconds to read, which would block every binder
+//! call queued behind them. Callers get a recent snapshot and a refresh is
+//! queued to a worker thread; they only read synchronously when there is no
+//! snapshot or it is too old to trust.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+
+use crate::{
+    lock::MutexExt,
+    sysfs::{self, paths, SysfsPath},
+};
+
+/// Snapshots older than this are refreshed in the background.
+pub const REFRESH_AFTER: Duration = Duration::from_secs(10);
+/// Snapshots older than this are not served at all.
+pub const MAX_AGE: Duration = Duration::from_secs(60);
+/// How long the worker waits for work before checking whether the service
+/// side of the queue is gone.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AsyncOp {
+    HealthStatsPoll,
+    FirmwareStatusPoll,
+    SessionSample,
+}
+
+impl AsyncOp {
+    fn path(self) -> SysfsPath {
+        match self {
+            Self::HealthStatsPoll => paths::HEALTH_INDEX_STATS,
+            Self::FirmwareStatusPoll => paths::FW_UPDATE_FIRMWARE,
+            Self::SessionSample => paths::CHARGE_STATS,
+        }
+    }
+
+    /// Firmware status is handed to callers as is, so it is trimmed; the
+    /// compound nodes go to parsers untouched. Health stats have stalled on
+    /// a wedged fuel gauge, and a caller without a snapshot reads them on its
+    /// binder thread, so that read is bounded.
+    fn read(self) -> sysfs::Result<String> {
+        match self {
+            Self::FirmwareStatusPoll => self.path().read_string(),
+            Self::HealthStatsPoll => self.path().read_string_raw_timeout(sysfs::SLOW_READ_TIMEOUT),
+            Self::SessionSample => self.path().read_string_raw(),
+        }
+    }
+}
+
+struct Snapshot {
+    content: String,
+    at: Instant,
+}
+
+type Snapshots = Arc<Mutex<HashMap<AsyncOp, Snapshot>>>;
+
+fn refresh(snapshots: &Snapshots, op: AsyncOp) -> sysfs::Result<String> {
+    let content = op.read()?;
+    snapshots.lock_recover().insert(
+        op,
+        Snapshot {
+            content: content.clone(),
+            at: Instant::now(),
+        },
+    );
+    Ok(content)
+}
+
+pub struct Worker {
+    tx: Sender<AsyncOp>,
+    snapshots: Snapshots,
+}
+
+impl Worker {
+    /// Starts the worker thread. If that fails every read is synchronous.
+    pub fn spawn() -> Self {
+        let (tx, rx) = mpsc::channel::<AsyncOp>();
+        let snapshots = Snapshots::default();
+        let worker_snapshots = snapshots.clone();
+        let spawned = thread::Builder::new()
+            .name("battery_worker".into())
+            .spawn(move || loop {
+                match rx.recv_timeout(IDLE_TIMEOUT) {
+                    Ok(op) => {
+                        // Several callers may have queued the same refresh.
+                        let fresh = worker_snapshots
+                            .lock_recover()
+                            .get(&op)
+                            .is_some_and(|s| s.at.elapsed() < REFRESH_AFTER);
+                        if !fresh {
+                            if let Err(e) = refresh(&worker_snapshots, op) {
+                                debug!("{op:?} failed: {e}");
+                            }
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            });
+        if let Err(e) = spawned {
+            warn!("Failed to start worker thread: {e}");
+        }
+        Self { tx, snapshots }
+    }
+
+    /// Content for `op`, from a snapshot when one is recent enough.
+    pub fn read(&self, op: AsyncOp) -> sysfs::Result<String> {
+        let cached = {
+            let snapshots = self.snapshots.lock_recover();
+            match snapshots.get(&op) {
+                Some(s) if s.at.elapsed() < MAX_AGE => Some((s.content.clone(), s.at.elapsed())),
+                _ => None,
+            }
+        };
+        match cached {
+            Some((content, age)) => {
+                if age >= REFRESH_AFTER {
+                    // A send only fails if the worker never started; the next
+                    // call past MAX_AGE then reads synchronously.
+                    let _ = self.tx.send(op);
+                }
+                Ok(content)
+            }
+            None => refresh(&self.snapshots, op),
+        }
+    }
+}